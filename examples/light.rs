@@ -1,5 +1,5 @@
 use std::time::Duration;
-use toio::{Cube, LightOp};
+use toio::{Cube, LightOp, Repeat};
 use tokio::time::delay_for;
 
 #[tokio::main]
@@ -24,7 +24,7 @@ async fn main() {
 
     // Light as programmed.
     cube.light(
-        10,
+        Repeat::Times(10),
         vec![
             LightOp::new(255, 0, 0, Some(Duration::from_millis(100))),
             LightOp::new(0, 255, 0, Some(Duration::from_millis(100))),
@@ -1,5 +1,5 @@
 use std::time::Duration;
-use toio::{Cube, Note, SoundOp, SoundPresetId};
+use toio::{Cube, Note, Repeat, SoundOp, SoundPresetId};
 use tokio::time::delay_for;
 
 #[tokio::main]
@@ -21,7 +21,7 @@ async fn main() {
 
     // Play as programmed.
     cube.play(
-        3,
+        Repeat::Times(3),
         vec![
             SoundOp::new(Note::C5, Duration::from_millis(500)),
             SoundOp::new(Note::A6, Duration::from_millis(500)),
@@ -33,9 +33,12 @@ async fn main() {
     delay_for(Duration::from_secs(4)).await;
 
     // Play the sound.
-    cube.play(1, vec![SoundOp::new(Note::C5, Duration::from_millis(2000))])
-        .await
-        .unwrap();
+    cube.play(
+        Repeat::Times(1),
+        vec![SoundOp::new(Note::C5, Duration::from_millis(2000))],
+    )
+    .await
+    .unwrap();
 
     delay_for(Duration::from_secs(1)).await;
 
@@ -0,0 +1,65 @@
+/*
+ * C API of the toio driver.
+ *
+ * Build the library with `cargo rustc --release --features ffi --crate-type cdylib`.
+ * See the documentation of `toio::ffi` for the conventions.
+ */
+
+#ifndef TOIO_H
+#define TOIO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ToioCube ToioCube;
+
+typedef enum ToioEventKind {
+    TOIO_EVENT_NONE = 0,
+    TOIO_EVENT_BATTERY = 1,
+    TOIO_EVENT_BATTERY_LOW = 2,
+    TOIO_EVENT_COLLISION = 3,
+    TOIO_EVENT_SLOPE = 4,
+    TOIO_EVENT_BUTTON = 5,
+    TOIO_EVENT_POSTURE = 6,
+    TOIO_EVENT_POSITION = 7,
+    TOIO_EVENT_POSITION_MISSED = 8,
+    TOIO_EVENT_STD_ID = 9,
+    TOIO_EVENT_STD_ID_MISSED = 10,
+    TOIO_EVENT_UNRESPONSIVE = 11,
+    TOIO_EVENT_LAGGED = 12,
+    TOIO_EVENT_PROXIMITY = 13,
+    TOIO_EVENT_OTHER = 255,
+} ToioEventKind;
+
+typedef struct ToioEvent {
+    ToioEventKind kind;
+    int64_t value;
+    uint16_t x;
+    uint16_t y;
+    uint16_t angle;
+} ToioEvent;
+
+const char *toio_last_error(void);
+
+ToioCube *toio_search(uint32_t timeout_ms);
+int toio_search_all(ToioCube **out, size_t cap, uint32_t timeout_ms);
+void toio_cube_free(ToioCube *cube);
+const char *toio_cube_id(ToioCube *cube);
+
+int toio_connect(ToioCube *cube);
+int toio_go(ToioCube *cube, int left, int right, uint32_t duration_ms);
+int toio_stop(ToioCube *cube);
+int toio_light_on(ToioCube *cube, uint8_t red, uint8_t green, uint8_t blue, uint32_t duration_ms);
+int toio_light_off(ToioCube *cube);
+int toio_play_preset(ToioCube *cube, uint8_t id);
+int toio_poll_event(ToioCube *cube, ToioEvent *event, uint32_t timeout_ms);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const DEFAULT_WINDOW: Duration = Duration::from_secs(30 * 60);
+
+/// Estimates the remaining runtime from the recent discharge rate.
+///
+/// Keeps the battery percentage over the window and fits a line to it.
+/// The battery of the cube is reported in coarse steps, so the estimation
+/// gets accurate after the battery has dropped a few steps.
+#[derive(Debug, Clone)]
+pub struct Estimator {
+    samples: VecDeque<(Instant, usize)>,
+    window: Duration,
+}
+
+impl Default for Estimator {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl Estimator {
+    /// Creates an estimator which uses the samples within the window.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            window,
+        }
+    }
+
+    /// Adds the battery percentage at the time.
+    pub fn push(&mut self, at: Instant, pct: usize) {
+        // Charging invalidates the discharge history.
+        if let Some((_, last)) = self.samples.back() {
+            if pct > *last {
+                self.samples.clear();
+            }
+        }
+        self.samples.push_back((at, pct));
+        while let Some((t, _)) = self.samples.front() {
+            if at.duration_since(*t) > self.window {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Gets the discharge rate in percent per hour.
+    ///
+    /// Returns `None` if there aren't enough samples.
+    pub fn rate(&self) -> Option<f64> {
+        let (first, _) = self.samples.front()?;
+        let (last, _) = self.samples.back()?;
+        if self.samples.len() < 2 || *last == *first {
+            return None;
+        }
+
+        // Least squares fit of percentage over hours.
+        let points: Vec<_> = self
+            .samples
+            .iter()
+            .map(|(t, p)| (t.duration_since(*first).as_secs_f64() / 3600.0, *p as f64))
+            .collect();
+        let n = points.len() as f64;
+        let mx = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let my = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let sxy: f64 = points.iter().map(|(x, y)| (x - mx) * (y - my)).sum();
+        let sxx: f64 = points.iter().map(|(x, _)| (x - mx) * (x - mx)).sum();
+        Some(-sxy / sxx)
+    }
+
+    /// Gets the estimated time until the battery runs out.
+    ///
+    /// Returns `None` if there aren't enough samples or the battery isn't discharging.
+    pub fn estimate(&self) -> Option<Duration> {
+        let rate = self.rate().filter(|r| *r > 0.0)?;
+        let (_, pct) = self.samples.back()?;
+        Some(Duration::from_secs_f64(*pct as f64 / rate * 3600.0))
+    }
+}
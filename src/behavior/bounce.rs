@@ -0,0 +1,135 @@
+use anyhow::{bail, Result};
+use futures::prelude::*;
+use std::time::Duration;
+use tokio::time::delay_for;
+
+use crate::{Cube, Event, EventFilter};
+
+/// Drives straight, and bounces off obstacles.
+///
+/// When the cube collides with an object, it backs up, turns by a random angle,
+/// and drives on. Collisions detected during the maneuver are ignored.
+/// On the mat, the cube turns with [`Cube::align_to`][]. Off the mat, it spins for
+/// the time estimated from [`Bounce::turn_rate`][].
+///
+/// The behavior runs until an error occurs. Make sure the collision detection is
+/// enabled with a threshold that suits the speed.
+///
+/// ```no_run
+/// use toio::{behavior::Bounce, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     Bounce::new().speed(40).run(&mut cube).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bounce {
+    speed: isize,
+    backup: Duration,
+    turn: (u16, u16),
+    turn_rate: f32,
+    seed: Option<u64>,
+}
+
+impl Default for Bounce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bounce {
+    /// Creates the behavior.
+    pub fn new() -> Self {
+        Self {
+            speed: 30,
+            backup: Duration::from_millis(500),
+            turn: (90, 270),
+            turn_rate: 200.0,
+            seed: None,
+        }
+    }
+
+    /// Sets the wheel speed from 1 to 100. By default, it's `30`.
+    pub fn speed(mut self, speed: isize) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Sets the time to back up. By default, it's 500 milliseconds.
+    pub fn backup(mut self, backup: Duration) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// Sets the range of the angle to turn clockwise, in degrees from 0 to 360.
+    /// By default, it's from 90 to 270.
+    pub fn turn(mut self, min: u16, max: u16) -> Self {
+        self.turn = (min, max);
+        self
+    }
+
+    /// Sets the degrees per second the cube spins at the speed, used off the mat.
+    /// By default, it's `200.0`.
+    pub fn turn_rate(mut self, rate: f32) -> Self {
+        self.turn_rate = rate;
+        self
+    }
+
+    /// Sets the seed of the random numbers, to repeat the same turns.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Bounces until an error occurs.
+    pub async fn run(&self, cube: &mut Cube) -> Result<()> {
+        if self.speed < 1 || self.speed > 100 {
+            bail!("Wheel speed must be between 1 and 100");
+        }
+        if self.turn.0 > self.turn.1 || self.turn.1 > 360 {
+            bail!("Turn angles must be between 0 and 360 in order");
+        }
+        if self.turn_rate <= 0.0 {
+            bail!("Turn rate must be positive");
+        }
+
+        let mut rng = self
+            .seed
+            .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+        let filter = EventFilter::new().collision().position();
+        let mut events = cube.events_filtered(filter.clone()).await?;
+        let mut pos = None;
+
+        cube.go(self.speed, self.speed, None).await?;
+        while let Some(event) = events.next().await {
+            match event {
+                Event::Position(p) => pos = p,
+                Event::Collision(true) => {
+                    cube.go(-self.speed, -self.speed, None).await?;
+                    delay_for(self.backup).await;
+
+                    let turn = rng.u16(self.turn.0..=self.turn.1);
+                    match pos.take() {
+                        Some(p) => cube.align_to((p.angle + turn) % 360).await?,
+                        None => {
+                            let spin = Duration::from_secs_f32(turn as f32 / self.turn_rate);
+                            cube.go(self.speed, -self.speed, None).await?;
+                            delay_for(spin).await;
+                        }
+                    }
+
+                    // Drops the events queued during the maneuver.
+                    events = cube.events_filtered(filter.clone()).await?;
+                    cube.go(self.speed, self.speed, None).await?;
+                }
+                _ => {}
+            }
+        }
+
+        bail!("Event stream closed")
+    }
+}
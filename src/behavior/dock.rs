@@ -0,0 +1,183 @@
+use anyhow::{anyhow, bail, Result};
+use futures::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{delay_for, timeout, Instant};
+
+use crate::{Cube, Event, EventFilter, EventStream, StdId};
+
+/// The interval to update the speeds on the spiral.
+const SPIRAL_STEP: Duration = Duration::from_millis(200);
+
+/// Progress of [`Dock`][].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DockEvent {
+    /// The card isn't under the cube, so searching for it on a spiral.
+    Searching,
+    /// The card is found under the cube.
+    Found(StdId),
+    /// Moving to the center of the card.
+    Centering,
+    /// Stopped on the card.
+    Docked(StdId),
+}
+
+/// Drives the cube onto a card with the standard id.
+///
+/// If the cube isn't on the card, it searches for the card on an outward spiral.
+/// Once on the card, the cube drives straight until it leaves the card, then goes back
+/// by half the time, so that it stops around the center of the card.
+///
+/// ```no_run
+/// use toio::{behavior::Dock, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     let id = Dock::new(3670016)
+///         .run(&mut cube, |event| println!("{:?}", event))
+///         .await
+///         .unwrap();
+///     println!("docked at angle {}", id.angle);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Dock {
+    id: u32,
+    speed: isize,
+    spiral: Duration,
+    timeout: Duration,
+}
+
+impl Dock {
+    /// Creates the behavior to dock to the card with the standard id.
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            speed: 30,
+            spiral: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the wheel speed from 1 to 100. By default, it's `30`.
+    pub fn speed(mut self, speed: isize) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Sets how slowly the spiral widens.
+    ///
+    /// The inner wheel runs at half the speed after this time. By default, it's 10 seconds.
+    pub fn spiral(mut self, spiral: Duration) -> Self {
+        self.spiral = spiral;
+        self
+    }
+
+    /// Sets the time limit of the whole docking. By default, it's 60 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Docks the cube, reporting the progress to the callback.
+    ///
+    /// Returns the standard id read after stopping on the card.
+    /// The cube is stopped also on failure.
+    pub async fn run<F>(&self, cube: &mut Cube, mut progress: F) -> Result<StdId>
+    where
+        F: FnMut(DockEvent),
+    {
+        if self.speed < 1 || self.speed > 100 {
+            bail!("Wheel speed must be between 1 and 100");
+        }
+
+        let res = self.dock(cube, &mut progress).await;
+        if res.is_err() {
+            cube.stop().await?;
+        }
+        res
+    }
+
+    async fn dock<F>(&self, cube: &mut Cube, progress: &mut F) -> Result<StdId>
+    where
+        F: FnMut(DockEvent),
+    {
+        let mut events = cube.events_filtered(EventFilter::new().std_id()).await?;
+        let deadline = Instant::now() + self.timeout;
+
+        let id = match cube.std_id_now().await? {
+            Some(id) if id.id == self.id => id,
+            _ => {
+                progress(DockEvent::Searching);
+                self.search(cube, &mut events, deadline).await?
+            }
+        };
+        let entered = Instant::now();
+        progress(DockEvent::Found(id));
+
+        progress(DockEvent::Centering);
+        cube.go(self.speed, self.speed, None).await?;
+        loop {
+            match next(&mut events, deadline).await? {
+                Some(Some(id)) if id.id == self.id => {}
+                Some(_) => break,
+                None => bail!("Cube doesn't leave card {}", self.id),
+            }
+        }
+        let across = entered.elapsed();
+        cube.go(-self.speed, -self.speed, None).await?;
+        delay_for(across / 2).await;
+        cube.stop().await?;
+
+        let id = cube
+            .std_id_now()
+            .await?
+            .filter(|id| id.id == self.id)
+            .ok_or_else(|| anyhow!("Cube isn't on card {} after centering", self.id))?;
+        progress(DockEvent::Docked(id.clone()));
+        Ok(id)
+    }
+
+    async fn search(
+        &self,
+        cube: &mut Cube,
+        events: &mut EventStream,
+        deadline: Instant,
+    ) -> Result<StdId> {
+        let start = Instant::now();
+        loop {
+            let t = start.elapsed();
+            let ratio = t.as_secs_f32() / (t + self.spiral).as_secs_f32();
+            let inner = (self.speed as f32 * ratio).round() as isize;
+            cube.go(self.speed, inner, None).await?;
+
+            let until = deadline.min(Instant::now() + SPIRAL_STEP);
+            while let Some(id) = next(events, until).await? {
+                if let Some(id) = id.filter(|id| id.id == self.id) {
+                    return Ok(id);
+                }
+            }
+            if Instant::now() >= deadline {
+                bail!("Card {} isn't found", self.id);
+            }
+        }
+    }
+}
+
+/// Waits for the next standard id event until the deadline.
+///
+/// Returns `None` on the deadline.
+async fn next(events: &mut EventStream, deadline: Instant) -> Result<Option<Option<StdId>>> {
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match timeout(left, events.next()).await {
+            Ok(Some(Event::StdId(id))) => return Ok(Some(id)),
+            Ok(Some(_)) => {}
+            Ok(None) => bail!("Event stream closed"),
+            Err(_) => return Ok(None),
+        }
+    }
+}
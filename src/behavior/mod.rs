@@ -0,0 +1,9 @@
+mod bounce;
+mod dock;
+mod tree;
+mod wander;
+
+pub use bounce::*;
+pub use dock::*;
+pub use tree::*;
+pub use wander::*;
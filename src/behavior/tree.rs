@@ -0,0 +1,393 @@
+use anyhow::Result;
+use futures::{future, pin_mut};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{delay_for, Instant};
+
+use crate::Cube;
+
+/// The result of a tick of [`Behavior`][].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// Not finished yet. The behavior wants to be ticked again.
+    Running,
+    /// Finished successfully.
+    Success,
+    /// Finished unsuccessfully.
+    Failure,
+}
+
+/// A step-wise behavior of the cube, which is the node of a behavior tree.
+///
+/// The behavior is ticked repeatedly while it returns [`Status::Running`][], and starts
+/// over on the next tick after it finishes. A tick should return quickly, so that the
+/// siblings in [`Parallel`][] run together.
+#[async_trait::async_trait]
+pub trait Behavior: Send {
+    /// Runs a step of the behavior.
+    async fn tick(&mut self, cube: &mut Cube) -> Result<Status>;
+
+    /// Interrupts the behavior, which returned [`Status::Running`][] on the last tick.
+    ///
+    /// The behavior starts over on the next tick.
+    /// The default implementation does nothing.
+    async fn cancel(&mut self, _cube: &mut Cube) -> Result<()> {
+        Ok(())
+    }
+
+    /// Boxes the behavior to compose it.
+    fn boxed(self) -> Box<dyn Behavior>
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior for Box<dyn Behavior> {
+    async fn tick(&mut self, cube: &mut Cube) -> Result<Status> {
+        (**self).tick(cube).await
+    }
+
+    async fn cancel(&mut self, cube: &mut Cube) -> Result<()> {
+        (**self).cancel(cube).await
+    }
+}
+
+/// Runs the children in order while they succeed.
+///
+/// Succeeds if all the children succeed, and fails as soon as one fails.
+/// A running child is resumed on the next tick, without ticking the previous ones again.
+pub struct Sequence {
+    children: Vec<Box<dyn Behavior>>,
+    index: usize,
+    running: bool,
+}
+
+impl Sequence {
+    /// Creates the sequence of the children.
+    pub fn new(children: Vec<Box<dyn Behavior>>) -> Self {
+        Self {
+            children,
+            index: 0,
+            running: false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior for Sequence {
+    async fn tick(&mut self, cube: &mut Cube) -> Result<Status> {
+        while let Some(child) = self.children.get_mut(self.index) {
+            match child.tick(cube).await? {
+                Status::Running => {
+                    self.running = true;
+                    return Ok(Status::Running);
+                }
+                Status::Success => self.index += 1,
+                Status::Failure => break,
+            }
+        }
+        let status = if self.index == self.children.len() {
+            Status::Success
+        } else {
+            Status::Failure
+        };
+        self.index = 0;
+        self.running = false;
+        Ok(status)
+    }
+
+    async fn cancel(&mut self, cube: &mut Cube) -> Result<()> {
+        if self.running {
+            self.children[self.index].cancel(cube).await?;
+        }
+        self.index = 0;
+        self.running = false;
+        Ok(())
+    }
+}
+
+/// Runs the children in order until one succeeds.
+///
+/// Succeeds as soon as one child succeeds, and fails if all the children fail.
+/// A running child is resumed on the next tick, without ticking the previous ones again.
+pub struct Fallback {
+    children: Vec<Box<dyn Behavior>>,
+    index: usize,
+    running: bool,
+}
+
+impl Fallback {
+    /// Creates the fallback of the children.
+    pub fn new(children: Vec<Box<dyn Behavior>>) -> Self {
+        Self {
+            children,
+            index: 0,
+            running: false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior for Fallback {
+    async fn tick(&mut self, cube: &mut Cube) -> Result<Status> {
+        while let Some(child) = self.children.get_mut(self.index) {
+            match child.tick(cube).await? {
+                Status::Running => {
+                    self.running = true;
+                    return Ok(Status::Running);
+                }
+                Status::Success => break,
+                Status::Failure => self.index += 1,
+            }
+        }
+        let status = if self.index == self.children.len() {
+            Status::Failure
+        } else {
+            Status::Success
+        };
+        self.index = 0;
+        self.running = false;
+        Ok(status)
+    }
+
+    async fn cancel(&mut self, cube: &mut Cube) -> Result<()> {
+        if self.running {
+            self.children[self.index].cancel(cube).await?;
+        }
+        self.index = 0;
+        self.running = false;
+        Ok(())
+    }
+}
+
+/// Ticks all the children together.
+///
+/// By default, succeeds if all the children succeed, and fails as soon as one fails.
+/// With [`Parallel::any`][], succeeds as soon as one succeeds, and fails if all fail.
+/// The children still running at the end are cancelled.
+pub struct Parallel {
+    children: Vec<Box<dyn Behavior>>,
+    results: Vec<Option<Status>>,
+    any: bool,
+    running: bool,
+}
+
+impl Parallel {
+    /// Creates the parallel of the children.
+    pub fn new(children: Vec<Box<dyn Behavior>>) -> Self {
+        let results = vec![None; children.len()];
+        Self {
+            children,
+            results,
+            any: false,
+            running: false,
+        }
+    }
+
+    /// Succeeds as soon as one child succeeds.
+    pub fn any(mut self) -> Self {
+        self.any = true;
+        self
+    }
+
+    async fn finish(&mut self, cube: &mut Cube, status: Status) -> Result<Status> {
+        self.cancel(cube).await?;
+        Ok(status)
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior for Parallel {
+    async fn tick(&mut self, cube: &mut Cube) -> Result<Status> {
+        self.running = true;
+        for (child, result) in self.children.iter_mut().zip(&mut self.results) {
+            if result.is_none() {
+                match child.tick(cube).await? {
+                    Status::Running => {}
+                    status => *result = Some(status),
+                }
+            }
+        }
+
+        let (decisive, rest) = if self.any {
+            (Status::Success, Status::Failure)
+        } else {
+            (Status::Failure, Status::Success)
+        };
+        if self.results.contains(&Some(decisive)) {
+            self.finish(cube, decisive).await
+        } else if self.results.iter().all(Option::is_some) {
+            self.finish(cube, rest).await
+        } else {
+            Ok(Status::Running)
+        }
+    }
+
+    async fn cancel(&mut self, cube: &mut Cube) -> Result<()> {
+        if self.running {
+            for (child, result) in self.children.iter_mut().zip(&self.results) {
+                if result.is_none() {
+                    child.cancel(cube).await?;
+                }
+            }
+        }
+        self.results.iter_mut().for_each(|r| *r = None);
+        self.running = false;
+        Ok(())
+    }
+}
+
+/// Waits for the duration.
+#[derive(Debug, Clone)]
+pub struct Wait {
+    duration: Duration,
+    start: Option<Instant>,
+}
+
+impl Wait {
+    /// Creates the behavior to wait for the duration.
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            start: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior for Wait {
+    async fn tick(&mut self, _cube: &mut Cube) -> Result<Status> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        if start.elapsed() >= self.duration {
+            self.start = None;
+            Ok(Status::Success)
+        } else {
+            Ok(Status::Running)
+        }
+    }
+
+    async fn cancel(&mut self, _cube: &mut Cube) -> Result<()> {
+        self.start = None;
+        Ok(())
+    }
+}
+
+/// Sets the wheel speeds with [`Cube::go`][], and succeeds.
+#[derive(Debug, Clone)]
+pub struct Go {
+    left: isize,
+    right: isize,
+    duration: Option<Duration>,
+}
+
+impl Go {
+    /// Creates the behavior to set the wheel speeds.
+    pub fn new(left: isize, right: isize, duration: Option<Duration>) -> Self {
+        Self {
+            left,
+            right,
+            duration,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior for Go {
+    async fn tick(&mut self, cube: &mut Cube) -> Result<Status> {
+        cube.go(self.left, self.right, self.duration).await?;
+        Ok(Status::Success)
+    }
+}
+
+/// Ticks a behavior at the interval until it finishes.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use toio::{
+///     behavior::{Behavior, Executor, Go, Sequence, Wait},
+///     Cube,
+/// };
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     // Drives forward for a second, then spins for a second.
+///     let mut square = Sequence::new(vec![
+///         Go::new(30, 30, None).boxed(),
+///         Wait::new(Duration::from_secs(1)).boxed(),
+///         Go::new(30, -30, None).boxed(),
+///         Wait::new(Duration::from_secs(1)).boxed(),
+///         Go::new(0, 0, None).boxed(),
+///     ]);
+///     let status = Executor::new().run(&mut cube, &mut square).await.unwrap();
+///     println!("{:?}", status);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Executor {
+    interval: Duration,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor {
+    /// Creates the executor.
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_millis(50),
+        }
+    }
+
+    /// Sets the interval of the ticks. By default, it's 50 milliseconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Runs the behavior until it succeeds or fails.
+    pub async fn run(&self, cube: &mut Cube, behavior: &mut dyn Behavior) -> Result<Status> {
+        loop {
+            match behavior.tick(cube).await? {
+                Status::Running => delay_for(self.interval).await,
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Runs the behavior until it finishes, or the future completes.
+    ///
+    /// On the completion of the future, the behavior is cancelled before the next tick,
+    /// and `None` is returned.
+    pub async fn run_until<F>(
+        &self,
+        cube: &mut Cube,
+        behavior: &mut dyn Behavior,
+        cancel: F,
+    ) -> Result<Option<Status>>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        pin_mut!(cancel);
+        loop {
+            match behavior.tick(cube).await? {
+                Status::Running => {}
+                status => return Ok(Some(status)),
+            }
+            let delay = delay_for(self.interval);
+            pin_mut!(delay);
+            if let future::Either::Right(_) = future::select(delay, cancel.as_mut()).await {
+                behavior.cancel(cube).await?;
+                return Ok(None);
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{bail, Result};
+use futures::prelude::*;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::{drive::HeadingController, Cube, Event, EventFilter, Position};
+
+/// The duration of each motor command, so that the cube stops if the updates stop.
+const COMMAND: Duration = Duration::from_millis(500);
+
+/// Wanders around the mat at random.
+///
+/// The cube drives on a random heading for a random time, and then picks another one.
+/// When it comes close to the edge of the area heading outward, it turns back toward
+/// the center. The cube stops while it's off the mat.
+///
+/// The behavior runs until an error occurs, so spawn it to run in the background,
+/// and abort it to cancel.
+///
+/// ```no_run
+/// use futures::future::abortable;
+/// use std::time::Duration;
+/// use tokio::time::delay_for;
+/// use toio::{behavior::Wander, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     let (task, handle) = abortable(async move {
+///         Wander::new().speed(40).run(&mut cube).await
+///     });
+///     tokio::spawn(task);
+///
+///     delay_for(Duration::from_secs(60)).await;
+///     handle.abort();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Wander {
+    speed: isize,
+    jitter: isize,
+    legs: (Duration, Duration),
+    area: (u16, u16, u16, u16),
+    margin: u16,
+    seed: Option<u64>,
+}
+
+impl Default for Wander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wander {
+    /// Creates the behavior.
+    pub fn new() -> Self {
+        Self {
+            speed: 30,
+            jitter: 10,
+            legs: (Duration::from_secs(1), Duration::from_secs(4)),
+            area: (45, 45, 455, 455),
+            margin: 40,
+            seed: None,
+        }
+    }
+
+    /// Sets the average wheel speed. By default, it's `30`.
+    pub fn speed(mut self, speed: isize) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Sets the maximum random change of the speed on each heading. By default, it's `10`.
+    pub fn jitter(mut self, jitter: isize) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the range of the time to keep a heading. By default, it's from 1 to 4 seconds.
+    pub fn legs(mut self, min: Duration, max: Duration) -> Self {
+        self.legs = (min, max);
+        self
+    }
+
+    /// Sets the area to stay in by the top-left and bottom-right corners.
+    /// By default, it's the area of the mat of toio collection, `(45, 45)` to `(455, 455)`.
+    pub fn area(mut self, min: (u16, u16), max: (u16, u16)) -> Self {
+        self.area = (min.0, min.1, max.0, max.1);
+        self
+    }
+
+    /// Sets the distance from the edge of the area to turn back. By default, it's `40`.
+    pub fn margin(mut self, margin: u16) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Sets the seed of the random numbers, to repeat the same walk.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Wanders until an error occurs.
+    pub async fn run(&self, cube: &mut Cube) -> Result<()> {
+        if self.jitter < 0 || self.speed - self.jitter < 1 || self.speed + self.jitter > 100 {
+            bail!("Wheel speed with the jitter must be between 1 and 100");
+        }
+        if self.legs.0 > self.legs.1 {
+            bail!("Minimum time of the heading exceeds the maximum");
+        }
+
+        let mut rng = self
+            .seed
+            .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+        let mut events = cube.events_filtered(EventFilter::new().position()).await?;
+        let mut ctrl = HeadingController::new(0.0)
+            .cross_track(0.0)
+            .max_correction(self.speed);
+        let mut speed = self.speed;
+        let mut leg = Instant::now();
+        let mut last = Instant::now();
+        let mut sent: Option<((isize, isize), Instant)> = None;
+
+        while let Some(event) = events.next().await {
+            let pos = match event {
+                Event::Position(Some(pos)) => pos,
+                Event::Position(None) => {
+                    if sent.take().is_some() {
+                        cube.stop().await?;
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let now = Instant::now();
+            if let Some(heading) = self.avoid(&pos, ctrl.heading(), &mut rng) {
+                ctrl.set_heading(heading);
+                leg = now + self.legs.0;
+            } else if now >= leg {
+                ctrl.set_heading(rng.f32() * 360.0);
+                speed = self.speed + rng.isize(-self.jitter..=self.jitter);
+                leg = now + self.legs.0 + (self.legs.1 - self.legs.0).mul_f32(rng.f32());
+            }
+            let speeds = ctrl.update(speed, &pos, now - last);
+            last = now;
+
+            // Sends only changes, and refreshes the command before it runs out.
+            match sent {
+                Some((s, at)) if s == speeds && now < at + COMMAND / 2 => {}
+                _ => {
+                    cube.go(speeds.0, speeds.1, Some(COMMAND)).await?;
+                    sent = Some((speeds, now));
+                }
+            }
+        }
+
+        bail!("Event stream closed")
+    }
+
+    /// Gets the heading back toward the center if the cube is heading out of the area.
+    fn avoid(&self, pos: &Position, heading: f32, rng: &mut fastrand::Rng) -> Option<f32> {
+        let (min_x, min_y, max_x, max_y) = self.area;
+        let (sin, cos) = heading.to_radians().sin_cos();
+        let outward = (pos.x < min_x.saturating_add(self.margin) && cos < 0.0)
+            || (pos.x > max_x.saturating_sub(self.margin) && cos > 0.0)
+            || (pos.y < min_y.saturating_add(self.margin) && sin < 0.0)
+            || (pos.y > max_y.saturating_sub(self.margin) && sin > 0.0);
+        if !outward {
+            return None;
+        }
+
+        let dx = (min_x + max_x) as f32 / 2.0 - pos.x as f32;
+        let dy = (min_y + max_y) as f32 / 2.0 - pos.y as f32;
+        let center = dy.atan2(dx).to_degrees();
+        Some((center + rng.f32() * 60.0 - 30.0).rem_euclid(360.0))
+    }
+}
@@ -0,0 +1,255 @@
+use bevy_app::{App, Plugin, Startup, Update};
+use bevy_ecs::prelude::*;
+use futures::prelude::*;
+use log::*;
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+use tokio::{runtime::Handle, sync::mpsc as async_mpsc};
+
+use crate::{proto::Message, Cube, SoundPresetId};
+
+/// The cube which the entity stands for.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct ToioCube {
+    /// The id of the cube.
+    pub id: String,
+    index: usize,
+}
+
+/// The pose of the cube on the mat, updated from the position events.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pose {
+    /// The x coordinate on the mat.
+    pub x: u16,
+    /// The y coordinate on the mat.
+    pub y: u16,
+    /// The angle in degrees.
+    pub angle: u16,
+    /// `true` if the cube is on the mat.
+    pub on_mat: bool,
+}
+
+/// The event emitted by a cube.
+#[derive(Event, Debug, Clone)]
+pub struct CubeEvent {
+    /// The entity of the cube.
+    pub entity: Entity,
+    /// The event.
+    pub event: crate::Event,
+}
+
+/// The command to a cube.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Runs the motors. See [`Cube::go`][].
+    Go {
+        /// The speed of the left wheel.
+        left: isize,
+        /// The speed of the right wheel.
+        right: isize,
+        /// The duration to run.
+        duration: Option<Duration>,
+    },
+    /// Stops the motors.
+    Stop,
+    /// Turns on the light. See [`Cube::light_on`][].
+    LightOn {
+        /// The value of red light.
+        red: u8,
+        /// The value of green light.
+        green: u8,
+        /// The value of blue light.
+        blue: u8,
+        /// The duration to turn on.
+        duration: Option<Duration>,
+    },
+    /// Turns off the light.
+    LightOff,
+    /// Plays the preset sound.
+    PlayPreset(SoundPresetId),
+    /// Writes the raw message.
+    Message(Message),
+}
+
+/// The event to send a command to a cube.
+#[derive(Event, Debug, Clone)]
+pub struct CubeCommand {
+    /// The entity of the cube.
+    pub entity: Entity,
+    /// The command.
+    pub command: Command,
+}
+
+#[derive(Resource)]
+struct Link {
+    ids: Vec<String>,
+    events: Mutex<mpsc::Receiver<(usize, crate::Event)>>,
+    commands: Vec<async_mpsc::UnboundedSender<Command>>,
+}
+
+/// Bevy plugin exposing cubes as entities.
+///
+/// Spawns an entity with [`ToioCube`][] and [`Pose`][] for each cube on startup.
+/// Events from the cubes are delivered as [`CubeEvent`][]s, and [`CubeCommand`][]s
+/// are sent to the cubes. The cubes are driven on the tokio runtime of the handle,
+/// so they should be connected on the same runtime beforehand.
+///
+/// Enabled by the `bevy` feature.
+///
+/// ```no_run
+/// use bevy_app::App;
+/// use toio::{bevy::ToioPlugin, Cube};
+///
+/// fn main() {
+///     let mut rt = tokio::runtime::Runtime::new().unwrap();
+///     let cubes = rt.block_on(async {
+///         let mut cubes = Cube::search().all().await.unwrap();
+///         for cube in &mut cubes {
+///             cube.connect().await.unwrap();
+///         }
+///         cubes
+///     });
+///
+///     App::new()
+///         .add_plugins(ToioPlugin::new(rt.handle().clone(), cubes))
+///         .run();
+/// }
+/// ```
+pub struct ToioPlugin {
+    handle: Handle,
+    cubes: Mutex<Option<Vec<Cube>>>,
+}
+
+impl ToioPlugin {
+    /// Creates a plugin for the connected cubes.
+    pub fn new(handle: Handle, cubes: Vec<Cube>) -> Self {
+        Self {
+            handle,
+            cubes: Mutex::new(Some(cubes)),
+        }
+    }
+}
+
+impl Plugin for ToioPlugin {
+    fn build(&self, app: &mut App) {
+        let cubes = self.cubes.lock().unwrap().take().unwrap_or_default();
+        let (tx, rx) = mpsc::channel();
+        let mut ids = vec![];
+        let mut commands = vec![];
+
+        for (index, cube) in cubes.into_iter().enumerate() {
+            let (cmd_tx, cmd_rx) = async_mpsc::unbounded_channel();
+            ids.push(cube.id().to_string());
+            commands.push(cmd_tx);
+            self.handle.spawn(drive(index, cube, tx.clone(), cmd_rx));
+        }
+
+        app.insert_resource(Link {
+            ids,
+            events: Mutex::new(rx),
+            commands,
+        })
+        .add_event::<CubeEvent>()
+        .add_event::<CubeCommand>()
+        .add_systems(Startup, spawn_cubes)
+        .add_systems(Update, (receive_events, send_commands));
+    }
+}
+
+async fn drive(
+    index: usize,
+    mut cube: Cube,
+    tx: mpsc::Sender<(usize, crate::Event)>,
+    mut commands: async_mpsc::UnboundedReceiver<Command>,
+) {
+    let mut events = match cube.events().await {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("Couldn't subscribe to events of {}: {}", cube.id(), e);
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => {
+                    if tx.send((index, event)).is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            cmd = commands.recv() => match cmd {
+                Some(cmd) => {
+                    let res = match cmd {
+                        Command::Go { left, right, duration } => cube.go(left, right, duration).await,
+                        Command::Stop => cube.stop().await,
+                        Command::LightOn { red, green, blue, duration } => {
+                            cube.light_on(red, green, blue, duration).await
+                        }
+                        Command::LightOff => cube.light_off().await,
+                        Command::PlayPreset(id) => cube.play_preset(id).await,
+                        Command::Message(msg) => cube.write_msg(msg, false).await,
+                    };
+                    if let Err(e) = res {
+                        warn!("Couldn't send command to {}: {}", cube.id(), e);
+                    }
+                }
+                None => break,
+            },
+        }
+    }
+}
+
+fn spawn_cubes(mut commands: Commands, link: Res<Link>) {
+    for (index, id) in link.ids.iter().enumerate() {
+        commands.spawn((
+            ToioCube {
+                id: id.clone(),
+                index,
+            },
+            Pose::default(),
+        ));
+    }
+}
+
+fn receive_events(
+    link: Res<Link>,
+    mut cubes: Query<(Entity, &ToioCube, &mut Pose)>,
+    mut writer: EventWriter<CubeEvent>,
+) {
+    let events = link.events.lock().unwrap();
+    while let Ok((index, event)) = events.try_recv() {
+        let (entity, _, mut pose) = match cubes.iter_mut().find(|(_, c, _)| c.index == index) {
+            Some(cube) => cube,
+            None => continue,
+        };
+        if let crate::Event::Position(pos) = &event {
+            *pose = match pos {
+                Some(p) => Pose {
+                    x: p.x,
+                    y: p.y,
+                    angle: p.angle,
+                    on_mat: true,
+                },
+                None => Pose {
+                    on_mat: false,
+                    ..*pose
+                },
+            };
+        }
+        writer.send(CubeEvent { entity, event });
+    }
+}
+
+fn send_commands(link: Res<Link>, cubes: Query<&ToioCube>, mut reader: EventReader<CubeCommand>) {
+    for cmd in reader.read() {
+        match cubes.get(cmd.entity) {
+            Ok(cube) => {
+                let _ = link.commands[cube.index].send(cmd.command.clone());
+            }
+            Err(_) => warn!("Command to an entity which isn't a cube: {:?}", cmd.entity),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Context, Result};
+use futures::prelude::*;
+use std::time::Duration;
+use structopt::{clap::AppSettings, StructOpt};
+use toio::{Cube, Event, SoundPresetId};
+use tokio::time::{delay_for, timeout};
+
+/// Command line tool to check toio cubes.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "toio")]
+struct Opt {
+    /// The id of the cube to use. The nearest cube is used if not specified.
+    #[structopt(short, long, global = true)]
+    id: Option<String>,
+
+    /// The seconds to search for cubes.
+    #[structopt(short, long, default_value = "10", global = true)]
+    timeout: u64,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Lists the cubes nearby.
+    Scan,
+    /// Shows the status of the cube.
+    Status,
+    /// Runs the motors.
+    #[structopt(setting = AppSettings::AllowNegativeNumbers)]
+    Drive {
+        /// The speed of the left wheel from -100 to 100.
+        left: isize,
+        /// The speed of the right wheel from -100 to 100.
+        right: isize,
+        /// The milliseconds to run.
+        #[structopt(short, long, default_value = "1000")]
+        duration: u64,
+    },
+    /// Plays the preset sound.
+    Sound {
+        /// The id of the preset sound from 0 to 10.
+        preset: u8,
+    },
+    /// Turns on the light. Turns off if all the values are zero.
+    Light {
+        /// The value of red light.
+        red: u8,
+        /// The value of green light.
+        green: u8,
+        /// The value of blue light.
+        blue: u8,
+        /// The milliseconds to turn on. Keeps it on if not specified.
+        #[structopt(short, long)]
+        duration: Option<u64>,
+    },
+    /// Prints the events of the cube.
+    Events {
+        /// The seconds to print. Prints until interrupted if not specified.
+        #[structopt(short, long)]
+        duration: Option<u64>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let opt = Opt::from_args();
+    let search_timeout = Duration::from_secs(opt.timeout);
+
+    if let Command::Scan = opt.cmd {
+        let cubes = Cube::search().all_timeout(search_timeout).await?;
+        if cubes.is_empty() {
+            println!("No cube found");
+        }
+        for cube in cubes {
+            println!("{}  rssi={}", cube.id(), cube.rssi());
+        }
+        return Ok(());
+    }
+
+    let mut cube = match &opt.id {
+        Some(id) => Cube::search()
+            .all_timeout(search_timeout)
+            .await?
+            .into_iter()
+            .find(|c| c.id() == id)
+            .ok_or_else(|| anyhow!("No such cube: {}", id))?,
+        None => Cube::search().nearest_timeout(search_timeout).await?,
+    };
+    cube.connect()
+        .await
+        .with_context(|| format!("Couldn't connect to {}", cube.id()))?;
+
+    match opt.cmd {
+        Command::Scan => unreachable!(),
+        Command::Status => {
+            println!("id        : {}", cube.id());
+            println!("rssi      : {}", cube.rssi());
+            println!("version   : {}", cube.version().await?);
+            println!("battery   : {}%", cube.battery().await?);
+            println!("button    : {}", cube.button().await?);
+            println!("collision : {}", cube.collision().await?);
+            println!("slope     : {}", cube.slope().await?);
+            println!("position  : {:?}", cube.position().await?);
+            println!("std id    : {:?}", cube.std_id().await?);
+        }
+        Command::Drive {
+            left,
+            right,
+            duration,
+        } => {
+            let duration = Duration::from_millis(duration);
+            cube.go(left, right, Some(duration)).await?;
+            delay_for(duration).await;
+        }
+        Command::Sound { preset } => {
+            let id: SoundPresetId = serde_json::from_value(preset.into())
+                .map_err(|_| anyhow!("Invalid preset sound: {}", preset))?;
+            cube.play_preset(id).await?;
+            // Waits a bit to hear the sound before disconnecting.
+            delay_for(Duration::from_secs(1)).await;
+        }
+        Command::Light {
+            red,
+            green,
+            blue,
+            duration,
+        } => {
+            if red == 0 && green == 0 && blue == 0 {
+                cube.light_off().await?;
+            } else {
+                let duration = duration.map(Duration::from_millis);
+                cube.light_on(red, green, blue, duration).await?;
+                if let Some(duration) = duration {
+                    delay_for(duration).await;
+                }
+            }
+        }
+        Command::Events { duration } => {
+            let events = cube
+                .events()
+                .await?
+                .filter(|e| future::ready(!matches!(e, Event::Lagged(_))))
+                .for_each(|e| {
+                    println!("{:?}", e);
+                    future::ready(())
+                });
+            match duration {
+                Some(duration) => {
+                    let _ = timeout(Duration::from_secs(duration), events).await;
+                }
+                None => events.await,
+            }
+        }
+    }
+
+    Ok(())
+}
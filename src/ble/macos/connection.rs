@@ -1,4 +1,5 @@
-use anyhow::{bail, Result};
+use crate::ble::Unavailable;
+use anyhow::{anyhow, Result};
 use core_bluetooth::central::*;
 use core_bluetooth::*;
 use core_bluetooth::{
@@ -11,17 +12,18 @@ use futures::{
 };
 use log::*;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::RecvTimeoutError,
-        Arc,
+        Arc, Mutex,
     },
     time::Duration,
 };
-use tokio::sync::{broadcast, mpsc};
-
-const CHANNEL_CAPACITY: usize = 100000;
+use tokio::{
+    sync::{broadcast, mpsc, watch},
+    time::timeout,
+};
 
 #[derive(Clone, Debug)]
 pub enum Event {
@@ -30,6 +32,15 @@ pub enum Event {
     Disconnected(Peripheral),
     Value(Peripheral, Characteristic, Vec<u8>),
     WriteRes(Peripheral, Characteristic, bool),
+    Rssi(Peripheral, i32),
+}
+
+/// The state of the Bluetooth adapter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Unknown,
+    PoweredOn,
+    Unavailable(Unavailable),
 }
 
 enum InnerMsg {
@@ -39,10 +50,19 @@ enum InnerMsg {
     Discover(Uuid),
 }
 
+/// Senders of events routed to each peripheral.
+type Routes = Arc<Mutex<HashMap<Uuid, broadcast::Sender<Event>>>>;
+
+/// Characteristics discovered for each peripheral, reused on reconnection.
+type Cache = Arc<Mutex<HashMap<Uuid, Vec<Characteristic>>>>;
+
 struct Inner {
     central: CentralManager,
     client_tx: broadcast::Sender<Event>,
+    routes: Routes,
+    cache: Cache,
     manager_rx: mpsc::UnboundedReceiver<InnerMsg>,
+    state_tx: watch::Sender<State>,
     connected: HashSet<Peripheral>,
 }
 
@@ -50,16 +70,44 @@ impl Inner {
     fn new(
         central: CentralManager,
         client_tx: broadcast::Sender<Event>,
+        routes: Routes,
+        cache: Cache,
         manager_rx: mpsc::UnboundedReceiver<InnerMsg>,
+        state_tx: watch::Sender<State>,
     ) -> Self {
         Self {
             central,
             client_tx,
+            routes,
+            cache,
             manager_rx,
+            state_tx,
             connected: HashSet::new(),
         }
     }
 
+    /// Subscribes to the characteristics which can notify.
+    fn subscribe(peripheral: &Peripheral, characteristics: &[Characteristic]) {
+        for c in characteristics
+            .iter()
+            .filter(|c| c.properties().can_notify())
+        {
+            debug!(
+                "Subscribing to characteristic {} of {}",
+                c.id(),
+                peripheral.id()
+            );
+            peripheral.subscribe(c);
+        }
+    }
+
+    /// Sends the event only to the subscribers of the peripheral.
+    fn route(&self, id: Uuid, event: Event) {
+        if let Some(tx) = self.routes.lock().unwrap().get(&id) {
+            let _ = tx.send(event);
+        }
+    }
+
     async fn run(&mut self) -> Result<()> {
         while let Some(msg) = self.manager_rx.next().await {
             match msg {
@@ -88,21 +136,21 @@ impl Inner {
         trace!("Event: {:?}", event);
 
         match event {
-            CentralEvent::ManagerStateChanged { new_state } => match new_state {
-                ManagerState::Unsupported => {
-                    bail!("Bluetooth is not supported");
-                }
-                ManagerState::Unauthorized => {
-                    bail!("Not authorized to use Bluetooth");
-                }
-                ManagerState::PoweredOff => {
-                    warn!("Bluetooth is disabled");
+            CentralEvent::ManagerStateChanged { new_state } => {
+                let state = match new_state {
+                    ManagerState::Unsupported => State::Unavailable(Unavailable::Unsupported),
+                    ManagerState::Unauthorized => State::Unavailable(Unavailable::Unauthorized),
+                    ManagerState::PoweredOff => State::Unavailable(Unavailable::PoweredOff),
+                    ManagerState::PoweredOn => State::PoweredOn,
+                    _ => State::Unknown,
+                };
+                match state {
+                    State::Unavailable(e) => warn!("{}", e),
+                    State::PoweredOn => debug!("Bluetooth is enabled."),
+                    State::Unknown => {}
                 }
-                ManagerState::PoweredOn => {
-                    debug!("Bluetooth is enabled.");
-                }
-                _ => {}
-            },
+                let _ = self.state_tx.broadcast(state);
+            }
             CentralEvent::PeripheralDiscovered {
                 peripheral,
                 advertisement_data,
@@ -126,7 +174,18 @@ impl Inner {
                 tag: _,
             } => {}
             CentralEvent::PeripheralConnected { peripheral } => {
-                peripheral.discover_services();
+                let cached = self.cache.lock().unwrap().get(&peripheral.id()).cloned();
+                match cached {
+                    Some(characteristics) => {
+                        debug!("Reusing characteristics of {}", peripheral.id());
+                        Self::subscribe(&peripheral, &characteristics);
+                        self.route(
+                            peripheral.id(),
+                            Event::Connected(peripheral, characteristics),
+                        );
+                    }
+                    None => peripheral.discover_services(),
+                }
             }
             CentralEvent::PeripheralDisconnected {
                 peripheral,
@@ -136,7 +195,7 @@ impl Inner {
                     warn!("Reconnecting to {}", peripheral.id());
                     self.central.connect(&peripheral);
                 } else {
-                    let _ = self.client_tx.send(Event::Disconnected(peripheral));
+                    self.route(peripheral.id(), Event::Disconnected(peripheral));
                 }
             }
             CentralEvent::PeripheralConnectFailed { peripheral, error } => {
@@ -171,6 +230,8 @@ impl Inner {
                         "Couldn't subscribe to characteristic of {}",
                         peripheral.id()
                     );
+                    // The cached characteristics may be stale.
+                    self.cache.lock().unwrap().remove(&peripheral.id());
                 } else {
                     debug!("Subscribe to characteristic of {}", peripheral.id());
                 }
@@ -181,21 +242,19 @@ impl Inner {
                 characteristics,
             } => match characteristics {
                 Ok(characteristics) => {
-                    for c in characteristics
-                        .iter()
-                        .filter(|c| c.properties().can_notify())
+                    Self::subscribe(&peripheral, &characteristics);
+
                     {
-                        debug!(
-                            "Subscribing to characteristic {} of {}",
-                            c.id(),
-                            peripheral.id()
-                        );
-                        peripheral.subscribe(&c);
+                        let mut cache = self.cache.lock().unwrap();
+                        let cached = cache.entry(peripheral.id()).or_insert_with(Vec::new);
+                        cached.retain(|c| characteristics.iter().all(|d| d.id() != c.id()));
+                        cached.extend(characteristics.iter().cloned());
                     }
 
-                    let _ = self
-                        .client_tx
-                        .send(Event::Connected(peripheral, characteristics));
+                    self.route(
+                        peripheral.id(),
+                        Event::Connected(peripheral, characteristics),
+                    );
                 }
                 Err(err) => error!(
                     "Couldn't discover characteristics of {}: {}",
@@ -215,21 +274,25 @@ impl Inner {
                         peripheral.id(),
                         value
                     );
-                    let _ = self
-                        .client_tx
-                        .send(Event::Value(peripheral, characteristic, value));
+                    self.route(
+                        peripheral.id(),
+                        Event::Value(peripheral, characteristic, value),
+                    );
                 }
             }
+            CentralEvent::RssiRead { peripheral, rssi } => match rssi {
+                Ok(rssi) => self.route(peripheral.id(), Event::Rssi(peripheral, rssi)),
+                Err(err) => warn!("Couldn't read rssi of {}: {}", peripheral.id(), err),
+            },
             CentralEvent::WriteCharacteristicResult {
                 peripheral,
                 characteristic,
                 result,
             } => {
-                let _ = self.client_tx.send(Event::WriteRes(
-                    peripheral,
-                    characteristic,
-                    result.is_ok(),
-                ));
+                self.route(
+                    peripheral.id(),
+                    Event::WriteRes(peripheral, characteristic, result.is_ok()),
+                );
             }
             _ => {}
         }
@@ -242,17 +305,31 @@ pub struct ConnectionManager {
     thread: Option<std::thread::JoinHandle<Result<()>>>,
     stop: Arc<AtomicBool>,
     client_tx: broadcast::Sender<Event>,
+    routes: Routes,
+    cache: Cache,
+    capacity: usize,
     manager_tx: mpsc::UnboundedSender<InnerMsg>,
+    state: watch::Receiver<State>,
     inner_handle: AbortHandle,
 }
 
 impl ConnectionManager {
-    pub fn new() -> Self {
+    pub fn new(capacity: usize) -> Self {
         let (manager_tx, manager_rx) = mpsc::unbounded_channel();
-        let (client_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (client_tx, _) = broadcast::channel(capacity);
+        let routes = Routes::default();
+        let cache = Cache::default();
         let (central, central_rx) = CentralManager::new();
+        let (state_tx, state) = watch::channel(State::Unknown);
 
-        let mut inner = Inner::new(central, client_tx.clone(), manager_rx);
+        let mut inner = Inner::new(
+            central,
+            client_tx.clone(),
+            routes.clone(),
+            cache.clone(),
+            manager_rx,
+            state_tx,
+        );
         let (inner, inner_handle) = abortable(async move {
             if let Err(e) = inner.run().await {
                 error!("Error in connection manager: {}", e);
@@ -285,11 +362,38 @@ impl ConnectionManager {
             thread: Some(thread),
             stop,
             client_tx,
+            routes,
+            cache,
+            capacity,
             manager_tx,
+            state,
             inner_handle,
         }
     }
 
+    /// Waits for the adapter to be powered on, and fails if it's unavailable.
+    ///
+    /// Proceeds anyway if the state isn't reported within the time.
+    pub async fn ready(&self, time: Duration) -> Result<()> {
+        let mut rx = self.state.clone();
+        let wait = async {
+            loop {
+                let state = *rx.borrow();
+                match state {
+                    State::PoweredOn => return Ok(()),
+                    State::Unavailable(e) => return Err(e.into()),
+                    State::Unknown => {}
+                }
+                if rx.recv().await.is_none() {
+                    return Err(anyhow!(
+                        "Internal channel closed while waiting for Bluetooth"
+                    ));
+                }
+            }
+        };
+        timeout(time, wait).await.unwrap_or(Ok(()))
+    }
+
     pub fn discover(&self, uuid: &Uuid) {
         let _ = self.manager_tx.send(InnerMsg::Discover(uuid.clone()));
     }
@@ -302,9 +406,31 @@ impl ConnectionManager {
         let _ = self.manager_tx.send(InnerMsg::Disconnect(p.clone()));
     }
 
+    /// Subscribes to events not bound to a connected peripheral, i.e. discovery.
     pub fn subscribe(&self) -> broadcast::Receiver<Event> {
         self.client_tx.subscribe()
     }
+
+    /// Subscribes to events of the peripheral.
+    pub fn subscribe_peripheral(&self, p: &Peripheral) -> broadcast::Receiver<Event> {
+        self.routes
+            .lock()
+            .unwrap()
+            .entry(p.id())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe()
+    }
+
+    /// Forgets the characteristics of the peripheral, so that they're discovered again
+    /// on the next connection.
+    pub fn invalidate(&self, p: &Peripheral) {
+        self.cache.lock().unwrap().remove(&p.id());
+    }
+
+    /// Stops routing events to the peripheral.
+    pub fn unroute(&self, p: &Peripheral) {
+        self.routes.lock().unwrap().remove(&p.id());
+    }
 }
 
 impl Drop for ConnectionManager {
@@ -1,4 +1,6 @@
-use crate::ble::{self, PeripheralOps, SearchOps, ValueStream};
+use crate::ble::{
+    self, Lagged, LaggingStream, PeripheralOps, SearchOps, StopSearch, ValueStream, WriteOps,
+};
 
 use anyhow::{anyhow, bail, Context, Error, Result};
 use futures::prelude::*;
@@ -7,7 +9,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
-use tokio::time::timeout;
+use tokio::{sync::broadcast::RecvError, time::timeout};
 
 use core_bluetooth::{
     central::{
@@ -21,40 +23,69 @@ mod connection;
 
 use self::connection::{ConnectionManager, Event};
 
-const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
-const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
-
 pub struct Adaptor {
     id: String,
+    name: Option<String>,
     peripheral: Peripheral,
     rssi: i32,
     characteristics: HashMap<Uuid, Characteristic>,
     manager: Arc<ConnectionManager>,
+    opts: ble::Options,
 }
 
 impl Adaptor {
-    fn new(peripheral: Peripheral, rssi: i32, manager: Arc<ConnectionManager>) -> Self {
+    fn new(
+        peripheral: Peripheral,
+        rssi: i32,
+        manager: Arc<ConnectionManager>,
+        opts: ble::Options,
+    ) -> Self {
         Self {
             id: peripheral.id().to_string(),
+            name: None,
             peripheral,
             rssi,
             characteristics: HashMap::new(),
             manager,
+            opts,
         }
     }
 
     fn ch(&self, uuid: &Uuid) -> Result<&Characteristic> {
-        let ch = self
+        let ch = self.characteristics.get(uuid).ok_or_else(|| {
+            // The characteristics reused from the last connection may be stale.
+            self.manager.invalidate(&self.peripheral);
+            anyhow!("No such characteristic {}", uuid)
+        })?;
+        Ok(ch)
+    }
+}
+
+/// Writer sharing the peripheral with the adaptor, which never waits for responses.
+struct AdaptorWriter {
+    peripheral: Peripheral,
+    characteristics: HashMap<Uuid, Characteristic>,
+}
+
+#[async_trait::async_trait]
+impl WriteOps for AdaptorWriter {
+    async fn write(&mut self, uuid: &ble::Uuid, value: &[u8]) -> Result<()> {
+        let uuid = Uuid::from_bytes(uuid.0);
+        let c = self
             .characteristics
-            .get(uuid)
+            .get(&uuid)
             .ok_or_else(|| anyhow!("No such characteristic {}", uuid))?;
-        Ok(ch)
+        debug!("Writing value to characteristic {}: {:?}", c.id(), value);
+        self.peripheral
+            .write_characteristic(c, value, WriteKind::WithoutResponse);
+        Ok(())
     }
 }
 
 impl Drop for Adaptor {
     fn drop(&mut self) {
         self.manager.disconnect(&self.peripheral);
+        self.manager.unroute(&self.peripheral);
     }
 }
 
@@ -68,12 +99,53 @@ impl PeripheralOps for Adaptor {
         self.rssi
     }
 
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The length of writes without response, which is the shorter of the two kinds.
+    fn max_write_len(&self) -> Option<usize> {
+        Some(self.peripheral.max_write_len(WriteKind::WithoutResponse))
+    }
+
+    fn writer(&self) -> Option<ble::Writer> {
+        Some(Box::new(AdaptorWriter {
+            peripheral: self.peripheral.clone(),
+            characteristics: self.characteristics.clone(),
+        }))
+    }
+
+    async fn read_rssi(&mut self) -> Result<i32> {
+        let mut rx = self.manager.subscribe_peripheral(&self.peripheral);
+
+        self.peripheral.read_rssi();
+
+        let resp = async {
+            loop {
+                let event = rx
+                    .recv()
+                    .await
+                    .context("Internal channel closed while waiting for rssi")?;
+
+                if let Event::Rssi(_, rssi) = event {
+                    return Ok::<_, Error>(rssi);
+                }
+            }
+        };
+
+        self.rssi = timeout(self.opts.read_timeout, resp).await??;
+
+        Ok(self.rssi)
+    }
+
     async fn connect(&mut self) -> Result<()> {
-        let mut rx = self.manager.subscribe();
+        self.manager.ready(self.opts.connect_timeout).await?;
+
+        let mut rx = self.manager.subscribe_peripheral(&self.peripheral);
 
         self.manager.connect(&self.peripheral);
 
-        let id = self.peripheral.id();
+        let time = self.opts.connect_timeout;
         let connect = async {
             loop {
                 let event = rx
@@ -81,33 +153,28 @@ impl PeripheralOps for Adaptor {
                     .await
                     .context("Internal channel closed while waiting for connection status")?;
 
-                match event {
-                    Event::Connected(peripheral, characteristics) => {
-                        if peripheral.id() == id {
-                            debug!("Connected to peripheral {}", peripheral.id());
-                            self.characteristics = characteristics
-                                .into_iter()
-                                .map(|c| (c.id().clone(), c))
-                                .collect();
-                            return Ok::<_, Error>(());
-                        }
-                    }
-                    _ => {}
+                if let Event::Connected(peripheral, characteristics) = event {
+                    debug!("Connected to peripheral {}", peripheral.id());
+                    self.characteristics = characteristics
+                        .into_iter()
+                        .map(|c| (c.id().clone(), c))
+                        .collect();
+                    return Ok::<_, Error>(());
                 }
             }
         };
 
-        timeout(CONNECT_TIMEOUT, connect).await??;
+        timeout(time, connect).await??;
 
         Ok(())
     }
 
     async fn disconnect(&mut self) -> Result<()> {
-        let mut rx = self.manager.subscribe();
+        let mut rx = self.manager.subscribe_peripheral(&self.peripheral);
 
         self.manager.disconnect(&self.peripheral);
 
-        let id = self.peripheral.id();
+        let time = self.opts.connect_timeout;
         let connect = async {
             loop {
                 let event = rx
@@ -115,19 +182,14 @@ impl PeripheralOps for Adaptor {
                     .await
                     .context("Internal channel closed while waiting for disconnection result")?;
 
-                match event {
-                    Event::Disconnected(peripheral) => {
-                        if peripheral.id() == id {
-                            debug!("Disconnected peripheral {}", peripheral.id());
-                            return Ok::<_, Error>(());
-                        }
-                    }
-                    _ => {}
+                if let Event::Disconnected(peripheral) = event {
+                    debug!("Disconnected peripheral {}", peripheral.id());
+                    return Ok::<_, Error>(());
                 }
             }
         };
 
-        timeout(CONNECT_TIMEOUT, connect).await??;
+        timeout(time, connect).await??;
 
         Ok(())
     }
@@ -141,7 +203,7 @@ impl PeripheralOps for Adaptor {
     }
 
     async fn write(&mut self, uuid: &ble::Uuid, value: &[u8], with_resp: bool) -> Result<()> {
-        let mut rx = self.manager.subscribe();
+        let mut rx = self.manager.subscribe_peripheral(&self.peripheral);
 
         let uuid = Uuid::from_bytes(uuid.0);
         let c = self.ch(&uuid)?;
@@ -154,7 +216,6 @@ impl PeripheralOps for Adaptor {
         self.peripheral.write_characteristic(c, value, w);
 
         if with_resp {
-            let pid = self.peripheral.id();
             let cid = uuid;
             let mut ok = None;
             let resp = async {
@@ -165,8 +226,8 @@ impl PeripheralOps for Adaptor {
                         .context("Internal channel closed while waiting for write response")?;
 
                     match event {
-                        Event::WriteRes(peripheral, characteristics, res_ok)
-                            if peripheral.id() == pid && characteristics.id() == cid =>
+                        Event::WriteRes(_, characteristics, res_ok)
+                            if characteristics.id() == cid =>
                         {
                             ok = Some(res_ok);
                             break;
@@ -177,7 +238,7 @@ impl PeripheralOps for Adaptor {
                 Ok::<_, Error>(())
             };
 
-            timeout(WRITE_TIMEOUT, resp).await??;
+            timeout(self.opts.write_timeout, resp).await??;
 
             return match ok {
                 Some(true) => Ok(()),
@@ -189,17 +250,52 @@ impl PeripheralOps for Adaptor {
         Ok(())
     }
 
+    async fn notify(&mut self, uuid: &ble::Uuid, enable: bool) -> Result<()> {
+        let uuid = Uuid::from_bytes(uuid.0);
+        let c = self.ch(&uuid)?;
+        if enable {
+            debug!("Subscribing to characteristic {}", c.id());
+            self.peripheral.subscribe(c);
+        } else {
+            debug!("Unsubscribing from characteristic {}", c.id());
+            self.peripheral.unsubscribe(c);
+        }
+        Ok(())
+    }
+
     fn subscribe(&mut self) -> Result<ValueStream> {
-        let rx = self.manager.subscribe();
+        let rx = self.manager.subscribe_peripheral(&self.peripheral);
         let id = self.peripheral.id();
 
         Ok(rx
             .into_stream()
             .filter_map(move |event| async move {
                 match event {
-                    Ok(Event::Value(p, c, value)) if p.id() == id => {
-                        Some((ble::Uuid(c.id().bytes()), value))
+                    Ok(Event::Value(_, c, value)) => {
+                        Some((ble::Uuid(c.id().bytes()), value.into()))
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("Dropped {} events of peripheral {}", n, id);
+                        None
+                    }
+                    _ => None,
+                }
+            })
+            .boxed())
+    }
+
+    fn subscribe_lagging(&mut self) -> Result<LaggingStream> {
+        let rx = self.manager.subscribe_peripheral(&self.peripheral);
+
+        Ok(rx
+            .into_stream()
+            .filter_map(move |event| async move {
+                match event {
+                    Ok(Event::Value(_, c, value)) => {
+                        Some(Ok((ble::Uuid(c.id().bytes()), value.into())))
                     }
+                    // Other events of the peripheral are dropped too, so the count is an upper bound.
+                    Err(RecvError::Lagged(n)) => Some(Err(Lagged(n as usize))),
                     _ => None,
                 }
             })
@@ -207,18 +303,29 @@ impl PeripheralOps for Adaptor {
     }
 }
 
-pub fn searcher() -> ble::Searcher {
-    Box::new(Searcher::new())
+/// CoreBluetooth only exposes the system adapter.
+pub fn adapters() -> Result<Vec<ble::AdapterInfo>> {
+    Ok(vec![ble::AdapterInfo {
+        index: 0,
+        name: "CoreBluetooth".into(),
+        address: None,
+    }])
+}
+
+pub fn searcher(opts: ble::Options) -> ble::Searcher {
+    Box::new(Searcher::new(opts))
 }
 
 pub struct Searcher {
     manager: Arc<ConnectionManager>,
+    opts: ble::Options,
 }
 
 impl Searcher {
-    pub fn new() -> Self {
+    pub fn new(opts: ble::Options) -> Self {
         Self {
-            manager: Arc::new(ConnectionManager::new()),
+            manager: Arc::new(ConnectionManager::new(opts.capacity)),
+            opts,
         }
     }
 }
@@ -226,6 +333,23 @@ impl Searcher {
 #[async_trait::async_trait]
 impl SearchOps for Searcher {
     async fn search(&mut self, uuid: &ble::Uuid, time: Duration) -> Result<Vec<ble::Peripheral>> {
+        self.search_until(uuid, time, &mut |_| false).await
+    }
+
+    async fn search_until(
+        &mut self,
+        uuid: &ble::Uuid,
+        time: Duration,
+        stop: &mut StopSearch<'_>,
+    ) -> Result<Vec<ble::Peripheral>> {
+        if let Some(adapter) = &self.opts.adapter {
+            if adapter.find(&adapters()?).is_none() {
+                bail!("No such adapter: {:?}", adapter);
+            }
+        }
+
+        self.manager.ready(time).await?;
+
         let uuid = Uuid::from_bytes(uuid.0);
 
         let mut rx = self.manager.subscribe();
@@ -242,19 +366,35 @@ impl SearchOps for Searcher {
                 match event {
                     Event::Discovered(peripheral, ad, rssi) => {
                         if ad.service_uuids().contains(&uuid) {
-                            debug!("Discovered peripheral: {:?}", peripheral);
-                            found.insert(
-                                peripheral.id(),
-                                Box::new(Adaptor::new(peripheral, rssi, self.manager.clone()))
-                                    as ble::Peripheral,
-                            );
+                            // A cube advertises many times during the search.
+                            let id = peripheral.id();
+                            match found.get_mut(&id) {
+                                Some(adaptor) => adaptor.rssi = rssi,
+                                None => {
+                                    debug!("Discovered peripheral: {:?}", peripheral);
+                                    found.insert(
+                                        peripheral.id(),
+                                        Adaptor::new(
+                                            peripheral,
+                                            rssi,
+                                            self.manager.clone(),
+                                            self.opts.clone(),
+                                        ),
+                                    );
+                                }
+                            }
+                            if let Some(name) = ad.local_name() {
+                                found.get_mut(&id).unwrap().name = Some(name.to_string());
+                            }
+                            if stop(&found[&id]) {
+                                break;
+                            }
                         }
                     }
                     _ => {}
                 }
             }
 
-            #[allow(unreachable_code)]
             Ok::<_, Error>(())
         };
 
@@ -262,6 +402,9 @@ impl SearchOps for Searcher {
             e?
         }
 
-        Ok(found.into_iter().map(|(_, p)| p).collect())
+        Ok(found
+            .into_iter()
+            .map(|(_, p)| Box::new(p) as ble::Peripheral)
+            .collect())
     }
 }
@@ -0,0 +1,282 @@
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use futures::prelude::*;
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicBool, AtomicI32, Ordering},
+    Arc, Mutex,
+};
+use tokio::sync::broadcast;
+
+use tokio::sync::broadcast::RecvError;
+
+use crate::ble::{
+    self, Lagged, LaggingStream, PeripheralOps, Uuid, ValueStream, WriteOps, CHANNEL_CAPACITY,
+};
+
+struct Shared {
+    tx: broadcast::Sender<(Uuid, Bytes)>,
+    last: Mutex<HashMap<Uuid, Bytes>>,
+    writes: Mutex<Vec<(Uuid, Vec<u8>)>>,
+    reads: Mutex<Vec<Uuid>>,
+    notifications: Mutex<Vec<(Uuid, bool)>>,
+    connected: AtomicBool,
+    paired: AtomicBool,
+    rssi: AtomicI32,
+    max_write_len: Mutex<Option<usize>>,
+}
+
+impl Shared {
+    fn write(&self, id: &str, uuid: &Uuid, value: &[u8]) -> Result<()> {
+        if !self.connected.load(Ordering::SeqCst) {
+            bail!("Peripheral {} is not connected", id);
+        }
+        if let Some(max) = self
+            .max_write_len
+            .lock()
+            .unwrap()
+            .filter(|max| value.len() > *max)
+        {
+            bail!(
+                "Value of {} bytes exceeds the maximum write length {}",
+                value.len(),
+                max
+            );
+        }
+        self.writes.lock().unwrap().push((*uuid, value.to_vec()));
+        Ok(())
+    }
+}
+
+/// Creates a mock peripheral and the handle to control it.
+///
+/// ```
+/// use futures::prelude::*;
+/// use toio::{ble::{self, PeripheralOps}, proto::UUID_BATTERY};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (mut dev, handle) = ble::mock::mock("cube");
+///     let mut rx = dev.subscribe().unwrap();
+///
+///     handle.notify(UUID_BATTERY, vec![80]);
+///     assert_eq!(rx.next().await, Some((UUID_BATTERY, vec![80].into())));
+/// }
+/// ```
+pub fn mock(id: &str) -> (Mock, MockHandle) {
+    mock_named(id, None)
+}
+
+/// Creates a mock peripheral advertising the local name.
+pub fn mock_named(id: &str, name: Option<&str>) -> (Mock, MockHandle) {
+    mock_with_capacity(id, name, CHANNEL_CAPACITY)
+}
+
+/// Creates a mock peripheral with the capacity of the channel to deliver values.
+///
+/// If the channel is full, the oldest values are dropped, as a platform would.
+pub fn mock_with_capacity(id: &str, name: Option<&str>, capacity: usize) -> (Mock, MockHandle) {
+    let shared = Arc::new(Shared {
+        tx: broadcast::channel(capacity).0,
+        last: Mutex::new(HashMap::new()),
+        writes: Mutex::new(vec![]),
+        reads: Mutex::new(vec![]),
+        notifications: Mutex::new(vec![]),
+        connected: AtomicBool::new(false),
+        paired: AtomicBool::new(false),
+        rssi: AtomicI32::new(0),
+        max_write_len: Mutex::new(None),
+    });
+    let dev = Mock {
+        id: id.to_string(),
+        name: name.map(|n| n.to_string()),
+        shared: shared.clone(),
+    };
+    (dev, MockHandle { shared })
+}
+
+/// Peripheral which works without hardware.
+///
+/// Values are sent through [`MockHandle`][], and writes are kept for inspection.
+/// Read requests are answered with the latest value sent to the characteristic.
+/// Values of the characteristics whose notifications are disabled aren't delivered
+/// until enabled again.
+pub struct Mock {
+    id: String,
+    name: Option<String>,
+    shared: Arc<Shared>,
+}
+
+/// Handle to control a mock peripheral.
+#[derive(Clone)]
+pub struct MockHandle {
+    shared: Arc<Shared>,
+}
+
+impl MockHandle {
+    /// Sends a value from the characteristic as if notified by the peripheral.
+    pub fn notify(&self, uuid: Uuid, value: Vec<u8>) {
+        let value = Bytes::from(value);
+        self.shared.last.lock().unwrap().insert(uuid, value.clone());
+        if self.is_notifying(&uuid) {
+            let _ = self.shared.tx.send((uuid, value));
+        }
+    }
+
+    /// Gets the values written to the peripheral so far.
+    pub fn writes(&self) -> Vec<(Uuid, Vec<u8>)> {
+        self.shared.writes.lock().unwrap().clone()
+    }
+
+    /// Gets the characteristics requested to read so far.
+    pub fn reads(&self) -> Vec<Uuid> {
+        self.shared.reads.lock().unwrap().clone()
+    }
+
+    /// Gets the requests to enable/disable notifications so far.
+    pub fn notifications(&self) -> Vec<(Uuid, bool)> {
+        self.shared.notifications.lock().unwrap().clone()
+    }
+
+    /// Returns `true` unless the notifications from the characteristic are disabled.
+    pub fn is_notifying(&self, uuid: &Uuid) -> bool {
+        let notifications = self.shared.notifications.lock().unwrap();
+        !matches!(
+            notifications.iter().rev().find(|(u, _)| u == uuid),
+            Some((_, false))
+        )
+    }
+
+    /// Returns `true` if the peripheral is connected.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::SeqCst)
+    }
+
+    /// Sets the signal strength of the peripheral. By default, it's 0.
+    pub fn set_rssi(&self, rssi: i32) {
+        self.shared.rssi.store(rssi, Ordering::SeqCst);
+    }
+
+    /// Limits the length of values to write as if negotiated by MTU. By default, it's unlimited.
+    ///
+    /// Writes of longer values fail, as the platform would reject them.
+    /// Cubes read the length on connection, so set it before connecting.
+    pub fn set_max_write_len(&self, len: Option<usize>) {
+        *self.shared.max_write_len.lock().unwrap() = len;
+    }
+
+    /// Returns `true` if the peripheral is paired.
+    pub fn is_paired(&self) -> bool {
+        self.shared.paired.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait::async_trait]
+impl PeripheralOps for Mock {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn rssi(&self) -> i32 {
+        self.shared.rssi.load(Ordering::SeqCst)
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn max_write_len(&self) -> Option<usize> {
+        *self.shared.max_write_len.lock().unwrap()
+    }
+
+    fn writer(&self) -> Option<ble::Writer> {
+        Some(Box::new(MockWriter {
+            id: self.id.clone(),
+            shared: self.shared.clone(),
+        }))
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        self.shared.connected.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.shared.connected.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn pair(&mut self) -> Result<()> {
+        if !self.shared.connected.load(Ordering::SeqCst) {
+            bail!("Peripheral {} is not connected", self.id);
+        }
+        self.shared.paired.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn unpair(&mut self) -> Result<()> {
+        self.shared.paired.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn read(&mut self, uuid: &Uuid) -> Result<()> {
+        self.shared.reads.lock().unwrap().push(*uuid);
+        let value = self.shared.last.lock().unwrap().get(uuid).cloned();
+        if let Some(value) = value {
+            let _ = self.shared.tx.send((*uuid, value));
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, uuid: &Uuid, value: &[u8], _with_resp: bool) -> Result<()> {
+        self.shared.write(&self.id, uuid, value)
+    }
+
+    async fn notify(&mut self, uuid: &Uuid, enable: bool) -> Result<()> {
+        self.shared
+            .notifications
+            .lock()
+            .unwrap()
+            .push((*uuid, enable));
+        Ok(())
+    }
+
+    fn subscribe(&mut self) -> Result<ValueStream> {
+        Ok(self
+            .shared
+            .tx
+            .subscribe()
+            .into_stream()
+            .filter_map(|v| async move { v.ok() })
+            .boxed())
+    }
+
+    fn subscribe_lagging(&mut self) -> Result<LaggingStream> {
+        Ok(self
+            .shared
+            .tx
+            .subscribe()
+            .into_stream()
+            .filter_map(|v| async move {
+                match v {
+                    Ok(v) => Some(Ok(v)),
+                    Err(RecvError::Lagged(n)) => Some(Err(Lagged(n as usize))),
+                    Err(RecvError::Closed) => None,
+                }
+            })
+            .boxed())
+    }
+}
+
+/// Writer of a mock peripheral, recording the writes in the same order.
+struct MockWriter {
+    id: String,
+    shared: Arc<Shared>,
+}
+
+#[async_trait::async_trait]
+impl WriteOps for MockWriter {
+    async fn write(&mut self, uuid: &Uuid, value: &[u8]) -> Result<()> {
+        self.shared.write(&self.id, uuid, value)
+    }
+}
@@ -1,33 +1,21 @@
-use anyhow::{Context, Error, Result};
-use derive_new::new;
-use futures::{prelude::*, stream::BoxStream};
+use anyhow::{anyhow, Context, Error, Result};
+use bytes::Bytes;
+use futures::{future, prelude::*, stream::BoxStream};
+use serde::{Deserialize, Serialize};
 use std::{
     convert::{TryFrom, TryInto},
-    fmt::{self, Display},
+    fmt,
+    sync::RwLock,
     time::Duration,
 };
 
-/// Helper to construct [`ble::Uuid`][] from a string at compile time.
-#[macro_export]
-macro_rules! uuid {
-    ($hex:literal) => {
-        $crate::ble::Uuid(hex_literal::hex!($hex))
-    };
-}
-
-/// Uuid for services or characteristics.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, new)]
-pub struct Uuid(pub [u8; 16]);
-
-impl Display for Uuid {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let b = self.0;
-        write!(f, "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}", b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7], b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15])
-    }
-}
+pub use crate::proto::Uuid;
 
 /// Callback to receive values from peripherals.
-pub type ValueStream = BoxStream<'static, (Uuid, Vec<u8>)>;
+pub type ValueStream = BoxStream<'static, (Uuid, Bytes)>;
+
+/// Callback to receive values from peripherals, telling the values dropped on overflow.
+pub type LaggingStream = BoxStream<'static, std::result::Result<(Uuid, Bytes), Lagged>>;
 
 /// Callback to receive values from peripherals.
 pub type MessageStream<T> = BoxStream<'static, Result<T>>;
@@ -38,11 +26,45 @@ pub type Peripheral = Box<dyn PeripheralOps + Send>;
 /// Searcher
 pub type Searcher = Box<dyn SearchOps + Send>;
 
+/// Writer
+pub type Writer = Box<dyn WriteOps + Send>;
+
+/// The condition to stop searching, called with each discovery.
+pub type StopSearch<'a> = dyn FnMut(&dyn PeripheralOps) -> bool + Send + 'a;
+
 /// The interface for platform-specific BLE searcher.
 #[async_trait::async_trait]
 pub trait SearchOps {
     /// Search for peripherals.
     async fn search(&mut self, uuid: &Uuid, timeout: Duration) -> Result<Vec<Peripheral>>;
+
+    /// Search for peripherals until `stop` returns `true` for a discovery.
+    ///
+    /// `stop` is called every time a peripheral is discovered or advertises again.
+    /// The default implementation searches for the whole timeout without calling `stop`.
+    async fn search_until(
+        &mut self,
+        uuid: &Uuid,
+        timeout: Duration,
+        _stop: &mut StopSearch<'_>,
+    ) -> Result<Vec<Peripheral>> {
+        self.search(uuid, timeout).await
+    }
+
+    /// Retrieve the peripherals paired before by their ids without scanning.
+    ///
+    /// Peripherals unknown to the platform are skipped.
+    /// The default implementation returns none.
+    async fn retrieve(&mut self, _ids: &[String]) -> Result<Vec<Peripheral>> {
+        Ok(vec![])
+    }
+}
+
+/// The interface to write to a peripheral apart from its other operations.
+#[async_trait::async_trait]
+pub trait WriteOps {
+    /// Write without response.
+    async fn write(&mut self, uuid: &Uuid, value: &[u8]) -> Result<()>;
 }
 
 /// The interface for platform-specific BLE peripheral.
@@ -54,20 +76,82 @@ pub trait PeripheralOps {
     // Rssi
     fn rssi(&self) -> i32;
 
+    /// The local name in the advertisement.
+    ///
+    /// The default implementation returns `None`.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// The maximum length of a value to write, negotiated with the peripheral by MTU.
+    ///
+    /// The default implementation returns `None`, for platforms not telling it,
+    /// in which case the length of values isn't checked before writing.
+    fn max_write_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Create a writer which works while the peripheral is busy with other operations,
+    /// e.g., waiting for a write response or a read.
+    ///
+    /// Used to stop the cube urgently, so created after connecting.
+    /// The default implementation returns `None`, in which case such writes wait for
+    /// the operation in progress.
+    fn writer(&self) -> Option<Writer> {
+        None
+    }
+
+    /// Read the latest signal strength.
+    ///
+    /// The default implementation returns the value at discovery.
+    async fn read_rssi(&mut self) -> Result<i32> {
+        Ok(self.rssi())
+    }
+
     /// Connect to the peripheral.
     async fn connect(&mut self) -> Result<()>;
 
     /// Disconnect the peripheral.
     async fn disconnect(&mut self) -> Result<()>;
 
+    /// Pair with the connected peripheral, so that the platform remembers it.
+    ///
+    /// The default implementation does nothing, for platforms pairing on demand.
+    async fn pair(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Remove the pairing with the peripheral.
+    ///
+    /// The default implementation does nothing.
+    async fn unpair(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     /// Send a read request.
     async fn read(&mut self, uuid: &Uuid) -> Result<()>;
 
     /// Write with/without response.
     async fn write(&mut self, uuid: &Uuid, value: &[u8], with_resp: bool) -> Result<()>;
 
+    /// Enable/disable notifications from a characteristic.
+    ///
+    /// The default implementation does nothing, for platforms keeping all the
+    /// characteristics notifying while connected.
+    async fn notify(&mut self, _uuid: &Uuid, _enable: bool) -> Result<()> {
+        Ok(())
+    }
+
     /// Subscribe to the peripheral.
     fn subscribe(&mut self) -> Result<ValueStream>;
+
+    /// Subscribe to the peripheral, telling the number of values dropped when the subscriber
+    /// falls behind.
+    ///
+    /// The default implementation never tells, for platforms not dropping values.
+    fn subscribe_lagging(&mut self) -> Result<LaggingStream> {
+        Ok(self.subscribe()?.map(Ok).boxed())
+    }
 }
 
 /// The interface to help reading/writing protocol messages.
@@ -84,10 +168,34 @@ pub trait PeripheralOpsExt: PeripheralOps {
         Ok(())
     }
 
+    /// Subscribe to a characteristic of the peripheral, filtering the values from
+    /// [`PeripheralOps::subscribe`][].
+    fn subscribe_to(&mut self, uuid: &Uuid) -> Result<ValueStream> {
+        let uuid = *uuid;
+        Ok(self
+            .subscribe()?
+            .filter(move |(u, _)| future::ready(*u == uuid))
+            .boxed())
+    }
+
+    /// Send a read request and wait for the value.
+    async fn read_value(&mut self, uuid: &Uuid, timeout: Duration) -> Result<Bytes>
+    where
+        Self: Send,
+    {
+        let mut rx = self.subscribe_to(uuid)?;
+        self.read(uuid).await?;
+        let (_, value) = tokio::time::timeout(timeout, rx.next())
+            .await
+            .context(format!("Couldn't read characteristic {}", uuid))?
+            .ok_or_else(|| anyhow!("Stream ends while reading characteristic {}", uuid))?;
+        Ok(value)
+    }
+
     /// Subscribe to the peripheral parsing bytes to protocol messge.
     fn subscribe_msg<T>(&mut self) -> Result<MessageStream<T>>
     where
-        T: TryFrom<(Uuid, Vec<u8>), Error = Error> + Send,
+        T: TryFrom<(Uuid, Bytes), Error = Error> + Send,
     {
         Ok(self
             .subscribe()?
@@ -99,6 +207,22 @@ pub trait PeripheralOpsExt: PeripheralOps {
             })
             .boxed())
     }
+
+    /// Subscribe to a characteristic parsing bytes to protocol messge.
+    fn subscribe_msg_to<T>(&mut self, uuid: &Uuid) -> Result<MessageStream<T>>
+    where
+        T: TryFrom<(Uuid, Bytes), Error = Error> + Send,
+    {
+        Ok(self
+            .subscribe_to(uuid)?
+            .map(|(uuid, value)| {
+                (uuid, value).try_into().context(format!(
+                    "Couldn't unpack message from characteristic {}",
+                    uuid
+                ))
+            })
+            .boxed())
+    }
 }
 
 #[async_trait::async_trait]
@@ -114,6 +238,22 @@ where
         (**self).rssi()
     }
 
+    fn name(&self) -> Option<&str> {
+        (**self).name()
+    }
+
+    fn max_write_len(&self) -> Option<usize> {
+        (**self).max_write_len()
+    }
+
+    fn writer(&self) -> Option<Writer> {
+        (**self).writer()
+    }
+
+    async fn read_rssi(&mut self) -> Result<i32> {
+        (**self).read_rssi().await
+    }
+
     async fn connect(&mut self) -> Result<()> {
         (**self).connect().await
     }
@@ -122,6 +262,14 @@ where
         (**self).disconnect().await
     }
 
+    async fn pair(&mut self) -> Result<()> {
+        (**self).pair().await
+    }
+
+    async fn unpair(&mut self) -> Result<()> {
+        (**self).unpair().await
+    }
+
     async fn read(&mut self, uuid: &Uuid) -> Result<()> {
         (**self).read(uuid).await
     }
@@ -130,13 +278,31 @@ where
         (**self).write(uuid, value, with_resp).await
     }
 
+    async fn notify(&mut self, uuid: &Uuid, enable: bool) -> Result<()> {
+        (**self).notify(uuid, enable).await
+    }
+
     fn subscribe(&mut self) -> Result<ValueStream> {
         (**self).subscribe()
     }
+
+    fn subscribe_lagging(&mut self) -> Result<LaggingStream> {
+        (**self).subscribe_lagging()
+    }
 }
 
 impl<T> PeripheralOpsExt for T where T: PeripheralOps {}
 
+/// Mock peripheral to work without hardware.
+pub mod mock;
+
+/// Backend to use peripherals on another machine over TCP.
+pub mod remote;
+
+mod retry;
+
+pub use retry::RetryWrite;
+
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
@@ -144,8 +310,227 @@ mod macos;
 #[cfg(target_os = "windows")]
 mod windows;
 
-/// Create a platform-specific searcher instance.
+/// The default capacity of the channel to deliver BLE events.
+pub const CHANNEL_CAPACITY: usize = 100000;
+
+/// The default timeout to connect/disconnect peripherals.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default timeout to wait for write responses.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default timeout to wait for read values, including the signal strength.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The error of the values dropped because the subscriber fell behind, with the number of them.
+///
+/// Reported by [`PeripheralOps::subscribe_lagging`][] and [`Cube::try_raw_msgs`](crate::Cube::try_raw_msgs).
+///
+/// ```
+/// use toio::ble::Lagged;
+///
+/// let err: anyhow::Error = Lagged(3).into();
+/// assert_eq!(err.downcast_ref::<Lagged>(), Some(&Lagged(3)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Lagged(pub usize);
+
+impl fmt::Display for Lagged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Dropped {} values as the subscriber fell behind", self.0)
+    }
+}
+
+impl std::error::Error for Lagged {}
+
+/// The error when Bluetooth can't be used on the machine.
+///
+/// Returned by searching and connecting, so that applications can tell the user what to do.
+/// Find it in the error chain by downcasting.
+///
+/// ```no_run
+/// use toio::{ble::Unavailable, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     match Cube::search().nearest().await {
+///         Ok(cube) => println!("Found {}", cube.id()),
+///         Err(e) => match e.downcast_ref::<Unavailable>() {
+///             Some(Unavailable::PoweredOff) => println!("Turn on Bluetooth"),
+///             Some(Unavailable::Unauthorized) => println!("Allow this app to use Bluetooth"),
+///             _ => println!("Error: {}", e),
+///         },
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unavailable {
+    /// Bluetooth is turned off.
+    PoweredOff,
+    /// The application isn't allowed to use Bluetooth.
+    Unauthorized,
+    /// The machine doesn't support Bluetooth Low Energy.
+    Unsupported,
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unavailable::PoweredOff => write!(f, "Bluetooth is disabled"),
+            Unavailable::Unauthorized => write!(f, "Not authorized to use Bluetooth"),
+            Unavailable::Unsupported => write!(f, "Bluetooth is not supported"),
+        }
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+/// The Bluetooth adapter to scan and connect on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Adapter {
+    /// The adapter at the index of [`adapters`][].
+    Index(usize),
+    /// The adapter with the MAC address, e.g., `00:1A:7D:DA:71:13`. Case-insensitive.
+    Address(String),
+}
+
+impl Adapter {
+    /// Finds the selected adapter in the list returned by [`adapters`][].
+    pub fn find<'a>(&self, adapters: &'a [AdapterInfo]) -> Option<&'a AdapterInfo> {
+        adapters.iter().find(|a| match self {
+            Adapter::Index(index) => a.index == *index,
+            Adapter::Address(addr) => {
+                matches!(&a.address, Some(a) if a.eq_ignore_ascii_case(addr))
+            }
+        })
+    }
+}
+
+/// A Bluetooth adapter of the machine.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    /// The index to select by [`Adapter::Index`][].
+    pub index: usize,
+    /// The name given by the platform.
+    pub name: String,
+    /// The MAC address, if the platform exposes it.
+    pub address: Option<String>,
+}
+
+/// Lists the Bluetooth adapters of the machine.
+///
+/// ```no_run
+/// use toio::{ble::{self, Adapter}, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     for a in ble::adapters().unwrap() {
+///         println!("{}: {} {:?}", a.index, a.name, a.address);
+///     }
+///
+///     let cube = Cube::search().adapter(Adapter::Index(1)).nearest().await.unwrap();
+/// }
+/// ```
+pub fn adapters() -> Result<Vec<AdapterInfo>> {
+    #[cfg(target_os = "linux")]
+    use linux::adapters as a;
+    #[cfg(target_os = "macos")]
+    use macos::adapters as a;
+    #[cfg(target_os = "windows")]
+    use windows::adapters as a;
+
+    a()
+}
+
+/// The options of the platform-specific backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// The capacity of the channel to deliver BLE events.
+    ///
+    /// If the channel is full, the oldest events are dropped.
+    pub capacity: usize,
+    /// The timeout to connect/disconnect peripherals.
+    pub connect_timeout: Duration,
+    /// The timeout to wait for write responses.
+    pub write_timeout: Duration,
+    /// The timeout to wait for read values, including the signal strength.
+    pub read_timeout: Duration,
+    /// The adapter to scan and connect on.
+    ///
+    /// `None` uses the default adapter of the platform.
+    pub adapter: Option<Adapter>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            capacity: CHANNEL_CAPACITY,
+            connect_timeout: CONNECT_TIMEOUT,
+            write_timeout: WRITE_TIMEOUT,
+            read_timeout: READ_TIMEOUT,
+            adapter: None,
+        }
+    }
+}
+
+/// The factory of the searcher registered by [`register_backend`][].
+static BACKEND: RwLock<Option<fn() -> Searcher>> = RwLock::new(None);
+
+/// Registers the factory of the searcher to use instead of the platform-specific one.
+///
+/// Once registered, [`searcher`][] and the searchers of [`Cube::search`](crate::Cube::search)
+/// create the backend by the factory, so that a simulator or a remote transport can be
+/// used without changing the application. The options of the platform-specific backend
+/// are ignored.
+///
+/// ```no_run
+/// use toio::{ble, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     ble::register_backend(|| ble::remote::searcher("192.168.0.10:7070"));
+///
+///     // Searches for the cubes on the remote machine.
+///     let cube = Cube::search().nearest().await.unwrap();
+/// }
+/// ```
+pub fn register_backend(factory: fn() -> Searcher) {
+    *BACKEND.write().unwrap() = Some(factory);
+}
+
+/// Unregisters the factory to use the platform-specific backend again.
+pub fn unregister_backend() {
+    *BACKEND.write().unwrap() = None;
+}
+
+/// Create a searcher instance.
+///
+/// The backend registered by [`register_backend`][] is used if any.
+/// Otherwise, the platform-specific one is used.
 pub fn searcher() -> Searcher {
+    searcher_with_options(Options::default())
+}
+
+/// Create a searcher instance with the capacity of the internal channel.
+///
+/// If the channel is full, the oldest events are dropped.
+pub fn searcher_with_capacity(capacity: usize) -> Searcher {
+    searcher_with_options(Options {
+        capacity,
+        ..Options::default()
+    })
+}
+
+/// Create a searcher instance with options.
+pub fn searcher_with_options(opts: Options) -> Searcher {
+    match *BACKEND.read().unwrap() {
+        Some(factory) => factory(),
+        None => native_searcher(opts),
+    }
+}
+
+/// Create a platform-specific searcher instance with options, regardless of the registered backend.
+pub(crate) fn native_searcher(opts: Options) -> Searcher {
     #[cfg(target_os = "linux")]
     use linux::searcher as s;
     #[cfg(target_os = "macos")]
@@ -153,5 +538,5 @@ pub fn searcher() -> Searcher {
     #[cfg(target_os = "windows")]
     use windows::searcher as s;
 
-    s()
+    s(opts)
 }
@@ -0,0 +1,546 @@
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use futures::{
+    future::{abortable, AbortHandle},
+    prelude::*,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex as StdMutex,
+};
+use std::time::Duration;
+use tokio::{
+    io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, WriteHalf},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        broadcast::{self, RecvError},
+        mpsc, oneshot, Mutex,
+    },
+};
+
+use crate::ble::{
+    self, Lagged, LaggingStream, PeripheralOps, SearchOps, Uuid, ValueStream, WriteOps,
+    CHANNEL_CAPACITY,
+};
+
+/// The maximum size of a frame.
+const MAX_FRAME: usize = 1 << 20;
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Request {
+    Search(Uuid, Duration),
+    Connect(usize),
+    Disconnect(usize),
+    Read(usize, Uuid),
+    Write(usize, Uuid, Vec<u8>, bool),
+    Notify(usize, Uuid, bool),
+    ReadRssi(usize),
+    Pair(usize),
+    Unpair(usize),
+    Retrieve(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Reply {
+    Done,
+    Found(Vec<(usize, String, i32)>),
+    Rssi(i32),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Frame {
+    Request(u64, Request),
+    Response(u64, std::result::Result<Reply, String>),
+    Value(usize, Uuid, Vec<u8>),
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(w: &mut W, frame: &Frame) -> Result<()> {
+    let buf = serde_json::to_vec(frame)?;
+    w.write_all(&(buf.len() as u32).to_be_bytes()).await?;
+    w.write_all(&buf).await?;
+    Ok(())
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(r: &mut R) -> Result<Frame> {
+    let len = r.read_u32().await? as usize;
+    if len > MAX_FRAME {
+        bail!("Frame too large: {} bytes", len);
+    }
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf).await?;
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+type Pending = StdMutex<HashMap<u64, oneshot::Sender<std::result::Result<Reply, String>>>>;
+
+/// The connection to the proxy shared by the searcher and the peripherals.
+struct Client {
+    wr: Mutex<WriteHalf<TcpStream>>,
+    pending: Arc<Pending>,
+    next: AtomicU64,
+    values: broadcast::Sender<(usize, Uuid, Bytes)>,
+    handle: AbortHandle,
+}
+
+impl Client {
+    async fn connect(addr: &str) -> Result<Arc<Self>> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .with_context(|| format!("Couldn't connect to proxy {}", addr))?;
+        let (mut rd, wr) = split(stream);
+        let pending = Arc::new(Pending::default());
+        let values = broadcast::channel(CHANNEL_CAPACITY).0;
+
+        let (task, handle) = abortable({
+            let pending = pending.clone();
+            let values = values.clone();
+            async move {
+                loop {
+                    match read_frame(&mut rd).await {
+                        Ok(Frame::Response(id, res)) => {
+                            if let Some(tx) = pending.lock().unwrap().remove(&id) {
+                                let _ = tx.send(res);
+                            }
+                        }
+                        Ok(Frame::Value(dev, uuid, value)) => {
+                            let _ = values.send((dev, uuid, value.into()));
+                        }
+                        Ok(frame) => warn!("Unexpected frame from proxy: {:?}", frame),
+                        Err(e) => {
+                            warn!("Connection to proxy closed: {}", e);
+                            // Fails the requests waiting for responses.
+                            pending.lock().unwrap().clear();
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        tokio::spawn(task);
+
+        Ok(Arc::new(Self {
+            wr: Mutex::new(wr),
+            pending,
+            next: AtomicU64::new(0),
+            values,
+            handle,
+        }))
+    }
+
+    async fn call(&self, req: Request) -> Result<Reply> {
+        let id = self.next.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        write_frame(&mut *self.wr.lock().await, &Frame::Request(id, req)).await?;
+        rx.await
+            .context("Connection to proxy closed")?
+            .map_err(|e| anyhow!(e))
+    }
+
+    async fn call_done(&self, req: Request) -> Result<()> {
+        match self.call(req).await? {
+            Reply::Done => Ok(()),
+            r => bail!("Unexpected reply from proxy: {:?}", r),
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Searcher for the peripherals served by a proxy.
+struct Searcher {
+    addr: String,
+    client: Option<Arc<Client>>,
+}
+
+/// Creates a searcher for the peripherals served by the proxy at the address.
+///
+/// The connection to the proxy is established on the first search.
+/// See [`serve`][] to run the proxy.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use toio::{
+///     ble::{self, PeripheralOps, SearchOps},
+///     proto::*,
+/// };
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut searcher = ble::remote::searcher("raspberrypi.local:7070");
+///     let mut peripherals = searcher
+///         .search(&UUID_SERVICE, Duration::from_secs(5))
+///         .await
+///         .unwrap();
+///     let mut peripheral = peripherals.pop().unwrap();
+///     peripheral.connect().await.unwrap();
+/// }
+/// ```
+pub fn searcher(addr: &str) -> ble::Searcher {
+    Box::new(Searcher {
+        addr: addr.to_string(),
+        client: None,
+    })
+}
+
+impl Searcher {
+    async fn call(&mut self, req: Request) -> Result<Vec<ble::Peripheral>> {
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => {
+                let client = Client::connect(&self.addr).await?;
+                self.client = Some(client.clone());
+                client
+            }
+        };
+
+        let found = match client.call(req).await? {
+            Reply::Found(found) => found,
+            r => bail!("Unexpected reply from proxy: {:?}", r),
+        };
+        Ok(found
+            .into_iter()
+            .map(|(handle, id, rssi)| {
+                Box::new(Peripheral {
+                    client: client.clone(),
+                    handle,
+                    id,
+                    rssi,
+                }) as ble::Peripheral
+            })
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl SearchOps for Searcher {
+    async fn search(&mut self, uuid: &Uuid, timeout: Duration) -> Result<Vec<ble::Peripheral>> {
+        self.call(Request::Search(*uuid, timeout)).await
+    }
+
+    async fn retrieve(&mut self, ids: &[String]) -> Result<Vec<ble::Peripheral>> {
+        self.call(Request::Retrieve(ids.to_vec())).await
+    }
+}
+
+/// Writer of a peripheral served by a proxy.
+///
+/// The proxy still writes after the operation of the peripheral in progress there.
+struct PeripheralWriter {
+    client: Arc<Client>,
+    handle: usize,
+}
+
+#[async_trait::async_trait]
+impl WriteOps for PeripheralWriter {
+    async fn write(&mut self, uuid: &Uuid, value: &[u8]) -> Result<()> {
+        self.client
+            .call_done(Request::Write(self.handle, *uuid, value.to_vec(), false))
+            .await
+    }
+}
+
+/// Peripheral served by a proxy.
+struct Peripheral {
+    client: Arc<Client>,
+    handle: usize,
+    id: String,
+    rssi: i32,
+}
+
+#[async_trait::async_trait]
+impl PeripheralOps for Peripheral {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn rssi(&self) -> i32 {
+        self.rssi
+    }
+
+    fn writer(&self) -> Option<ble::Writer> {
+        Some(Box::new(PeripheralWriter {
+            client: self.client.clone(),
+            handle: self.handle,
+        }))
+    }
+
+    async fn read_rssi(&mut self) -> Result<i32> {
+        match self.client.call(Request::ReadRssi(self.handle)).await? {
+            Reply::Rssi(rssi) => {
+                self.rssi = rssi;
+                Ok(rssi)
+            }
+            r => bail!("Unexpected reply from proxy: {:?}", r),
+        }
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        self.client.call_done(Request::Connect(self.handle)).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.client
+            .call_done(Request::Disconnect(self.handle))
+            .await
+    }
+
+    async fn pair(&mut self) -> Result<()> {
+        self.client.call_done(Request::Pair(self.handle)).await
+    }
+
+    async fn unpair(&mut self) -> Result<()> {
+        self.client.call_done(Request::Unpair(self.handle)).await
+    }
+
+    async fn read(&mut self, uuid: &Uuid) -> Result<()> {
+        self.client
+            .call_done(Request::Read(self.handle, *uuid))
+            .await
+    }
+
+    async fn write(&mut self, uuid: &Uuid, value: &[u8], with_resp: bool) -> Result<()> {
+        self.client
+            .call_done(Request::Write(
+                self.handle,
+                *uuid,
+                value.to_vec(),
+                with_resp,
+            ))
+            .await
+    }
+
+    async fn notify(&mut self, uuid: &Uuid, enable: bool) -> Result<()> {
+        self.client
+            .call_done(Request::Notify(self.handle, *uuid, enable))
+            .await
+    }
+
+    fn subscribe(&mut self) -> Result<ValueStream> {
+        let handle = self.handle;
+        Ok(self
+            .client
+            .values
+            .subscribe()
+            .into_stream()
+            .filter_map(move |v| async move {
+                match v {
+                    Ok((h, uuid, value)) if h == handle => Some((uuid, value)),
+                    _ => None,
+                }
+            })
+            .boxed())
+    }
+
+    fn subscribe_lagging(&mut self) -> Result<LaggingStream> {
+        let handle = self.handle;
+        Ok(self
+            .client
+            .values
+            .subscribe()
+            .into_stream()
+            .filter_map(move |v| async move {
+                match v {
+                    Ok((h, uuid, value)) if h == handle => Some(Ok((uuid, value))),
+                    // Values of all the peripherals share the channel.
+                    Err(RecvError::Lagged(n)) => Some(Err(Lagged(n as usize))),
+                    _ => None,
+                }
+            })
+            .boxed())
+    }
+}
+
+/// Serves the peripherals found by the searcher to remote machines.
+///
+/// Runs on the host near the cubes, e.g., a Raspberry Pi. Remote machines use
+/// [`searcher`][] to search for and control the peripherals through the host.
+/// The peripherals connected by a client are disconnected when the client goes away.
+///
+/// ```no_run
+/// use toio::ble;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     ble::remote::serve("0.0.0.0:7070", ble::searcher()).await.unwrap();
+/// }
+/// ```
+pub async fn serve<A: ToSocketAddrs>(addr: A, searcher: ble::Searcher) -> Result<()> {
+    let mut listener = TcpListener::bind(addr)
+        .await
+        .context("Couldn't bind proxy")?;
+    let searcher = Arc::new(Mutex::new(searcher));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        debug!("Client {} is connected", peer);
+        let searcher = searcher.clone();
+        tokio::spawn(async move {
+            if let Err(e) = Session::new(searcher).run(stream).await {
+                debug!("Client {} is disconnected: {}", peer, e);
+            }
+        });
+    }
+}
+
+type Shared = Arc<Mutex<ble::Peripheral>>;
+
+/// The state of a client on the proxy.
+struct Session {
+    searcher: Arc<Mutex<ble::Searcher>>,
+    devs: Arc<StdMutex<Vec<Shared>>>,
+    tasks: Arc<StdMutex<Vec<AbortHandle>>>,
+}
+
+impl Session {
+    fn new(searcher: Arc<Mutex<ble::Searcher>>) -> Self {
+        Self {
+            searcher,
+            devs: Arc::new(StdMutex::new(vec![])),
+            tasks: Arc::new(StdMutex::new(vec![])),
+        }
+    }
+
+    async fn run(&self, stream: TcpStream) -> Result<()> {
+        let (mut rd, mut wr) = split(stream);
+
+        // Frames to the client are written by a single task.
+        let (tx, mut rx) = mpsc::channel::<Frame>(CHANNEL_CAPACITY);
+        let (writer, handle) = abortable(async move {
+            while let Some(frame) = rx.recv().await {
+                if let Err(e) = write_frame(&mut wr, &frame).await {
+                    warn!("Couldn't send frame to client: {}", e);
+                    break;
+                }
+            }
+        });
+        tokio::spawn(writer);
+        self.tasks.lock().unwrap().push(handle);
+
+        loop {
+            let (id, req) = match read_frame(&mut rd).await? {
+                Frame::Request(id, req) => (id, req),
+                frame => bail!("Unexpected frame from client: {:?}", frame),
+            };
+            let searcher = self.searcher.clone();
+            let devs = self.devs.clone();
+            let tasks = self.tasks.clone();
+            let mut tx = tx.clone();
+            tokio::spawn(async move {
+                let res = serve_request(req, searcher, devs, tasks, tx.clone()).await;
+                let res = res.map_err(|e| e.to_string());
+                let _ = tx.send(Frame::Response(id, res)).await;
+            });
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        for handle in self.tasks.lock().unwrap().drain(..) {
+            handle.abort();
+        }
+        for dev in self.devs.lock().unwrap().drain(..) {
+            tokio::spawn(async move {
+                let _ = dev.lock().await.disconnect().await;
+            });
+        }
+    }
+}
+
+async fn serve_request(
+    req: Request,
+    searcher: Arc<Mutex<ble::Searcher>>,
+    devs: Arc<StdMutex<Vec<Shared>>>,
+    tasks: Arc<StdMutex<Vec<AbortHandle>>>,
+    tx: mpsc::Sender<Frame>,
+) -> Result<Reply> {
+    let dev = |handle: usize| {
+        devs.lock()
+            .unwrap()
+            .get(handle)
+            .cloned()
+            .ok_or_else(|| anyhow!("Invalid peripheral handle {}", handle))
+    };
+
+    let reply = match req {
+        Request::Search(uuid, timeout) => {
+            let found = searcher.lock().await.search(&uuid, timeout).await?;
+            register(found, &devs, &tasks, &tx)?
+        }
+        Request::Retrieve(ids) => {
+            let found = searcher.lock().await.retrieve(&ids).await?;
+            register(found, &devs, &tasks, &tx)?
+        }
+        Request::Connect(h) => {
+            dev(h)?.lock().await.connect().await?;
+            Reply::Done
+        }
+        Request::Disconnect(h) => {
+            dev(h)?.lock().await.disconnect().await?;
+            Reply::Done
+        }
+        Request::Read(h, uuid) => {
+            dev(h)?.lock().await.read(&uuid).await?;
+            Reply::Done
+        }
+        Request::Write(h, uuid, value, with_resp) => {
+            dev(h)?.lock().await.write(&uuid, &value, with_resp).await?;
+            Reply::Done
+        }
+        Request::Notify(h, uuid, enable) => {
+            dev(h)?.lock().await.notify(&uuid, enable).await?;
+            Reply::Done
+        }
+        Request::ReadRssi(h) => Reply::Rssi(dev(h)?.lock().await.read_rssi().await?),
+        Request::Pair(h) => {
+            dev(h)?.lock().await.pair().await?;
+            Reply::Done
+        }
+        Request::Unpair(h) => {
+            dev(h)?.lock().await.unpair().await?;
+            Reply::Done
+        }
+    };
+    Ok(reply)
+}
+
+/// Registers the peripherals found to the session, and forwards their values to the client.
+fn register(
+    found: Vec<ble::Peripheral>,
+    devs: &StdMutex<Vec<Shared>>,
+    tasks: &StdMutex<Vec<AbortHandle>>,
+    tx: &mpsc::Sender<Frame>,
+) -> Result<Reply> {
+    let mut res = vec![];
+    for mut p in found {
+        // Forwards the values from the peripheral to the client.
+        let mut values = p.subscribe()?;
+        let mut devs = devs.lock().unwrap();
+        let handle = devs.len();
+        let mut tx = tx.clone();
+        let (task, abort) = abortable(async move {
+            while let Some((uuid, value)) = values.next().await {
+                if tx
+                    .send(Frame::Value(handle, uuid, value.to_vec()))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        tokio::spawn(task);
+        tasks.lock().unwrap().push(abort);
+
+        res.push((handle, p.id().to_string(), p.rssi()));
+        devs.push(Arc::new(Mutex::new(p)));
+    }
+    Ok(Reply::Found(res))
+}
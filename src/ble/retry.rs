@@ -0,0 +1,133 @@
+use anyhow::Result;
+use log::*;
+use tokio::time::delay_for;
+
+use crate::{
+    ble::{LaggingStream, Peripheral, PeripheralOps, Uuid, ValueStream, Writer},
+    Backoff,
+};
+
+/// Peripheral wrapper which retries failed writes with response.
+///
+/// Writes without response are never retried because failures are not reported by the peripheral.
+///
+/// Retrying may apply a message twice if the write reached the peripheral but the response was lost.
+/// This is harmless for most of the messages of toio cubes:
+///
+/// * Configuration messages set values, so applying twice has no extra effect.
+/// * Motor messages with a duration restart the duration.
+/// * Light and sound messages restart the operations from the beginning.
+/// * Motor messages with target positions restart the move, and may report the result twice.
+#[derive(Debug)]
+pub struct RetryWrite<P> {
+    inner: P,
+    backoff: Backoff,
+}
+
+impl<P> RetryWrite<P> {
+    /// Wraps the peripheral.
+    pub fn new(inner: P, backoff: Backoff) -> Self {
+        Self { inner, backoff }
+    }
+
+    /// Unwraps the peripheral.
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl RetryWrite<Peripheral> {
+    /// Wraps the peripheral into a boxed peripheral if the backoff retries.
+    pub fn boxed(inner: Peripheral, backoff: Backoff) -> Peripheral {
+        if backoff.retries == 0 {
+            inner
+        } else {
+            Box::new(Self::new(inner, backoff))
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P> PeripheralOps for RetryWrite<P>
+where
+    P: PeripheralOps + Send,
+{
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    fn rssi(&self) -> i32 {
+        self.inner.rssi()
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    fn max_write_len(&self) -> Option<usize> {
+        self.inner.max_write_len()
+    }
+
+    fn writer(&self) -> Option<Writer> {
+        self.inner.writer()
+    }
+
+    async fn read_rssi(&mut self) -> Result<i32> {
+        self.inner.read_rssi().await
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        self.inner.connect().await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn pair(&mut self) -> Result<()> {
+        self.inner.pair().await
+    }
+
+    async fn unpair(&mut self) -> Result<()> {
+        self.inner.unpair().await
+    }
+
+    async fn read(&mut self, uuid: &Uuid) -> Result<()> {
+        self.inner.read(uuid).await
+    }
+
+    async fn write(&mut self, uuid: &Uuid, value: &[u8], with_resp: bool) -> Result<()> {
+        if !with_resp {
+            return self.inner.write(uuid, value, with_resp).await;
+        }
+
+        let mut delays = self.backoff.delays();
+        loop {
+            match self.inner.write(uuid, value, with_resp).await {
+                Ok(()) => return Ok(()),
+                Err(e) => match delays.next() {
+                    Some(d) => {
+                        warn!(
+                            "Couldn't write to characteristic {}, retrying in {:?}: {}",
+                            uuid, d, e
+                        );
+                        delay_for(d).await;
+                    }
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+
+    async fn notify(&mut self, uuid: &Uuid, enable: bool) -> Result<()> {
+        self.inner.notify(uuid, enable).await
+    }
+
+    fn subscribe(&mut self) -> Result<ValueStream> {
+        self.inner.subscribe()
+    }
+
+    fn subscribe_lagging(&mut self) -> Result<LaggingStream> {
+        self.inner.subscribe_lagging()
+    }
+}
@@ -1,3 +1,7 @@
-pub fn searcher() -> crate::ble::Searcher {
+pub fn searcher(_opts: crate::ble::Options) -> crate::ble::Searcher {
     unimplemented!("Windows is not supported yet")
 }
+
+pub fn adapters() -> anyhow::Result<Vec<crate::ble::AdapterInfo>> {
+    anyhow::bail!("Windows is not supported yet")
+}
@@ -0,0 +1,271 @@
+use anyhow::Result;
+use futures::prelude::*;
+use std::time::Duration;
+
+use crate::{
+    proto::Message, runtime::block_on, Event, EventFilter, EventStream, LightOp, Position, Repeat,
+    Searcher as AsyncSearcher, SoundOp, SoundPresetId, StdId,
+};
+
+/// Synchronous version of [`crate::Searcher`][].
+///
+/// Configure the search by the asynchronous searcher, and convert it by `From`:
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use toio::blocking::Searcher;
+///
+/// let mut searcher = Searcher::from(toio::Cube::search().connect_timeout(Duration::from_secs(5)));
+/// let cube = searcher.nearest().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Searcher {
+    inner: AsyncSearcher,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::from(AsyncSearcher::new())
+    }
+}
+
+impl From<AsyncSearcher> for Searcher {
+    fn from(inner: AsyncSearcher) -> Self {
+        Self { inner }
+    }
+}
+
+impl Searcher {
+    /// Creates a new searcher instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Searches for all the cubes. See [`crate::Searcher::all`][].
+    pub fn all(&mut self) -> Result<Vec<Cube>> {
+        let cubes = block_on(self.inner.all())?;
+        Ok(cubes.into_iter().map(Cube::from).collect())
+    }
+
+    /// Searches for the nearest cube. See [`crate::Searcher::nearest`][].
+    pub fn nearest(&mut self) -> Result<Cube> {
+        block_on(self.inner.nearest()).map(Cube::from)
+    }
+
+    /// Searches for all the cubes with the timeout. See [`crate::Searcher::all_timeout`][].
+    pub fn all_timeout(&mut self, timeout: Duration) -> Result<Vec<Cube>> {
+        let cubes = block_on(self.inner.all_timeout(timeout))?;
+        Ok(cubes.into_iter().map(Cube::from).collect())
+    }
+
+    /// Searches for the nearest cube with the timeout. See [`crate::Searcher::nearest_timeout`][].
+    pub fn nearest_timeout(&mut self, timeout: Duration) -> Result<Cube> {
+        block_on(self.inner.nearest_timeout(timeout)).map(Cube::from)
+    }
+}
+
+/// Synchronous version of [`crate::Cube`][].
+///
+/// The calls block until they complete on a runtime shared in the process,
+/// so no async runtime is needed. The calls must not be made from inside an async runtime.
+///
+/// ```no_run
+/// use std::{thread::sleep, time::Duration};
+/// use toio::blocking::Cube;
+///
+/// fn main() {
+///     let mut cube = Cube::search().nearest().unwrap();
+///     cube.connect().unwrap();
+///
+///     println!("battery: {}%", cube.battery().unwrap());
+///
+///     // Move forward for 1 second.
+///     cube.go(30, 30, Some(Duration::from_secs(1))).unwrap();
+///     sleep(Duration::from_secs(1));
+///
+///     for event in cube.events().unwrap() {
+///         println!("{:?}", event);
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Cube {
+    inner: crate::Cube,
+}
+
+impl From<crate::Cube> for Cube {
+    fn from(inner: crate::Cube) -> Self {
+        Self { inner }
+    }
+}
+
+impl Cube {
+    /// Searches for cubes.
+    pub fn search() -> Searcher {
+        Searcher::new()
+    }
+
+    /// Gets the asynchronous cube.
+    pub fn get_ref(&self) -> &crate::Cube {
+        &self.inner
+    }
+
+    /// Gets the asynchronous cube mutably.
+    pub fn get_mut(&mut self) -> &mut crate::Cube {
+        &mut self.inner
+    }
+
+    /// Unwraps the asynchronous cube.
+    pub fn into_inner(self) -> crate::Cube {
+        self.inner
+    }
+
+    /// Gets the device id.
+    pub fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    /// Gets the signal strength.
+    pub fn rssi(&self) -> i32 {
+        self.inner.rssi()
+    }
+
+    /// Connects to the cube.
+    pub fn connect(&mut self) -> Result<()> {
+        block_on(self.inner.connect())
+    }
+
+    /// Gets the BLE protocol version.
+    pub fn version(&mut self) -> Result<String> {
+        block_on(self.inner.version())
+    }
+
+    /// Gets the battery status in percent.
+    pub fn battery(&mut self) -> Result<usize> {
+        block_on(self.inner.battery())
+    }
+
+    /// Gets the collision status.
+    pub fn collision(&mut self) -> Result<bool> {
+        block_on(self.inner.collision())
+    }
+
+    /// Gets the slope status.
+    pub fn slope(&mut self) -> Result<bool> {
+        block_on(self.inner.slope())
+    }
+
+    /// Gets the button status.
+    pub fn button(&mut self) -> Result<bool> {
+        block_on(self.inner.button())
+    }
+
+    /// Gets the position on the mat, or `None` if the cube is off the mat.
+    pub fn position(&mut self) -> Result<Option<Position>> {
+        block_on(self.inner.position())
+    }
+
+    /// Gets the standard id.
+    pub fn std_id(&mut self) -> Result<Option<StdId>> {
+        block_on(self.inner.std_id())
+    }
+
+    /// Runs the motors. See [`crate::Cube::go`][].
+    pub fn go(&mut self, left: isize, right: isize, duration: Option<Duration>) -> Result<()> {
+        block_on(self.inner.go(left, right, duration))
+    }
+
+    /// Stops the motors.
+    pub fn stop(&mut self) -> Result<()> {
+        block_on(self.inner.stop())
+    }
+
+    /// Plays the preset sound.
+    pub fn play_preset(&mut self, id: SoundPresetId) -> Result<()> {
+        block_on(self.inner.play_preset(id))
+    }
+
+    /// Plays the sound as programmed. See [`crate::Cube::play`][].
+    pub fn play(&mut self, repeat: Repeat, ops: Vec<SoundOp>) -> Result<()> {
+        block_on(self.inner.play(repeat, ops))
+    }
+
+    /// Stops the sound.
+    pub fn stop_sound(&mut self) -> Result<()> {
+        block_on(self.inner.stop_sound())
+    }
+
+    /// Controls the light as programmed. See [`crate::Cube::light`][].
+    pub fn light(&mut self, repeat: Repeat, ops: Vec<LightOp>) -> Result<()> {
+        block_on(self.inner.light(repeat, ops))
+    }
+
+    /// Turns on the light. See [`crate::Cube::light_on`][].
+    pub fn light_on(
+        &mut self,
+        red: u8,
+        green: u8,
+        blue: u8,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        block_on(self.inner.light_on(red, green, blue, duration))
+    }
+
+    /// Turns off the light.
+    pub fn light_off(&mut self) -> Result<()> {
+        block_on(self.inner.light_off())
+    }
+
+    /// Waits until the cube collides with an object.
+    pub fn until_collision(&mut self) -> Result<()> {
+        block_on(self.inner.until_collision())
+    }
+
+    /// Waits until the button is pressed.
+    pub fn until_button_pressed(&mut self) -> Result<()> {
+        block_on(self.inner.until_button_pressed())
+    }
+
+    /// Waits until the cube is put on the mat.
+    pub fn until_on_mat(&mut self) -> Result<Position> {
+        block_on(self.inner.until_on_mat())
+    }
+
+    /// Writes a raw message.
+    pub fn write_msg(&mut self, msg: Message, with_resp: bool) -> Result<()> {
+        block_on(self.inner.write_msg(msg, with_resp))
+    }
+
+    /// Subscribes to events.
+    pub fn events(&mut self) -> Result<Events> {
+        block_on(self.inner.events()).map(Events)
+    }
+
+    /// Subscribes to the selected events.
+    pub fn events_filtered(&mut self, filter: EventFilter) -> Result<Events> {
+        block_on(self.inner.events_filtered(filter)).map(Events)
+    }
+}
+
+/// Iterator of events blocking until the next event arrives.
+pub struct Events(EventStream);
+
+impl Events {
+    /// Waits for the next event for up to the timeout.
+    ///
+    /// Returns `None` if the timeout elapses or the events end.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Event> {
+        let events = &mut self.0;
+        block_on(async { tokio::time::timeout(timeout, events.next()).await })
+            .ok()
+            .flatten()
+    }
+}
+
+impl Iterator for Events {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        block_on(self.0.next())
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::{filters::Registration, Event, EventFilter};
+
+type Callback = Arc<Mutex<Box<dyn FnMut(&Event) + Send>>>;
+
+/// The callbacks registered to a cube, called by its dispatcher task.
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    next: u64,
+    entries: Vec<Entry>,
+}
+
+/// A callback with the filter, which keeps the notifications it requires while registered.
+struct Entry {
+    id: u64,
+    filter: EventFilter,
+    f: Callback,
+    _registration: Registration,
+}
+
+/// The callbacks shared between the cube and its dispatcher task.
+pub(crate) type Registry = Arc<Mutex<Callbacks>>;
+
+/// Adds the callback called on the events passing the filter.
+pub(crate) fn register<F>(
+    registry: &Registry,
+    filter: EventFilter,
+    registration: Registration,
+    f: F,
+) -> CallbackHandle
+where
+    F: FnMut(&Event) + Send + 'static,
+{
+    let mut callbacks = registry.lock().unwrap();
+    let id = callbacks.next;
+    callbacks.next += 1;
+    callbacks.entries.push(Entry {
+        id,
+        filter,
+        f: Arc::new(Mutex::new(Box::new(f))),
+        _registration: registration,
+    });
+    CallbackHandle {
+        id,
+        registry: Arc::downgrade(registry),
+    }
+}
+
+/// Calls the callbacks interested in the event.
+pub(crate) fn dispatch(registry: &Registry, event: &Event) {
+    // Called without the lock, so that the callbacks can unregister themselves.
+    let targets: Vec<Callback> = registry
+        .lock()
+        .unwrap()
+        .entries
+        .iter()
+        .filter(|entry| entry.filter.matches(event))
+        .map(|entry| entry.f.clone())
+        .collect();
+    for f in targets {
+        (f.lock().unwrap())(event);
+    }
+}
+
+/// Handle of a callback registered by [`Cube::on_event`](crate::Cube::on_event) and the like.
+///
+/// The callback stays registered after the handle is dropped, until the cube is dropped.
+pub struct CallbackHandle {
+    id: u64,
+    registry: Weak<Mutex<Callbacks>>,
+}
+
+impl CallbackHandle {
+    /// Stops calling the callback.
+    pub fn unregister(self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry
+                .lock()
+                .unwrap()
+                .entries
+                .retain(|entry| entry.id != self.id);
+        }
+    }
+}
+
+impl fmt::Debug for CallbackHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallbackHandle")
+            .field("id", &self.id)
+            .finish()
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use futures::{prelude::*, stream};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tokio::time::Instant;
+
+use crate::{Cube, Event};
+
+/// The kind of events to export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// The position on the mat.
+    Position,
+    /// The motion sensor, i.e., collision, slope and posture.
+    Motion,
+    /// The battery level.
+    Battery,
+}
+
+impl Kind {
+    /// All the kinds.
+    pub const ALL: &'static [Kind] = &[Kind::Position, Kind::Motion, Kind::Battery];
+
+    fn of(event: &Event) -> Option<Self> {
+        match event {
+            Event::Position(_) => Some(Kind::Position),
+            Event::Collision(_) | Event::Slope(_) | Event::Posture(_) => Some(Kind::Motion),
+            Event::Battery(_) => Some(Kind::Battery),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Position => "position",
+            Kind::Motion => "motion",
+            Kind::Battery => "battery",
+        }
+    }
+}
+
+const HEADER: &str = "timestamp,elapsed,cube,kind,x,y,angle,on_mat,collision,slope,posture,battery";
+
+/// Writes events to CSV for analysis in spreadsheets or notebooks.
+///
+/// Each row has the UTC timestamp, the seconds elapsed since the sink was created,
+/// the cube id and the kind, followed by the columns of the event.
+/// The columns which don't belong to the event are left empty:
+///
+/// ```text
+/// timestamp,elapsed,cube,kind,x,y,angle,on_mat,collision,slope,posture,battery
+/// 2020-06-01T12:00:00.120Z,0.120,<id>,position,250,300,90,true,,,,
+/// 2020-06-01T12:00:00.150Z,0.150,<id>,motion,,,,,true,,,
+/// ```
+///
+/// ```no_run
+/// use toio::{csv::{CsvSink, Kind}, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cubes = Cube::search().all().await.unwrap();
+///     for cube in &mut cubes {
+///         cube.connect().await.unwrap();
+///     }
+///
+///     CsvSink::create("session.csv")
+///         .unwrap()
+///         .kinds(&[Kind::Position, Kind::Battery])
+///         .run(&mut cubes)
+///         .await
+///         .unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CsvSink<W> {
+    out: W,
+    start: Instant,
+    kinds: Vec<Kind>,
+    cubes: HashMap<String, Vec<Kind>>,
+    header: bool,
+}
+
+impl CsvSink<BufWriter<File>> {
+    /// Creates a sink writing to the file. The file is truncated if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    /// Creates a sink writing all the kinds of events to the writer.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            start: Instant::now(),
+            kinds: Kind::ALL.to_vec(),
+            cubes: HashMap::new(),
+            header: false,
+        }
+    }
+
+    /// Sets the kinds of events to write for the cubes not configured by [`CsvSink::cube`][].
+    pub fn kinds(mut self, kinds: &[Kind]) -> Self {
+        self.kinds = kinds.to_vec();
+        self
+    }
+
+    /// Sets the kinds of events to write for the cube.
+    ///
+    /// Pass an empty slice to exclude the cube.
+    pub fn cube(mut self, id: &str, kinds: &[Kind]) -> Self {
+        self.cubes.insert(id.to_string(), kinds.to_vec());
+        self
+    }
+
+    /// Writes the event of the cube if it's selected.
+    ///
+    /// Returns `false` if the event is skipped.
+    pub fn write(&mut self, id: &str, event: &Event) -> Result<bool> {
+        let kind = match Kind::of(event) {
+            Some(kind) if self.cubes.get(id).unwrap_or(&self.kinds).contains(&kind) => kind,
+            _ => return Ok(false),
+        };
+
+        if !self.header {
+            writeln!(self.out, "{}", HEADER)?;
+            self.header = true;
+        }
+
+        let elapsed = self.start.elapsed();
+        let mut cols = vec![String::new(); 8];
+        match event {
+            Event::Position(Some(p)) => {
+                cols[0] = p.x.to_string();
+                cols[1] = p.y.to_string();
+                cols[2] = p.angle.to_string();
+                cols[3] = "true".into();
+            }
+            Event::Position(None) => cols[3] = "false".into(),
+            Event::Collision(v) => cols[4] = v.to_string(),
+            Event::Slope(v) => cols[5] = v.to_string(),
+            Event::Posture(p) => cols[6] = format!("{:?}", p),
+            Event::Battery(v) => cols[7] = v.to_string(),
+            _ => {}
+        }
+        writeln!(
+            self.out,
+            "{},{}.{:03},{},{},{}",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            escape(id),
+            kind.name(),
+            cols.join(",")
+        )?;
+        // Flushes each row so that the file is readable while the session goes on.
+        self.out.flush()?;
+        Ok(true)
+    }
+
+    /// Writes the events of the cubes until all the event streams end.
+    pub async fn run(&mut self, cubes: &mut [Cube]) -> Result<()> {
+        let mut streams = vec![];
+        for cube in cubes.iter_mut() {
+            let id = cube.id().to_string();
+            let events = cube.events().await?;
+            streams.push(events.map(move |event| (id.clone(), event)).boxed());
+        }
+
+        let mut events = stream::select_all(streams);
+        while let Some((id, event)) = events.next().await {
+            self.write(&id, &event)?;
+        }
+        Ok(())
+    }
+
+    /// Unwraps the writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+fn escape(field: &str) -> String {
+    if field.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,25 +1,50 @@
 use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
 use derive_new::new;
 use futures::{
-    future::{abortable, AbortHandle},
+    future::{self, abortable, AbortHandle},
     prelude::*,
     stream::{self, BoxStream},
 };
 use log::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
 use std::fmt::{self, Debug};
+use std::mem;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::{sync::Mutex, time::timeout};
+use tokio::{
+    sync::{
+        broadcast::{self, RecvError},
+        oneshot, watch, Mutex,
+    },
+    time::{delay_for, timeout, Instant},
+};
 
 use crate::{
+    battery::Estimator,
     ble::{self, PeripheralOps, PeripheralOpsExt, Uuid},
+    callback::{self, CallbackHandle},
+    drive::{Navigation, PatrolOptions, Progress, Ramp, ReacquireOptions, Superseded, Update},
+    filters::Filters,
+    hub::Hub,
+    latency::Latency,
+    light::{self, Animation, Correction},
+    metrics, monitor,
     proto::{self, *},
+    proximity::Proximity,
+    queue::{Overflow, Recv},
+    record::Recorder,
+    retry::Backoff,
+    tracking::{Tracker, TrackingQuality},
+    writer::{Preemptor, Writer},
     Searcher,
 };
 
 /// A light operation.
-#[derive(Serialize, Deserialize, Debug, Clone, new)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, new)]
 pub struct LightOp {
     /// The value of red light.
     pub red: u8,
@@ -32,7 +57,7 @@ pub struct LightOp {
 }
 
 /// A sound operation.
-#[derive(Serialize, Deserialize, Debug, Clone, new)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, new)]
 pub struct SoundOp {
     /// Sound note.
     pub note: Note,
@@ -40,11 +65,62 @@ pub struct SoundOp {
     pub duration: Duration,
 }
 
+/// The repeat count of sound and light operations.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Repeat {
+    /// Repeats the given number of times. The count must be from 1 to 255.
+    Times(usize),
+    /// Repeats forever.
+    Forever,
+}
+
+impl Repeat {
+    /// Converts to the protocol value, where 0 means infinite.
+    fn count(self) -> Result<u8> {
+        match self {
+            Repeat::Times(n) if (1..=255).contains(&n) => Ok(n as u8),
+            Repeat::Times(_) => Err(anyhow!("The repeat count must be from 1 to 255")),
+            Repeat::Forever => Ok(0),
+        }
+    }
+}
+
+/// How often the cube notifies the position.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PositionRate {
+    /// As often as the cube reads the mat.
+    Max,
+    /// The given times per second at most. The rate must be 1 or more.
+    Hz(u32),
+    /// Only when the position changes.
+    OnChange,
+}
+
+impl PositionRate {
+    /// Converts to the protocol values of the interval and the condition.
+    fn config(self) -> Result<ConfigIdNotify> {
+        match self {
+            PositionRate::Max => Ok(ConfigIdNotify::new(0, NotifyCondition::Always)),
+            PositionRate::Hz(0) => Err(anyhow!("The rate must be 1 or more")),
+            PositionRate::Hz(n) => {
+                let interval = (100 / n).clamp(1, 255) as u8;
+                Ok(ConfigIdNotify::new(interval, NotifyCondition::Always))
+            }
+            PositionRate::OnChange => Ok(ConfigIdNotify::new(0, NotifyCondition::OnChange)),
+        }
+    }
+}
+
 /// The event sent when the status is updated.
-#[derive(Serialize, Deserialize, Debug, Clone, new)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
 pub enum Event {
     /// Battery is updated.
     Battery(usize),
+    /// Battery falls to the threshold or below.
+    ///
+    /// Emitted once until the battery is charged above the threshold again.
+    /// See [`Searcher::battery_low`][].
+    BatteryLow(usize),
     /// Set if the cube collides with an object.
     Collision(bool),
     /// Set if the cube is on a slope.
@@ -53,12 +129,214 @@ pub enum Event {
     Button(bool),
     /// Posture of the cube.
     Posture(Posture),
+    /// The whole state of the motion sensor.
+    ///
+    /// Sent together with [`Event::Slope`][], [`Event::Collision`][] and [`Event::Posture`][],
+    /// for the fields without the dedicated events, e.g., the double tap.
+    Motion(MotionDetect),
+    /// The magnetic force measured in [`MagnetMode::Force`][].
+    ///
+    /// See [`Cube::set_magnet_mode`][] to enable the measurement.
+    MagneticForce(Vector3),
     /// Position information.
     Position(Option<Position>),
     /// Standard id information.
     StdId(Option<StdId>),
     /// The protocol version.
     Version(String),
+    /// The cube doesn't respond to the liveness check.
+    ///
+    /// Emitted once until the cube sends a message again.
+    /// See [`Searcher::watchdog`][].
+    Unresponsive,
+    /// Notifications seem to be dropped on the way, e.g., by BLE congestion.
+    ///
+    /// See [`Searcher::detect_gaps`][].
+    NotificationGap {
+        /// The characteristic of the notifications.
+        characteristic: Characteristic,
+        /// The estimated number of the notifications dropped.
+        estimated_missed: usize,
+    },
+    /// Another cube joining the same [`Proximity`][] comes within the range.
+    Proximity {
+        /// The id of the other cube.
+        other: String,
+        /// The distance between the cubes in mat units.
+        distance: u16,
+    },
+    /// The number of events dropped because the subscriber fell behind.
+    ///
+    /// The cached status is cleared, so the getters read fresh values afterward.
+    Lagged(usize),
+}
+
+/// The filter to select events.
+///
+/// Only the characteristics required by the selected events are listened to.
+/// Notifications from the ID, motion, button and battery characteristics are
+/// disabled at the BLE level only while neither a live stream from
+/// [`Cube::events_filtered`][] nor the cube itself requires them. The cube tracks
+/// its status with all of them while connected. [`Cube::set_notifications`][]
+/// overrides the filters.
+///
+/// ```no_run
+/// use futures::prelude::*;
+/// use toio::{Cube, EventFilter};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     let filter = EventFilter::new().collision().button().position();
+///     let mut events = cube.events_filtered(filter).await.unwrap();
+///     while let Some(event) = events.next().await {
+///         println!("{:?}", event);
+///     }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct EventFilter {
+    battery: bool,
+    collision: bool,
+    slope: bool,
+    button: bool,
+    posture: bool,
+    motion: bool,
+    magnetic_force: bool,
+    position: bool,
+    std_id: bool,
+    version: bool,
+    proximity: bool,
+}
+
+impl EventFilter {
+    /// Creates a filter which selects no events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a filter which selects all the events.
+    pub fn all() -> Self {
+        Self::new()
+            .battery()
+            .collision()
+            .slope()
+            .button()
+            .posture()
+            .motion()
+            .magnetic_force()
+            .position()
+            .std_id()
+            .version()
+            .proximity()
+    }
+
+    /// Selects battery events.
+    pub fn battery(mut self) -> Self {
+        self.battery = true;
+        self
+    }
+
+    /// Selects collision events.
+    pub fn collision(mut self) -> Self {
+        self.collision = true;
+        self
+    }
+
+    /// Selects slope events.
+    pub fn slope(mut self) -> Self {
+        self.slope = true;
+        self
+    }
+
+    /// Selects button events.
+    pub fn button(mut self) -> Self {
+        self.button = true;
+        self
+    }
+
+    /// Selects posture events.
+    pub fn posture(mut self) -> Self {
+        self.posture = true;
+        self
+    }
+
+    /// Selects the events of the whole motion sensor state.
+    pub fn motion(mut self) -> Self {
+        self.motion = true;
+        self
+    }
+
+    /// Selects magnetic force events.
+    pub fn magnetic_force(mut self) -> Self {
+        self.magnetic_force = true;
+        self
+    }
+
+    /// Selects position events.
+    pub fn position(mut self) -> Self {
+        self.position = true;
+        self
+    }
+
+    /// Selects standard id events.
+    pub fn std_id(mut self) -> Self {
+        self.std_id = true;
+        self
+    }
+
+    /// Selects protocol version events.
+    pub fn version(mut self) -> Self {
+        self.version = true;
+        self
+    }
+
+    /// Selects proximity events. See [`Cube::join_proximity`][].
+    pub fn proximity(mut self) -> Self {
+        self.proximity = true;
+        self
+    }
+
+    /// Returns `true` if the event is selected.
+    pub fn matches(&self, event: &Event) -> bool {
+        match event {
+            Event::Battery(_) | Event::BatteryLow(_) => self.battery,
+            Event::Collision(_) => self.collision,
+            Event::Slope(_) => self.slope,
+            Event::Button(_) => self.button,
+            Event::Posture(_) => self.posture,
+            Event::Motion(_) => self.motion,
+            Event::MagneticForce(_) => self.magnetic_force,
+            Event::Position(_) => self.position,
+            Event::StdId(_) => self.std_id,
+            Event::Version(_) => self.version,
+            Event::Proximity { .. } => self.proximity,
+            Event::Unresponsive | Event::NotificationGap { .. } | Event::Lagged(_) => true,
+        }
+    }
+
+    /// Gets the characteristics required by the selected events.
+    pub fn uuids(&self) -> Vec<Uuid> {
+        let mut uuids = vec![];
+        if self.battery {
+            uuids.push(UUID_BATTERY);
+        }
+        if self.collision || self.slope || self.posture || self.motion || self.magnetic_force {
+            uuids.push(UUID_MOTION);
+        }
+        if self.button {
+            uuids.push(UUID_BUTTON);
+        }
+        if self.position || self.std_id || self.proximity {
+            uuids.push(UUID_ID);
+        }
+        if self.version {
+            uuids.push(UUID_CONFIG);
+        }
+        uuids
+    }
 }
 
 /// The stream of events.
@@ -67,6 +345,39 @@ pub type EventStream = BoxStream<'static, Event>;
 /// The stream of raw messages.
 pub type MessageStream = BoxStream<'static, Message>;
 
+/// The stream of raw values.
+pub type ValueStream = BoxStream<'static, Bytes>;
+
+/// The streams of each sensor, returned by [`Cube::subscriptions`][].
+///
+/// Each field is an independent subscription, so the fields can be moved to different
+/// tasks. Dropping a field unsubscribes only that one. Unlike [`EventStream`][],
+/// the events lost by lagging are skipped silently.
+pub struct Subscriptions {
+    /// The positions on the mat, or `None` when the cube leaves the mat.
+    pub positions: BoxStream<'static, Option<Position>>,
+    /// The standard ids under the cube, or `None` when the cube leaves the id.
+    pub std_ids: BoxStream<'static, Option<StdId>>,
+    /// The button states. `true` if pressed.
+    pub buttons: BoxStream<'static, bool>,
+    /// The battery percentages.
+    pub battery: BoxStream<'static, usize>,
+    /// The collision states.
+    pub collisions: BoxStream<'static, bool>,
+    /// The slope states.
+    pub slopes: BoxStream<'static, bool>,
+    /// The postures.
+    pub postures: BoxStream<'static, Posture>,
+    /// The motion detection results.
+    pub motions: BoxStream<'static, MotionDetect>,
+}
+
+impl Debug for Subscriptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Subscriptions").finish()
+    }
+}
+
 /// The standard id information.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, new)]
 pub struct StdId {
@@ -94,6 +405,28 @@ impl From<IdPos> for Position {
     }
 }
 
+/// A vector in the coordinates of the cube.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, new)]
+pub struct Vector3 {
+    pub x: i8,
+    pub y: i8,
+    pub z: i8,
+}
+
+impl Vector3 {
+    /// Gets the length of the vector.
+    pub fn magnitude(&self) -> f32 {
+        let (x, y, z) = (self.x as f32, self.y as f32, self.z as f32);
+        (x * x + y * y + z * z).sqrt()
+    }
+}
+
+impl From<MotionMagnet> for Vector3 {
+    fn from(m: MotionMagnet) -> Self {
+        Self::new(m.x, m.y, m.z)
+    }
+}
+
 #[derive(Default, Debug)]
 struct Status {
     version: Option<String>,
@@ -103,6 +436,7 @@ struct Status {
     button: Option<bool>,
     position: Option<Option<Position>>,
     std_id: Option<Option<StdId>>,
+    posture: Option<Posture>,
 }
 
 macro_rules! fetch_if_none {
@@ -112,7 +446,7 @@ macro_rules! fetch_if_none {
         $($t)*
 
         if $self.status.lock().await.$field.is_none() {
-            Ok(timeout(READ_TIMEOUT, async move {
+            Ok(timeout($self.read_timeout, async move {
                 while let Some(event) = events.next().await {
                     match event {
                         Event::$msg(v) => return Ok(v),
@@ -133,6 +467,21 @@ macro_rules! fetch_if_none {
     }};
 }
 
+macro_rules! fetch_now {
+    ($self:tt, $uuid:expr, $msg:tt($v:pat) => $e:expr $(, $other:tt($ov:pat) => $oe:expr)*) => {{
+        $self
+            .read_events(&$uuid)
+            .await?
+            .into_iter()
+            .find_map(|event| match event {
+                Event::$msg($v) => Some($e),
+                $(Event::$other($ov) => Some($oe),)*
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("Couldn't read {}", stringify!($msg)))
+    }};
+}
+
 /// The cube.
 ///
 /// Provides API to control the cube. The API has two types:
@@ -179,9 +528,33 @@ macro_rules! fetch_if_none {
 /// }
 /// ```
 pub struct Cube {
-    dev: ble::Peripheral,
+    dev: Arc<Mutex<ble::Peripheral>>,
+    id: String,
+    rssi: watch::Receiver<i32>,
+    rssi_tx: Arc<watch::Sender<i32>>,
+    rssi_interval: Option<Duration>,
+    writer: Writer,
     status: Arc<Mutex<Status>>,
-    handle: Option<AbortHandle>,
+    tasks: Vec<AbortHandle>,
+    hub: Hub,
+    notices: broadcast::Sender<Event>,
+    battery_interval: Option<Duration>,
+    battery_low: Option<usize>,
+    watchdog: Option<Duration>,
+    detect_gaps: bool,
+    estimator: Arc<std::sync::Mutex<Estimator>>,
+    tracker: Arc<std::sync::Mutex<Tracker>>,
+    navigation: Option<(u8, oneshot::Sender<()>)>,
+    latency: Option<Latency>,
+    callbacks: callback::Registry,
+    correction: Correction,
+    ramp: Option<Ramp>,
+    speed: Speed,
+    dedup: bool,
+    filters: Filters,
+    read_timeout: Duration,
+    connect_retry: Backoff,
+    profile: Profile,
 }
 
 impl Debug for Cube {
@@ -190,14 +563,195 @@ impl Debug for Cube {
     }
 }
 
-const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// The interval to update the wheel speeds while searching for the mat.
+const REACQUIRE_STEP: Duration = Duration::from_millis(100);
+
+/// The capacity of the channel of events generated by the cube itself.
+const NOTICE_CAPACITY: usize = 64;
+
+/// The options of cubes.
+///
+/// Raise the timeouts on congested environments or slow adapters,
+/// or lower them to give up early.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use toio::{Cube, Options};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let opts = Options {
+///         connect_timeout: Duration::from_secs(10),
+///         ..Options::default()
+///     };
+///     let cube = Cube::search().options(opts).nearest().await.unwrap();
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Options {
+    /// The capacity of channels to deliver messages and events.
+    pub capacity: usize,
+    /// The policy when the channel to a subscriber is full.
+    pub overflow: Overflow,
+    /// The timeout to connect/disconnect the cube.
+    pub connect_timeout: Duration,
+    /// The backoff to retry connecting the cube.
+    ///
+    /// Defaults to [`Backoff::none`][], which doesn't retry.
+    pub connect_retry: Backoff,
+    /// The timeout to wait for write responses.
+    pub write_timeout: Duration,
+    /// The backoff to retry failed writes with response.
+    ///
+    /// See [`ble::RetryWrite`][] for which messages are safe to retry.
+    pub write_retry: Backoff,
+    /// The timeout to read values from the cube.
+    pub read_timeout: Duration,
+    /// The maximum number of writes per second.
+    ///
+    /// If set, writes are queued and sent in the background at the rate,
+    /// and writes without response return without waiting.
+    /// A write without response is dropped if the same value is already queued
+    /// to the same characteristic.
+    /// `None` means no limit, and each write is sent as soon as the previous one is delivered.
+    ///
+    /// In any case, writes are delivered in call order.
+    pub max_write_rate: Option<u32>,
+    /// Keeps only the latest pending motor/light command of the same kind.
+    ///
+    /// Useful when a control loop issues commands faster than they can be written.
+    /// Writes are queued even if no maximum write rate is set.
+    /// A replaced write with response fails with [`Replaced`](crate::Replaced).
+    /// Target requests of different ids don't replace each other.
+    /// The parts of a split multi-target request are never replaced.
+    pub coalesce: bool,
+    /// The UUIDs of the service and the characteristics.
+    pub profile: Profile,
+    /// Rejects notifications with trailing bytes instead of ignoring the bytes.
+    ///
+    /// Rejected notifications are logged with the details.
+    pub strict_decode: bool,
+    /// The interval to refresh the signal strength while connected.
+    ///
+    /// Each refresh waits for the other operations on the cube, so it's disabled by default.
+    /// `None` disables refreshing.
+    pub rssi_interval: Option<Duration>,
+    /// The interval to read the battery while connected.
+    ///
+    /// `None` disables polling, and the battery is updated only by notifications.
+    pub battery_interval: Option<Duration>,
+    /// The battery percentage to emit [`Event::BatteryLow`][].
+    ///
+    /// `None` disables the event.
+    pub battery_low: Option<usize>,
+    /// The time without notifications to check if the cube is alive.
+    ///
+    /// `None` disables the check.
+    pub watchdog: Option<Duration>,
+    /// Analyzes the intervals of the position notifications to emit [`Event::NotificationGap`][].
+    pub detect_gaps: bool,
+    /// The Bluetooth adapter to scan and connect on.
+    ///
+    /// `None` uses the default adapter of the platform.
+    pub adapter: Option<ble::Adapter>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            capacity: ble::CHANNEL_CAPACITY,
+            overflow: Overflow::DropOldest,
+            connect_timeout: ble::CONNECT_TIMEOUT,
+            connect_retry: Backoff::none(),
+            write_timeout: ble::WRITE_TIMEOUT,
+            write_retry: Backoff::none(),
+            read_timeout: ble::READ_TIMEOUT,
+            max_write_rate: None,
+            coalesce: false,
+            profile: Profile::default(),
+            strict_decode: false,
+            rssi_interval: None,
+            battery_interval: None,
+            battery_low: None,
+            watchdog: None,
+            detect_gaps: false,
+            adapter: None,
+        }
+    }
+}
+
+impl Options {
+    pub(crate) fn ble(&self) -> ble::Options {
+        ble::Options {
+            capacity: self.capacity,
+            connect_timeout: self.connect_timeout,
+            write_timeout: self.write_timeout,
+            read_timeout: self.read_timeout,
+            adapter: self.adapter.clone(),
+        }
+    }
+}
 
 impl Cube {
-    pub(crate) fn new(dev: ble::Peripheral) -> Self {
+    /// Creates a cube on the peripheral, e.g., of a custom transport or a simulator.
+    ///
+    /// The cube isn't connected yet. Call [`Cube::connect`][] to start.
+    ///
+    /// ```
+    /// use toio::{ble, proto::*, Cube, Options};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (dev, handle) = ble::mock::mock("cube");
+    ///     let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     handle.notify(UUID_BATTERY, vec![80]);
+    ///     assert_eq!(cube.battery().await.unwrap(), 80);
+    /// }
+    /// ```
+    pub fn from_peripheral(dev: ble::Peripheral, opts: Options) -> Self {
+        let id = dev.id().to_string();
+        let (rssi_tx, rssi) = watch::channel(dev.rssi());
+        let dev = Arc::new(Mutex::new(ble::RetryWrite::boxed(dev, opts.write_retry)));
         Self {
-            dev,
+            dev: dev.clone(),
+            id,
+            rssi,
+            rssi_tx: Arc::new(rssi_tx),
+            rssi_interval: opts.rssi_interval,
+            writer: Writer::new(
+                dev.clone(),
+                opts.max_write_rate,
+                opts.coalesce,
+                opts.profile,
+            ),
             status: Arc::new(Mutex::new(Status::default())),
-            handle: None,
+            tasks: vec![],
+            hub: Hub::new(
+                opts.capacity,
+                opts.overflow,
+                opts.profile,
+                opts.strict_decode,
+            ),
+            notices: broadcast::channel(NOTICE_CAPACITY).0,
+            battery_interval: opts.battery_interval,
+            battery_low: opts.battery_low,
+            watchdog: opts.watchdog,
+            detect_gaps: opts.detect_gaps,
+            estimator: Arc::new(std::sync::Mutex::new(Estimator::default())),
+            tracker: Arc::new(std::sync::Mutex::new(Tracker::default())),
+            navigation: None,
+            latency: None,
+            callbacks: callback::Registry::default(),
+            profile: opts.profile,
+            correction: Correction::default(),
+            ramp: None,
+            speed: Speed::default(),
+            dedup: false,
+            filters: Filters::new(dev, opts.profile),
+            read_timeout: opts.read_timeout,
+            connect_retry: opts.connect_retry,
         }
     }
 
@@ -239,21 +793,58 @@ impl Cube {
 
     /// Gets the device id.
     pub fn id(&self) -> &str {
-        self.dev.id()
+        &self.id
     }
 
     /// Gets the signal strength.
+    ///
+    /// The value is the one at discovery, or refreshed periodically while connected
+    /// if enabled by [`Searcher::rssi_interval`][].
     pub fn rssi(&self) -> i32 {
-        self.dev.rssi()
+        *self.rssi.borrow()
+    }
+
+    /// Subscribes to the signal strength.
+    ///
+    /// The stream yields the current value first, and then the refreshed values.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use std::time::Duration;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search()
+    ///         .rssi_interval(Some(Duration::from_secs(2)))
+    ///         .nearest()
+    ///         .await
+    ///         .unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mut rssi = cube.rssi_stream();
+    ///     while let Some(rssi) = rssi.next().await {
+    ///         if rssi < -80 {
+    ///             println!("Move closer");
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn rssi_stream(&self) -> BoxStream<'static, i32> {
+        self.rssi.clone().boxed()
     }
 
     /// Gets the BLE protocol version.
     pub async fn version(&mut self) -> Result<String> {
         fetch_if_none!(self, version, Version, {
-            self.dev
+            self.writer
                 .write_msg(Config::Version(ConfigVersion::new()), true)
                 .await?;
-            self.dev.read(&UUID_CONFIG).await?;
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_CONFIG))
+                .await?;
         })
     }
 
@@ -262,16 +853,128 @@ impl Cube {
     /// Returns the percentage of the remaining battery.
     pub async fn battery(&mut self) -> Result<usize> {
         fetch_if_none!(self, battery, Battery, {
-            self.dev.read(&UUID_BATTERY).await?;
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_BATTERY))
+                .await?;
         })
     }
 
+    /// Estimates the time until the battery runs out.
+    ///
+    /// The estimation is based on the discharge rate in the last 30 minutes.
+    /// Returns `None` until the battery has dropped enough to estimate,
+    /// or while charging. Polling the battery by [`Searcher::battery_interval`][]
+    /// keeps the estimation up to date.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search()
+    ///         .battery_interval(Duration::from_secs(60))
+    ///         .nearest()
+    ///         .await
+    ///         .unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     if let Some(runtime) = cube.estimated_runtime() {
+    ///         println!("{} minutes left", runtime.as_secs() / 60);
+    ///     }
+    /// }
+    /// ```
+    pub fn estimated_runtime(&self) -> Option<Duration> {
+        self.estimator.lock().unwrap().estimate()
+    }
+
+    /// Gets how well the cube is localized on the mat in the last second.
+    ///
+    /// The quality degrades when the cube often loses the mat, or the position
+    /// notifications are delayed, e.g., by BLE congestion.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let quality = cube.tracking_quality();
+    ///     if quality.ratio < 0.9 || quality.rate < 10.0 {
+    ///         // Slows down not to get lost.
+    ///         cube.go(10, 10, None).await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn tracking_quality(&self) -> TrackingQuality {
+        self.tracker.lock().unwrap().quality(Instant::now())
+    }
+
+    /// Measures the BLE delay by the round trips of writes with response.
+    ///
+    /// Writes the protocol version request `samples` times one after another, bypassing
+    /// the write queue, and keeps the result for [`Cube::latency`][]. Measure again when
+    /// the environment changes, e.g., after more cubes are connected.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let latency = cube.probe_latency(10).await.unwrap();
+    ///     println!("Commands reach the cube in about {:?}", latency.one_way());
+    /// }
+    /// ```
+    pub async fn probe_latency(&mut self, samples: usize) -> Result<Latency> {
+        let (uuid, value): (Uuid, Vec<u8>) = Message::Config(Config::Version(ConfigVersion::new()))
+            .try_into()
+            .context("Couldn't pack message")?;
+        let uuid = self.profile.resolve(&uuid);
+
+        let mut round_trips = vec![];
+        for _ in 0..samples {
+            let mut dev = self.dev.lock().await;
+            let start = Instant::now();
+            dev.write(&uuid, &value, true).await?;
+            round_trips.push(start.elapsed());
+        }
+        let latency = Latency::from_round_trips(&round_trips)
+            .ok_or_else(|| anyhow!("Number of samples must be at least 1"))?;
+        self.latency = Some(latency);
+        Ok(latency)
+    }
+
+    /// Gets the BLE delay measured last by [`Cube::probe_latency`][].
+    ///
+    /// `None` if not measured yet.
+    pub fn latency(&self) -> Option<Latency> {
+        self.latency
+    }
+
+    /// Sets the BLE delay known in advance, e.g., measured on the previous run.
+    ///
+    /// Used by [`Scheduler`](crate::schedule::Scheduler) to write earlier.
+    pub fn set_latency(&mut self, latency: Option<Latency>) {
+        self.latency = latency;
+    }
+
     /// Gets the collision status.
     ///
     /// Returns `true` if the cube is in collision.
     pub async fn collision(&mut self) -> Result<bool> {
         fetch_if_none!(self, collision, Collision, {
-            self.dev.read(&UUID_MOTION).await?;
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_MOTION))
+                .await?;
         })
     }
 
@@ -280,16 +983,81 @@ impl Cube {
     /// Returns `true` if the cube slopes.
     pub async fn slope(&mut self) -> Result<bool> {
         fetch_if_none!(self, slope, Slope, {
-            self.dev.read(&UUID_MOTION).await?;
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_MOTION))
+                .await?;
+        })
+    }
+
+    /// Gets the posture.
+    ///
+    /// Returns which side of the cube is up.
+    pub async fn posture(&mut self) -> Result<Posture> {
+        fetch_if_none!(self, posture, Posture, {
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_MOTION))
+                .await?;
         })
     }
 
+    /// Subscribes to the changes of the posture.
+    ///
+    /// The stream yields the first posture notified, and then only the ones different
+    /// from the previous one. Use [`Cube::posture`][] for the current one.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::{Cube, Posture};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mut postures = cube.postures().await.unwrap();
+    ///     while let Some(posture) = postures.next().await {
+    ///         if posture == Posture::BottomUp {
+    ///             println!("Turned over");
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn postures(&mut self) -> Result<BoxStream<'static, Posture>> {
+        let events = self.events_filtered(EventFilter::new().posture()).await?;
+        Ok(events
+            .scan(None, |last, event| {
+                let changed = match event {
+                    Event::Posture(p) if *last != Some(p) => {
+                        *last = Some(p);
+                        Some(p)
+                    }
+                    Event::Lagged(_) => {
+                        // The posture may have changed while lagging.
+                        *last = None;
+                        None
+                    }
+                    _ => None,
+                };
+                future::ready(Some(changed))
+            })
+            .filter_map(future::ready)
+            .boxed())
+    }
+
     /// Gets the button status.
     ///
     /// Returns `true` if the button is pressed.
     pub async fn button(&mut self) -> Result<bool> {
         fetch_if_none!(self, button, Button, {
-            self.dev.read(&UUID_BUTTON).await?;
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_BUTTON))
+                .await?;
         })
     }
 
@@ -299,7 +1067,11 @@ impl Cube {
     /// Returns `None` if no position information is available.
     pub async fn position(&mut self) -> Result<Option<Position>> {
         fetch_if_none!(self, position, Position, {
-            self.dev.read(&UUID_ID).await?;
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_ID))
+                .await?;
         })
     }
 
@@ -309,18 +1081,92 @@ impl Cube {
     /// Returns `None` if no id is available.
     pub async fn std_id(&mut self) -> Result<Option<StdId>> {
         fetch_if_none!(self, std_id, StdId, {
-            self.dev.read(&UUID_ID).await?;
+            self.dev
+                .lock()
+                .await
+                .read(&self.profile.resolve(&UUID_ID))
+                .await?;
         })
     }
 
-    /// Moves the cube.
+    /// Reads the BLE protocol version from the cube.
     ///
-    /// `left` and `right` are the rotation speed of each wheel.
-    /// The value must be in the range from -100 to 100.
-    /// The negative number rotates backward, while the positive rotates forward.
-    /// If specified, the wheels rotate for the given duration. If the duration is `None`,
-    /// wheels rotate forever.
-    /// The duration must be in the range from 1 to 2559 milliseconds.
+    /// Unlike [`Cube::version`][], this always reads a fresh value and updates the cache.
+    pub async fn version_now(&mut self) -> Result<String> {
+        self.writer
+            .write_msg(Config::Version(ConfigVersion::new()), true)
+            .await?;
+        fetch_now!(self, UUID_CONFIG, Version(v) => v)
+    }
+
+    /// Reads the battery status from the cube.
+    ///
+    /// Unlike [`Cube::battery`][], this always reads a fresh value and updates the cache.
+    pub async fn battery_now(&mut self) -> Result<usize> {
+        fetch_now!(self, UUID_BATTERY, Battery(v) => v)
+    }
+
+    /// Reads the collision status from the cube.
+    ///
+    /// Unlike [`Cube::collision`][], this always reads a fresh value and updates the cache.
+    pub async fn collision_now(&mut self) -> Result<bool> {
+        fetch_now!(self, UUID_MOTION, Collision(v) => v)
+    }
+
+    /// Reads the slope status from the cube.
+    ///
+    /// Unlike [`Cube::slope`][], this always reads a fresh value and updates the cache.
+    pub async fn slope_now(&mut self) -> Result<bool> {
+        fetch_now!(self, UUID_MOTION, Slope(v) => v)
+    }
+
+    /// Reads the posture from the cube.
+    ///
+    /// Unlike [`Cube::posture`][], this always reads a fresh value and updates the cache.
+    pub async fn posture_now(&mut self) -> Result<Posture> {
+        fetch_now!(self, UUID_MOTION, Posture(v) => v)
+    }
+
+    /// Reads the button status from the cube.
+    ///
+    /// Unlike [`Cube::button`][], this always reads a fresh value and updates the cache.
+    pub async fn button_now(&mut self) -> Result<bool> {
+        fetch_now!(self, UUID_BUTTON, Button(v) => v)
+    }
+
+    /// Reads the position information from the cube.
+    ///
+    /// Unlike [`Cube::position`][], this always reads a fresh value and updates the cache.
+    /// Returns `None` if the cube is not on the mat.
+    pub async fn position_now(&mut self) -> Result<Option<Position>> {
+        fetch_now!(self, UUID_ID, Position(v) => v, StdId(_) => None)
+    }
+
+    /// Reads the standard id from the cube.
+    ///
+    /// Unlike [`Cube::std_id`][], this always reads a fresh value and updates the cache.
+    /// Returns `None` if the cube is not on a card with the standard id.
+    pub async fn std_id_now(&mut self) -> Result<Option<StdId>> {
+        fetch_now!(self, UUID_ID, StdId(v) => v, Position(_) => None)
+    }
+
+    async fn read_events(&mut self, uuid: &Uuid) -> Result<Vec<Event>> {
+        let value = self.read_value(uuid).await?;
+        let events = convert(Message::try_from((*uuid, value))?).unwrap_or_default();
+        for event in &events {
+            update(&self.status, event.clone()).await;
+        }
+        Ok(events)
+    }
+
+    /// Moves the cube.
+    ///
+    /// `left` and `right` are the rotation speed of each wheel.
+    /// The value must be in the range from -100 to 100.
+    /// The negative number rotates backward, while the positive rotates forward.
+    /// If specified, the wheels rotate for the given duration. If the duration is `None`,
+    /// wheels rotate forever.
+    /// The duration must be in the range from 1 to 2559 milliseconds.
     ///
     /// ```no_run
     /// use std::time::Duration;
@@ -350,54 +1196,55 @@ impl Cube {
         right: isize,
         duration: Option<Duration>,
     ) -> Result<()> {
-        if left < -100 || left > 100 || right < -100 || right > 100 {
-            return Err(anyhow!("Wheel speed must be between -100 and 100"));
-        }
-        let adjust = |v: isize| {
-            (
-                if v > 0 {
-                    MotorDir::Forward
-                } else {
-                    MotorDir::Backward
-                },
-                (v.abs() * (115 - 7) / 100 + 7) as u8,
-            )
-        };
-        let (left_dir, left) = adjust(left);
-        let (right_dir, right) = adjust(right);
+        check_speeds(left, right, duration)?;
 
-        let motor = if let Some(d) = duration {
-            let d = d.as_millis() / 10;
-            if d > 255 {
-                return Err(anyhow!("Duration must be less than 2560 milliseconds"));
+        if let Some(ramp) = self.ramp {
+            let current = self.speed.current(self.writer.preempted());
+            for (l, r) in ramp.steps(current, (left, right)) {
+                self.writer.write_msg(motor(l, r, None), false).await?;
+                delay_for(ramp.step).await;
             }
-            let d = d as u8;
-
-            Motor::Timed(MotorTimed::new(
-                MotorId::Left,
-                left_dir,
-                left,
-                MotorId::Right,
-                right_dir,
-                right,
-                d,
-            ))
-        } else {
-            Motor::Simple(MotorSimple::new(
-                MotorId::Left,
-                left_dir,
-                left,
-                MotorId::Right,
-                right_dir,
-                right,
-            ))
+        }
+        self.writer
+            .write_msg(motor(left, right, duration), false)
+            .await?;
+        self.speed = Speed {
+            left,
+            right,
+            until: duration.map(|d| Instant::now() + d),
+            preempted: self.writer.preempted(),
         };
 
-        self.dev.write_msg(motor, false).await?;
-
         Ok(())
     }
 
+    /// Sets the acceleration limit applied to [`Cube::go`][] and [`Cube::stop`][].
+    ///
+    /// `None` disables the limit, which is the default.
+    ///
+    /// ```no_run
+    /// use toio::{drive::Ramp, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.set_ramp(Some(Ramp::default()));
+    ///
+    ///     // Speeds up gradually.
+    ///     cube.go(100, 100, None).await.unwrap();
+    /// }
+    /// ```
+    pub fn set_ramp(&mut self, ramp: Option<Ramp>) {
+        self.ramp = ramp;
+    }
+
+    /// Gets the acceleration limit.
+    pub fn ramp(&self) -> Option<Ramp> {
+        self.ramp
+    }
+
     /// Stops the cube.
     ///
     /// Both wheels stop rotating.
@@ -426,6 +1273,311 @@ impl Cube {
         Ok(())
     }
 
+    /// Stops the motor, the light and the sound at once, ahead of everything else.
+    ///
+    /// The writes waiting in the queue are cancelled, and the stop commands are written
+    /// without response right away regardless of the maximum write rate. They don't wait
+    /// for the operation in progress, e.g., a write waiting for the response, if the
+    /// backend supports [`ble::PeripheralOps::writer`][]. The target control running on
+    /// the cube is overwritten, so [`Cube::goto`][] in progress fails. The ramp set by
+    /// [`Cube::set_ramp`][] doesn't apply.
+    ///
+    /// Use [`Cube::stop_handle`][] to stop the cube while it's borrowed by another task.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.go(100, 100, None).await.unwrap();
+    ///     cube.emergency_stop().await.unwrap();
+    /// }
+    /// ```
+    pub async fn emergency_stop(&mut self) -> Result<()> {
+        self.navigation = None;
+        self.stop_handle().emergency_stop().await
+    }
+
+    /// Creates a handle to stop the cube from other tasks.
+    ///
+    /// The handle works while the cube is borrowed, e.g., by [`Cube::goto`][] or
+    /// [`Cube::play`][] in another task.
+    ///
+    /// ```no_run
+    /// use toio::{proto::MotorTarget, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let stop = cube.stop_handle();
+    ///     tokio::spawn(async move {
+    ///         tokio::signal::ctrl_c().await.unwrap();
+    ///         stop.emergency_stop().await.unwrap();
+    ///     });
+    ///
+    ///     let target = MotorTarget::builder(200, 200).build().unwrap();
+    ///     let _ = cube.goto(target).await;
+    /// }
+    /// ```
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            preemptor: self.writer.preemptor(),
+        }
+    }
+
+    /// Moves the cube to the target with the target control of the firmware.
+    ///
+    /// Waits until the cube arrives. Fails if the firmware gives up the request,
+    /// e.g., when the cube leaves the mat or another request overwrites it.
+    /// If the request is overwritten by another target request of this cube,
+    /// the error is [`Superseded`](crate::drive::Superseded).
+    ///
+    /// ```no_run
+    /// use toio::{proto::MotorTarget, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let target = MotorTarget::builder(200, 200).max_speed(80).build().unwrap();
+    ///     cube.goto(target).await.unwrap();
+    /// }
+    /// ```
+    pub async fn goto(&mut self, target: MotorTarget) -> Result<()> {
+        self.goto_and_wait(target).await?.finish().await
+    }
+
+    /// Moves the cube to the target like [`Cube::goto`][], reporting the progress.
+    ///
+    /// Returns once the request is written. The returned [`Navigation`][] streams the
+    /// position and the remaining distance until the cube arrives, and gives the result
+    /// by [`Navigation::finish`][].
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::{proto::MotorTarget, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let target = MotorTarget::builder(200, 200).build().unwrap();
+    ///     let mut nav = cube.goto_and_wait(target).await.unwrap();
+    ///     while let Some(progress) = nav.next().await {
+    ///         println!("{:.0} to go", progress.remaining);
+    ///     }
+    ///     nav.finish().await.unwrap();
+    /// }
+    /// ```
+    pub async fn goto_and_wait(&mut self, target: MotorTarget) -> Result<Navigation> {
+        let (id, x, y) = (target.id, target.x, target.y);
+        let msgs = self.hub.subscribe(Some(vec![UUID_ID, UUID_MOTOR]));
+        let registration = self.filters.register(EventFilter::new().position());
+        self.writer.write_msg(Motor::Target(target), false).await?;
+        self.speed = Speed::default();
+
+        // The firmware answers the previous request as overwritten. If it has the same id,
+        // the answer must not be taken for the one of this request.
+        let mut stale = false;
+        if let Some((prev, tx)) = self.navigation.take() {
+            stale = prev == id && !tx.is_closed();
+            let _ = tx.send(());
+        }
+        let (tx, rx) = oneshot::channel();
+        self.navigation = Some((id, tx));
+        let superseded = rx.into_stream().filter_map(|res| {
+            future::ready(res.ok().map(|()| Update::Done(Err(Superseded.into()))))
+        });
+
+        let updates = msgs.filter_map(move |msg| {
+            let _ = &registration;
+            future::ready(match msg {
+                Recv::Value(Message::Id(Id::Pos(pos))) => {
+                    let position: Position = pos.into();
+                    let keep = |v: u16, cur: u16| if v == KEEP_COORDINATE { cur } else { v };
+                    let dx = keep(x, position.x) as f32 - position.x as f32;
+                    let dy = keep(y, position.y) as f32 - position.y as f32;
+                    let remaining = (dx * dx + dy * dy).sqrt();
+                    Some(Update::Progress(Progress::new(position, remaining)))
+                }
+                Recv::Value(Message::Motor(Motor::TargetRes(res))) if res.id == id => {
+                    if stale && res.res == TargetResValue::OtherWrite {
+                        stale = false;
+                        return future::ready(None);
+                    }
+                    Some(Update::Done(match res.res {
+                        TargetResValue::Ok => Ok(()),
+                        res => Err(anyhow!("Couldn't reach the target: {:?}", res)),
+                    }))
+                }
+                _ => None,
+            })
+        });
+        Ok(Navigation::new(stream::select(superseded, updates).boxed()))
+    }
+
+    /// Visits the waypoints in order with [`Cube::goto`][].
+    ///
+    /// Repeats forever if [`PatrolOptions::looping`][] is set, which is the default.
+    /// Dropping the future cancels the patrol; the cube keeps moving to the current
+    /// waypoint, so call [`Cube::stop`][] afterward to stop it there.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use tokio::time::timeout;
+    /// use toio::{drive::PatrolOptions, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Patrol the corners for a minute.
+    ///     let corners = [(100, 100), (400, 100), (400, 400), (100, 400)];
+    ///     let options = PatrolOptions {
+    ///         pause: Duration::from_secs(1),
+    ///         ..PatrolOptions::default()
+    ///     };
+    ///     let _ = timeout(Duration::from_secs(60), cube.patrol(&corners, options)).await;
+    ///     cube.stop().await.unwrap();
+    /// }
+    /// ```
+    pub async fn patrol(&mut self, waypoints: &[(u16, u16)], options: PatrolOptions) -> Result<()> {
+        if waypoints.is_empty() {
+            return Err(anyhow!("No waypoints to patrol"));
+        }
+        let targets = waypoints
+            .iter()
+            .map(|&(x, y)| MotorTarget::builder(x, y).max_speed(options.speed).build())
+            .collect::<Result<Vec<_>>>()?;
+
+        loop {
+            for target in &targets {
+                self.goto(target.clone()).await?;
+                delay_for(options.pause).await;
+            }
+            if !options.looping {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Rotates the cube in place to the angle on the mat within ±2 degrees.
+    ///
+    /// Turns with short pulses of the motors, reading the angle after each of them,
+    /// so that the cube settles closer than with the target control of the firmware.
+    /// Fails if the cube is off the mat, or doesn't settle within 30 pulses.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Face down the mat.
+    ///     cube.align_to(90).await.unwrap();
+    /// }
+    /// ```
+    pub async fn align_to(&mut self, angle: u16) -> Result<()> {
+        if angle > 360 {
+            return Err(anyhow!("Angle must be between 0 and 360"));
+        }
+
+        for _ in 0..30 {
+            let pos = self
+                .position_now()
+                .await?
+                .ok_or_else(|| anyhow!("Cube is not on the mat"))?;
+            let error = (angle as isize - pos.angle as isize).rem_euclid(360);
+            let error = if error > 180 { error - 360 } else { error };
+            if error.abs() <= 2 {
+                return Ok(());
+            }
+
+            // The larger the error, the faster and longer the pulse.
+            let speed = if error.abs() > 30 { 30 } else { 10 } * error.signum();
+            let pulse = Duration::from_millis((error.unsigned_abs() as u64 * 5).clamp(20, 200));
+            self.go(speed, -speed, Some(pulse)).await?;
+            delay_for(pulse + Duration::from_millis(50)).await;
+        }
+
+        Err(anyhow!("Cube doesn't settle at angle {}", angle))
+    }
+
+    /// Searches for the mat after the cube lost it, e.g., picked up and put down.
+    ///
+    /// Turns in place first, and then drives in a widening spiral, until the cube reads
+    /// a position or the timeout elapses. The cube stops at the end either way.
+    /// Returns the position at once if the cube is already on the mat.
+    ///
+    /// ```no_run
+    /// use toio::{drive::ReacquireOptions, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     if cube.position().await.unwrap().is_none() {
+    ///         let pos = cube.reacquire_position(ReacquireOptions::default()).await.unwrap();
+    ///         println!("Back on the mat at {:?}", pos);
+    ///     }
+    /// }
+    /// ```
+    pub async fn reacquire_position(&mut self, options: ReacquireOptions) -> Result<Position> {
+        if !(1..=100).contains(&options.speed) {
+            return Err(anyhow!("Speed must be between 1 and 100"));
+        }
+        let mut msgs = self.subscribe_msgs(EventFilter::new().position());
+        if let Some(Some(pos)) = self.status.lock().await.position.clone() {
+            return Ok(pos);
+        }
+
+        let start = Instant::now();
+        let deadline = start + options.timeout;
+        let res = loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::from_secs(0) {
+                break Err(anyhow!("Couldn't find the mat in {:?}", options.timeout));
+            }
+
+            // The inner wheel speeds up from backward to forward over the spread.
+            let spread = if options.spread > Duration::from_secs(0) {
+                (start.elapsed().as_secs_f64() / options.spread.as_secs_f64()).min(1.0)
+            } else {
+                1.0
+            };
+            let inner = (options.speed as f64 * (2.0 * spread - 1.0)).round() as isize;
+            self.go(options.speed, inner, None).await?;
+
+            let found = timeout(left.min(REACQUIRE_STEP), async {
+                while let Some(msg) = msgs.next().await {
+                    if let Recv::Value(Message::Id(Id::Pos(pos))) = msg {
+                        return Some(pos.into());
+                    }
+                }
+                None
+            })
+            .await;
+            match found {
+                Ok(Some(pos)) => break Ok(pos),
+                Ok(None) => break Err(anyhow!("Stream ends while searching for the mat")),
+                Err(_) => {}
+            }
+        };
+        self.stop().await?;
+        res
+    }
+
     /// Plays sound preset.
     ///
     /// ```no_run
@@ -440,7 +1592,7 @@ impl Cube {
     /// }
     /// ```
     pub async fn play_preset(&mut self, id: SoundPresetId) -> Result<()> {
-        self.dev
+        self.writer
             .write_msg(Sound::Preset(SoundPreset::new(id, 255)), true)
             .await?;
         Ok(())
@@ -451,11 +1603,11 @@ impl Cube {
     /// Play sound in accordance with the list of sound operations.
     /// The number of sound operations must be less than 60.
     /// The duration for each sound must be in the range from 1 to 2559 milliseconds.
-    /// The repeat count must be less than 256.
+    /// The repeat count must be from 1 to 255, or [`Repeat::Forever`][].
     ///
     /// ```no_run
     /// use std::time::Duration;
-    /// use toio::{Cube, SoundOp, Note};
+    /// use toio::{Cube, Note, Repeat, SoundOp};
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -464,7 +1616,7 @@ impl Cube {
     ///
     ///     cube.play(
     ///         // Repeats three times.
-    ///         3,
+    ///         Repeat::Times(3),
     ///         // Plays two sound for 500 milliseconds for each.
     ///         vec![
     ///             SoundOp::new(Note::C5, Duration::from_millis(500)),
@@ -473,13 +1625,11 @@ impl Cube {
     ///     ).await.unwrap();
     /// }
     /// ```
-    pub async fn play(&mut self, repeat: usize, ops: Vec<SoundOp>) -> Result<()> {
+    pub async fn play(&mut self, repeat: Repeat, ops: Vec<SoundOp>) -> Result<()> {
         if ops.len() == 0 || ops.len() >= 60 {
             return Err(anyhow!("The number of operations must be from 1 to 59"));
         }
-        if repeat > 255 {
-            return Err(anyhow!("The repeat count must be less than 256"));
-        }
+        let repeat = repeat.count()?;
 
         let ops: Result<Vec<_>> = ops
             .iter()
@@ -495,9 +1645,9 @@ impl Cube {
             .collect();
         let ops = ops?;
 
-        self.dev
+        self.writer
             .write_msg(
-                Sound::Play(SoundPlay::new(repeat as u8, ops.len() as u8, ops)),
+                Sound::Play(SoundPlay::new(repeat, ops.len() as u8, ops)),
                 true,
             )
             .await?;
@@ -510,7 +1660,7 @@ impl Cube {
     /// ```no_run
     /// use std::time::Duration;
     /// use tokio::time::delay_for;
-    /// use toio::{Cube, SoundOp, Note};
+    /// use toio::{Cube, Note, Repeat, SoundOp};
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -518,7 +1668,7 @@ impl Cube {
     ///     cube.connect().await.unwrap();
     ///
     ///     // Starts playing sound.
-    ///     cube.play(1, vec![SoundOp::new(Note::C5, Duration::from_secs(2))]).await.unwrap();
+    ///     cube.play(Repeat::Times(1), vec![SoundOp::new(Note::C5, Duration::from_secs(2))]).await.unwrap();
     ///
     ///     delay_for(Duration::from_secs(1)).await;
     ///
@@ -527,7 +1677,7 @@ impl Cube {
     /// }
     /// ```
     pub async fn stop_sound(&mut self) -> Result<()> {
-        self.dev.write_msg(proto::Sound::Stop, true).await?;
+        self.writer.write_msg(proto::Sound::Stop, true).await?;
         Ok(())
     }
 
@@ -535,12 +1685,12 @@ impl Cube {
     ///
     /// The light color is set by RGB value, each of which must be in range 0 to 255.
     /// The number of light operations must be less than 30.
-    /// The repeat count must be less than 256.
+    /// The repeat count must be from 1 to 255, or [`Repeat::Forever`][].
     /// The duration of each light operation must be less than 2560 milliseconds.
     ///
     /// ```no_run
     /// use std::time::Duration;
-    /// use toio::{Cube, LightOp};
+    /// use toio::{Cube, LightOp, Repeat};
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -549,7 +1699,7 @@ impl Cube {
     ///
     ///     cube.light(
     ///         // Repeats 10 times.
-    ///         10,
+    ///         Repeat::Times(10),
     ///         // Turns on the red, green, blue light for 100 milliseconds for each.
     ///         vec![
     ///             LightOp::new(255, 0, 0, Some(Duration::from_millis(100))),
@@ -559,13 +1709,11 @@ impl Cube {
     ///     ).await.unwrap();
     /// }
     /// ```
-    pub async fn light(&mut self, repeat: usize, ops: Vec<LightOp>) -> Result<()> {
+    pub async fn light(&mut self, repeat: Repeat, ops: Vec<LightOp>) -> Result<()> {
         if ops.len() == 0 || ops.len() >= 30 {
             return Err(anyhow!("The number of operations must be from 1 to 29"));
         }
-        if repeat > 255 {
-            return Err(anyhow!("The repeat count must be less than 256"));
-        }
+        let repeat = repeat.count()?;
 
         let ops: Result<Vec<_>> = ops
             .iter()
@@ -585,16 +1733,60 @@ impl Cube {
             .collect();
         let ops = ops?;
 
-        self.dev
-            .write_msg(
-                Light::Ctrl(LightCtrl::new(repeat as u8, ops.len() as u8, ops)),
-                true,
-            )
+        self.write_light(Light::Ctrl(LightCtrl::new(repeat, ops.len() as u8, ops)))
             .await?;
 
         Ok(())
     }
 
+    /// Plays light animation.
+    ///
+    /// If the animation fits in a single light control message, the repeat is done by the cube.
+    /// Otherwise, the messages are written one after another until the animation completes.
+    /// The repeat count must be from 1 to 255, or [`Repeat::Forever`][].
+    /// To stop the animation repeating forever, drop the future.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::{light::Animation, Cube, Repeat};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = toio::Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Breathes white light for a minute.
+    ///     let anim = Animation::new()
+    ///         .keyframe(Duration::from_secs(0), 0, 0, 0)
+    ///         .keyframe(Duration::from_secs(2), 255, 255, 255)
+    ///         .keyframe(Duration::from_secs(4), 0, 0, 0);
+    ///
+    ///     cube.animate(&anim, Repeat::Times(15)).await.unwrap();
+    /// }
+    /// ```
+    pub async fn animate(&mut self, anim: &Animation, repeat: Repeat) -> Result<()> {
+        let repeat = repeat.count()?;
+        let mut ctrls = anim.compile()?;
+
+        if ctrls.len() == 1 {
+            let mut ctrl = ctrls.remove(0);
+            ctrl.repeat = repeat;
+            self.write_light(Light::Ctrl(ctrl)).await?;
+            return Ok(());
+        }
+
+        let mut count = 0;
+        while repeat == 0 || count < repeat {
+            for ctrl in &ctrls {
+                self.write_light(Light::Ctrl(ctrl.clone())).await?;
+                delay_for(light::ctrl_duration(ctrl)).await;
+            }
+            count += 1;
+        }
+
+        Ok(())
+    }
+
     /// Turns on the light.
     ///
     /// The light color is set by RGB value, each of which must be in range 0 to 255.
@@ -621,11 +1813,7 @@ impl Cube {
     ) -> Result<()> {
         let duration = duration.as_ref().map(|d| d.as_millis() / 10).unwrap_or(0);
 
-        self.dev
-            .write_msg(
-                Light::On(LightOn::new(duration as u8, red, green, blue)),
-                true,
-            )
+        self.write_light(Light::On(LightOn::new(duration as u8, red, green, blue)))
             .await?;
 
         Ok(())
@@ -653,79 +1841,914 @@ impl Cube {
     /// }
     /// ```
     pub async fn light_off(&mut self) -> Result<()> {
-        self.dev
+        self.writer
             .write_msg(Light::Off(LightOff::new()), true)
             .await?;
         Ok(())
     }
 
-    /// Connects to the cube.
+    /// Sets the color correction of the light.
     ///
-    /// This must be called first before operating on the cube.
+    /// The correction is applied to all the light operations of the high-level API
+    /// ([`Cube::light`][], [`Cube::light_on`][] and [`Cube::animate`][]).
+    /// Raw messages written by [`Cube::write_msg`][] are not corrected.
     ///
     /// ```no_run
-    /// use toio::Cube;
+    /// use toio::{light::Correction, Cube};
     ///
     /// #[tokio::main]
     /// async fn main() {
-    ///     let mut cube = Cube::search().nearest().await.unwrap();
-    ///
-    ///     // Connects to the cube.
+    ///     let mut cube = toio::Cube::search().nearest().await.unwrap();
     ///     cube.connect().await.unwrap();
+    ///
+    ///     // Applies gamma 2.2 and halves the brightness.
+    ///     cube.set_light_correction(Correction::new(2.2, 0.5));
+    ///
+    ///     cube.light_on(255, 128, 0, None).await.unwrap();
     /// }
     /// ```
-    pub async fn connect(&mut self) -> Result<()> {
-        let status = self.status.clone();
-        let mut rx = self.events().await?;
-        let (forward, handle) = abortable(async move {
-            while let Some(event) = rx.next().await {
-                update(&status, event).await
-            }
-        });
-        tokio::spawn(forward);
-        self.handle = Some(handle);
+    pub fn set_light_correction(&mut self, correction: Correction) {
+        self.correction = correction;
+    }
 
-        self.dev.connect().await?;
+    /// Gets the color correction of the light.
+    pub fn light_correction(&self) -> Correction {
+        self.correction
+    }
 
+    async fn write_light(&mut self, mut light: Light) -> Result<()> {
+        self.correct(&mut light);
+        self.writer.write_msg(light, true).await?;
         Ok(())
     }
 
-    /// Subscribes to events.
+    /// Applies the color correction to the light message.
+    fn correct(&self, light: &mut Light) {
+        match light {
+            Light::On(op) => self.correction.apply_op(op),
+            Light::Ctrl(ctrl) => ctrl
+                .ops
+                .iter_mut()
+                .for_each(|op| self.correction.apply_op(op)),
+            _ => {}
+        }
+    }
+
+    /// Starts a sequence of commands written back-to-back by a single await.
+    ///
+    /// Useful for cues combining the light, the sound and the motor, which should start
+    /// together. See [`Sequence`][] for the details.
     ///
     /// ```no_run
-    /// use futures::prelude::*;
-    /// use toio::{Cube, Event};
+    /// use std::time::Duration;
+    /// use toio::{Cube, SoundPresetId};
     ///
     /// #[tokio::main]
     /// async fn main() {
     ///     let mut cube = Cube::search().nearest().await.unwrap();
     ///     cube.connect().await.unwrap();
     ///
+    ///     // Flashes red, beeps and spins at once.
+    ///     cube.sequence()
+    ///         .light_on(255, 0, 0, Some(Duration::from_millis(500)))
+    ///         .play_preset(SoundPresetId::Cancel)
+    ///         .go(50, -50, Some(Duration::from_millis(500)))
+    ///         .send()
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn sequence(&mut self) -> Sequence<'_> {
+        Sequence {
+            cube: self,
+            msgs: vec![],
+            speed: None,
+            error: None,
+        }
+    }
+
+    /// Connects to the cube.
+    ///
+    /// This must be called first before operating on the cube.
+    /// Failed attempts are retried with the backoff set by [`Searcher::connect_retry`][].
+    ///
+    /// This completes as soon as the characteristics are discovered. No value is read
+    /// on connection; getters like [`Cube::battery`][] read the value on the first call.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///
+    ///     // Connects to the cube.
+    ///     cube.connect().await.unwrap();
+    /// }
+    /// ```
+    pub async fn connect(&mut self) -> Result<()> {
+        self.hub.start(&mut *self.dev.lock().await)?;
+        self.stop_tasks();
+
+        let status = self.status.clone();
+        let estimator = self.estimator.clone();
+        let registration = self.filters.register(EventFilter::all());
+        let mut rx = self.subscribe_events(Arc::new(EventFilter::all()));
+        self.spawn(async move {
+            let _registration = registration;
+            while let Some(event) = rx.next().await {
+                if let Event::Battery(v) = &event {
+                    estimator
+                        .lock()
+                        .unwrap()
+                        .push(Instant::now().into_std(), *v);
+                }
+                update(&status, event).await
+            }
+        });
+
+        let callbacks = self.callbacks.clone();
+        let mut rx = self.subscribe_events(Arc::new(EventFilter::all()));
+        self.spawn(async move {
+            while let Some(event) = rx.next().await {
+                callback::dispatch(&callbacks, &event);
+            }
+        });
+
+        if let Some(threshold) = self.battery_low {
+            let msgs = self.subscribe_msgs(EventFilter::new().battery());
+            self.spawn(monitor::battery_low(msgs, self.notices.clone(), threshold));
+        }
+
+        let msgs = self.subscribe_msgs(EventFilter::new().position());
+        self.spawn(monitor::track(msgs, self.tracker.clone()));
+
+        self.connect_with_retry().await?;
+
+        self.filters.start().await;
+        self.filters.apply().await?;
+        self.spawn(self.filters.clone().run());
+
+        self.writer.attach().await;
+
+        let battery = self.profile.resolve(&UUID_BATTERY);
+        if let Some(interval) = self.battery_interval {
+            self.spawn(monitor::poll_battery(self.dev.clone(), battery, interval));
+        }
+
+        if self.detect_gaps {
+            let msgs = self.subscribe_msgs(EventFilter::new().position());
+            self.spawn(monitor::detect_gaps(msgs, self.notices.clone()));
+        }
+
+        if let Some(idle) = self.watchdog {
+            let msgs = self.hub.subscribe(None);
+            self.spawn(monitor::watchdog(
+                self.dev.clone(),
+                battery,
+                idle,
+                self.read_timeout,
+                msgs,
+                self.notices.clone(),
+            ));
+        }
+
+        if let Some(interval) = self.rssi_interval {
+            let tx = self.rssi_tx.clone();
+            self.spawn(monitor::poll_rssi(self.dev.clone(), tx, interval));
+        }
+
+        Ok(())
+    }
+
+    /// Polls the motion sensor at the interval.
+    ///
+    /// The cube notifies the motion sensor only when the state changes, so this helps
+    /// to track the state steadily. The states are delivered to [`Cube::events`][] as notified.
+    /// The state is requested on firmware 2.1.0 or later, and read on older firmware.
+    ///
+    /// The polling runs in the background until the cube is dropped or connected again.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.poll_motion(Duration::from_millis(500)).await.unwrap();
+    /// }
+    /// ```
+    pub async fn poll_motion(&mut self, interval: Duration) -> Result<()> {
+        let request = firmware_at_least(&self.version().await?, [2, 1, 0]);
+        let dev = self.dev.clone();
+        let writer = self.writer.clone();
+        let uuid = self.profile.resolve(&UUID_MOTION);
+        self.spawn(async move {
+            loop {
+                delay_for(interval).await;
+                let res = if request {
+                    // Goes through the pipeline, following the rate and the preemption.
+                    writer.write_msg(Motion::RequestDetect, false).await
+                } else {
+                    dev.lock().await.read(&uuid).await
+                };
+                if let Err(e) = res {
+                    debug!("Couldn't poll motion: {}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Pairs with the connected cube, so that the platform remembers it.
+    ///
+    /// The paired cube can be found quickly by [`Searcher::bonded`][] next time.
+    /// Some platforms pair on demand, where this does nothing.
+    pub async fn pair(&mut self) -> Result<()> {
+        self.dev.lock().await.pair().await
+    }
+
+    /// Removes the pairing with the cube.
+    pub async fn unpair(&mut self) -> Result<()> {
+        self.dev.lock().await.unpair().await
+    }
+
+    async fn connect_with_retry(&mut self) -> Result<()> {
+        let mut delays = self.connect_retry.delays();
+        loop {
+            let res = self.dev.lock().await.connect().await;
+            match res {
+                Ok(()) => {
+                    metrics::connection(self.id());
+                    return Ok(());
+                }
+                Err(e) => {
+                    metrics::connection_failure(self.id());
+                    match delays.next() {
+                        Some(d) => {
+                            warn!(
+                                "Couldn't connect to {}, retrying in {:?}: {}",
+                                self.id(),
+                                d,
+                                e
+                            );
+                            delay_for(d).await;
+                        }
+                        None => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Spawns a background task which stops when the cube is dropped.
+    fn spawn<F>(&mut self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (task, handle) = abortable(task);
+        tokio::spawn(task);
+        self.tasks.push(handle);
+    }
+
+    fn stop_tasks(&mut self) {
+        for handle in self.tasks.drain(..) {
+            handle.abort();
+        }
+    }
+
+    /// Registers the callback called on the events passing the filter.
+    ///
+    /// The callbacks are called one by one on a task of the cube, in the order of the
+    /// events. Keep them short, and spawn a task for long work; otherwise the following
+    /// events are delayed. The callbacks are kept over reconnection, and keep the
+    /// notifications required by the filter enabled while registered.
+    ///
+    /// ```no_run
+    /// use toio::{Cube, Event, EventFilter};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let handle = cube.on_event(EventFilter::new().slope().collision(), |event| {
+    ///         println!("{:?}", event);
+    ///     });
+    ///
+    ///     cube.go(50, 50, None).await.unwrap();
+    ///     cube.until_collision().await.unwrap();
+    ///     handle.unregister();
+    /// }
+    /// ```
+    pub fn on_event<F>(&mut self, filter: EventFilter, f: F) -> CallbackHandle
+    where
+        F: FnMut(&Event) + Send + 'static,
+    {
+        let registration = self.filters.register(filter.clone());
+        callback::register(&self.callbacks, filter, registration, f)
+    }
+
+    /// Registers the callback called with the collision state. See [`Cube::on_event`][].
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.on_collision(|collided| {
+    ///         if collided {
+    ///             println!("Ouch");
+    ///         }
+    ///     });
+    /// }
+    /// ```
+    pub fn on_collision<F>(&mut self, mut f: F) -> CallbackHandle
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        self.on_event(EventFilter::new().collision(), move |event| {
+            if let Event::Collision(v) = event {
+                f(*v)
+            }
+        })
+    }
+
+    /// Registers the callback called with the button state. See [`Cube::on_event`][].
+    pub fn on_button<F>(&mut self, mut f: F) -> CallbackHandle
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        self.on_event(EventFilter::new().button(), move |event| {
+            if let Event::Button(v) = event {
+                f(*v)
+            }
+        })
+    }
+
+    /// Registers the callback called with the slope state. See [`Cube::on_event`][].
+    pub fn on_slope<F>(&mut self, mut f: F) -> CallbackHandle
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        self.on_event(EventFilter::new().slope(), move |event| {
+            if let Event::Slope(v) = event {
+                f(*v)
+            }
+        })
+    }
+
+    /// Registers the callback called with the posture. See [`Cube::on_event`][].
+    pub fn on_posture<F>(&mut self, mut f: F) -> CallbackHandle
+    where
+        F: FnMut(Posture) + Send + 'static,
+    {
+        self.on_event(EventFilter::new().posture(), move |event| {
+            if let Event::Posture(v) = event {
+                f(*v)
+            }
+        })
+    }
+
+    /// Registers the callback called with the position, or `None` off the mat.
+    /// See [`Cube::on_event`][].
+    pub fn on_position<F>(&mut self, mut f: F) -> CallbackHandle
+    where
+        F: FnMut(Option<Position>) + Send + 'static,
+    {
+        self.on_event(EventFilter::new().position(), move |event| {
+            if let Event::Position(v) = event {
+                f(v.clone())
+            }
+        })
+    }
+
+    /// Registers the callback called with the battery percentage. See [`Cube::on_event`][].
+    pub fn on_battery<F>(&mut self, mut f: F) -> CallbackHandle
+    where
+        F: FnMut(usize) + Send + 'static,
+    {
+        self.on_event(EventFilter::new().battery(), move |event| {
+            if let Event::Battery(v) = event {
+                f(*v)
+            }
+        })
+    }
+
+    /// Subscribes to events.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::{Cube, Event};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mut events = cube.events().await.unwrap();
+    ///     while let Some(event) = events.next().await {
+    ///         match event {
+    ///             Event::Collision(collided) => println!("collided: {}", collided),
+    ///             Event::Battery(remain) => println!("battery: {}%", remain),
+    ///             _ => {},
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn events(&mut self) -> Result<EventStream> {
+        self.events_filtered(EventFilter::all()).await
+    }
+
+    /// Subscribes to the sensors as separate typed streams.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let subs = cube.subscriptions().await.unwrap();
+    ///     let mut buttons = subs.buttons;
+    ///     tokio::spawn(async move {
+    ///         while let Some(pressed) = buttons.next().await {
+    ///             println!("pressed: {}", pressed);
+    ///         }
+    ///     });
+    ///
+    ///     let mut positions = subs.positions;
+    ///     while let Some(pos) = positions.next().await {
+    ///         println!("{:?}", pos);
+    ///     }
+    /// }
+    /// ```
+    pub async fn subscriptions(&mut self) -> Result<Subscriptions> {
+        fn typed<T: Send + 'static>(
+            events: EventStream,
+            f: fn(Event) -> Option<T>,
+        ) -> BoxStream<'static, T> {
+            events.filter_map(move |e| future::ready(f(e))).boxed()
+        }
+
+        Ok(Subscriptions {
+            positions: typed(
+                self.events_filtered(EventFilter::new().position()).await?,
+                |e| match e {
+                    Event::Position(v) => Some(v),
+                    _ => None,
+                },
+            ),
+            std_ids: typed(
+                self.events_filtered(EventFilter::new().std_id()).await?,
+                |e| match e {
+                    Event::StdId(v) => Some(v),
+                    _ => None,
+                },
+            ),
+            buttons: typed(
+                self.events_filtered(EventFilter::new().button()).await?,
+                |e| match e {
+                    Event::Button(v) => Some(v),
+                    _ => None,
+                },
+            ),
+            battery: typed(
+                self.events_filtered(EventFilter::new().battery()).await?,
+                |e| match e {
+                    Event::Battery(v) => Some(v),
+                    _ => None,
+                },
+            ),
+            collisions: typed(
+                self.events_filtered(EventFilter::new().collision()).await?,
+                |e| match e {
+                    Event::Collision(v) => Some(v),
+                    _ => None,
+                },
+            ),
+            slopes: typed(
+                self.events_filtered(EventFilter::new().slope()).await?,
+                |e| match e {
+                    Event::Slope(v) => Some(v),
+                    _ => None,
+                },
+            ),
+            postures: typed(
+                self.events_filtered(EventFilter::new().posture()).await?,
+                |e| match e {
+                    Event::Posture(v) => Some(v),
+                    _ => None,
+                },
+            ),
+            motions: typed(
+                self.events_filtered(EventFilter::new().motion()).await?,
+                |e| match e {
+                    Event::Motion(v) => Some(v),
+                    _ => None,
+                },
+            ),
+        })
+    }
+
+    /// Subscribes to the selected events.
+    ///
+    /// Only the messages of the characteristics required by the filter are delivered.
+    /// Their notifications are kept enabled while the stream is alive. Notifications
+    /// required by none of the live streams and the cube itself are disabled, and enabled
+    /// again when a stream requires them.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::{Cube, Event, EventFilter};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mut events = cube
+    ///         .events_filtered(EventFilter::new().collision().button())
+    ///         .await
+    ///         .unwrap();
+    ///     while let Some(event) = events.next().await {
+    ///         match event {
+    ///             Event::Collision(collided) => println!("collided: {}", collided),
+    ///             Event::Button(pressed) => println!("pressed: {}", pressed),
+    ///             _ => unreachable!(),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn events_filtered(&mut self, filter: EventFilter) -> Result<EventStream> {
+        let registration = self.filters.register(filter.clone());
+        self.filters.apply().await?;
+        Ok(self
+            .subscribe_events(Arc::new(filter))
+            .map(move |event| {
+                let _ = &registration;
+                event
+            })
+            .boxed())
+    }
+
+    /// Subscribes to the messages of the characteristics required by the filter,
+    /// keeping their notifications enabled while the stream is alive.
+    fn subscribe_msgs(&self, filter: EventFilter) -> BoxStream<'static, Recv<Message>> {
+        let msgs = self.hub.subscribe(Some(filter.uuids()));
+        let registration = self.filters.register(filter);
+        msgs.map(move |msg| {
+            let _ = &registration;
+            msg
+        })
+        .boxed()
+    }
+
+    /// Subscribes to the selected events without changing the notifications.
+    fn subscribe_events(&self, filter: Arc<EventFilter>) -> EventStream {
+        let events = self
+            .hub
+            .subscribe(Some(filter.uuids()))
+            .filter_map(|msg| async move {
+                match msg {
+                    Recv::Value(msg) => convert(msg).map(stream::iter),
+                    Recv::Lagged(n) => Some(stream::iter(vec![Event::Lagged(n)])),
+                }
+            })
+            .flatten();
+        let notices = self.notices.subscribe().into_stream().filter_map(|event| {
+            future::ready(match event {
+                Ok(event) => Some(event),
+                Err(RecvError::Lagged(n)) => Some(Event::Lagged(n as usize)),
+                Err(RecvError::Closed) => None,
+            })
+        });
+        let events = stream::select(events, notices)
+            .filter(move |event| future::ready(filter.matches(event)));
+
+        if self.dedup {
+            changes(events).boxed()
+        } else {
+            events.boxed()
+        }
+    }
+
+    /// Enables/disables notifications from a characteristic.
+    ///
+    /// This unsubscribes from the characteristic at the BLE level, which saves
+    /// the battery and the bandwidth when the values are not needed.
+    /// The setting overrides the event filters, and is kept over reconnection
+    /// until it's set again.
+    ///
+    /// ```no_run
+    /// use toio::{Characteristic, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Stops position updates.
+    ///     cube.set_notifications(Characteristic::Id, false).await.unwrap();
+    /// }
+    /// ```
+    pub async fn set_notifications(&mut self, ch: Characteristic, enable: bool) -> Result<()> {
+        self.filters.set(ch.uuid(), enable).await
+    }
+
+    /// Enables/disables the magnetic sensor, selecting what to detect.
+    ///
+    /// Requires firmware 2.2.0 or later, and 2.3.0 or later for [`MagnetMode::Force`][].
+    /// The sensor notifies at most every 20 milliseconds when the value changes.
+    /// Fails if the cube doesn't accept the setting.
+    ///
+    /// ```no_run
+    /// use toio::{proto::MagnetMode, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.set_magnet_mode(MagnetMode::State).await.unwrap();
+    /// }
+    /// ```
+    pub async fn set_magnet_mode(&mut self, mode: MagnetMode) -> Result<()> {
+        let config = ConfigMagnet::new(mode, 1, NotifyCondition::OnChange);
+        self.configure(Config::Magnet(config), |res| match res {
+            Config::MagnetRes(res) => Some(res.res),
+            _ => None,
+        })
+        .await
+        .context("Couldn't set the magnet mode")
+    }
+
+    /// Sets how often the cube notifies the position and the standard id.
+    ///
+    /// Lower rates save the bandwidth when the application doesn't need every position.
+    /// Requires firmware 2.1.0 or later.
+    ///
+    /// ```no_run
+    /// use toio::{Cube, PositionRate};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.set_position_rate(PositionRate::Hz(5)).await.unwrap();
+    /// }
+    /// ```
+    pub async fn set_position_rate(&mut self, rate: PositionRate) -> Result<()> {
+        let config = rate.config()?;
+        self.configure(Config::IdNotify(config), |res| match res {
+            Config::IdNotifyRes(res) => Some(res.res),
+            _ => None,
+        })
+        .await
+        .context("Couldn't set the position rate")
+    }
+
+    /// Sets how long the cube waits to notify that it left the mat or the card.
+    ///
+    /// If the cube reads an id again within the delay, [`Event::Position`][] with `None` isn't
+    /// sent, so that brief dropouts while moving fast keep the position. The delay is rounded
+    /// to 10 milliseconds, up to 2550 milliseconds. Requires firmware 2.1.0 or later.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.set_position_missed_delay(Duration::from_millis(200)).await.unwrap();
+    /// }
+    /// ```
+    pub async fn set_position_missed_delay(&mut self, delay: Duration) -> Result<()> {
+        if delay > Duration::from_millis(2550) {
+            return Err(anyhow!("The delay must be 2550 milliseconds or less"));
+        }
+        let config = ConfigIdMissed::new((delay.as_millis() / 10) as u8);
+        self.configure(Config::IdMissed(config), |res| match res {
+            Config::IdMissedRes(res) => Some(res.res),
+            _ => None,
+        })
+        .await
+        .context("Couldn't set the position missed delay")
+    }
+
+    /// Sets how often the cube notifies the attitude, i.e., the angles of the posture.
+    ///
+    /// The interval is rounded to 10 milliseconds, up to 2550 milliseconds.
+    /// Short intervals help motion control, and `None` stops the notifications
+    /// to save the battery. Requires firmware 2.2.0 or later.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::{proto::{AttitudeFormat, NotifyCondition}, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Every 10 milliseconds while moving.
+    ///     cube.set_attitude_notify(
+    ///         AttitudeFormat::Euler,
+    ///         Some(Duration::from_millis(10)),
+    ///         NotifyCondition::OnChange,
+    ///     ).await.unwrap();
+    /// }
+    /// ```
+    pub async fn set_attitude_notify(
+        &mut self,
+        format: AttitudeFormat,
+        interval: Option<Duration>,
+        condition: NotifyCondition,
+    ) -> Result<()> {
+        let interval = match interval {
+            Some(interval) if interval > Duration::from_millis(2550) => {
+                return Err(anyhow!("The interval must be 2550 milliseconds or less"));
+            }
+            Some(interval) => (interval.as_millis() / 10).max(1) as u8,
+            None => 0,
+        };
+        let config = ConfigAttitude::new(format, interval, condition);
+        self.configure(Config::Attitude(config), |res| match res {
+            Config::AttitudeRes(res) => Some(res.res),
+            _ => None,
+        })
+        .await
+        .context("Couldn't set the attitude notifications")
+    }
+
+    /// Writes the configuration and waits for the response picked by `res`.
+    async fn configure<F>(&mut self, config: Config, res: F) -> Result<()>
+    where
+        F: Fn(&Config) -> Option<ConfigResValue>,
+    {
+        let mut msgs = self.hub.subscribe(Some(vec![UUID_CONFIG]));
+        self.writer.write_msg(config, true).await?;
+
+        let res = timeout(self.read_timeout, async move {
+            while let Some(msg) = msgs.next().await {
+                if let Recv::Value(Message::Config(config)) = msg {
+                    if let Some(res) = res(&config) {
+                        return Ok(res);
+                    }
+                }
+            }
+            Err(anyhow!("Stream ends while waiting for the response"))
+        })
+        .await
+        .context("No response from the cube")??;
+
+        match res {
+            ConfigResValue::Ok => Ok(()),
+            res => Err(anyhow!("The cube rejected the configuration: {:?}", res)),
+        }
+    }
+
+    /// Shares the position with the other cubes in the group to emit [`Event::Proximity`][].
+    ///
+    /// The cube leaves the group when it's dropped.
+    pub fn join_proximity(&mut self, proximity: &Proximity) {
+        let member = proximity.join(self.id(), self.notices.clone());
+        let mut msgs = self.subscribe_msgs(EventFilter::new().proximity());
+        self.spawn(async move {
+            while let Some(msg) = msgs.next().await {
+                match msg {
+                    Recv::Value(Message::Id(Id::Pos(pos))) => member.update(Some(pos.into())),
+                    Recv::Value(Message::Id(Id::PosMissed)) => member.update(None),
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    /// Waits until the cube collides with an object.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Moves forward until collision.
+    ///     cube.go(30, 30, None).await.unwrap();
+    ///     cube.until_collision().await.unwrap();
+    ///     cube.stop().await.unwrap();
+    /// }
+    /// ```
+    pub async fn until_collision(&mut self) -> Result<()> {
+        self.until(EventFilter::new().collision(), |e| match e {
+            Event::Collision(true) => Some(()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Waits until the button is pressed.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Starts moving after the button is pressed.
+    ///     cube.until_button_pressed().await.unwrap();
+    ///     cube.go(30, 30, None).await.unwrap();
+    /// }
+    /// ```
+    pub async fn until_button_pressed(&mut self) -> Result<()> {
+        self.until(EventFilter::new().button(), |e| match e {
+            Event::Button(true) => Some(()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Waits until the cube is put on the mat.
+    ///
+    /// Returns the first position read on the mat.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let pos = cube.until_on_mat().await.unwrap();
+    ///     println!("on mat at ({}, {})", pos.x, pos.y);
+    /// }
+    /// ```
+    pub async fn until_on_mat(&mut self) -> Result<Position> {
+        self.until(EventFilter::new().position(), |e| match e {
+            Event::Position(p) => p,
+            _ => None,
+        })
+        .await
+    }
+
+    async fn until<F, T>(&mut self, filter: EventFilter, f: F) -> Result<T>
+    where
+        F: Fn(Event) -> Option<T>,
+    {
+        // Doesn't change the notifications, which the other subscribers may require.
+        let mut events = self.subscribe_events(Arc::new(filter));
+        while let Some(event) = events.next().await {
+            if let Some(v) = f(event) {
+                return Ok(v);
+            }
+        }
+        Err(anyhow!("Stream ends while waiting for event"))
+    }
+
+    /// Makes event streams yield events only on change.
+    ///
+    /// The sensors repeatedly notify the same values (e.g. `Event::Slope(false)`).
+    /// If enabled, the streams returned by [`Cube::events`][] afterward
+    /// skip an event which has the same value as the previous one of the same kind.
+    /// Disabled by default.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     cube.dedup_events(true);
+    ///
+    ///     // Prints only transitions.
     ///     let mut events = cube.events().await.unwrap();
     ///     while let Some(event) = events.next().await {
-    ///         match event {
-    ///             Event::Collision(collided) => println!("collided: {}", collided),
-    ///             Event::Battery(remain) => println!("battery: {}%", remain),
-    ///             _ => {},
-    ///         }
+    ///         println!("{:?}", event);
     ///     }
     /// }
     /// ```
-    pub async fn events(&mut self) -> Result<EventStream> {
-        let rx = self.dev.subscribe_msg()?;
-
-        Ok(rx
-            .filter_map(move |event| async move {
-                match event {
-                    Ok(msg) => convert(msg).map(|v| stream::iter(v)),
-                    Err(e) => {
-                        warn!("Error on handling events: {}", e);
-                        None
-                    }
-                }
-            })
-            .flatten()
-            .boxed())
+    pub fn dedup_events(&mut self, enable: bool) {
+        self.dedup = enable;
     }
 
     /// Writes a raw message to the device.
@@ -757,7 +2780,7 @@ impl Cube {
     /// }
     /// ```
     pub async fn write_msg(&mut self, msg: Message, with_resp: bool) -> Result<()> {
-        self.dev.write_msg(msg, with_resp).await?;
+        self.writer.write_msg(msg, with_resp).await?;
         Ok(())
     }
 
@@ -794,10 +2817,61 @@ impl Cube {
     /// }
     /// ```
     pub async fn read_msg(&mut self, uuid: &Uuid) -> Result<()> {
-        self.dev.read(uuid).await?;
+        self.dev
+            .lock()
+            .await
+            .read(&self.profile.resolve(uuid))
+            .await?;
         Ok(())
     }
 
+    /// Sends a read request and waits for the value.
+    ///
+    /// This is the low-level API to read raw bytes from a characteristic.
+    /// Values of the other characteristics are ignored, and it fails unless the value
+    /// arrives within [`Options::read_timeout`][].
+    ///
+    /// ```no_run
+    /// use toio::{Cube, proto::*};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let value = cube.read_value(&UUID_BATTERY).await.unwrap();
+    ///     println!("{:?}", value);
+    /// }
+    /// ```
+    pub async fn read_value(&mut self, uuid: &Uuid) -> Result<Bytes> {
+        let uuid = self.profile.resolve(uuid);
+        self.dev
+            .lock()
+            .await
+            .read_value(&uuid, self.read_timeout)
+            .await
+    }
+
+    /// Sends a read request and waits for the message.
+    ///
+    /// The characteristic to read is determined by the message type.
+    ///
+    /// ```no_run
+    /// use toio::{Cube, proto::*};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let button: Button = cube.read_parsed().await.unwrap();
+    ///     println!("{:?}", button);
+    /// }
+    /// ```
+    pub async fn read_parsed<T: TypedMessage>(&mut self) -> Result<T> {
+        T::try_from(self.read_value(&T::UUID).await?)
+    }
+
     /// Subscribe to raw messages.
     ///
     /// This is the low-level API to subscribe to raw protocol messages from the cube device.
@@ -825,19 +2899,118 @@ impl Cube {
     /// }
     /// ```
     pub async fn raw_msgs(&mut self) -> Result<MessageStream> {
+        Ok(self
+            .hub
+            .subscribe(None)
+            .filter_map(|msg| async move {
+                match msg {
+                    Recv::Value(msg) => Some(msg),
+                    Recv::Lagged(n) => {
+                        warn!("Dropped {} messages as the subscriber fell behind", n);
+                        None
+                    }
+                }
+            })
+            .boxed())
+    }
+
+    /// Subscribe to raw messages, telling the messages dropped as the subscriber fell behind.
+    ///
+    /// Same as [`Cube::raw_msgs`][], except that the drops are yielded as [`ble::Lagged`][]
+    /// errors instead of only logged.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::{ble::Lagged, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mut msgs = cube.try_raw_msgs().await.unwrap();
+    ///     while let Some(msg) = msgs.next().await {
+    ///         match msg {
+    ///             Ok(msg) => println!("{:?}", msg),
+    ///             Err(e) => match e.downcast_ref::<Lagged>() {
+    ///                 Some(Lagged(n)) => println!("Missed {} messages", n),
+    ///                 None => println!("Error: {}", e),
+    ///             },
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn try_raw_msgs(&mut self) -> Result<ble::MessageStream<Message>> {
+        Ok(self
+            .hub
+            .subscribe(None)
+            .map(|msg| match msg {
+                Recv::Value(msg) => Ok(msg),
+                Recv::Lagged(n) => Err(ble::Lagged(n).into()),
+            })
+            .boxed())
+    }
+
+    /// Subscribe to raw values of a characteristic.
+    ///
+    /// This is the low-level API to receive bytes from any characteristic,
+    /// including the ones not defined in [`proto`][], without decoding them.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use toio::{Cube, proto::*};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mut values = cube.raw_values(&UUID_MOTION).await.unwrap();
+    ///
+    ///     while let Some(value) = values.next().await {
+    ///         println!("{:02x?}", value);
+    ///     }
+    /// }
+    /// ```
+    pub async fn raw_values(&mut self, uuid: &Uuid) -> Result<ValueStream> {
         Ok(self
             .dev
-            .subscribe_msg()?
-            .filter_map(|msg| async move { msg.ok() })
+            .lock()
+            .await
+            .subscribe_to(&self.profile.resolve(uuid))?
+            .map(|(_, value)| value)
             .boxed())
     }
+
+    /// Records the values received from the cube to the file.
+    ///
+    /// Values are appended as [`Record`](crate::record::Record)s until the cube is dropped
+    /// or reconnected. The UUIDs are converted into the ones of the toio cube,
+    /// so sessions recorded with custom profiles can be decoded in the same way.
+    /// See [`Recorder`][] for details.
+    pub async fn record<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut recorder = Recorder::create(path)?;
+        let mut rx = self.dev.lock().await.subscribe()?;
+        let profile = self.profile;
+        self.spawn(async move {
+            while let Some((uuid, value)) = rx.next().await {
+                let uuid = profile
+                    .characteristic(&uuid)
+                    .map(|ch| ch.uuid())
+                    .unwrap_or(uuid);
+                if let Err(e) = recorder.record(uuid, value.to_vec()) {
+                    warn!("Couldn't record value: {}", e);
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
 }
 
 impl Drop for Cube {
     fn drop(&mut self) {
-        if let Some(handle) = self.handle.as_ref() {
-            handle.abort();
-        }
+        self.stop_tasks();
     }
 }
 
@@ -865,10 +3038,217 @@ async fn update(status: &Arc<Mutex<Status>>, event: Event) {
         Event::StdId(p) => {
             status.std_id = Some(p);
         }
+        Event::Posture(p) => {
+            status.posture = Some(p);
+        }
+        Event::Lagged(_) => {
+            *status = Status::default();
+        }
         _ => {}
     }
 }
 
+fn changes<S>(events: S) -> impl Stream<Item = Event>
+where
+    S: Stream<Item = Event>,
+{
+    events
+        .scan(HashMap::new(), |last, event| {
+            if let Event::Lagged(_) = event {
+                // Values may have changed while lagging.
+                last.clear();
+            }
+            let changed =
+                last.insert(mem::discriminant(&event), event.clone()) != Some(event.clone());
+            future::ready(Some(if changed { Some(event) } else { None }))
+        })
+        .filter_map(future::ready)
+}
+
+/// Commands to write back-to-back, started by [`Cube::sequence`][].
+///
+/// The commands are validated as added, and nothing is written if any of them is invalid.
+/// On [`Sequence::send`][], they are written in order without other writes in between,
+/// even by the tasks running on the same cube. If a write fails, the rest are skipped;
+/// the commands already written take effect anyway. The ramp set by [`Cube::set_ramp`][]
+/// doesn't apply to the motor commands here.
+pub struct Sequence<'a> {
+    cube: &'a mut Cube,
+    msgs: Vec<(Message, bool)>,
+    speed: Option<(isize, isize, Option<Duration>)>,
+    error: Option<anyhow::Error>,
+}
+
+impl<'a> Sequence<'a> {
+    /// Adds a raw message. See [`Cube::write_msg`][].
+    pub fn msg<T: Into<Message>>(mut self, msg: T, with_resp: bool) -> Self {
+        self.msgs.push((msg.into(), with_resp));
+        self
+    }
+
+    /// Moves the cube. See [`Cube::go`][].
+    pub fn go(mut self, left: isize, right: isize, duration: Option<Duration>) -> Self {
+        if let Err(e) = check_speeds(left, right, duration) {
+            self.error.get_or_insert(e);
+            return self;
+        }
+        // Takes effect only if all are written.
+        self.speed = Some((left, right, duration));
+        self.msg(motor(left, right, duration), false)
+    }
+
+    /// Stops the cube. See [`Cube::stop`][].
+    pub fn stop(self) -> Self {
+        self.go(0, 0, None)
+    }
+
+    /// Turns on the light with the color correction. See [`Cube::light_on`][].
+    pub fn light_on(self, red: u8, green: u8, blue: u8, duration: Option<Duration>) -> Self {
+        let duration = duration.as_ref().map(|d| d.as_millis() / 10).unwrap_or(0);
+        let mut light = Light::On(LightOn::new(duration as u8, red, green, blue));
+        self.cube.correct(&mut light);
+        self.msg(light, true)
+    }
+
+    /// Turns off the light. See [`Cube::light_off`][].
+    pub fn light_off(self) -> Self {
+        self.msg(Light::Off(LightOff::new()), true)
+    }
+
+    /// Plays the preset sound. See [`Cube::play_preset`][].
+    pub fn play_preset(self, id: SoundPresetId) -> Self {
+        self.msg(Sound::Preset(SoundPreset::new(id, 255)), true)
+    }
+
+    /// Stops playing sound. See [`Cube::stop_sound`][].
+    pub fn stop_sound(self) -> Self {
+        self.msg(Sound::Stop, true)
+    }
+
+    /// Writes all the commands, and waits until they are delivered.
+    ///
+    /// Fails with the first error, either of the validation or of the write.
+    pub async fn send(self) -> Result<()> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.cube.writer.write_all(self.msgs).await?;
+        if let Some((left, right, duration)) = self.speed {
+            self.cube.speed = Speed {
+                left,
+                right,
+                until: duration.map(|d| Instant::now() + d),
+                preempted: self.cube.writer.preempted(),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Handle to stop the cube from other tasks, created by [`Cube::stop_handle`][].
+#[derive(Clone)]
+pub struct StopHandle {
+    preemptor: Preemptor,
+}
+
+impl StopHandle {
+    /// Stops the motor, the light and the sound at once, ahead of everything else.
+    ///
+    /// Same as [`Cube::emergency_stop`][].
+    pub async fn emergency_stop(&self) -> Result<()> {
+        self.preemptor
+            .preempt(vec![
+                motor(0, 0, None).into(),
+                Light::Off(LightOff::new()).into(),
+                Sound::Stop.into(),
+            ])
+            .await
+    }
+}
+
+impl Debug for StopHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StopHandle").finish()
+    }
+}
+
+/// Validates the wheel speeds and the duration of [`Cube::go`][].
+fn check_speeds(left: isize, right: isize, duration: Option<Duration>) -> Result<()> {
+    if !(-100..=100).contains(&left) || !(-100..=100).contains(&right) {
+        return Err(anyhow!("Wheel speed must be between -100 and 100"));
+    }
+    if let Some(d) = duration {
+        if d.as_millis() / 10 > 255 {
+            return Err(anyhow!("Duration must be less than 2560 milliseconds"));
+        }
+    }
+    Ok(())
+}
+
+/// Creates the motor command with the wheel speeds from `-100` to `100`.
+fn motor(left: isize, right: isize, duration: Option<Duration>) -> Motor {
+    let adjust = |v: isize| {
+        (
+            if v > 0 {
+                MotorDir::Forward
+            } else {
+                MotorDir::Backward
+            },
+            (v.abs() * (115 - 7) / 100 + 7) as u8,
+        )
+    };
+    let (left_dir, left) = adjust(left);
+    let (right_dir, right) = adjust(right);
+
+    match duration {
+        Some(d) => Motor::Timed(MotorTimed::new(
+            MotorId::Left,
+            left_dir,
+            left,
+            MotorId::Right,
+            right_dir,
+            right,
+            (d.as_millis() / 10).min(255) as u8,
+        )),
+        None => Motor::Simple(MotorSimple::new(
+            MotorId::Left,
+            left_dir,
+            left,
+            MotorId::Right,
+            right_dir,
+            right,
+        )),
+    }
+}
+
+/// The wheel speeds commanded last.
+#[derive(Debug, Clone, Copy, Default)]
+struct Speed {
+    left: isize,
+    right: isize,
+    /// The time the timed command ends.
+    until: Option<Instant>,
+    /// The number of preempted writes when commanded. The preemption stops the cube.
+    preempted: usize,
+}
+
+impl Speed {
+    /// Gets the speeds the cube is running at now, given the number of preempted writes.
+    fn current(&self, preempted: usize) -> (isize, isize) {
+        match self.until {
+            _ if preempted != self.preempted => (0, 0),
+            Some(until) if until <= Instant::now() => (0, 0),
+            _ => (self.left, self.right),
+        }
+    }
+}
+
+/// Returns `true` if the firmware version like `2.1.0` is the minimum or later.
+fn firmware_at_least(version: &str, min: [u32; 3]) -> bool {
+    let version: Vec<u32> = version.split('.').map(|v| v.parse().unwrap_or(0)).collect();
+    version.as_slice() >= &min[..]
+}
+
 fn convert(msg: Message) -> Option<Vec<Event>> {
     match msg {
         Message::Id(Id::Pos(pos)) => Some(vec![Event::Position(Some(pos.into()))]),
@@ -879,7 +3259,9 @@ fn convert(msg: Message) -> Option<Vec<Event>> {
             Event::Slope(!m.level),
             Event::Collision(m.collision),
             Event::Posture(m.posture),
+            Event::Motion(m),
         ]),
+        Message::Motion(Motion::Magnet(m)) => Some(vec![Event::MagneticForce(m.into())]),
         Message::Button(Button::Func(b)) => Some(vec![Event::Button(b == ButtonState::Pressed)]),
         Message::Battery(v) => Some(vec![Event::Battery(v as usize)]),
         Message::Config(Config::VersionRes(v)) => Some(vec![Event::Version(v.version)]),
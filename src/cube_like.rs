@@ -0,0 +1,156 @@
+use anyhow::Result;
+use std::time::Duration;
+
+use crate::{
+    proto::{MotorTarget, SoundPresetId},
+    Cube, EventStream, LightOp, Position, Repeat, SoundOp, StdId,
+};
+
+/// The high-level operations of a cube.
+///
+/// Implemented by [`Cube`][] and [`FakeCube`](crate::fake::FakeCube), so that the
+/// application logic written against this trait can be tested without BLE.
+/// See the methods of [`Cube`][] for the details.
+///
+/// ```
+/// use toio::{fake::FakeCube, CubeLike, Event};
+///
+/// /// Warns with the red light when the battery is low.
+/// async fn check_battery<C: CubeLike>(cube: &mut C) -> anyhow::Result<()> {
+///     if cube.battery().await? < 20 {
+///         cube.light_on(255, 0, 0, None).await?;
+///     }
+///     Ok(())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = FakeCube::new("fake");
+///     cube.emit(Event::Battery(10));
+///
+///     check_battery(&mut cube).await.unwrap();
+///     assert_eq!(cube.calls().len(), 1);
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait CubeLike: Send {
+    /// Gets the id of the cube.
+    fn id(&self) -> &str;
+
+    /// Moves the cube. See [`Cube::go`][].
+    async fn go(&mut self, left: isize, right: isize, duration: Option<Duration>) -> Result<()>;
+
+    /// Stops the cube. See [`Cube::stop`][].
+    async fn stop(&mut self) -> Result<()>;
+
+    /// Moves the cube to the target. See [`Cube::goto`][].
+    async fn goto(&mut self, target: MotorTarget) -> Result<()>;
+
+    /// Plays the preset sound. See [`Cube::play_preset`][].
+    async fn play_preset(&mut self, id: SoundPresetId) -> Result<()>;
+
+    /// Plays sound. See [`Cube::play`][].
+    async fn play(&mut self, repeat: Repeat, ops: Vec<SoundOp>) -> Result<()>;
+
+    /// Stops sound. See [`Cube::stop_sound`][].
+    async fn stop_sound(&mut self) -> Result<()>;
+
+    /// Turns on the light in the pattern. See [`Cube::light`][].
+    async fn light(&mut self, repeat: Repeat, ops: Vec<LightOp>) -> Result<()>;
+
+    /// Turns on the light. See [`Cube::light_on`][].
+    async fn light_on(
+        &mut self,
+        red: u8,
+        green: u8,
+        blue: u8,
+        duration: Option<Duration>,
+    ) -> Result<()>;
+
+    /// Turns off the light. See [`Cube::light_off`][].
+    async fn light_off(&mut self) -> Result<()>;
+
+    /// Gets the battery percentage. See [`Cube::battery`][].
+    async fn battery(&mut self) -> Result<usize>;
+
+    /// Gets the button state. See [`Cube::button`][].
+    async fn button(&mut self) -> Result<bool>;
+
+    /// Gets the position on the mat. See [`Cube::position`][].
+    async fn position(&mut self) -> Result<Option<Position>>;
+
+    /// Gets the standard id under the cube. See [`Cube::std_id`][].
+    async fn std_id(&mut self) -> Result<Option<StdId>>;
+
+    /// Subscribes to the events. See [`Cube::events`][].
+    async fn events(&mut self) -> Result<EventStream>;
+}
+
+#[async_trait::async_trait]
+impl CubeLike for Cube {
+    fn id(&self) -> &str {
+        Cube::id(self)
+    }
+
+    async fn go(&mut self, left: isize, right: isize, duration: Option<Duration>) -> Result<()> {
+        Cube::go(self, left, right, duration).await
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Cube::stop(self).await
+    }
+
+    async fn goto(&mut self, target: MotorTarget) -> Result<()> {
+        Cube::goto(self, target).await
+    }
+
+    async fn play_preset(&mut self, id: SoundPresetId) -> Result<()> {
+        Cube::play_preset(self, id).await
+    }
+
+    async fn play(&mut self, repeat: Repeat, ops: Vec<SoundOp>) -> Result<()> {
+        Cube::play(self, repeat, ops).await
+    }
+
+    async fn stop_sound(&mut self) -> Result<()> {
+        Cube::stop_sound(self).await
+    }
+
+    async fn light(&mut self, repeat: Repeat, ops: Vec<LightOp>) -> Result<()> {
+        Cube::light(self, repeat, ops).await
+    }
+
+    async fn light_on(
+        &mut self,
+        red: u8,
+        green: u8,
+        blue: u8,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        Cube::light_on(self, red, green, blue, duration).await
+    }
+
+    async fn light_off(&mut self) -> Result<()> {
+        Cube::light_off(self).await
+    }
+
+    async fn battery(&mut self) -> Result<usize> {
+        Cube::battery(self).await
+    }
+
+    async fn button(&mut self) -> Result<bool> {
+        Cube::button(self).await
+    }
+
+    async fn position(&mut self) -> Result<Option<Position>> {
+        Cube::position(self).await
+    }
+
+    async fn std_id(&mut self) -> Result<Option<StdId>> {
+        Cube::std_id(self).await
+    }
+
+    async fn events(&mut self) -> Result<EventStream> {
+        Cube::events(self).await
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::Result;
+use chrono::Local;
+use crossterm::{
+    event::{Event as TermEvent, EventStream as TermEventStream, KeyCode},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use futures::{prelude::*, stream};
+use std::collections::VecDeque;
+use std::io::{self, Stdout};
+use std::time::Duration;
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+
+use crate::{Cube, Event, Position};
+
+#[derive(Debug, Clone)]
+struct CubeState {
+    id: String,
+    battery: Option<usize>,
+    rssi: i32,
+    pos: Option<Position>,
+}
+
+/// Terminal dashboard showing the live status of cubes.
+///
+/// Shows the battery, RSSI and pose of each cube, the cubes on an ASCII mat
+/// and the recent events. [`Dashboard::run`][] drives the dashboard until `q` or `Esc` is pressed.
+/// To embed it into another terminal UI, feed the events by [`Dashboard::update`][]
+/// and render it by [`Dashboard::draw`][].
+///
+/// Enabled by the `dashboard` feature.
+///
+/// ```no_run
+/// use toio::{dashboard::Dashboard, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cubes = Cube::search().all().await.unwrap();
+///     for cube in &mut cubes {
+///         cube.connect().await.unwrap();
+///     }
+///
+///     Dashboard::new().run(&mut cubes).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Dashboard {
+    cubes: Vec<CubeState>,
+    events: VecDeque<(String, String, Event)>,
+    history: usize,
+    top_left: (u16, u16),
+    bottom_right: (u16, u16),
+    tick: Duration,
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dashboard {
+    /// Creates an empty dashboard.
+    pub fn new() -> Self {
+        Self {
+            cubes: vec![],
+            events: VecDeque::new(),
+            history: 100,
+            top_left: (45, 45),
+            bottom_right: (455, 455),
+            tick: Duration::from_millis(200),
+        }
+    }
+
+    /// Sets the number of recent events to keep. By default, it's 100.
+    pub fn history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Sets the coordinates of the mat corners.
+    ///
+    /// By default, the ones of the toio collection mat (ring side) are used.
+    pub fn mat(mut self, top_left: (u16, u16), bottom_right: (u16, u16)) -> Self {
+        self.top_left = top_left;
+        self.bottom_right = bottom_right;
+        self
+    }
+
+    /// Sets the interval to redraw. By default, it's 200 milliseconds.
+    pub fn tick(mut self, tick: Duration) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    /// Updates the dashboard with the event of the cube.
+    ///
+    /// The cube is added to the dashboard if it's unknown.
+    pub fn update(&mut self, id: &str, event: &Event) {
+        let cube = self.cube(id);
+        match event {
+            Event::Battery(pct) | Event::BatteryLow(pct) => cube.battery = Some(*pct),
+            Event::Position(pos) => cube.pos = pos.clone(),
+            Event::Lagged(_) => return,
+            _ => {}
+        }
+
+        if self.history == 0 {
+            return;
+        }
+        while self.events.len() >= self.history {
+            self.events.pop_front();
+        }
+        let time = Local::now().format("%H:%M:%S%.3f").to_string();
+        self.events.push_back((time, id.to_string(), event.clone()));
+    }
+
+    /// Updates the RSSI of the cube.
+    pub fn set_rssi(&mut self, id: &str, rssi: i32) {
+        self.cube(id).rssi = rssi;
+    }
+
+    fn cube(&mut self, id: &str) -> &mut CubeState {
+        match self.cubes.iter().position(|c| c.id == id) {
+            Some(index) => &mut self.cubes[index],
+            None => {
+                self.cubes.push(CubeState {
+                    id: id.to_string(),
+                    battery: None,
+                    rssi: 0,
+                    pos: None,
+                });
+                self.cubes.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Renders the dashboard to the whole frame.
+    pub fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(self.cubes.len() as u16 + 3),
+                Constraint::Min(0),
+            ])
+            .split(f.size());
+        let cols = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rows[1]);
+
+        self.draw_cubes(f, rows[0]);
+        self.draw_mat(f, cols[0]);
+        self.draw_events(f, cols[1]);
+    }
+
+    fn draw_cubes<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let rows = self.cubes.iter().enumerate().map(|(i, c)| {
+            let battery = c.battery.map(|b| format!("{}%", b)).unwrap_or_default();
+            let pose = match &c.pos {
+                Some(p) => format!("({}, {}) {}°", p.x, p.y, p.angle),
+                None => "-".to_string(),
+            };
+            Row::new(vec![
+                marker(i).to_string(),
+                c.id.clone(),
+                battery,
+                c.rssi.to_string(),
+                pose,
+            ])
+        });
+        let widths = [
+            Constraint::Length(2),
+            Constraint::Percentage(40),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Min(18),
+        ];
+        let table = Table::new(rows)
+            .header(Row::new(vec!["", "Cube", "Battery", "RSSI", "Pose"]))
+            .block(Block::default().title("Cubes").borders(Borders::ALL))
+            .widths(&widths);
+        f.render_widget(table, area);
+    }
+
+    fn draw_mat<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let w = area.width.saturating_sub(2) as usize;
+        let h = area.height.saturating_sub(2) as usize;
+        let mut grid = vec![vec!['.'; w]; h];
+
+        let scale = |v: u16, min: u16, max: u16, len: usize| {
+            let span = max.saturating_sub(min).max(1) as usize;
+            let v = v.max(min).min(max).saturating_sub(min) as usize;
+            v * len.saturating_sub(1) / span
+        };
+        for (i, c) in self.cubes.iter().enumerate() {
+            if let (Some(p), true) = (&c.pos, w > 0 && h > 0) {
+                let col = scale(p.x, self.top_left.0, self.bottom_right.0, w);
+                let row = scale(p.y, self.top_left.1, self.bottom_right.1, h);
+                grid[row][col] = marker(i);
+            }
+        }
+
+        let text = grid
+            .into_iter()
+            .map(|row| row.into_iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mat = Paragraph::new(text).block(Block::default().title("Mat").borders(Borders::ALL));
+        f.render_widget(mat, area);
+    }
+
+    fn draw_events<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let visible = area.height.saturating_sub(2) as usize;
+        let items: Vec<_> = self
+            .events
+            .iter()
+            .rev()
+            .take(visible)
+            .map(|(time, id, event)| ListItem::new(format!("{} {} {:?}", time, id, event)))
+            .collect();
+        let list = List::new(items).block(Block::default().title("Events").borders(Borders::ALL));
+        f.render_widget(list, area);
+    }
+
+    /// Shows the dashboard of the connected cubes until `q` or `Esc` is pressed.
+    pub async fn run(mut self, cubes: &mut [Cube]) -> Result<()> {
+        let mut streams = vec![];
+        for cube in cubes.iter_mut() {
+            let id = cube.id().to_string();
+            self.set_rssi(&id, cube.rssi());
+            if let Ok(pct) = cube.battery().await {
+                self.cube(&id).battery = Some(pct);
+            }
+            let events = cube.events().await?;
+            streams.push(events.map(move |event| (id.clone(), event)).boxed());
+        }
+        // Keeps the dashboard open even if there's no cube.
+        let events = stream::select_all(streams).chain(stream::pending());
+
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+        let res = self.event_loop(&mut terminal, cubes, events).await;
+
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        res
+    }
+
+    async fn event_loop<S>(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        cubes: &[Cube],
+        events: S,
+    ) -> Result<()>
+    where
+        S: Stream<Item = (String, Event)> + Unpin,
+    {
+        let mut events = events;
+        let mut keys = TermEventStream::new();
+        let mut tick = tokio::time::interval(self.tick);
+
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {
+                    for cube in cubes {
+                        self.set_rssi(cube.id(), cube.rssi());
+                    }
+                    terminal.draw(|f| self.draw(f))?;
+                }
+                event = events.next() => {
+                    if let Some((id, event)) = event {
+                        self.update(&id, &event);
+                    }
+                }
+                key = keys.next() => match key {
+                    Some(Ok(TermEvent::Key(key))) => {
+                        if let KeyCode::Char('q') | KeyCode::Esc = key.code {
+                            break;
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => break,
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn marker(index: usize) -> char {
+    std::char::from_digit(((index + 1) % 36) as u32, 36).unwrap_or('?')
+}
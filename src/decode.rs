@@ -1,58 +1,109 @@
+use alloc::string::{String, ToString};
 use bytes::Buf;
+use core::fmt::{self, Display};
 use derive_new::new;
 use serde::de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor};
-use std::fmt::{self, Display};
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
-pub fn decode<T: DeserializeOwned>(buf: &[u8]) -> anyhow::Result<T> {
+/// Decodes the message. If `strict` is `true`, fails if bytes are left after the message.
+pub fn decode<T: DeserializeOwned>(buf: &[u8], strict: bool) -> anyhow::Result<T> {
     let mut de = Deserializer::new(buf);
     let t = T::deserialize(&mut de)?;
+    if strict && !de.buf.is_empty() {
+        return Err(Error::Trailing {
+            expected: buf.len() - de.buf.len(),
+            actual: buf.len(),
+        }
+        .into());
+    }
     Ok(t)
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Error(String);
-
-impl From<std::io::Error> for Error {
-    fn from(e: std::io::Error) -> Self {
-        Self(e.to_string())
-    }
+/// The error on decoding a message.
+///
+/// The lengths are counted from the byte after the message type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The bytes end in the middle of the field.
+    TooShort {
+        /// The name of the field, if it's in a struct.
+        field: Option<&'static str>,
+        /// The number of bytes the field needs.
+        expected: usize,
+        /// The number of bytes left.
+        actual: usize,
+    },
+    /// The bytes end before the field.
+    Missing(&'static str),
+    /// Bytes are left after the message. Only reported in the strict mode.
+    Trailing {
+        /// The length of the message.
+        expected: usize,
+        /// The length of the bytes.
+        actual: usize,
+    },
+    /// Other errors.
+    Custom(String),
 }
 
 impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error(msg.to_string())
+        Error::Custom(msg.to_string())
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        match self {
+            Error::TooShort {
+                field: Some(field),
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Buffer is too short for field `{}`: expect {} but {}",
+                field, expected, actual
+            ),
+            Error::TooShort {
+                field: None,
+                expected,
+                actual,
+            } => write!(f, "Buffer is too short: expect {} but {}", expected, actual),
+            Error::Missing(field) => write!(f, "Buffer ends before field `{}`", field),
+            Error::Trailing { expected, actual } => write!(
+                f,
+                "Buffer has trailing bytes: expect {} but {}",
+                expected, actual
+            ),
+            Error::Custom(msg) => write!(f, "{}", msg),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl core::error::Error for Error {}
 
 #[derive(new)]
 pub struct Deserializer<'de> {
     buf: &'de [u8],
+    #[new(default)]
+    field: Option<&'static str>,
 }
 
 impl<'de> Deserializer<'de> {
     fn parse_str(&mut self) -> String {
-        let s = String::from_utf8_lossy(self.buf).to_string();
+        let s = String::from_utf8_lossy(self.buf).into_owned();
         self.buf = &self.buf[self.buf.len()..];
         s
     }
 
     fn check(&mut self, len: usize) -> Result<()> {
         if self.buf.len() < len {
-            Err(Error(format!(
-                "Buffer is too short: expect {} but {}",
-                len,
-                self.buf.len()
-            )))
+            Err(Error::TooShort {
+                field: self.field,
+                expected: len,
+                actual: self.buf.len(),
+            })
         } else {
             Ok(())
         }
@@ -254,13 +305,17 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_struct<V>(
         self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        visitor.visit_seq(StructAccess {
+            de: self,
+            fields,
+            index: 0,
+        })
     }
 
     fn deserialize_enum<V>(
@@ -304,3 +359,29 @@ impl<'de, 'a> SeqAccess<'de> for &'a mut Deserializer<'de> {
         }
     }
 }
+
+/// Visits the fields of a struct, keeping track of the field being decoded for errors.
+struct StructAccess<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    fields: &'static [&'static str],
+    index: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for StructAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.fields.get(self.index) {
+            Some(field) if self.de.buf.is_empty() => Err(Error::Missing(field)),
+            Some(field) => {
+                self.index += 1;
+                self.de.field = Some(field);
+                seed.deserialize(&mut *self.de).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
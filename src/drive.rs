@@ -0,0 +1,512 @@
+use anyhow::{anyhow, bail, Result};
+use derive_new::new;
+use futures::{
+    prelude::*,
+    stream::{self, BoxStream},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{timeout, Instant};
+
+use crate::{
+    proto::{MotorMultiTarget, MotorTarget, MoveType, SpeedChange, Target, KEEP_COORDINATE},
+    sim::{degrees_per_sec, units_per_sec},
+    Cube, Event, EventFilter, Position,
+};
+
+/// Acceleration limit of the wheel speeds.
+///
+/// If set by [`Cube::set_ramp`](crate::Cube::set_ramp), an abrupt change of the speeds by
+/// [`Cube::go`](crate::Cube::go) is split into intermediate commands sent at the interval,
+/// so that the cube doesn't slip or tip over. This works on any firmware version,
+/// unlike [`MotorAcc`](crate::proto::MotorAcc).
+///
+/// ```
+/// use std::time::Duration;
+/// use toio::drive::Ramp;
+///
+/// let ramp = Ramp::new(200.0, Duration::from_millis(100));
+/// assert_eq!(ramp.steps((0, 0), (60, -60)), vec![(20, -20), (40, -40)]);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, new)]
+pub struct Ramp {
+    /// The maximum change of the wheel speed per second, in the unit of `go`.
+    pub accel: f32,
+    /// The interval of the intermediate commands.
+    pub step: Duration,
+}
+
+impl Default for Ramp {
+    fn default() -> Self {
+        Self::new(400.0, Duration::from_millis(50))
+    }
+}
+
+impl Ramp {
+    /// Gets the intermediate speeds to change the speeds from `from` to `to`.
+    ///
+    /// The speeds are sent one by one at the interval, followed by `to`.
+    pub fn steps(&self, from: (isize, isize), to: (isize, isize)) -> Vec<(isize, isize)> {
+        let max_delta = self.accel * self.step.as_secs_f32();
+        let diff = (to.0 - from.0).abs().max((to.1 - from.1).abs()) as f32;
+        if max_delta <= 0.0 || diff <= max_delta {
+            return vec![];
+        }
+
+        let n = (diff / max_delta).ceil() as isize;
+        let lerp = |a: isize, b: isize, i: isize| a + (b - a) * i / n;
+        (1..n)
+            .map(|i| (lerp(from.0, to.0, i), lerp(from.1, to.1, i)))
+            .collect()
+    }
+}
+
+/// Options of [`Cube::patrol`](crate::Cube::patrol).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PatrolOptions {
+    /// Goes back to the first waypoint after the last one, and repeats forever.
+    /// Otherwise, visits the waypoints once.
+    pub looping: bool,
+    /// The time to stay at each waypoint.
+    pub pause: Duration,
+    /// The maximum speed of the target control from 10 to 115.
+    pub speed: u8,
+}
+
+impl Default for PatrolOptions {
+    fn default() -> Self {
+        Self {
+            looping: true,
+            pause: Duration::from_secs(0),
+            speed: 50,
+        }
+    }
+}
+
+/// Options of [`Cube::reacquire_position`](crate::Cube::reacquire_position).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReacquireOptions {
+    /// The speed of the outer wheel from 1 to 100.
+    pub speed: isize,
+    /// The time to widen the spiral from turning in place to going straight.
+    pub spread: Duration,
+    /// The time to give up.
+    pub timeout: Duration,
+}
+
+impl Default for ReacquireOptions {
+    fn default() -> Self {
+        Self {
+            speed: 20,
+            spread: Duration::from_secs(10),
+            timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Progress of [`Cube::goto_and_wait`](crate::Cube::goto_and_wait).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, new)]
+pub struct Progress {
+    /// The current position of the cube.
+    pub position: Position,
+    /// The straight distance to the target position in the mat units.
+    pub remaining: f32,
+}
+
+/// The error of the target control overwritten by another one from the same cube.
+///
+/// [`Cube::goto`](crate::Cube::goto) and [`Navigation`][] fail with this error when
+/// a new target request starts before the cube arrives. The new request goes on.
+///
+/// ```
+/// use toio::drive::Superseded;
+///
+/// let err: anyhow::Error = Superseded.into();
+/// assert!(err.downcast_ref::<Superseded>().is_some());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Superseded;
+
+impl fmt::Display for Superseded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Superseded by another target request")
+    }
+}
+
+impl std::error::Error for Superseded {}
+
+/// An update of the target control, either the progress or the final result.
+pub(crate) enum Update {
+    Progress(Progress),
+    Done(Result<()>),
+}
+
+/// The target control in flight, started by [`Cube::goto_and_wait`](crate::Cube::goto_and_wait).
+///
+/// Streams the progress on every position notification, and ends when the firmware
+/// answers the request. Then [`Navigation::finish`][] gives the result.
+pub struct Navigation {
+    updates: BoxStream<'static, Update>,
+    result: Option<Result<()>>,
+}
+
+impl Navigation {
+    pub(crate) fn new(updates: BoxStream<'static, Update>) -> Self {
+        Self {
+            updates,
+            result: None,
+        }
+    }
+
+    /// Waits until the cube arrives, skipping the remaining progress.
+    ///
+    /// Fails if the firmware gives up the request, the same as [`Cube::goto`][].
+    pub async fn finish(mut self) -> Result<()> {
+        while self.next().await.is_some() {}
+        self.result.take().expect("Navigation ends with the result")
+    }
+}
+
+impl Stream for Navigation {
+    type Item = Progress;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Progress>> {
+        let this = self.get_mut();
+        if this.result.is_some() {
+            return Poll::Ready(None);
+        }
+        match this.updates.poll_next_unpin(cx) {
+            Poll::Ready(Some(Update::Progress(progress))) => Poll::Ready(Some(progress)),
+            Poll::Ready(Some(Update::Done(res))) => {
+                // Releases the subscription, which tells the cube that this one is over.
+                this.updates = stream::empty().boxed();
+                this.result = Some(res);
+                Poll::Ready(None)
+            }
+            Poll::Ready(None) => {
+                this.result = Some(Err(anyhow!(
+                    "Stream ends while waiting for the target response"
+                )));
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// PID controller holding the cube on a heading of the mat while driving straight.
+///
+/// Each position update is turned into the wheel speeds: the steering correction is
+/// added to the left wheel and subtracted from the right one. Besides the angle, the
+/// lateral offset from the line through the first position is fed back, so that the
+/// cube returns to the line instead of drifting in parallel to it.
+///
+/// ```
+/// use std::time::Duration;
+/// use toio::{drive::HeadingController, Position};
+///
+/// let mut ctrl = HeadingController::new(90.0).cross_track(0.0);
+///
+/// // On the heading.
+/// let dt = Duration::from_millis(100);
+/// assert_eq!(ctrl.update(30, &Position::new(100, 100, 90), dt), (30, 30));
+///
+/// // Turned clockwise, so steer counterclockwise.
+/// let (left, right) = ctrl.update(30, &Position::new(100, 110, 100), dt);
+/// assert!(left < right);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HeadingController {
+    heading: f32,
+    kp: f32,
+    ki: f32,
+    kd: f32,
+    cross_track: f32,
+    max_correction: isize,
+    integral: f32,
+    last_error: Option<f32>,
+    origin: Option<(f32, f32)>,
+}
+
+impl HeadingController {
+    /// Creates a controller holding the heading in degrees.
+    pub fn new(heading: f32) -> Self {
+        Self {
+            heading,
+            kp: 0.5,
+            ki: 0.1,
+            kd: 0.05,
+            cross_track: 0.5,
+            max_correction: 30,
+            integral: 0.0,
+            last_error: None,
+            origin: None,
+        }
+    }
+
+    /// Sets the proportional, integral and derivative gains per degree of the error.
+    /// By default, they're `0.5`, `0.1` and `0.05`.
+    pub fn gains(mut self, kp: f32, ki: f32, kd: f32) -> Self {
+        self.kp = kp;
+        self.ki = ki;
+        self.kd = kd;
+        self
+    }
+
+    /// Sets the degrees of the heading error per unit of the lateral offset from the line.
+    /// `0.0` holds only the angle. By default, it's `0.5`.
+    pub fn cross_track(mut self, gain: f32) -> Self {
+        self.cross_track = gain;
+        self
+    }
+
+    /// Sets the limit of the steering correction. By default, it's `30`.
+    pub fn max_correction(mut self, max: isize) -> Self {
+        self.max_correction = max;
+        self
+    }
+
+    /// Gets the heading in degrees.
+    pub fn heading(&self) -> f32 {
+        self.heading
+    }
+
+    /// Changes the heading in degrees, and starts a new line from the next position.
+    pub fn set_heading(&mut self, heading: f32) {
+        self.heading = heading;
+        self.reset();
+    }
+
+    /// Clears the accumulated error and the line.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_error = None;
+        self.origin = None;
+    }
+
+    /// Gets the wheel speeds to drive at `speed` from the position observed `dt` after
+    /// the previous one.
+    pub fn update(&mut self, speed: isize, pos: &Position, dt: Duration) -> (isize, isize) {
+        let (x, y) = (pos.x as f32, pos.y as f32);
+        let (ox, oy) = *self.origin.get_or_insert((x, y));
+
+        // The mat y-axis points down, so a positive offset is on the clockwise side.
+        let (sin, cos) = self.heading.to_radians().sin_cos();
+        let offset = cos * (y - oy) - sin * (x - ox);
+        let bias = (self.cross_track * offset).clamp(-45.0, 45.0) * speed.signum() as f32;
+        let error = wrap(self.heading - bias - pos.angle as f32);
+
+        let dt = dt.as_secs_f32();
+        let max = self.max_correction as f32;
+        let mut derivative = 0.0;
+        if dt > 0.0 {
+            if self.ki != 0.0 {
+                let limit = max / self.ki.abs();
+                self.integral = (self.integral + error * dt).clamp(-limit, limit);
+            }
+            if let Some(last) = self.last_error {
+                derivative = wrap(error - last) / dt;
+            }
+        }
+        self.last_error = Some(error);
+
+        let correction = (self.kp * error + self.ki * self.integral + self.kd * derivative)
+            .clamp(-max, max)
+            .round() as isize;
+        let clamp = |v: isize| v.clamp(-100, 100);
+        (clamp(speed + correction), clamp(speed - correction))
+    }
+
+    /// Drives the cube at `speed` for the duration holding the heading, then stops it.
+    ///
+    /// While the cube is off the mat, it keeps the last speeds.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::{drive::HeadingController, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Drive to the right of the mat for 3 seconds.
+    ///     let mut ctrl = HeadingController::new(0.0);
+    ///     ctrl.drive(&mut cube, 40, Duration::from_secs(3)).await.unwrap();
+    /// }
+    /// ```
+    pub async fn drive(&mut self, cube: &mut Cube, speed: isize, duration: Duration) -> Result<()> {
+        let mut events = cube.events_filtered(EventFilter::new().position()).await?;
+        let deadline = Instant::now() + duration;
+        let mut last = Instant::now();
+
+        self.reset();
+        cube.go(speed, speed, None).await?;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match timeout(deadline - now, events.next()).await {
+                Ok(Some(Event::Position(Some(pos)))) => {
+                    let now = Instant::now();
+                    let (left, right) = self.update(speed, &pos, now - last);
+                    last = now;
+                    cube.go(left, right, None).await?;
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => bail!("Event stream closed"),
+                Err(_) => break,
+            }
+        }
+        cube.stop().await
+    }
+}
+
+/// Estimates how long the cube takes to reach the target from the position.
+///
+/// The estimate assumes the nominal wheel speed of about 4.3 mm/s per unit of the speed,
+/// the same as [`Model`](crate::sim::Model), and counts the spin before going straight,
+/// the turn to the final angle and the slower average speed by [`SpeedChange`][].
+/// Cubes on a worn mat or with a low battery run slower, so give a margin, e.g., by
+/// [`firmware_timeout`][].
+///
+/// ```
+/// use std::time::Duration;
+/// use toio::{drive, proto::*, Position};
+///
+/// let from = Position::new(100, 250, 0);
+/// let target = MotorTarget::builder(300, 250).max_speed(50).build().unwrap();
+/// let time = drive::travel_time(&from, &target);
+/// assert!(time > Duration::from_millis(600) && time < Duration::from_millis(800));
+///
+/// // Accelerating from zero takes twice as long on average.
+/// let target = MotorTarget::builder(300, 250)
+///     .max_speed(50)
+///     .speed_change(SpeedChange::Acc)
+///     .build()
+///     .unwrap();
+/// assert!(drive::travel_time(&from, &target) > time * 2 - Duration::from_millis(10));
+/// ```
+pub fn travel_time(from: &Position, target: &MotorTarget) -> Duration {
+    let mut pose = (from.x as f32, from.y as f32, from.angle as f32);
+    let leg = Target::new(target.x, target.y, target.angle);
+    let motion = Motion::new(target.move_type, target.max_speed, target.speed_change);
+    Duration::from_secs_f32(motion.leg(&mut pose, &leg))
+}
+
+/// Estimates how long the cube takes to visit all the targets from the position.
+///
+/// Each target is estimated as [`travel_time`][], starting from where the previous one
+/// ended. Appending to the targets already running isn't taken into account.
+pub fn path_time(from: &Position, targets: &MotorMultiTarget) -> Duration {
+    let mut pose = (from.x as f32, from.y as f32, from.angle as f32);
+    let motion = Motion::new(targets.move_type, targets.max_speed, targets.speed_change);
+    let secs = targets
+        .targets
+        .iter()
+        .map(|target| motion.leg(&mut pose, target))
+        .sum();
+    Duration::from_secs_f32(secs)
+}
+
+/// Gets the timeout in seconds for the target control taking the estimated time.
+///
+/// Doubles the estimate and adds a second for the slow start, and rounds it up.
+/// The firmware regards 0 as 10 seconds, so the result is from 1 to 255.
+///
+/// ```
+/// use std::time::Duration;
+/// use toio::drive::firmware_timeout;
+///
+/// assert_eq!(firmware_timeout(Duration::from_millis(1200)), 4);
+/// assert_eq!(firmware_timeout(Duration::from_secs(600)), 255);
+/// ```
+pub fn firmware_timeout(estimate: Duration) -> u8 {
+    let secs = (estimate.as_secs_f32() * 2.0 + 1.0).ceil();
+    secs.clamp(1.0, 255.0) as u8
+}
+
+/// The parameters of the target control shared by the targets.
+#[derive(Debug, Clone, Copy, new)]
+struct Motion {
+    move_type: MoveType,
+    max_speed: u8,
+    speed_change: SpeedChange,
+}
+
+impl Motion {
+    /// Gets the seconds to reach the target, and updates the pose to the target.
+    fn leg(&self, pose: &mut (f32, f32, f32), target: &Target) -> f32 {
+        let max = self.max_speed as f32;
+        let (x, y, start) = *pose;
+        let keep = |v: u16, cur: f32| {
+            if v == KEEP_COORDINATE {
+                cur
+            } else {
+                v as f32
+            }
+        };
+        let (tx, ty) = (keep(target.x, x), keep(target.y, y));
+        let (dx, dy) = (tx - x, ty - y);
+        let dist = (dx * dx + dy * dy).sqrt();
+
+        let mut secs = 0.0;
+        let mut angle = start;
+        if dist > 0.0 {
+            let heading = dy.atan2(dx).to_degrees();
+            let error = wrap(heading - start);
+            let (error, facing) = match self.move_type {
+                MoveType::Curve if error.abs() > 90.0 => (wrap(error - 180.0), heading + 180.0),
+                _ => (error, heading),
+            };
+            secs += match self.move_type {
+                // Spins in place at the full speed of both wheels.
+                MoveType::Straight => error.abs() / degrees_per_sec(2.0 * max),
+                // Turns while going, but can't go forward while facing away.
+                MoveType::ForwardOnly => (error.abs() - 90.0).max(0.0) / degrees_per_sec(max),
+                MoveType::Curve => 0.0,
+            };
+            let average = match self.speed_change {
+                SpeedChange::Const => max,
+                SpeedChange::Acc | SpeedChange::Dec | SpeedChange::AccDec => max / 2.0,
+            };
+            secs += dist / units_per_sec(average);
+            angle = facing;
+        }
+
+        let turn = self.turn(target.angle, start, angle);
+        secs += turn.abs() / degrees_per_sec(2.0 * max);
+        *pose = (tx, ty, (angle + turn).rem_euclid(360.0));
+        secs
+    }
+
+    /// Gets the degrees to turn at the target position, signed by the direction.
+    fn turn(&self, field: u16, start: f32, angle: f32) -> f32 {
+        let value = (field & 0x1fff) as f32;
+        let turns = (value / 360.0).floor() * 360.0;
+        // The upper 3 bits are `AngleType`.
+        match field >> 13 {
+            0x00 => wrap(value - angle),
+            0x01 => (value - angle).rem_euclid(360.0),
+            0x02 => -(angle - value).rem_euclid(360.0),
+            0x03 => turns + (start + value - angle).rem_euclid(360.0),
+            0x04 => -(turns + (angle - start + value).rem_euclid(360.0)),
+            0x06 => wrap(start - angle),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Wraps the angle in degrees to the range from -180 to 180.
+fn wrap(angle: f32) -> f32 {
+    let angle = angle.rem_euclid(360.0);
+    if angle > 180.0 {
+        angle - 360.0
+    } else {
+        angle
+    }
+}
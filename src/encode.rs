@@ -1,27 +1,32 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
 use bytes::BufMut;
+use core::fmt::{self, Display};
 use derive_new::new;
 use serde::{ser, Serialize};
-use std::fmt::{self, Display};
-use std::io::prelude::*;
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
-pub fn encode<T: Serialize, W: Write>(mut buf: W, msg: T) -> anyhow::Result<()> {
-    let mut ser = Serializer::new();
+pub fn encode<T: Serialize>(buf: &mut Vec<u8>, msg: T) -> anyhow::Result<()> {
+    let mut ser = Serializer::new(buf);
     msg.serialize(&mut ser)?;
-    buf.write(&ser.buf)?;
     Ok(())
 }
 
+/// Encodes the message to the head of the buffer and returns the number of bytes written.
+pub fn encode_into<T: Serialize>(buf: &mut [u8], msg: T) -> anyhow::Result<usize> {
+    let len = buf.len();
+    let mut ser = Serializer::new(buf);
+    msg.serialize(&mut ser)?;
+    Ok(len - ser.buf.len())
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Error(String);
 
-impl From<std::io::Error> for Error {
-    fn from(e: std::io::Error) -> Self {
-        Self(e.to_string())
-    }
-}
-
 impl ser::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error(msg.to_string())
@@ -34,15 +39,28 @@ impl Display for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl core::error::Error for Error {}
 
 #[derive(new)]
-pub struct Serializer {
-    #[new(default)]
-    buf: Vec<u8>,
+pub struct Serializer<B> {
+    buf: B,
+}
+
+impl<B: BufMut> Serializer<B> {
+    fn check(&self, len: usize) -> Result<()> {
+        if self.buf.remaining_mut() < len {
+            Err(Error(format!(
+                "Buffer is too short: expect {} but {}",
+                len,
+                self.buf.remaining_mut()
+            )))
+        } else {
+            Ok(())
+        }
+    }
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl<'a, B: BufMut> ser::Serializer for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -55,39 +73,57 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type SerializeStructVariant = Self;
 
     fn serialize_bool(self, v: bool) -> Result<()> {
-        Ok(self.buf.put_u8(v as u8))
+        self.check(1)?;
+        self.buf.put_u8(v as u8);
+        Ok(())
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
-        Ok(self.buf.put_i8(v))
+        self.check(1)?;
+        self.buf.put_i8(v);
+        Ok(())
     }
 
     fn serialize_i16(self, v: i16) -> Result<()> {
-        Ok(self.buf.put_i16_le(v))
+        self.check(2)?;
+        self.buf.put_i16_le(v);
+        Ok(())
     }
 
     fn serialize_i32(self, v: i32) -> Result<()> {
-        Ok(self.buf.put_i32_le(v))
+        self.check(4)?;
+        self.buf.put_i32_le(v);
+        Ok(())
     }
 
     fn serialize_i64(self, v: i64) -> Result<()> {
-        Ok(self.buf.put_i64_le(v))
+        self.check(8)?;
+        self.buf.put_i64_le(v);
+        Ok(())
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        Ok(self.buf.put_u8(v))
+        self.check(1)?;
+        self.buf.put_u8(v);
+        Ok(())
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        Ok(self.buf.put_u16_le(v))
+        self.check(2)?;
+        self.buf.put_u16_le(v);
+        Ok(())
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
-        Ok(self.buf.put_u32_le(v))
+        self.check(4)?;
+        self.buf.put_u32_le(v);
+        Ok(())
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
-        Ok(self.buf.put_u64_le(v))
+        self.check(8)?;
+        self.buf.put_u64_le(v);
+        Ok(())
     }
 
     fn serialize_f32(self, _v: f32) -> Result<()> {
@@ -103,7 +139,8 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        self.buf.write(v.as_bytes())?;
+        self.check(v.len())?;
+        self.buf.put_slice(v.as_bytes());
         Ok(())
     }
 
@@ -205,7 +242,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl<'a, B: BufMut> ser::SerializeSeq for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -221,7 +258,7 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl<'a, B: BufMut> ser::SerializeTuple for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -237,7 +274,7 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl<'a, B: BufMut> ser::SerializeTupleStruct for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -253,7 +290,7 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl<'a, B: BufMut> ser::SerializeTupleVariant for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -269,7 +306,7 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl<'a, B: BufMut> ser::SerializeMap for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -292,7 +329,7 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl<'a, B: BufMut> ser::SerializeStruct for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -308,7 +345,7 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+impl<'a, B: BufMut> ser::SerializeStructVariant for &'a mut Serializer<B> {
     type Ok = ();
     type Error = Error;
 
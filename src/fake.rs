@@ -0,0 +1,246 @@
+use anyhow::{anyhow, Result};
+use futures::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::{
+    proto::{MotorTarget, SoundPresetId, KEEP_COORDINATE},
+    CubeLike, Event, EventStream, LightOp, Position, Repeat, SoundOp, StdId,
+};
+
+const EVENT_CAPACITY: usize = 64;
+
+/// An operation requested to [`FakeCube`][].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    /// [`CubeLike::go`][].
+    Go {
+        /// The speed of the left wheel.
+        left: isize,
+        /// The speed of the right wheel.
+        right: isize,
+        /// The duration.
+        duration: Option<Duration>,
+    },
+    /// [`CubeLike::stop`][].
+    Stop,
+    /// [`CubeLike::goto`][].
+    Goto(MotorTarget),
+    /// [`CubeLike::play_preset`][].
+    PlayPreset(SoundPresetId),
+    /// [`CubeLike::play`][].
+    Play(Repeat, Vec<SoundOp>),
+    /// [`CubeLike::stop_sound`][].
+    StopSound,
+    /// [`CubeLike::light`][].
+    Light(Repeat, Vec<LightOp>),
+    /// [`CubeLike::light_on`][].
+    LightOn {
+        /// The value of red light.
+        red: u8,
+        /// The value of green light.
+        green: u8,
+        /// The value of blue light.
+        blue: u8,
+        /// The duration.
+        duration: Option<Duration>,
+    },
+    /// [`CubeLike::light_off`][].
+    LightOff,
+}
+
+#[derive(Debug)]
+struct State {
+    calls: Vec<Call>,
+    battery: usize,
+    button: bool,
+    position: Option<Position>,
+    std_id: Option<StdId>,
+    error: Option<String>,
+}
+
+/// Test double of a cube, which records the operations instead of sending them.
+///
+/// The sensor values are given by [`FakeCube::emit`][], which also delivers the event to
+/// the subscribers. [`CubeLike::goto`][] moves the position to the target at once.
+/// Clones share the state, so keep one to inspect the fake passed to the logic under test.
+#[derive(Debug, Clone)]
+pub struct FakeCube {
+    id: String,
+    state: Arc<Mutex<State>>,
+    tx: broadcast::Sender<Event>,
+}
+
+impl FakeCube {
+    /// Creates a fake on the mat with the full battery.
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            state: Arc::new(Mutex::new(State {
+                calls: vec![],
+                battery: 100,
+                button: false,
+                position: None,
+                std_id: None,
+                error: None,
+            })),
+            tx: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Updates the sensor value by the event, and delivers it to the subscribers.
+    pub fn emit(&self, event: Event) {
+        {
+            let mut state = self.state.lock().unwrap();
+            match &event {
+                Event::Battery(v) | Event::BatteryLow(v) => state.battery = *v,
+                Event::Button(v) => state.button = *v,
+                Event::Position(v) => state.position = v.clone(),
+                Event::StdId(v) => state.std_id = v.clone(),
+                _ => {}
+            }
+        }
+        let _ = self.tx.send(event);
+    }
+
+    /// Makes the operations fail with the message until cleared by `None`.
+    pub fn fail(&self, error: Option<&str>) {
+        self.state.lock().unwrap().error = error.map(|e| e.to_string());
+    }
+
+    /// Gets the operations requested so far.
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Clears the operations requested so far.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().calls.clear();
+    }
+
+    /// Gets the wheel speeds of the last motor operation. `(0, 0)` if stopped.
+    pub fn speeds(&self) -> Option<(isize, isize)> {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .iter()
+            .rev()
+            .find_map(|call| match call {
+                Call::Go { left, right, .. } => Some((*left, *right)),
+                Call::Stop => Some((0, 0)),
+                _ => None,
+            })
+    }
+
+    fn call(&self, call: Call) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(e) = &state.error {
+            return Err(anyhow!("{}", e));
+        }
+        state.calls.push(call);
+        Ok(())
+    }
+
+    fn get<T>(&self, f: impl FnOnce(&State) -> T) -> Result<T> {
+        let state = self.state.lock().unwrap();
+        match &state.error {
+            Some(e) => Err(anyhow!("{}", e)),
+            None => Ok(f(&state)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CubeLike for FakeCube {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn go(&mut self, left: isize, right: isize, duration: Option<Duration>) -> Result<()> {
+        if !(-100..=100).contains(&left) || !(-100..=100).contains(&right) {
+            return Err(anyhow!("Wheel speed must be between -100 and 100"));
+        }
+        self.call(Call::Go {
+            left,
+            right,
+            duration,
+        })
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.call(Call::Stop)
+    }
+
+    async fn goto(&mut self, target: MotorTarget) -> Result<()> {
+        let pos = self.get(|s| s.position.clone())?;
+        let pos = pos.ok_or_else(|| anyhow!("Couldn't reach the target: IdMissed"))?;
+        let keep = |v: u16, cur: u16| if v == KEEP_COORDINATE { cur } else { v };
+        let to = Position::new(keep(target.x, pos.x), keep(target.y, pos.y), pos.angle);
+        self.call(Call::Goto(target))?;
+        self.emit(Event::Position(Some(to)));
+        Ok(())
+    }
+
+    async fn play_preset(&mut self, id: SoundPresetId) -> Result<()> {
+        self.call(Call::PlayPreset(id))
+    }
+
+    async fn play(&mut self, repeat: Repeat, ops: Vec<SoundOp>) -> Result<()> {
+        self.call(Call::Play(repeat, ops))
+    }
+
+    async fn stop_sound(&mut self) -> Result<()> {
+        self.call(Call::StopSound)
+    }
+
+    async fn light(&mut self, repeat: Repeat, ops: Vec<LightOp>) -> Result<()> {
+        self.call(Call::Light(repeat, ops))
+    }
+
+    async fn light_on(
+        &mut self,
+        red: u8,
+        green: u8,
+        blue: u8,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        self.call(Call::LightOn {
+            red,
+            green,
+            blue,
+            duration,
+        })
+    }
+
+    async fn light_off(&mut self) -> Result<()> {
+        self.call(Call::LightOff)
+    }
+
+    async fn battery(&mut self) -> Result<usize> {
+        self.get(|s| s.battery)
+    }
+
+    async fn button(&mut self) -> Result<bool> {
+        self.get(|s| s.button)
+    }
+
+    async fn position(&mut self) -> Result<Option<Position>> {
+        self.get(|s| s.position.clone())
+    }
+
+    async fn std_id(&mut self) -> Result<Option<StdId>> {
+        self.get(|s| s.std_id.clone())
+    }
+
+    async fn events(&mut self) -> Result<EventStream> {
+        self.get(|_| ())?;
+        Ok(self
+            .tx
+            .subscribe()
+            .into_stream()
+            .filter_map(|e| future::ready(e.ok()))
+            .boxed())
+    }
+}
@@ -0,0 +1,397 @@
+//! Stable `extern "C"` functions to back bindings for other languages.
+//! Build a shared or static library with:
+//!
+//! ```sh
+//! $ cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! and include `include/toio.h`.
+//!
+//! * Functions returning `int` return `0` on success and `-1` on failure.
+//! * Functions returning a pointer return `NULL` on failure.
+//! * On failure, the message is available from
+//!   [`toio_last_error`](crate::ffi::toio_last_error) on the same thread.
+//! * Cubes returned by the API must be released by [`toio_cube_free`](crate::ffi::toio_cube_free).
+//!
+//! All the calls are driven on a runtime shared by the library,
+//! so they must not be called from inside a tokio runtime.
+
+use anyhow::{anyhow, bail, Result};
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+
+use crate::{
+    runtime::{block_on, runtime},
+    Cube, Event, EventStream, SoundPresetId,
+};
+
+/// A cube handle.
+pub struct ToioCube {
+    cube: Cube,
+    id: CString,
+    events: Option<EventStream>,
+}
+
+/// The kind of [`ToioEvent`][].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToioEventKind {
+    /// No event.
+    None = 0,
+    /// Battery is updated. `value` is the level in percent.
+    Battery = 1,
+    /// Battery falls to the threshold. `value` is the level in percent.
+    BatteryLow = 2,
+    /// Collision. `value` is `1` if detected.
+    Collision = 3,
+    /// Slope. `value` is `1` if detected.
+    Slope = 4,
+    /// Button. `value` is `1` if pressed.
+    Button = 5,
+    /// Posture. `value` is the raw posture value.
+    Posture = 6,
+    /// Position on the mat. `x`, `y` and `angle` are set.
+    Position = 7,
+    /// The cube is moved off the mat.
+    PositionMissed = 8,
+    /// Standard id. `value` is the id and `angle` is set.
+    StdId = 9,
+    /// The standard id is missed.
+    StdIdMissed = 10,
+    /// The cube doesn't respond.
+    Unresponsive = 11,
+    /// Events are dropped. `value` is the number of dropped events.
+    Lagged = 12,
+    /// Another cube comes close. `value` is the distance.
+    Proximity = 13,
+    /// Other events.
+    Other = 255,
+}
+
+/// An event from a cube.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ToioEvent {
+    /// The kind of the event.
+    pub kind: ToioEventKind,
+    /// The value of the event. See [`ToioEventKind`][].
+    pub value: i64,
+    /// The x coordinate on the mat.
+    pub x: u16,
+    /// The y coordinate on the mat.
+    pub y: u16,
+    /// The angle in degrees.
+    pub angle: u16,
+}
+
+impl From<Event> for ToioEvent {
+    fn from(event: Event) -> Self {
+        let value = |kind, value: i64| ToioEvent {
+            kind,
+            value,
+            x: 0,
+            y: 0,
+            angle: 0,
+        };
+        match event {
+            Event::Battery(v) => value(ToioEventKind::Battery, v as i64),
+            Event::BatteryLow(v) => value(ToioEventKind::BatteryLow, v as i64),
+            Event::Collision(v) => value(ToioEventKind::Collision, v as i64),
+            Event::Slope(v) => value(ToioEventKind::Slope, v as i64),
+            Event::Button(v) => value(ToioEventKind::Button, v as i64),
+            Event::Posture(v) => value(ToioEventKind::Posture, v as i64),
+            Event::Position(Some(p)) => ToioEvent {
+                x: p.x,
+                y: p.y,
+                angle: p.angle,
+                ..value(ToioEventKind::Position, 0)
+            },
+            Event::Position(None) => value(ToioEventKind::PositionMissed, 0),
+            Event::StdId(Some(id)) => ToioEvent {
+                angle: id.angle,
+                ..value(ToioEventKind::StdId, id.id as i64)
+            },
+            Event::StdId(None) => value(ToioEventKind::StdIdMissed, 0),
+            Event::Unresponsive => value(ToioEventKind::Unresponsive, 0),
+            Event::Lagged(n) => value(ToioEventKind::Lagged, n as i64),
+            Event::Proximity { distance, .. } => value(ToioEventKind::Proximity, distance as i64),
+            Event::Version(_)
+            | Event::Motion(_)
+            | Event::MagneticForce(_)
+            | Event::NotificationGap { .. } => value(ToioEventKind::Other, 0),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(e: anyhow::Error) {
+    let msg = CString::new(format!("{:#}", e).replace('\0', ""))
+        .unwrap_or_else(|_| CString::new("Unknown error").unwrap());
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+}
+
+/// Runs the function, converting errors and panics into the return value.
+fn guard<T, F: FnOnce() -> Result<T>>(fail: T, f: F) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => {
+            set_error(e);
+            fail
+        }
+        Err(_) => {
+            set_error(anyhow!("Panicked"));
+            fail
+        }
+    }
+}
+
+/// Gets the cube of the handle, failing if it's `NULL`.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+unsafe fn cube<'a>(cube: *mut ToioCube) -> Result<&'a mut ToioCube> {
+    cube.as_mut().ok_or_else(|| anyhow!("Cube is null"))
+}
+
+fn duration(ms: u32) -> Option<Duration> {
+    match ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms as u64)),
+    }
+}
+
+/// Converts a cube into a handle for the C API.
+///
+/// Lets a Rust host hand cubes to plugins using the C API.
+/// The cube is driven on the runtime of the library after this.
+pub fn cube_into_raw(cube: Cube) -> *mut ToioCube {
+    let id = CString::new(cube.id().replace('\0', "")).unwrap_or_default();
+    Box::into_raw(Box::new(ToioCube {
+        cube,
+        id,
+        events: None,
+    }))
+}
+
+/// Gets the message of the last error on the thread, or `NULL` if there's no error.
+///
+/// The string is valid until the next call failing on the same thread.
+#[no_mangle]
+pub extern "C" fn toio_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(msg) => msg.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Searches for the nearest cube for up to `timeout_ms` milliseconds.
+#[no_mangle]
+pub extern "C" fn toio_search(timeout_ms: u32) -> *mut ToioCube {
+    guard(ptr::null_mut(), || {
+        let timeout = Duration::from_millis(timeout_ms as u64);
+        let cube = block_on(Cube::search().nearest_timeout(timeout))?;
+        Ok(cube_into_raw(cube))
+    })
+}
+
+/// Searches for cubes for `timeout_ms` milliseconds.
+///
+/// Stores up to `cap` cubes to `out` and returns the number of the stored cubes, or `-1` on failure.
+///
+/// # Safety
+///
+/// `out` must be valid for `cap` writes.
+#[no_mangle]
+pub unsafe extern "C" fn toio_search_all(
+    out: *mut *mut ToioCube,
+    cap: usize,
+    timeout_ms: u32,
+) -> c_int {
+    guard(-1, || {
+        if out.is_null() && cap > 0 {
+            bail!("Output is null");
+        }
+        let timeout = Duration::from_millis(timeout_ms as u64);
+        let cubes = block_on(Cube::search().all_timeout(timeout))?;
+        let mut n = 0;
+        for cube in cubes.into_iter().take(cap) {
+            *out.add(n) = cube_into_raw(cube);
+            n += 1;
+        }
+        Ok(n as c_int)
+    })
+}
+
+/// Releases the cube.
+///
+/// # Safety
+///
+/// `cube` must be returned by this API and must not be used after this.
+#[no_mangle]
+pub unsafe extern "C" fn toio_cube_free(cube: *mut ToioCube) {
+    if !cube.is_null() {
+        drop(Box::from_raw(cube));
+    }
+}
+
+/// Gets the id of the cube. The string is valid until the cube is released.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn toio_cube_id(cube: *mut ToioCube) -> *const c_char {
+    guard(ptr::null(), || Ok(self::cube(cube)?.id.as_ptr()))
+}
+
+/// Connects to the cube.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn toio_connect(cube: *mut ToioCube) -> c_int {
+    guard(-1, || {
+        let c = self::cube(cube)?;
+        block_on(async {
+            c.cube.connect().await?;
+            c.events = Some(c.cube.events().await?);
+            Ok(0)
+        })
+    })
+}
+
+/// Runs the motors with the speeds from `-100` to `100`.
+///
+/// Runs for `duration_ms` milliseconds, or until stopped if it's `0`.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn toio_go(
+    cube: *mut ToioCube,
+    left: c_int,
+    right: c_int,
+    duration_ms: u32,
+) -> c_int {
+    guard(-1, || {
+        let c = self::cube(cube)?;
+        let (left, right) = (left as isize, right as isize);
+        block_on(c.cube.go(left, right, duration(duration_ms)))?;
+        Ok(0)
+    })
+}
+
+/// Stops the motors.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn toio_stop(cube: *mut ToioCube) -> c_int {
+    guard(-1, || {
+        block_on(self::cube(cube)?.cube.stop())?;
+        Ok(0)
+    })
+}
+
+/// Turns on the light for `duration_ms` milliseconds, or until turned off if it's `0`.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn toio_light_on(
+    cube: *mut ToioCube,
+    red: u8,
+    green: u8,
+    blue: u8,
+    duration_ms: u32,
+) -> c_int {
+    guard(-1, || {
+        let c = self::cube(cube)?;
+        block_on(c.cube.light_on(red, green, blue, duration(duration_ms)))?;
+        Ok(0)
+    })
+}
+
+/// Turns off the light.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn toio_light_off(cube: *mut ToioCube) -> c_int {
+    guard(-1, || {
+        block_on(self::cube(cube)?.cube.light_off())?;
+        Ok(0)
+    })
+}
+
+/// Plays the preset sound from `0` to `10`.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn toio_play_preset(cube: *mut ToioCube, id: u8) -> c_int {
+    guard(-1, || {
+        let c = self::cube(cube)?;
+        let id: SoundPresetId = serde_json::from_value(id.into())
+            .map_err(|_| anyhow!("Invalid preset sound: {}", id))?;
+        block_on(c.cube.play_preset(id))?;
+        Ok(0)
+    })
+}
+
+/// Waits for an event for up to `timeout_ms` milliseconds. Doesn't wait if it's `0`.
+///
+/// Returns `1` and stores the event to `event` if any, `0` if there's no event, or `-1` on failure.
+/// The events are queued since the cube is connected.
+///
+/// # Safety
+///
+/// `cube` must be `NULL` or returned by this API and not released yet,
+/// and `event` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn toio_poll_event(
+    cube: *mut ToioCube,
+    event: *mut ToioEvent,
+    timeout_ms: u32,
+) -> c_int {
+    guard(-1, || {
+        let c = self::cube(cube)?;
+        if event.is_null() {
+            bail!("Event is null");
+        }
+        let events = c
+            .events
+            .as_mut()
+            .ok_or_else(|| anyhow!("Cube is not connected"))?;
+        let next = if timeout_ms == 0 {
+            runtime().enter(|| events.next().now_or_never().flatten())
+        } else {
+            let timeout = Duration::from_millis(timeout_ms as u64);
+            block_on(async { tokio::time::timeout(timeout, events.next()).await })
+                .ok()
+                .flatten()
+        };
+        match next {
+            Some(e) => {
+                *event = e.into();
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    })
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use log::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use tokio::sync::{Mutex, Notify};
+
+use crate::{
+    ble::{self, PeripheralOps, Uuid},
+    proto::*,
+    EventFilter,
+};
+
+/// The characteristics whose notifications follow the live event filters.
+const FILTERED: [Uuid; 4] = [UUID_ID, UUID_MOTION, UUID_BUTTON, UUID_BATTERY];
+
+/// The filters registered and the settings by the user.
+#[derive(Default)]
+struct State {
+    live: Vec<Weak<EventFilter>>,
+    /// The notifications set by [`Cube::set_notifications`](crate::Cube::set_notifications).
+    overrides: HashMap<Uuid, bool>,
+    /// The filters are followed only while connected.
+    connected: bool,
+}
+
+/// Enables/disables the notifications as required by the live event filters.
+///
+/// The filters are registered by the event streams and the tasks of the cube,
+/// and the notifications are updated again when a registration is dropped.
+/// The notifications set by the user override the filters.
+#[derive(Clone)]
+pub(crate) struct Filters {
+    state: Arc<std::sync::Mutex<State>>,
+    /// The characteristics whose notifications are disabled on the peripheral.
+    disabled: Arc<Mutex<Vec<Uuid>>>,
+    changed: Arc<Notify>,
+    dev: Arc<Mutex<ble::Peripheral>>,
+    profile: Profile,
+}
+
+impl Filters {
+    pub(crate) fn new(dev: Arc<Mutex<ble::Peripheral>>, profile: Profile) -> Self {
+        Self {
+            state: Arc::default(),
+            disabled: Arc::default(),
+            changed: Arc::new(Notify::new()),
+            dev,
+            profile,
+        }
+    }
+
+    /// Registers the filter until the registration is dropped.
+    ///
+    /// The notifications are updated in the background; call [`Filters::apply`][]
+    /// to wait for the update.
+    pub(crate) fn register(&self, filter: EventFilter) -> Registration {
+        let filter = Arc::new(filter);
+        let mut state = self.state.lock().unwrap();
+        state.live.retain(|f| f.strong_count() > 0);
+        state.live.push(Arc::downgrade(&filter));
+        self.changed.notify();
+        Registration {
+            filter: Some(filter),
+            changed: self.changed.clone(),
+        }
+    }
+
+    /// Enables/disables the notifications of the characteristic regardless of the filters.
+    pub(crate) async fn set(&self, uuid: Uuid, enable: bool) -> Result<()> {
+        self.state.lock().unwrap().overrides.insert(uuid, enable);
+        self.apply().await
+    }
+
+    /// Enables/disables the notifications as required by the live filters.
+    pub(crate) async fn apply(&self) -> Result<()> {
+        // Held while writing, so that concurrent updates are applied in order.
+        let mut disabled = self.disabled.lock().await;
+        let targets: Vec<_> = {
+            let state = self.state.lock().unwrap();
+            let wanted: Vec<_> = state
+                .live
+                .iter()
+                .filter_map(Weak::upgrade)
+                .flat_map(|f| f.uuids())
+                .collect();
+            let filtered = FILTERED
+                .iter()
+                .filter(|uuid| state.connected && !state.overrides.contains_key(uuid))
+                .map(|uuid| (*uuid, wanted.contains(uuid)));
+            let overrides = state
+                .overrides
+                .iter()
+                .map(|(uuid, enable)| (*uuid, *enable));
+            filtered.chain(overrides).collect()
+        };
+
+        let mut dev = self.dev.lock().await;
+        for (uuid, enable) in targets {
+            if disabled.contains(&uuid) != enable {
+                continue;
+            }
+            dev.notify(&self.profile.resolve(&uuid), enable).await?;
+            if enable {
+                disabled.retain(|u| *u != uuid);
+            } else {
+                disabled.push(uuid);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts following the filters on connection, where the peripheral notifies
+    /// from all the characteristics.
+    pub(crate) async fn start(&self) {
+        let mut disabled = self.disabled.lock().await;
+        disabled.clear();
+        self.state.lock().unwrap().connected = true;
+    }
+
+    /// Updates the notifications whenever a filter is registered or dropped.
+    pub(crate) async fn run(self) {
+        loop {
+            self.changed.notified().await;
+            if let Err(e) = self.apply().await {
+                debug!("Couldn't update notifications: {}", e);
+            }
+        }
+    }
+}
+
+/// Keeps the filter registered while alive.
+pub(crate) struct Registration {
+    filter: Option<Arc<EventFilter>>,
+    changed: Arc<Notify>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // Released before the update, so that the filter is no longer counted.
+        self.filter.take();
+        self.changed.notify();
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::Position;
+
+/// The number of intervals to learn before detecting gaps.
+const WARMUP: usize = 4;
+
+/// The ratio of the interval to the usual one to regard as a gap.
+const GAP_RATIO: u32 = 3;
+
+/// Detects notifications dropped on the way from the arrival times.
+///
+/// The cube notifies the position at a steady rate while it's on the mat,
+/// so a much longer interval than usual means the notifications were lost,
+/// e.g., by BLE congestion. Intervals without movement aren't counted, because
+/// the cube may skip notifications of the same position by design.
+#[derive(Debug, Default)]
+pub(crate) struct GapDetector {
+    last: Option<(Instant, Position)>,
+    interval: Option<Duration>,
+    samples: usize,
+}
+
+impl GapDetector {
+    /// Adds the position notified at the time, or `None` if the cube left the mat.
+    ///
+    /// Returns the estimated number of the notifications missed before this one.
+    pub fn push(&mut self, at: Instant, pos: Option<Position>) -> Option<usize> {
+        let pos = match pos {
+            Some(pos) => pos,
+            None => {
+                self.last = None;
+                return None;
+            }
+        };
+        let (last_at, last_pos) = self.last.replace((at, pos.clone()))?;
+        let elapsed = at.duration_since(last_at);
+
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => {
+                self.interval = Some(elapsed);
+                self.samples += 1;
+                return None;
+            }
+        };
+        if pos == last_pos && elapsed > interval * GAP_RATIO {
+            return None;
+        }
+
+        // Follows the rate slowly, e.g., changed by `Cube::set_position_rate`.
+        self.interval = Some((interval * 7 + elapsed) / 8);
+        self.samples += 1;
+
+        if self.samples <= WARMUP || elapsed <= interval * GAP_RATIO {
+            return None;
+        }
+        let missed = elapsed.as_secs_f64() / interval.as_secs_f64();
+        Some((missed.round() as usize).saturating_sub(1))
+    }
+}
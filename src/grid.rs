@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::{proto::MotorTarget, Cube};
+
+/// A cell of [`Grid`][] by the row and the column.
+pub type Cell = (usize, usize);
+
+/// Grid of square cells over the mat coordinates, for puzzle and maze games.
+///
+/// Rows go down the y-axis, and columns go right along the x-axis of the mat.
+///
+/// ```
+/// use toio::grid::Grid;
+///
+/// // 4x4 cells of 100 units from the corner of the mat of toio collection.
+/// let grid = Grid::new((45, 45), 100, 4, 4).blocked(&[(1, 0), (1, 1), (1, 2)]);
+///
+/// assert_eq!(grid.center((0, 0)), Some((95, 95)));
+/// assert_eq!(grid.cell_at(260, 180), Some((1, 2)));
+///
+/// // Goes around the wall.
+/// let path = grid.path((0, 0), (2, 0)).unwrap();
+/// assert_eq!(path.len(), 9);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Grid {
+    origin: (u16, u16),
+    size: u16,
+    rows: usize,
+    cols: usize,
+    speed: u8,
+    blocked: HashSet<Cell>,
+}
+
+impl Grid {
+    /// Creates the grid with the top-left corner of the cell `(0, 0)` at `origin`,
+    /// and the cells of `size` units.
+    pub fn new(origin: (u16, u16), size: u16, rows: usize, cols: usize) -> Self {
+        Self {
+            origin,
+            size,
+            rows,
+            cols,
+            speed: 50,
+            blocked: HashSet::new(),
+        }
+    }
+
+    /// Sets the maximum speed to move between the cells from 10 to 115. By default, it's 50.
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Blocks the cells, which the paths go around.
+    pub fn blocked(mut self, cells: &[Cell]) -> Self {
+        self.blocked.extend(cells.iter().copied());
+        self
+    }
+
+    /// Blocks or unblocks the cell.
+    pub fn set_blocked(&mut self, cell: Cell, blocked: bool) {
+        if blocked {
+            self.blocked.insert(cell);
+        } else {
+            self.blocked.remove(&cell);
+        }
+    }
+
+    /// Returns `true` if the cell is blocked.
+    pub fn is_blocked(&self, cell: Cell) -> bool {
+        self.blocked.contains(&cell)
+    }
+
+    /// Returns `true` if the cell is in the grid.
+    pub fn contains(&self, (row, col): Cell) -> bool {
+        row < self.rows && col < self.cols
+    }
+
+    /// Gets the mat coordinates of the center of the cell.
+    pub fn center(&self, cell: Cell) -> Option<(u16, u16)> {
+        if !self.contains(cell) {
+            return None;
+        }
+        let at = |origin: u16, i: usize| {
+            origin as usize + self.size as usize * i + self.size as usize / 2
+        };
+        let x = at(self.origin.0, cell.1);
+        let y = at(self.origin.1, cell.0);
+        if x > u16::MAX as usize || y > u16::MAX as usize {
+            return None;
+        }
+        Some((x as u16, y as u16))
+    }
+
+    /// Gets the cell at the mat coordinates.
+    pub fn cell_at(&self, x: u16, y: u16) -> Option<Cell> {
+        if x < self.origin.0 || y < self.origin.1 || self.size == 0 {
+            return None;
+        }
+        let col = ((x - self.origin.0) / self.size) as usize;
+        let row = ((y - self.origin.1) / self.size) as usize;
+        Some((row, col)).filter(|&cell| self.contains(cell))
+    }
+
+    /// Gets the unblocked cells next to the cell, up, down, left and right.
+    pub fn neighbors(&self, (row, col): Cell) -> Vec<Cell> {
+        let mut cells = Vec::with_capacity(4);
+        if row > 0 {
+            cells.push((row - 1, col));
+        }
+        cells.push((row + 1, col));
+        if col > 0 {
+            cells.push((row, col - 1));
+        }
+        cells.push((row, col + 1));
+        cells.retain(|&cell| self.contains(cell) && !self.is_blocked(cell));
+        cells
+    }
+
+    /// Plans the shortest path between the cells with A* search.
+    ///
+    /// The path includes both ends. Returns `None` if the goal is unreachable.
+    pub fn path(&self, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        if !self.contains(from) || !self.contains(to) || self.is_blocked(to) {
+            return None;
+        }
+
+        let h = |(r, c): Cell| r.max(to.0) - r.min(to.0) + c.max(to.1) - c.min(to.1);
+        let mut open = BinaryHeap::new();
+        let mut costs = HashMap::new();
+        let mut parents = HashMap::new();
+        open.push(Reverse((h(from), 0, from)));
+        costs.insert(from, 0);
+
+        while let Some(Reverse((_, cost, cell))) = open.pop() {
+            if cell == to {
+                let mut path = vec![to];
+                while let Some(&parent) = parents.get(path.last().unwrap()) {
+                    path.push(parent);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if matches!(costs.get(&cell), Some(&c) if c < cost) {
+                continue;
+            }
+            for next in self.neighbors(cell) {
+                let cost = cost + 1;
+                if !matches!(costs.get(&next), Some(&c) if c <= cost) {
+                    costs.insert(next, cost);
+                    parents.insert(next, cell);
+                    open.push(Reverse((cost + h(next), cost, next)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Moves the cube to the center of the cell in a straight line.
+    pub async fn goto_cell(&self, cube: &mut Cube, cell: Cell) -> Result<()> {
+        let (x, y) = self
+            .center(cell)
+            .ok_or_else(|| anyhow!("Cell {:?} is out of the grid", cell))?;
+        cube.goto(MotorTarget::builder(x, y).max_speed(self.speed).build()?)
+            .await
+    }
+
+    /// Moves the cube to the center of the cell along the path around the blocked cells.
+    ///
+    /// The cube stops only at the turns of the path.
+    ///
+    /// ```no_run
+    /// use toio::{grid::Grid, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let grid = Grid::new((45, 45), 100, 4, 4).blocked(&[(1, 1), (2, 1)]);
+    ///     grid.navigate(&mut cube, (3, 3)).await.unwrap();
+    /// }
+    /// ```
+    pub async fn navigate(&self, cube: &mut Cube, to: Cell) -> Result<()> {
+        let pos = cube
+            .position_now()
+            .await?
+            .ok_or_else(|| anyhow!("Cube is not on the mat"))?;
+        let from = self
+            .cell_at(pos.x, pos.y)
+            .ok_or_else(|| anyhow!("Cube is out of the grid"))?;
+        let path = self
+            .path(from, to)
+            .ok_or_else(|| anyhow!("No path from {:?} to {:?}", from, to))?;
+
+        for (i, &cell) in path.iter().enumerate().skip(1) {
+            let turn = match path.get(i + 1) {
+                Some(&next) => step(path[i - 1], cell) != step(cell, next),
+                None => true,
+            };
+            if turn {
+                self.goto_cell(cube, cell).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn step(from: Cell, to: Cell) -> (isize, isize) {
+    (
+        to.0 as isize - from.0 as isize,
+        to.1 as isize - from.1 as isize,
+    )
+}
@@ -0,0 +1,194 @@
+use anyhow::Result;
+use futures::{
+    future::{abortable, AbortHandle},
+    prelude::*,
+    stream,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{Cube, Event, EventFilter, Position};
+
+/// The pose of a cube seen from another cube.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RelativePose {
+    /// The distance in mat units.
+    pub distance: f32,
+    /// The direction in degrees from the heading of the observer, from -180 to 180.
+    /// Positive is clockwise on the mat, i.e., to the right.
+    pub bearing: f32,
+}
+
+impl RelativePose {
+    /// Gets the pose of `target` seen from `observer`.
+    ///
+    /// ```
+    /// use toio::{Position, RelativePose};
+    ///
+    /// // The observer faces down the mat, and the target is to the left of it.
+    /// let pose = RelativePose::between(&Position::new(100, 100, 90), &Position::new(150, 100, 0));
+    /// assert_eq!(pose.distance, 50.0);
+    /// assert_eq!(pose.bearing, -90.0);
+    /// ```
+    pub fn between(observer: &Position, target: &Position) -> Self {
+        let dx = target.x as f32 - observer.x as f32;
+        let dy = target.y as f32 - observer.y as f32;
+        let bearing = (dy.atan2(dx).to_degrees() - observer.angle as f32).rem_euclid(360.0);
+        Self {
+            distance: (dx * dx + dy * dy).sqrt(),
+            bearing: if bearing > 180.0 {
+                bearing - 360.0
+            } else {
+                bearing
+            },
+        }
+    }
+}
+
+/// Cubes on the same mat.
+///
+/// Keeps track of the latest positions of the cubes from their position events.
+///
+/// ```no_run
+/// use toio::{Cube, CubeGroup};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cubes = Cube::search().all().await.unwrap();
+///     for cube in &mut cubes {
+///         cube.connect().await.unwrap();
+///     }
+///     let group = CubeGroup::new(cubes).await.unwrap();
+///
+///     if let Some(pose) = group.relative_pose(0, 1) {
+///         println!("cube 1 is {} away at {} degrees", pose.distance, pose.bearing);
+///     }
+/// }
+/// ```
+pub struct CubeGroup {
+    cubes: Vec<Cube>,
+    positions: Arc<Mutex<Vec<Option<Position>>>>,
+    tasks: Vec<AbortHandle>,
+}
+
+impl CubeGroup {
+    /// Creates the group of the cubes, which are connected already.
+    pub async fn new(mut cubes: Vec<Cube>) -> Result<Self> {
+        let positions = Arc::new(Mutex::new(vec![None; cubes.len()]));
+        let mut tasks = vec![];
+        for (i, cube) in cubes.iter_mut().enumerate() {
+            let mut events = cube.events_filtered(EventFilter::new().position()).await?;
+            let positions = positions.clone();
+            let (task, handle) = abortable(async move {
+                while let Some(event) = events.next().await {
+                    if let Event::Position(pos) = event {
+                        positions.lock().unwrap()[i] = pos;
+                    }
+                }
+            });
+            tokio::spawn(task);
+            tasks.push(handle);
+        }
+        Ok(Self {
+            cubes,
+            positions,
+            tasks,
+        })
+    }
+
+    /// Gets the number of the cubes.
+    pub fn len(&self) -> usize {
+        self.cubes.len()
+    }
+
+    /// Returns `true` if the group has no cube.
+    pub fn is_empty(&self) -> bool {
+        self.cubes.is_empty()
+    }
+
+    /// Gets the cubes.
+    pub fn cubes(&self) -> &[Cube] {
+        &self.cubes
+    }
+
+    /// Gets the cubes to control them.
+    pub fn cubes_mut(&mut self) -> &mut [Cube] {
+        &mut self.cubes
+    }
+
+    /// Gets the latest position of the cube.
+    ///
+    /// Returns `None` if the cube is off the mat, or hasn't sent the position yet.
+    pub fn position(&self, cube: usize) -> Option<Position> {
+        self.positions.lock().unwrap().get(cube).cloned().flatten()
+    }
+
+    /// Gets the pose of the cube `b` seen from the cube `a`, from their latest positions.
+    ///
+    /// Returns `None` if either position is unknown.
+    pub fn relative_pose(&self, a: usize, b: usize) -> Option<RelativePose> {
+        Some(RelativePose::between(
+            &self.position(a)?,
+            &self.position(b)?,
+        ))
+    }
+
+    /// Runs the operation on every cube concurrently, and gets the result of each by id.
+    ///
+    /// At most `limit` cubes run the operation at once, e.g., to keep the BLE adapter from
+    /// congestion. A failure on a cube doesn't stop the operation on the others.
+    ///
+    /// ```no_run
+    /// use toio::{Cube, CubeGroup};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cubes = Cube::search().all().await.unwrap();
+    ///     for cube in &mut cubes {
+    ///         cube.connect().await.unwrap();
+    ///     }
+    ///     let mut group = CubeGroup::new(cubes).await.unwrap();
+    ///
+    ///     let results = group
+    ///         .try_for_all(4, |cube| cube.light_on(0, 0, 255, None))
+    ///         .await;
+    ///     for (id, res) in results {
+    ///         if let Err(e) = res {
+    ///             println!("{} failed: {}", id, e);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn try_for_all<'a, T, F, Fut>(
+        &'a mut self,
+        limit: usize,
+        mut f: F,
+    ) -> HashMap<String, Result<T>>
+    where
+        F: FnMut(&'a mut Cube) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        stream::iter(self.cubes.iter_mut())
+            .map(|cube| {
+                let id = cube.id().to_string();
+                f(cube).map(move |res| (id, res))
+            })
+            .buffer_unordered(limit.max(1))
+            .collect()
+            .await
+    }
+
+    /// Takes the cubes out of the group.
+    pub fn into_cubes(mut self) -> Vec<Cube> {
+        std::mem::take(&mut self.cubes)
+    }
+}
+
+impl Drop for CubeGroup {
+    fn drop(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use futures::{
+    future::{abortable, AbortHandle},
+    prelude::*,
+    stream::BoxStream,
+};
+use log::*;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    ble::{self, PeripheralOps, Uuid},
+    metrics,
+    proto::*,
+    queue::{self, Overflow, Recv},
+};
+
+struct Subscriber {
+    uuids: Option<Vec<Uuid>>,
+    tx: queue::Sender<Message>,
+}
+
+impl Subscriber {
+    fn wants(&self, uuid: &Uuid) -> bool {
+        self.uuids
+            .as_ref()
+            .map(|u| u.contains(uuid))
+            .unwrap_or(true)
+    }
+}
+
+/// Decodes messages from the peripheral once and fans them out to subscribers.
+pub(crate) struct Hub {
+    subscribers: Arc<Mutex<Vec<Arc<Subscriber>>>>,
+    handle: Option<AbortHandle>,
+    capacity: usize,
+    overflow: Overflow,
+    profile: Profile,
+    strict: bool,
+}
+
+impl Hub {
+    /// Creates a hub whose subscriber channels have the given capacity and overflow policy.
+    ///
+    /// Messages are decoded in accordance with the profile, rejecting trailing bytes if `strict`.
+    pub fn new(capacity: usize, overflow: Overflow, profile: Profile, strict: bool) -> Self {
+        Self {
+            subscribers: Arc::new(Mutex::new(vec![])),
+            handle: None,
+            capacity,
+            overflow,
+            profile,
+            strict,
+        }
+    }
+
+    /// Starts the background task to receive messages from the peripheral.
+    ///
+    /// Does nothing if already started.
+    pub fn start(&mut self, dev: &mut ble::Peripheral) -> Result<()> {
+        if self.handle.is_some() {
+            return Ok(());
+        }
+
+        let mut rx = dev.subscribe_lagging()?;
+        let subscribers = self.subscribers.clone();
+        let profile = self.profile;
+        let strict = self.strict;
+        let (task, handle) = abortable(async move {
+            while let Some(value) = rx.next().await {
+                let (uuid, value) = match value {
+                    Ok(value) => value,
+                    Err(ble::Lagged(n)) => {
+                        warn!("Dropped {} notifications as the hub fell behind", n);
+                        lag(&subscribers, n);
+                        continue;
+                    }
+                };
+                metrics::notification(&profile, &uuid);
+                let msg = if strict {
+                    profile.decode_strict(&uuid, &value)
+                } else {
+                    profile.decode(&uuid, &value)
+                };
+                match msg {
+                    Ok(msg) => dispatch(&subscribers, msg).await,
+                    Err(e) => {
+                        metrics::decode_error(&profile, &uuid);
+                        warn!("Error on handling events: {}", e)
+                    }
+                }
+            }
+        });
+        tokio::spawn(task);
+        self.handle = Some(handle);
+
+        Ok(())
+    }
+
+    /// Subscribes to messages.
+    ///
+    /// If `uuids` is specified, only messages of the characteristics are received.
+    /// The stream reports the number of messages dropped when the subscriber falls behind.
+    /// Dropping the stream unsubscribes.
+    pub fn subscribe(&self, uuids: Option<Vec<Uuid>>) -> BoxStream<'static, Recv<Message>> {
+        let (tx, rx) = queue::channel(self.capacity, self.overflow);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.tx.is_closed());
+        subscribers.push(Arc::new(Subscriber { uuids, tx }));
+        rx.into_stream()
+    }
+}
+
+impl Drop for Hub {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.as_ref() {
+            handle.abort();
+        }
+    }
+}
+
+/// Tells all the subscribers the number of messages dropped, which are of unknown characteristics.
+fn lag(subscribers: &Mutex<Vec<Arc<Subscriber>>>, missed: usize) {
+    for s in subscribers.lock().unwrap().iter() {
+        s.tx.lag(missed);
+    }
+}
+
+async fn dispatch(subscribers: &Mutex<Vec<Arc<Subscriber>>>, msg: Message) {
+    let uuid = msg.uuid();
+    let targets: Vec<_> = subscribers
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|s| s.wants(&uuid))
+        .cloned()
+        .collect();
+
+    for s in targets {
+        s.tx.send(msg.clone()).await;
+    }
+
+    // Removes the dropped subscribers, including those not interested in the message.
+    subscribers.lock().unwrap().retain(|s| !s.tx.is_closed());
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The BLE delay of a cube, measured by [`Cube::probe_latency`](crate::Cube::probe_latency).
+///
+/// A command takes about [`Latency::one_way`][] to reach the cube after it's written.
+/// To make several cubes act together, write to each cube earlier by its own delay.
+///
+/// ```
+/// use std::time::Duration;
+/// use toio::Latency;
+///
+/// let ms = Duration::from_millis;
+/// let latency = Latency::from_round_trips(&[ms(40), ms(30), ms(90), ms(34)]).unwrap();
+/// assert_eq!(latency.round_trip, ms(37));
+/// assert_eq!(latency.one_way(), Duration::from_micros(18500));
+/// assert_eq!(latency.jitter, ms(60));
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    /// The median of the round trip times of the writes with response.
+    pub round_trip: Duration,
+    /// The difference between the longest and the shortest round trip.
+    pub jitter: Duration,
+    /// The number of the round trips measured.
+    pub samples: usize,
+}
+
+impl Latency {
+    /// Summarizes the measured round trip times. `None` if there is no sample.
+    pub fn from_round_trips(samples: &[Duration]) -> Option<Self> {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let (min, max) = (*sorted.first()?, *sorted.last()?);
+        let n = sorted.len();
+        let round_trip = (sorted[(n - 1) / 2] + sorted[n / 2]) / 2;
+        Some(Self {
+            round_trip,
+            jitter: max - min,
+            samples: sorted.len(),
+        })
+    }
+
+    /// Gets the estimated delay from writing a command to the cube receiving it.
+    pub fn one_way(&self) -> Duration {
+        self.round_trip / 2
+    }
+}
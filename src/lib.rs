@@ -40,19 +40,198 @@
 //!     delay_for(Duration::from_secs(3)).await;
 //! }
 //! ```
+//!
+//! # Metrics
+//!
+//! With the `metrics` feature, the driver records the following metrics through
+//! the [`metrics`](https://docs.rs/metrics) facade. Install a recorder such as
+//! [`metrics-exporter-prometheus`](https://docs.rs/metrics-exporter-prometheus) to export them.
+//!
+//! | Name | Type | Labels |
+//! |------|------|--------|
+//! | `toio_write_latency_seconds` | histogram | `characteristic` |
+//! | `toio_notifications_total` | counter | `characteristic` |
+//! | `toio_decode_errors_total` | counter | `characteristic` |
+//! | `toio_connections_total` | counter | `cube` |
+//! | `toio_connection_failures_total` | counter | `cube` |
+//!
+//! # Time in tests
+//!
+//! Timeouts, schedulers and behaviors measure time only by the tokio clock
+//! ([`tokio::time::Instant`] and timers), never by the system clock. With the `test-util`
+//! feature of tokio, tests can freeze the clock by `tokio::time::pause()` and fast-forward
+//! it by `tokio::time::advance()`, e.g., together with the [`sim`][] cubes.
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature, only [`proto`][] is built with `no_std` and `alloc`,
+//! so that the message definitions and the codec can be reused, e.g., in firmware of
+//! embedded BLE centrals.
+//!
+//! ```toml
+//! toio = { version = "0.1", default-features = false }
+//! ```
 
-/// Abstracts BLE.
-#[macro_use]
-pub mod ble;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+// `derive-new` refers to `::std::default::Default`.
+#[cfg(not(feature = "std"))]
+extern crate core as std;
 
 /// Protocol data structures.
+#[macro_use]
 pub mod proto;
 
-mod cube;
+/// Abstracts BLE.
+#[cfg(feature = "std")]
+pub mod ble;
+
+/// Light animation.
+#[cfg(feature = "std")]
+pub mod light;
+
+/// Driving helpers.
+#[cfg(feature = "std")]
+pub mod drive;
+
+/// Supervision of many cubes.
+#[cfg(feature = "std")]
+pub mod swarm;
+
+/// Actions of several cubes at the same time.
+#[cfg(feature = "std")]
+pub mod schedule;
+
+/// Grid navigation on the mat.
+#[cfg(feature = "std")]
+pub mod grid;
+
+/// Autonomous behaviors and behavior trees.
+#[cfg(feature = "std")]
+pub mod behavior;
+
+/// Battery runtime estimation.
+#[cfg(feature = "std")]
+pub mod battery;
+
+/// Telemetry recording and replay.
+#[cfg(feature = "std")]
+pub mod record;
+
+/// Test doubles of cubes.
+#[cfg(feature = "std")]
+pub mod fake;
+
+/// Simulated cubes.
+#[cfg(feature = "std")]
+pub mod sim;
+
+/// CSV export of sensor streams.
+#[cfg(feature = "std")]
+pub mod csv;
+
+/// Synchronous API.
+#[cfg(feature = "std")]
+pub mod blocking;
+
+/// MQTT bridge.
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+/// WebSocket server.
+#[cfg(feature = "websocket")]
+pub mod ws;
+
+/// ROS 2 bridge.
+#[cfg(feature = "ros")]
+pub mod ros;
+
+/// Bevy plugin.
+#[cfg(feature = "bevy")]
+pub mod bevy;
+
+/// Gamepad teleoperation.
+#[cfg(feature = "std")]
+pub mod teleop;
+
+/// Terminal dashboard.
+#[cfg(feature = "dashboard")]
+pub mod dashboard;
+
+/// 2D visualizer of cube poses.
+#[cfg(feature = "viz")]
+pub mod viz;
+
+/// Scripting with rhai.
+#[cfg(feature = "script")]
+pub mod script;
+
+/// C API.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 mod decode;
 mod encode;
+
+#[cfg(feature = "std")]
+mod callback;
+#[cfg(feature = "std")]
+mod cube;
+#[cfg(feature = "std")]
+mod cube_like;
+#[cfg(feature = "std")]
+mod filters;
+#[cfg(feature = "std")]
+mod gap;
+#[cfg(feature = "std")]
+mod group;
+#[cfg(feature = "std")]
+mod hub;
+#[cfg(feature = "std")]
+mod latency;
+#[cfg(feature = "std")]
+mod metrics;
+#[cfg(feature = "std")]
+mod monitor;
+#[cfg(feature = "std")]
+mod proximity;
+#[cfg(feature = "std")]
+mod queue;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+mod runtime;
+#[cfg(feature = "std")]
 mod searcher;
+#[cfg(feature = "std")]
+mod tracking;
+#[cfg(feature = "std")]
+mod writer;
 
-pub use cube::{Cube, Event, EventStream, LightOp, Position, SoundOp, StdId};
-pub use proto::{IdPos, IdStd, Note, Posture, SoundPresetId};
+#[cfg(feature = "std")]
+pub use callback::CallbackHandle;
+#[cfg(feature = "std")]
+pub use cube::{
+    Cube, Event, EventFilter, EventStream, LightOp, MessageStream, Options, Position, PositionRate,
+    Repeat, Sequence, SoundOp, StdId, StopHandle, Subscriptions, ValueStream, Vector3,
+};
+#[cfg(feature = "std")]
+pub use cube_like::CubeLike;
+#[cfg(feature = "std")]
+pub use group::{CubeGroup, RelativePose};
+#[cfg(feature = "std")]
+pub use latency::Latency;
+pub use proto::{Characteristic, IdPos, IdStd, Note, Posture, SoundPresetId};
+#[cfg(feature = "std")]
+pub use proximity::Proximity;
+#[cfg(feature = "std")]
+pub use queue::Overflow;
+#[cfg(feature = "std")]
+pub use retry::Backoff;
+#[cfg(feature = "std")]
 pub use searcher::*;
+#[cfg(feature = "std")]
+pub use tracking::TrackingQuality;
+#[cfg(feature = "std")]
+pub use writer::Replaced;
@@ -0,0 +1,217 @@
+use anyhow::{anyhow, Result};
+use derive_new::new;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::proto::{LightCtrl, LightOn};
+
+/// The maximum number of operations in a single light control message.
+const MAX_OPS: usize = 29;
+
+/// The maximum duration of a single operation in 10 milliseconds unit.
+const MAX_OP_DURATION: u128 = 255;
+
+const DEFAULT_STEP: Duration = Duration::from_millis(100);
+
+/// A keyframe of light animation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, new)]
+pub struct Keyframe {
+    /// The time of the keyframe from the start of the animation.
+    pub at: Duration,
+    /// The value of red light.
+    pub red: u8,
+    /// The value of green light.
+    pub green: u8,
+    /// The value of blue light.
+    pub blue: u8,
+}
+
+/// Light animation.
+///
+/// Describes color changes over time by keyframes. The color fades linearly
+/// from one keyframe to the next. The animation is compiled into the list of light control
+/// messages, so it can be longer than the limit of operations of a single message.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use toio::{light::Animation, Cube, Repeat};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     // Fades from red to blue in 5 seconds and back to red in 5 seconds.
+///     let anim = Animation::new()
+///         .keyframe(Duration::from_secs(0), 255, 0, 0)
+///         .keyframe(Duration::from_secs(5), 0, 0, 255)
+///         .keyframe(Duration::from_secs(10), 255, 0, 0);
+///
+///     // Repeats three times.
+///     cube.animate(&anim, Repeat::Times(3)).await.unwrap();
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Animation {
+    keyframes: Vec<Keyframe>,
+    step: Duration,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Animation {
+    /// Creates a new empty animation.
+    ///
+    /// The default interval to update the color during fade is 100 milliseconds.
+    pub fn new() -> Self {
+        Self {
+            keyframes: vec![],
+            step: DEFAULT_STEP,
+        }
+    }
+
+    /// Adds a keyframe.
+    ///
+    /// `at` is the time from the start of the animation.
+    /// To keep the same color for a while, add two keyframes with the same color.
+    pub fn keyframe(mut self, at: Duration, red: u8, green: u8, blue: u8) -> Self {
+        self.keyframes.push(Keyframe::new(at, red, green, blue));
+        self.keyframes.sort_by_key(|k| k.at);
+        self
+    }
+
+    /// Sets the interval to update the color during fade.
+    ///
+    /// The interval must be in the range from 10 to 2550 milliseconds.
+    pub fn step(mut self, step: Duration) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Gets the keyframes.
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Gets the total duration of the animation.
+    pub fn duration(&self) -> Duration {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.at - first.at,
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    /// Compiles the animation into light control messages.
+    ///
+    /// The messages are supposed to be written one after another,
+    /// each after the previous one completes. The last step shows the color of the last keyframe.
+    pub fn compile(&self) -> Result<Vec<LightCtrl>> {
+        if self.keyframes.len() < 2 {
+            return Err(anyhow!("Animation needs at least two keyframes"));
+        }
+        let step = self.step.as_millis() / 10;
+        if step == 0 || step > MAX_OP_DURATION {
+            return Err(anyhow!("The step must be from 10 to 2550 milliseconds"));
+        }
+
+        let mut ops: Vec<LightOn> = vec![];
+        let mut push = |units: u128, r: u8, g: u8, b: u8| {
+            let mut units = units;
+            if let Some(last) = ops.last_mut() {
+                if (last.red, last.green, last.blue) == (r, g, b) {
+                    let merged = (last.duration as u128 + units).min(MAX_OP_DURATION);
+                    units -= merged - last.duration as u128;
+                    last.duration = merged as u8;
+                }
+            }
+            while units > 0 {
+                let d = units.min(MAX_OP_DURATION);
+                ops.push(LightOn::new(d as u8, r, g, b));
+                units -= d;
+            }
+        };
+
+        let segments = self.keyframes.len() - 1;
+        for (i, w) in self.keyframes.windows(2).enumerate() {
+            let (from, to) = (&w[0], &w[1]);
+            let total = (to.at - from.at).as_millis() / 10;
+            // The next segment starts from the color of `to`, except for the last one,
+            // which fades until its last step to end with the color of the last keyframe.
+            let span = if i + 1 == segments && total > 0 {
+                (total - 1) / step * step
+            } else {
+                total
+            };
+            let mut elapsed = 0;
+            while elapsed < total {
+                let units = step.min(total - elapsed);
+                let lerp = |a: u8, b: u8| {
+                    if span == 0 {
+                        return b;
+                    }
+                    (a as i128 + (b as i128 - a as i128) * elapsed as i128 / span as i128) as u8
+                };
+                push(
+                    units,
+                    lerp(from.red, to.red),
+                    lerp(from.green, to.green),
+                    lerp(from.blue, to.blue),
+                );
+                elapsed += units;
+            }
+        }
+
+        if ops.is_empty() {
+            return Err(anyhow!("Animation must be longer than 10 milliseconds"));
+        }
+
+        Ok(ops
+            .chunks(MAX_OPS)
+            .map(|c| LightCtrl::new(1, c.len() as u8, c.to_vec()))
+            .collect())
+    }
+}
+
+/// Gets the time to complete the light control message.
+pub(crate) fn ctrl_duration(ctrl: &LightCtrl) -> Duration {
+    Duration::from_millis(ctrl.ops.iter().map(|op| op.duration as u64 * 10).sum())
+}
+
+/// Color correction applied to the light.
+///
+/// The cube LED doesn't map linear RGB values to the perceived brightness.
+/// The gamma correction makes fades look linear, and the brightness scales all the colors.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, new)]
+pub struct Correction {
+    /// The gamma value. `1.0` means no gamma correction.
+    pub gamma: f32,
+    /// The global brightness from `0.0` to `1.0`.
+    pub brightness: f32,
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Self::new(1.0, 1.0)
+    }
+}
+
+impl Correction {
+    /// Applies the correction to a color value.
+    pub fn apply(&self, value: u8) -> u8 {
+        let v = value as f32 / 255.0;
+        let gamma = if self.gamma > 0.0 { self.gamma } else { 1.0 };
+        let v = v.powf(gamma) * self.brightness.clamp(0.0, 1.0);
+        (v * 255.0).round() as u8
+    }
+
+    /// Applies the correction to a light operation.
+    pub fn apply_op(&self, op: &mut LightOn) {
+        op.red = self.apply(op.red);
+        op.green = self.apply(op.green);
+        op.blue = self.apply(op.blue);
+    }
+}
@@ -0,0 +1,49 @@
+// Records the metrics through the `metrics` facade if the feature is enabled.
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables, dead_code))]
+
+#[cfg(feature = "metrics")]
+use ::metrics::{counter, histogram};
+use std::time::Duration;
+
+use crate::{ble::Uuid, proto::Profile};
+
+/// Gets the label of the characteristic.
+fn characteristic(profile: &Profile, uuid: &Uuid) -> String {
+    profile
+        .characteristic(uuid)
+        .map(|ch| format!("{:?}", ch).to_lowercase())
+        .unwrap_or_else(|| uuid.to_string())
+}
+
+/// Records the time taken to write to the characteristic.
+pub(crate) fn write_latency(profile: &Profile, uuid: &Uuid, time: Duration) {
+    #[cfg(feature = "metrics")]
+    histogram!("toio_write_latency_seconds", "characteristic" => characteristic(profile, uuid))
+        .record(time.as_secs_f64());
+}
+
+/// Records a notification from the characteristic.
+pub(crate) fn notification(profile: &Profile, uuid: &Uuid) {
+    #[cfg(feature = "metrics")]
+    counter!("toio_notifications_total", "characteristic" => characteristic(profile, uuid))
+        .increment(1);
+}
+
+/// Records a value from the characteristic which couldn't be decoded.
+pub(crate) fn decode_error(profile: &Profile, uuid: &Uuid) {
+    #[cfg(feature = "metrics")]
+    counter!("toio_decode_errors_total", "characteristic" => characteristic(profile, uuid))
+        .increment(1);
+}
+
+/// Records a connection to the cube, including reconnections.
+pub(crate) fn connection(id: &str) {
+    #[cfg(feature = "metrics")]
+    counter!("toio_connections_total", "cube" => id.to_string()).increment(1);
+}
+
+/// Records a failed attempt to connect to the cube.
+pub(crate) fn connection_failure(id: &str) {
+    #[cfg(feature = "metrics")]
+    counter!("toio_connection_failures_total", "cube" => id.to_string()).increment(1);
+}
@@ -0,0 +1,148 @@
+use futures::{prelude::*, stream::BoxStream};
+use log::*;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::{
+    sync::{broadcast, watch, Mutex},
+    time::{delay_for, timeout, Instant},
+};
+
+use crate::{
+    ble::{self, PeripheralOps, Uuid},
+    gap::GapDetector,
+    proto::*,
+    queue::Recv,
+    tracking::Tracker,
+    Event,
+};
+
+/// The stream of messages from the hub.
+type Messages = BoxStream<'static, Recv<Message>>;
+
+/// Reads the battery at the interval, so that the level is notified even if it doesn't change.
+pub(crate) async fn poll_battery(dev: Arc<Mutex<ble::Peripheral>>, uuid: Uuid, interval: Duration) {
+    loop {
+        delay_for(interval).await;
+        let res = dev.lock().await.read(&uuid).await;
+        if let Err(e) = res {
+            debug!("Couldn't read battery: {}", e);
+        }
+    }
+}
+
+/// Sends [`Event::BatteryLow`][] once the battery level falls to the threshold.
+///
+/// The event is sent again only after the level rises above the threshold.
+pub(crate) async fn battery_low(
+    mut msgs: Messages,
+    notices: broadcast::Sender<Event>,
+    threshold: usize,
+) {
+    let mut low = false;
+    while let Some(msg) = msgs.next().await {
+        if let Recv::Value(Message::Battery(v)) = msg {
+            let v = v as usize;
+            if v <= threshold && !low {
+                let _ = notices.send(Event::BatteryLow(v));
+            }
+            low = v <= threshold;
+        }
+    }
+}
+
+/// Counts every id notification regardless of the deduplication of the events.
+pub(crate) async fn track(mut msgs: Messages, tracker: Arc<std::sync::Mutex<Tracker>>) {
+    while let Some(msg) = msgs.next().await {
+        let on_mat = match msg {
+            Recv::Value(Message::Id(Id::Pos(_))) => true,
+            Recv::Value(Message::Id(Id::PosMissed)) => false,
+            _ => continue,
+        };
+        tracker.lock().unwrap().push(Instant::now(), on_mat);
+    }
+}
+
+/// Sends [`Event::NotificationGap`][] when position notifications seem to be lost.
+pub(crate) async fn detect_gaps(mut msgs: Messages, notices: broadcast::Sender<Event>) {
+    let mut detector = GapDetector::default();
+    while let Some(msg) = msgs.next().await {
+        let pos = match msg {
+            Recv::Value(Message::Id(Id::Pos(pos))) => Some(pos.into()),
+            Recv::Value(Message::Id(Id::PosMissed)) => None,
+            Recv::Value(_) => continue,
+            Recv::Lagged(_) => {
+                // The intervals are unknown while lagging.
+                detector = GapDetector::default();
+                continue;
+            }
+        };
+        if let Some(missed) = detector.push(Instant::now(), pos) {
+            debug!("Missed about {} position notifications", missed);
+            let _ = notices.send(Event::NotificationGap {
+                characteristic: Characteristic::Id,
+                estimated_missed: missed,
+            });
+        }
+    }
+}
+
+/// Sends [`Event::Unresponsive`][] when the cube doesn't answer a read after being idle.
+pub(crate) async fn watchdog(
+    dev: Arc<Mutex<ble::Peripheral>>,
+    uuid: Uuid,
+    idle: Duration,
+    read_timeout: Duration,
+    mut msgs: Messages,
+    notices: broadcast::Sender<Event>,
+) {
+    let mut alive = true;
+    loop {
+        match timeout(idle, msgs.next()).await {
+            Ok(Some(_)) => {
+                alive = true;
+                continue;
+            }
+            Ok(None) => break,
+            Err(_) => {}
+        }
+
+        // Verifies the link by a lightweight read.
+        let res = dev.lock().await.read(&uuid).await;
+        let resp = match res {
+            Ok(()) => timeout(read_timeout, msgs.next()).await,
+            Err(e) => {
+                debug!("Couldn't send liveness check: {}", e);
+                continue;
+            }
+        };
+        match resp {
+            Ok(Some(_)) => alive = true,
+            Ok(None) => break,
+            Err(_) => {
+                if alive {
+                    warn!("The cube doesn't respond");
+                    let _ = notices.send(Event::Unresponsive);
+                }
+                alive = false;
+            }
+        }
+    }
+}
+
+/// Reads the RSSI at the interval.
+pub(crate) async fn poll_rssi(
+    dev: Arc<Mutex<ble::Peripheral>>,
+    tx: Arc<watch::Sender<i32>>,
+    interval: Duration,
+) {
+    loop {
+        delay_for(interval).await;
+        let rssi = dev.lock().await.read_rssi().await;
+        match rssi {
+            Ok(rssi) => {
+                let _ = tx.broadcast(rssi);
+            }
+            Err(e) => debug!("Couldn't read rssi: {}", e),
+        }
+    }
+}
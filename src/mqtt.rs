@@ -0,0 +1,297 @@
+use anyhow::{anyhow, bail, Context, Result};
+use futures::{future::abortable, prelude::*};
+use log::*;
+use std::time::Duration;
+use tokio::{
+    io::{split, AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::mpsc,
+    time::{delay_for, timeout},
+};
+
+use crate::{proto::Message, Cube, Event};
+
+const PROTOCOL_LEVEL: u8 = 4;
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xc0;
+
+/// Publishes the events of a cube to an MQTT broker.
+///
+/// Events are published to `<prefix>/<cube id>/<event>` with the values in JSON,
+/// e.g., `toio/<cube id>/battery` with `80`. The names of the events are
+/// `battery`, `battery_low`, `collision`, `slope`, `button`, `posture`, `motion`,
+/// `magnetic_force`, `position`, `std_id`, `version`, `unresponsive` and `notification_gap`.
+///
+/// If commands are enabled, [`Message`][]s in JSON published to `<prefix>/<cube id>/command`
+/// are written to the cube. See [`Message::from_json`][] for the format.
+///
+/// Supports MQTT 3.1.1 with QoS 0 only. Enabled by the `mqtt` feature.
+///
+/// ```no_run
+/// use toio::{mqtt::Bridge, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     Bridge::new("localhost:1883")
+///         .commands(true)
+///         .run(&mut cube)
+///         .await
+///         .unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    addr: String,
+    client_id: Option<String>,
+    prefix: String,
+    commands: bool,
+    keep_alive: Duration,
+}
+
+impl Bridge {
+    /// Creates a bridge to the broker at the address.
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            client_id: None,
+            prefix: "toio".to_string(),
+            commands: false,
+            keep_alive: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the client id. By default, `toio-<cube id>` is used.
+    pub fn client_id(mut self, id: &str) -> Self {
+        self.client_id = Some(id.to_string());
+        self
+    }
+
+    /// Sets the prefix of the topics. By default, `toio` is used.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Enables or disables writing messages published to the command topic. Disabled by default.
+    pub fn commands(mut self, enable: bool) -> Self {
+        self.commands = enable;
+        self
+    }
+
+    /// Sets the keep alive interval. By default, it's 60 seconds.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Connects to the broker and bridges the cube until the connection or the events end.
+    pub async fn run(&self, cube: &mut Cube) -> Result<()> {
+        let base = format!("{}/{}", self.prefix, cube.id());
+        let client_id = self
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("toio-{}", cube.id()));
+
+        let stream = TcpStream::connect(&self.addr)
+            .await
+            .with_context(|| format!("Couldn't connect to MQTT broker {}", self.addr))?;
+        let (mut rd, mut wr) = split(stream);
+
+        wr.write_all(&connect(&client_id, self.keep_alive)).await?;
+        let (header, body) = timeout(self.keep_alive, read_packet(&mut rd))
+            .await
+            .context("Timeout on waiting for CONNACK")??;
+        match (header & 0xf0, body.get(1)) {
+            (CONNACK, Some(0)) => {}
+            (CONNACK, Some(rc)) => bail!("MQTT broker refused connection: {}", rc),
+            _ => bail!("Unexpected packet {:#x} on connecting", header),
+        }
+        debug!("Connected to MQTT broker {}", self.addr);
+
+        let command_topic = format!("{}/command", base);
+        if self.commands {
+            wr.write_all(&subscribe(1, &command_topic)).await?;
+        }
+
+        // Receives packets from the broker in background.
+        let (tx, mut commands) = mpsc::channel(16);
+        let (reader, handle) = abortable(async move {
+            let mut tx = tx;
+            loop {
+                match read_packet(&mut rd).await {
+                    Ok((header, body)) if header & 0xf0 == PUBLISH => {
+                        match parse_publish(header, &body) {
+                            Ok(payload) => {
+                                if tx.send(Ok(payload)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => warn!("Invalid PUBLISH packet: {}", e),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        break;
+                    }
+                }
+            }
+        });
+        tokio::spawn(reader);
+
+        let mut events = cube.events().await?;
+        let res = async {
+            loop {
+                tokio::select! {
+                    event = events.next() => {
+                        let event = match event {
+                            Some(event) => event,
+                            None => break,
+                        };
+                        if let Some((name, value)) = topic(&event)? {
+                            let topic = format!("{}/{}", base, name);
+                            wr.write_all(&publish(&topic, value.as_bytes())).await?;
+                        }
+                    }
+                    payload = commands.recv() => {
+                        let payload = payload.ok_or_else(|| anyhow!("MQTT connection closed"))??;
+                        let msg = std::str::from_utf8(&payload)
+                            .map_err(anyhow::Error::from)
+                            .and_then(Message::from_json);
+                        match msg {
+                            Ok(msg) => cube.write_msg(msg, false).await?,
+                            Err(e) => warn!("Invalid command on {}: {}", command_topic, e),
+                        }
+                    }
+                    _ = delay_for(self.keep_alive / 2) => {
+                        wr.write_all(&[PINGREQ, 0]).await?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        .await;
+        handle.abort();
+        res
+    }
+}
+
+/// Gets the topic name and the JSON value of the event.
+fn topic(event: &Event) -> Result<Option<(&'static str, String)>> {
+    let (name, value) = match event {
+        Event::Battery(v) => ("battery", serde_json::to_string(v)?),
+        Event::BatteryLow(v) => ("battery_low", serde_json::to_string(v)?),
+        Event::Collision(v) => ("collision", serde_json::to_string(v)?),
+        Event::Slope(v) => ("slope", serde_json::to_string(v)?),
+        Event::Button(v) => ("button", serde_json::to_string(v)?),
+        Event::Posture(v) => ("posture", serde_json::to_string(v)?),
+        Event::Motion(v) => ("motion", serde_json::to_string(v)?),
+        Event::MagneticForce(v) => ("magnetic_force", serde_json::to_string(v)?),
+        Event::Position(v) => ("position", serde_json::to_string(v)?),
+        Event::StdId(v) => ("std_id", serde_json::to_string(v)?),
+        Event::Version(v) => ("version", serde_json::to_string(v)?),
+        Event::Unresponsive => ("unresponsive", "null".to_string()),
+        Event::NotificationGap {
+            characteristic,
+            estimated_missed,
+        } => (
+            "notification_gap",
+            serde_json::json!({
+                "characteristic": characteristic,
+                "estimated_missed": estimated_missed,
+            })
+            .to_string(),
+        ),
+        Event::Proximity { other, distance } => (
+            "proximity",
+            serde_json::json!({ "other": other, "distance": distance }).to_string(),
+        ),
+        Event::Lagged(_) => return Ok(None),
+    };
+    Ok(Some((name, value)))
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut buf = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut b = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            b |= 0x80;
+        }
+        buf.push(b);
+        if len == 0 {
+            break;
+        }
+    }
+    buf.extend(body);
+    buf
+}
+
+fn connect(client_id: &str, keep_alive: Duration) -> Vec<u8> {
+    let mut body = vec![];
+    put_str(&mut body, "MQTT");
+    body.push(PROTOCOL_LEVEL);
+    // Clean session.
+    body.push(0x02);
+    body.extend_from_slice(&(keep_alive.as_secs().min(u16::MAX as u64) as u16).to_be_bytes());
+    put_str(&mut body, client_id);
+    packet(CONNECT, body)
+}
+
+fn publish(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![];
+    put_str(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(PUBLISH, body)
+}
+
+fn subscribe(id: u16, topic: &str) -> Vec<u8> {
+    let mut body = id.to_be_bytes().to_vec();
+    put_str(&mut body, topic);
+    // QoS 0.
+    body.push(0);
+    packet(SUBSCRIBE, body)
+}
+
+fn parse_publish(header: u8, body: &[u8]) -> Result<Vec<u8>> {
+    let len = body
+        .get(..2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+        .ok_or_else(|| anyhow!("Missing topic"))?;
+    let mut pos = 2 + len;
+    // Packet id exists only if QoS > 0.
+    if header & 0x06 != 0 {
+        pos += 2;
+    }
+    body.get(pos..)
+        .map(|p| p.to_vec())
+        .ok_or_else(|| anyhow!("Truncated packet"))
+}
+
+async fn read_packet<R: AsyncRead + Unpin>(rd: &mut R) -> Result<(u8, Vec<u8>)> {
+    let header = rd.read_u8().await?;
+    let mut len = 0usize;
+    for i in 0..4 {
+        let b = rd.read_u8().await?;
+        len |= ((b & 0x7f) as usize) << (7 * i);
+        if b & 0x80 == 0 {
+            let mut body = vec![0; len];
+            rd.read_exact(&mut body).await?;
+            return Ok((header, body));
+        }
+    }
+    bail!("Malformed remaining length")
+}
@@ -1,14 +1,29 @@
-use anyhow::{anyhow, bail, Error, Result};
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use anyhow::Context;
+use anyhow::{anyhow, Error, Result};
+use bytes::Bytes;
+use core::convert::{TryFrom, TryInto};
 use derive_new::new;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::convert::{TryFrom, TryInto};
 
-use crate::{ble::Uuid, decode::decode, encode::encode, uuid};
+use crate::{
+    decode::decode,
+    encode::{encode, encode_into},
+};
 
+#[macro_use]
+mod uuid;
 mod note;
+mod profile;
+mod target;
 
+pub use crate::decode::Error as DecodeError;
 pub use note::Note;
+pub use profile::Profile;
+pub use target::*;
+pub use uuid::Uuid;
 
 /// The UUID of the toio cube service.
 pub const UUID_SERVICE: Uuid = uuid!("10b20100 5b3b 4571 9508 cf3efcd7bbae");
@@ -37,6 +52,62 @@ pub const UUID_BATTERY: Uuid = uuid!("10b20108 5b3b 4571 9508 cf3efcd7bbae");
 /// The UUID of the configuration characteristic.
 pub const UUID_CONFIG: Uuid = uuid!("10b201ff 5b3b 4571 9508 cf3efcd7bbae");
 
+/// The size of the buffer for [`Message::encode_into`][], which fits in the default BLE MTU.
+pub const MAX_MESSAGE_SIZE: usize = 20;
+
+/// The characteristics of the toio cube.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Characteristic {
+    /// The id reader.
+    Id,
+    /// The motor.
+    Motor,
+    /// The light.
+    Light,
+    /// The sound device.
+    Sound,
+    /// The motion sensor.
+    Motion,
+    /// The button.
+    Button,
+    /// The battery.
+    Battery,
+    /// The configuration.
+    Config,
+}
+
+impl Characteristic {
+    /// Gets the UUID of the characteristic.
+    pub fn uuid(&self) -> Uuid {
+        match self {
+            Characteristic::Id => UUID_ID,
+            Characteristic::Motor => UUID_MOTOR,
+            Characteristic::Light => UUID_LIGHT,
+            Characteristic::Sound => UUID_SOUND,
+            Characteristic::Motion => UUID_MOTION,
+            Characteristic::Button => UUID_BUTTON,
+            Characteristic::Battery => UUID_BATTERY,
+            Characteristic::Config => UUID_CONFIG,
+        }
+    }
+}
+
+macro_rules! variant {
+    ($variant:ident($value:ident), $buf:expr, $strict:expr) => {
+        Ok(Self::$variant(decode::<$value>($buf, $strict)?))
+    };
+    ($variant:ident, $buf:expr, $strict:expr) => {
+        match $buf.len() {
+            n if $strict && n > 0 => Err(DecodeError::Trailing {
+                expected: 0,
+                actual: n,
+            }
+            .into()),
+            _ => Ok(Self::$variant),
+        }
+    };
+}
+
 macro_rules! msg {
     ($uuid:expr;
      $(#[$attr:meta])?pub enum $name:tt {
@@ -46,24 +117,37 @@ macro_rules! msg {
         )*
     }) => {
         $(#[$attr])?
-        #[derive(Debug, Clone, PartialEq, Eq, new)]
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
         pub enum $name {
             $(
                 $(#[$vattr])?
                 $variant$(($value))?,
             )*
+            /// Message of an unknown type, e.g., from newer firmware.
+            ///
+            /// Holds the raw bytes including the type.
+            Unknown(Vec<u8>),
+        }
+
+        impl $name {
+            /// Decodes the message.
+            ///
+            /// If `strict` is `true`, bytes left after the message are rejected
+            /// instead of ignored. See [`DecodeError`][] for the errors.
+            pub fn decode(v: &[u8], strict: bool) -> Result<Self> {
+                match v.get(0) {
+                    $(Some($id) => variant!($variant$(($value))?, &v[1..], strict),)*
+                    Some(_) => Ok(Self::Unknown(v.to_vec())),
+                    None => Err(anyhow!("Empty bytes for {}", stringify!($name))),
+                }
+            }
         }
 
-        #[allow(non_snake_case)]
         impl TryFrom<&[u8]> for $name {
             type Error = Error;
 
             fn try_from(v: &[u8]) -> Result<Self> {
-                match v.get(0) {
-                    $(Some($id) => Ok(Self::$variant$((decode::<$value>(&v[1..])?))? ),)*
-                    Some(v) => Err(anyhow!("Invalid type {} for {}", v, stringify!(Self))),
-                    None => Err(anyhow!("Empty bytes for {}", stringify!(Self))),
-                }
+                Self::decode(v, false)
             }
         }
 
@@ -75,6 +159,46 @@ macro_rules! msg {
             }
         }
 
+        impl TryFrom<Bytes> for $name {
+            type Error = Error;
+
+            fn try_from(v: Bytes) -> Result<Self> {
+                Self::try_from(&v as &[u8])
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl $name {
+            /// Returns `true` if the message is a response to a request.
+            ///
+            /// The types of responses have the most significant bit set.
+            #[allow(unused_variables)]
+            pub fn is_response(&self) -> bool {
+                let id: u8 = match self {
+                    $($name::$variant$(($value))? => $id,)*
+                    $name::Unknown(data) => data.first().copied().unwrap_or(0),
+                };
+                id & 0x80 != 0
+            }
+
+            /// Encodes the message to the head of the buffer and returns the number of bytes written.
+            pub fn encode_into(&self, buf: &mut [u8]) -> Result<usize> {
+                if let $name::Unknown(data) = self {
+                    return copy_into(buf, data);
+                }
+                let (head, _rest) = buf
+                    .split_first_mut()
+                    .ok_or_else(|| anyhow!("Buffer is too short for {}", stringify!($name)))?;
+                match self {
+                    $($name::$variant$(($value))? => {
+                        *head = $id;
+                        Ok(1 $(+ encode_into(_rest, $value)?)?)
+                    },)*
+                    $name::Unknown(_) => unreachable!(),
+                }
+            }
+        }
+
         #[allow(non_snake_case, unused_mut)]
         impl TryFrom<$name> for Vec<u8> {
             type Error = Error;
@@ -86,6 +210,7 @@ macro_rules! msg {
                         $(encode(&mut buf, &$value)?;)?
                         Ok(buf)
                     },)*
+                    $name::Unknown(data) => Ok(data.clone()),
                 }
             }
         }
@@ -97,9 +222,25 @@ macro_rules! msg {
                 Ok(($uuid, v.try_into()?))
             }
         }
+
+        impl From<$name> for Message {
+            fn from(v: $name) -> Self {
+                Message::$name(v)
+            }
+        }
+
+        impl TypedMessage for $name {
+            const UUID: Uuid = $uuid;
+        }
     };
 }
 
+/// Message type bound to a characteristic.
+pub trait TypedMessage: TryFrom<Bytes, Error = Error> {
+    /// The UUID of the characteristic.
+    const UUID: Uuid;
+}
+
 /// Position id
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, new)]
 pub struct IdPos {
@@ -173,13 +314,34 @@ pub struct MotionDetect {
     pub posture: Posture,
 }
 
+/// The state of the magnetic sensor. Requires firmware 2.2.0 or later.
+///
+/// The fields filled depend on [`MagnetMode`][], and the others are zero.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
+pub struct MotionMagnet {
+    /// The placement of the magnet in [`MagnetMode::State`][]. `0` means no magnet.
+    pub state: u8,
+    /// The strength of the magnetic force in [`MagnetMode::Force`][].
+    pub strength: u8,
+    /// The x component of the magnetic force in [`MagnetMode::Force`][].
+    pub x: i8,
+    /// The y component of the magnetic force in [`MagnetMode::Force`][].
+    pub y: i8,
+    /// The z component of the magnetic force in [`MagnetMode::Force`][].
+    pub z: i8,
+}
+
 msg!(
     UUID_MOTION;
 
-    #[doc = "Message from the motion sensor."]
+    #[doc = "Message from/to the motion sensor."]
     pub enum Motion {
         #[doc = "The state of the motion sensor."]
         Detect(MotionDetect) = 0x01,
+        #[doc = "The state of the magnetic sensor."]
+        Magnet(MotionMagnet) = 0x02,
+        #[doc = "Requests the state of the motion sensor. Requires firmware 2.1.0 or later."]
+        RequestDetect = 0x81,
     }
 );
 
@@ -614,6 +776,108 @@ pub struct ConfigDoubleTap {
     pub interval: u8,
 }
 
+/// Changes the notifications of the id reader. Requires firmware 2.1.0 or later.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
+pub struct ConfigIdNotify {
+    /// Unused.
+    #[new(default)]
+    pub reserved: u8,
+    /// The minimum interval of the notifications in 10 milliseconds. `0` means no limit.
+    pub interval: u8,
+    /// The condition to notify.
+    pub condition: NotifyCondition,
+}
+
+/// Changes the delay of the notifications of missing ids. Requires firmware 2.1.0 or later.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
+pub struct ConfigIdMissed {
+    /// Unused.
+    #[new(default)]
+    pub reserved: u8,
+    /// The delay to notify after the id goes out in 10 milliseconds.
+    pub delay: u8,
+}
+
+/// The function of the magnetic sensor.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum MagnetMode {
+    /// Disables the magnetic sensor.
+    Disabled = 0x00,
+    /// Detects the state of the magnet, i.e., which magnet is placed how.
+    State = 0x01,
+    /// Measures the magnetic force. Requires firmware 2.3.0 or later.
+    Force = 0x02,
+}
+
+/// When the cube notifies the sensor values.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum NotifyCondition {
+    /// Notifies at every interval.
+    Always = 0x00,
+    /// Notifies at the interval only if the value changes.
+    OnChange = 0x01,
+}
+
+/// Changes the settings of the magnetic sensor. Requires firmware 2.2.0 or later.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
+pub struct ConfigMagnet {
+    /// Unused.
+    #[new(default)]
+    pub reserved: u8,
+    /// The function of the magnetic sensor.
+    pub mode: MagnetMode,
+    /// The interval of the notifications in 20 milliseconds.
+    pub interval: u8,
+    /// The condition to notify.
+    pub condition: NotifyCondition,
+}
+
+/// The format of the attitude in the notifications.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum AttitudeFormat {
+    /// Euler angles in degrees.
+    Euler = 0x01,
+    /// Quaternion.
+    Quaternion = 0x02,
+}
+
+/// Changes the notifications of the attitude. Requires firmware 2.2.0 or later.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
+pub struct ConfigAttitude {
+    /// Unused.
+    #[new(default)]
+    pub reserved: u8,
+    /// The format of the attitude.
+    pub format: AttitudeFormat,
+    /// The interval of the notifications in 10 milliseconds. `0` disables the notifications.
+    pub interval: u8,
+    /// The condition to notify.
+    pub condition: NotifyCondition,
+}
+
+/// The result of the configuration.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ConfigResValue {
+    /// Succeeded.
+    Ok = 0x00,
+    /// The configuration is not supported.
+    Unsupported = 0x01,
+}
+
+/// The response to the configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
+pub struct ConfigRes {
+    /// Unused.
+    #[new(default)]
+    pub reserved: u8,
+    /// The result of the configuration.
+    pub res: ConfigResValue,
+}
+
 /// The protocol version information.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
 pub struct ConfigVersionRes {
@@ -637,13 +901,74 @@ msg!(
         Collision(ConfigCollision) = 0x03,
         #[doc = "Changes the settings of double-tap detection."]
         DoubleTap(ConfigDoubleTap) = 0x04,
+        #[doc = "Changes the notifications of the id reader."]
+        IdNotify(ConfigIdNotify) = 0x18,
+        #[doc = "Changes the delay of the notifications of missing ids."]
+        IdMissed(ConfigIdMissed) = 0x19,
+        #[doc = "Changes the settings of the magnetic sensor."]
+        Magnet(ConfigMagnet) = 0x1b,
+        #[doc = "Changes the notifications of the attitude."]
+        Attitude(ConfigAttitude) = 0x1d,
         #[doc = "The protocol version information."]
         VersionRes(ConfigVersionRes) = 0x81,
+        #[doc = "The response to the notifications of the id reader."]
+        IdNotifyRes(ConfigRes) = 0x98,
+        #[doc = "The response to the delay of the notifications of missing ids."]
+        IdMissedRes(ConfigRes) = 0x99,
+        #[doc = "The response to the settings of the magnetic sensor."]
+        MagnetRes(ConfigRes) = 0x9b,
+        #[doc = "The response to the notifications of the attitude."]
+        AttitudeRes(ConfigRes) = 0x9d,
     }
 );
 
+/// The kind of [`Message`][], i.e., the variant without the content.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    /// [`Message::Id`][].
+    Id,
+    /// [`Message::Motion`][].
+    Motion,
+    /// [`Message::Button`][].
+    Button,
+    /// [`Message::Battery`][].
+    Battery,
+    /// [`Message::Motor`][].
+    Motor,
+    /// [`Message::Light`][].
+    Light,
+    /// [`Message::Sound`][].
+    Sound,
+    /// [`Message::Config`][].
+    Config,
+    /// [`Message::Unknown`][].
+    Unknown,
+}
+
 /// Message read/written from/to characteristics.
-#[derive(Debug, Clone, PartialEq, Eq, new)]
+///
+/// Messages can be serialized with serde in a human-readable form, e.g., to log or store them.
+/// This is independent of the binary format on the characteristics.
+///
+/// # JSON format
+///
+/// [`Message::to_json`][] and [`Message::from_json`][] use a stable format
+/// so that tools in other languages can construct messages:
+///
+/// * Enums are objects with a single key which is the name of the variant,
+///   e.g., `{"Battery": 80}`. Variants without values are strings, e.g., `"Stop"`.
+/// * Structs are objects keyed by the field names.
+/// * Ids and flags with fixed values (e.g., [`MotorId`][], [`MotorDir`][]) are the numbers
+///   defined in [the technical specification](https://toio.github.io/toio-spec/).
+///
+/// For example, the request to run the motors is:
+///
+/// ```json
+/// {"Motor": {"Simple": {"motor1": 1, "dir1": 1, "speed1": 30, "motor2": 2, "dir2": 2, "speed2": 20}}}
+/// ```
+///
+/// Names of the existing variants and fields are not changed in later versions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, new)]
 pub enum Message {
     /// Message for id reader.
     Id(Id),
@@ -661,33 +986,134 @@ pub enum Message {
     Sound(Sound),
     /// Message for configuration.
     Config(Config),
+    /// Message from an unknown characteristic.
+    Unknown {
+        /// The UUID of the characteristic.
+        uuid: Uuid,
+        /// The raw bytes.
+        data: Vec<u8>,
+    },
 }
 
-fn unpack_battery(v: &[u8]) -> Result<u8> {
-    v.get(0)
-        .cloned()
-        .ok_or_else(|| anyhow!("Battery field is empty"))
+fn copy_into(buf: &mut [u8], data: &[u8]) -> Result<usize> {
+    match buf.get_mut(..data.len()) {
+        Some(head) => {
+            head.copy_from_slice(data);
+            Ok(data.len())
+        }
+        None => Err(anyhow!(
+            "Buffer is too short: expect {} but {}",
+            data.len(),
+            buf.len()
+        )),
+    }
 }
 
-impl TryFrom<(Uuid, &[u8])> for Message {
-    type Error = Error;
+fn unpack_battery(v: &[u8], strict: bool) -> Result<u8> {
+    match v {
+        [] => Err(anyhow!("Battery field is empty")),
+        [_, rest @ ..] if strict && !rest.is_empty() => Err(DecodeError::Trailing {
+            expected: 1,
+            actual: v.len(),
+        }
+        .into()),
+        [v, ..] => Ok(*v),
+    }
+}
 
-    fn try_from((uuid, buf): (Uuid, &[u8])) -> Result<Self> {
+#[cfg(feature = "std")]
+impl Message {
+    /// Serializes the message into JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).context("Couldn't serialize message into JSON")
+    }
+
+    /// Deserializes a message from JSON.
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Couldn't deserialize message from JSON")
+    }
+}
+
+impl Message {
+    /// Gets the UUID of the characteristic the message belongs to.
+    pub fn uuid(&self) -> Uuid {
+        match self {
+            Message::Id(_) => UUID_ID,
+            Message::Motion(_) => UUID_MOTION,
+            Message::Button(_) => UUID_BUTTON,
+            Message::Battery(_) => UUID_BATTERY,
+            Message::Motor(_) => UUID_MOTOR,
+            Message::Light(_) => UUID_LIGHT,
+            Message::Sound(_) => UUID_SOUND,
+            Message::Config(_) => UUID_CONFIG,
+            Message::Unknown { uuid, .. } => *uuid,
+        }
+    }
+
+    /// Gets the kind of the message.
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::Id(_) => MessageKind::Id,
+            Message::Motion(_) => MessageKind::Motion,
+            Message::Button(_) => MessageKind::Button,
+            Message::Battery(_) => MessageKind::Battery,
+            Message::Motor(_) => MessageKind::Motor,
+            Message::Light(_) => MessageKind::Light,
+            Message::Sound(_) => MessageKind::Sound,
+            Message::Config(_) => MessageKind::Config,
+            Message::Unknown { .. } => MessageKind::Unknown,
+        }
+    }
+
+    /// Returns `true` if the message is a response to a request,
+    /// e.g., [`Motor::TargetRes`][] and [`Config::VersionRes`][].
+    pub fn is_response(&self) -> bool {
+        match self {
+            Message::Id(v) => v.is_response(),
+            // The motion sensor sends the states with or without requests,
+            // and the requests have the most significant bit set instead.
+            Message::Motion(_) => false,
+            Message::Button(v) => v.is_response(),
+            Message::Battery(_) => false,
+            Message::Motor(v) => v.is_response(),
+            Message::Light(v) => v.is_response(),
+            Message::Sound(v) => v.is_response(),
+            Message::Config(v) => v.is_response(),
+            Message::Unknown { .. } => false,
+        }
+    }
+
+    /// Decodes the message from the characteristic.
+    ///
+    /// If `strict` is `true`, bytes left after the message are rejected instead of ignored.
+    /// See [`DecodeError`][] for the errors.
+    pub fn decode(uuid: Uuid, buf: &[u8], strict: bool) -> Result<Self> {
         let msg = match uuid {
-            UUID_ID => Message::Id(buf.try_into()?),
-            UUID_MOTION => Message::Motion(buf.try_into()?),
-            UUID_BUTTON => Message::Button(buf.try_into()?),
-            UUID_BATTERY => Message::Battery(unpack_battery(buf)?),
-            UUID_MOTOR => Message::Motor(buf.try_into()?),
-            UUID_LIGHT => Message::Light(buf.try_into()?),
-            UUID_SOUND => Message::Sound(buf.try_into()?),
-            UUID_CONFIG => Message::Config(buf.try_into()?),
-            uuid => bail!("Unknown uuid: {}", uuid),
+            UUID_ID => Message::Id(Id::decode(buf, strict)?),
+            UUID_MOTION => Message::Motion(Motion::decode(buf, strict)?),
+            UUID_BUTTON => Message::Button(Button::decode(buf, strict)?),
+            UUID_BATTERY => Message::Battery(unpack_battery(buf, strict)?),
+            UUID_MOTOR => Message::Motor(Motor::decode(buf, strict)?),
+            UUID_LIGHT => Message::Light(Light::decode(buf, strict)?),
+            UUID_SOUND => Message::Sound(Sound::decode(buf, strict)?),
+            UUID_CONFIG => Message::Config(Config::decode(buf, strict)?),
+            uuid => Message::Unknown {
+                uuid,
+                data: buf.to_vec(),
+            },
         };
         Ok(msg)
     }
 }
 
+impl TryFrom<(Uuid, &[u8])> for Message {
+    type Error = Error;
+
+    fn try_from((uuid, buf): (Uuid, &[u8])) -> Result<Self> {
+        Self::decode(uuid, buf, false)
+    }
+}
+
 impl TryFrom<(Uuid, Vec<u8>)> for Message {
     type Error = Error;
 
@@ -696,6 +1122,38 @@ impl TryFrom<(Uuid, Vec<u8>)> for Message {
     }
 }
 
+impl TryFrom<(Uuid, Bytes)> for Message {
+    type Error = Error;
+
+    fn try_from((uuid, buf): (Uuid, Bytes)) -> Result<Self> {
+        (uuid, &buf as &[u8]).try_into()
+    }
+}
+
+impl Message {
+    /// Encodes the message into the buffer without allocation.
+    ///
+    /// Returns the UUID of the characteristic and the number of bytes written.
+    /// Fails if the message doesn't fit in the buffer, e.g., long sound or light programs.
+    pub fn encode_into(&self, buf: &mut [u8; MAX_MESSAGE_SIZE]) -> Result<(Uuid, usize)> {
+        let v = match self {
+            Message::Id(v) => (UUID_ID, v.encode_into(buf)?),
+            Message::Motion(v) => (UUID_MOTION, v.encode_into(buf)?),
+            Message::Button(v) => (UUID_BUTTON, v.encode_into(buf)?),
+            Message::Battery(v) => {
+                buf[0] = *v;
+                (UUID_BATTERY, 1)
+            }
+            Message::Motor(v) => (UUID_MOTOR, v.encode_into(buf)?),
+            Message::Light(v) => (UUID_LIGHT, v.encode_into(buf)?),
+            Message::Sound(v) => (UUID_SOUND, v.encode_into(buf)?),
+            Message::Config(v) => (UUID_CONFIG, v.encode_into(buf)?),
+            Message::Unknown { uuid, data } => (*uuid, copy_into(buf, data)?),
+        };
+        Ok(v)
+    }
+}
+
 impl TryFrom<Message> for (Uuid, Vec<u8>) {
     type Error = Error;
 
@@ -709,6 +1167,7 @@ impl TryFrom<Message> for (Uuid, Vec<u8>) {
             Message::Light(v) => (UUID_LIGHT, v.try_into()?),
             Message::Sound(v) => (UUID_SOUND, v.try_into()?),
             Message::Config(v) => (UUID_CONFIG, v.try_into()?),
+            Message::Unknown { uuid, data } => (uuid, data),
         };
         Ok(v)
     }
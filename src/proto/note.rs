@@ -1,6 +1,6 @@
 use anyhow::{bail, Error, Result};
+use core::convert::TryFrom;
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::convert::TryFrom;
 
 /// Sound note.
 #[derive(
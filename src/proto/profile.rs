@@ -0,0 +1,137 @@
+use anyhow::Result;
+use core::convert::TryInto;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// The set of UUIDs of the service and the characteristics.
+///
+/// By default, the UUIDs of the toio cube are used. Devices running toio-compatible
+/// or custom firmware may expose the same protocol with different UUIDs.
+///
+/// ```no_run
+/// use toio::{proto::Profile, uuid, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let profile = Profile {
+///         service: uuid!("00000000 0000 0000 0000 000000000000"),
+///         ..Profile::default()
+///     };
+///     let cube = Cube::search().profile(profile).nearest().await.unwrap();
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Profile {
+    /// The UUID of the service.
+    pub service: Uuid,
+    /// The UUID of the id reader characteristic.
+    pub id: Uuid,
+    /// The UUID of the motor characteristic.
+    pub motor: Uuid,
+    /// The UUID of the light characteristic.
+    pub light: Uuid,
+    /// The UUID of the sound device characteristic.
+    pub sound: Uuid,
+    /// The UUID of the motion sensor characteristic.
+    pub motion: Uuid,
+    /// The UUID of the button characteristic.
+    pub button: Uuid,
+    /// The UUID of the battery characteristic.
+    pub battery: Uuid,
+    /// The UUID of the configuration characteristic.
+    pub config: Uuid,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::toio()
+    }
+}
+
+const CHARACTERISTICS: [Characteristic; 8] = [
+    Characteristic::Id,
+    Characteristic::Motor,
+    Characteristic::Light,
+    Characteristic::Sound,
+    Characteristic::Motion,
+    Characteristic::Button,
+    Characteristic::Battery,
+    Characteristic::Config,
+];
+
+impl Profile {
+    /// The profile of the toio cube.
+    pub fn toio() -> Self {
+        Self {
+            service: UUID_SERVICE,
+            id: UUID_ID,
+            motor: UUID_MOTOR,
+            light: UUID_LIGHT,
+            sound: UUID_SOUND,
+            motion: UUID_MOTION,
+            button: UUID_BUTTON,
+            battery: UUID_BATTERY,
+            config: UUID_CONFIG,
+        }
+    }
+
+    /// Gets the UUID of the characteristic.
+    pub fn uuid(&self, ch: Characteristic) -> Uuid {
+        match ch {
+            Characteristic::Id => self.id,
+            Characteristic::Motor => self.motor,
+            Characteristic::Light => self.light,
+            Characteristic::Sound => self.sound,
+            Characteristic::Motion => self.motion,
+            Characteristic::Button => self.button,
+            Characteristic::Battery => self.battery,
+            Characteristic::Config => self.config,
+        }
+    }
+
+    /// Gets the characteristic of the UUID in this profile.
+    pub fn characteristic(&self, uuid: &Uuid) -> Option<Characteristic> {
+        CHARACTERISTICS
+            .iter()
+            .find(|ch| self.uuid(**ch) == *uuid)
+            .cloned()
+    }
+
+    /// Converts the UUID of the toio cube into the one of this profile.
+    ///
+    /// Other UUIDs are returned as they are.
+    pub fn resolve(&self, uuid: &Uuid) -> Uuid {
+        CHARACTERISTICS
+            .iter()
+            .find(|ch| ch.uuid() == *uuid)
+            .map(|ch| self.uuid(*ch))
+            .unwrap_or(*uuid)
+    }
+
+    /// Decodes bytes from the characteristic of this profile.
+    pub fn decode(&self, uuid: &Uuid, buf: &[u8]) -> Result<Message> {
+        self.decode_with(uuid, buf, false)
+    }
+
+    /// Decodes bytes from the characteristic of this profile, rejecting malformed bytes.
+    ///
+    /// See [`Message::decode`][].
+    pub fn decode_strict(&self, uuid: &Uuid, buf: &[u8]) -> Result<Message> {
+        self.decode_with(uuid, buf, true)
+    }
+
+    fn decode_with(&self, uuid: &Uuid, buf: &[u8], strict: bool) -> Result<Message> {
+        let uuid = self
+            .characteristic(uuid)
+            .map(|ch| ch.uuid())
+            .unwrap_or(*uuid);
+        Message::decode(uuid, buf, strict)
+    }
+
+    /// Encodes the message into bytes for the characteristic of this profile.
+    pub fn encode(&self, msg: Message) -> Result<(Uuid, Vec<u8>)> {
+        let (uuid, buf): (Uuid, Vec<u8>) = msg.try_into()?;
+        Ok((self.resolve(&uuid), buf))
+    }
+}
@@ -0,0 +1,346 @@
+use alloc::vec::Vec;
+use anyhow::{bail, Result};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use super::*;
+
+/// The minimum of the maximum speed of the target control.
+pub const TARGET_MIN_SPEED: u8 = 10;
+
+/// The maximum of the maximum speed of the target control.
+pub const TARGET_MAX_SPEED: u8 = 115;
+
+/// The maximum number of targets in [`MotorMultiTarget`][].
+pub const MAX_TARGETS: usize = 29;
+
+/// The encoded size of [`MotorMultiTarget`][] without targets.
+const MULTI_TARGET_HEADER_SIZE: usize = 8;
+
+/// The encoded size of each target of [`MotorMultiTarget`][].
+const TARGET_SIZE: usize = 6;
+
+/// The coordinate to keep the current one of the cube.
+pub const KEEP_COORDINATE: u16 = 0xffff;
+
+/// How the cube turns at the target position.
+///
+/// Stored in the upper 3 bits of the angle field.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum AngleType {
+    /// Turns to the absolute angle in the direction of the smaller rotation.
+    Absolute = 0x00,
+    /// Turns to the absolute angle in the positive direction.
+    AbsolutePositive = 0x01,
+    /// Turns to the absolute angle in the negative direction.
+    AbsoluteNegative = 0x02,
+    /// Turns by the angle relative to the one when written, in the positive direction.
+    RelativePositive = 0x03,
+    /// Turns by the angle relative to the one when written, in the negative direction.
+    RelativeNegative = 0x04,
+    /// Doesn't turn.
+    None = 0x05,
+    /// Turns to the angle when written, in the direction of the smaller rotation.
+    Initial = 0x06,
+}
+
+fn angle(angle_type: AngleType, angle: u16) -> Result<u16> {
+    match angle_type {
+        AngleType::Absolute | AngleType::AbsolutePositive | AngleType::AbsoluteNegative
+            if angle > 360 =>
+        {
+            bail!("Absolute angle must be from 0 to 360: {}", angle)
+        }
+        AngleType::RelativePositive | AngleType::RelativeNegative if angle > 0x1fff => {
+            bail!("Relative angle must be from 0 to 8191: {}", angle)
+        }
+        AngleType::None | AngleType::Initial => Ok((angle_type as u16) << 13),
+        _ => Ok((angle_type as u16) << 13 | angle),
+    }
+}
+
+fn max_speed(speed: u8) -> Result<u8> {
+    if !(TARGET_MIN_SPEED..=TARGET_MAX_SPEED).contains(&speed) {
+        bail!(
+            "Maximum speed must be from {} to {}: {}",
+            TARGET_MIN_SPEED,
+            TARGET_MAX_SPEED,
+            speed
+        );
+    }
+    Ok(speed)
+}
+
+impl Target {
+    /// Creates a target with the angle, validating the angle.
+    ///
+    /// Pass [`KEEP_COORDINATE`][] as `x` or `y` to keep the current one.
+    pub fn with_angle(x: u16, y: u16, angle_type: AngleType, angle: u16) -> Result<Self> {
+        Ok(Self::new(x, y, self::angle(angle_type, angle)?))
+    }
+}
+
+/// Builder of [`MotorTarget`][] validating the parameters against the specification.
+///
+/// ```
+/// use toio::proto::{AngleType, MotorTarget, MoveType};
+///
+/// let target = MotorTarget::builder(250, 250)
+///     .max_speed(80)
+///     .move_type(MoveType::ForwardOnly)
+///     .angle(AngleType::Absolute, 90)
+///     .build()
+///     .unwrap();
+/// assert_eq!(target.angle, 90);
+///
+/// assert!(MotorTarget::builder(250, 250).max_speed(200).build().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MotorTargetBuilder {
+    id: u8,
+    timeout: u8,
+    move_type: MoveType,
+    max_speed: u8,
+    speed_change: SpeedChange,
+    x: u16,
+    y: u16,
+    angle_type: AngleType,
+    angle: u16,
+}
+
+impl MotorTarget {
+    /// Creates a builder to move to the position.
+    ///
+    /// Pass [`KEEP_COORDINATE`][] as `x` or `y` to keep the current one.
+    pub fn builder(x: u16, y: u16) -> MotorTargetBuilder {
+        MotorTargetBuilder {
+            id: 0,
+            timeout: 0,
+            move_type: MoveType::Curve,
+            max_speed: 50,
+            speed_change: SpeedChange::Const,
+            x,
+            y,
+            angle_type: AngleType::None,
+            angle: 0,
+        }
+    }
+}
+
+impl MotorTargetBuilder {
+    /// Sets the request id. By default, it's `0`.
+    pub fn id(mut self, id: u8) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Sets the timeout in seconds. `0` means 10 seconds, which is the default.
+    pub fn timeout(mut self, timeout: u8) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the type of the movement. By default, it's [`MoveType::Curve`][].
+    pub fn move_type(mut self, move_type: MoveType) -> Self {
+        self.move_type = move_type;
+        self
+    }
+
+    /// Sets the maximum speed from 10 to 115. By default, it's 50.
+    pub fn max_speed(mut self, max_speed: u8) -> Self {
+        self.max_speed = max_speed;
+        self
+    }
+
+    /// Sets the change of the speed. By default, it's [`SpeedChange::Const`][].
+    pub fn speed_change(mut self, speed_change: SpeedChange) -> Self {
+        self.speed_change = speed_change;
+        self
+    }
+
+    /// Sets the angle at the target position.
+    ///
+    /// Absolute angles must be from 0 to 360, and relative ones from 0 to 8191.
+    /// By default, the cube doesn't turn.
+    pub fn angle(mut self, angle_type: AngleType, angle: u16) -> Self {
+        self.angle_type = angle_type;
+        self.angle = angle;
+        self
+    }
+
+    /// Validates the parameters and builds the request.
+    pub fn build(self) -> Result<MotorTarget> {
+        Ok(MotorTarget::new(
+            self.id,
+            self.timeout,
+            self.move_type,
+            max_speed(self.max_speed)?,
+            self.speed_change,
+            self.x,
+            self.y,
+            angle(self.angle_type, self.angle)?,
+        ))
+    }
+}
+
+/// Builder of [`MotorMultiTarget`][] validating the parameters against the specification.
+///
+/// ```
+/// use toio::proto::{AngleType, MotorMultiTarget};
+///
+/// let targets = MotorMultiTarget::builder()
+///     .target(100, 100)
+///     .target_with_angle(200, 200, AngleType::Absolute, 180)
+///     .build()
+///     .unwrap();
+/// assert_eq!(targets.targets.len(), 2);
+///
+/// assert!(MotorMultiTarget::builder().build().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MotorMultiTargetBuilder {
+    id: u8,
+    timeout: u8,
+    move_type: MoveType,
+    max_speed: u8,
+    speed_change: SpeedChange,
+    writeopt: WriteOpt,
+    targets: Vec<(u16, u16, AngleType, u16)>,
+}
+
+impl MotorMultiTarget {
+    /// Creates a builder to visit positions.
+    pub fn builder() -> MotorMultiTargetBuilder {
+        MotorMultiTargetBuilder {
+            id: 0,
+            timeout: 0,
+            move_type: MoveType::Curve,
+            max_speed: 50,
+            speed_change: SpeedChange::Const,
+            writeopt: WriteOpt::Overwrite,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Splits the request into ones whose encoded size fits in `max_len` bytes.
+    ///
+    /// The first one keeps the write option and the rest are appended to it with the same
+    /// request id, so that the cube visits all the targets in order. Fails if even a single
+    /// target doesn't fit.
+    ///
+    /// ```
+    /// use toio::proto::{MotorMultiTarget, WriteOpt};
+    ///
+    /// let targets = MotorMultiTarget::builder()
+    ///     .target(100, 100)
+    ///     .target(200, 100)
+    ///     .target(200, 200)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // Fits in the default MTU with two targets at most.
+    /// let split = targets.split(20).unwrap();
+    /// assert_eq!(split.len(), 2);
+    /// assert_eq!(split[0].targets.len(), 2);
+    /// assert_eq!(split[1].writeopt, WriteOpt::Append);
+    /// ```
+    pub fn split(&self, max_len: usize) -> Result<Vec<MotorMultiTarget>> {
+        if max_len < MULTI_TARGET_HEADER_SIZE + TARGET_SIZE {
+            bail!("No target fits in {} bytes", max_len);
+        }
+        let per_request = (max_len - MULTI_TARGET_HEADER_SIZE) / TARGET_SIZE;
+        if self.targets.len() <= per_request {
+            return Ok(vec![self.clone()]);
+        }
+        Ok(self
+            .targets
+            .chunks(per_request)
+            .enumerate()
+            .map(|(i, targets)| MotorMultiTarget {
+                writeopt: if i == 0 {
+                    self.writeopt
+                } else {
+                    WriteOpt::Append
+                },
+                targets: targets.to_vec(),
+                ..*self
+            })
+            .collect())
+    }
+}
+
+impl MotorMultiTargetBuilder {
+    /// Sets the request id. By default, it's `0`.
+    pub fn id(mut self, id: u8) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Sets the timeout in seconds. `0` means 10 seconds, which is the default.
+    pub fn timeout(mut self, timeout: u8) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the type of the movement. By default, it's [`MoveType::Curve`][].
+    pub fn move_type(mut self, move_type: MoveType) -> Self {
+        self.move_type = move_type;
+        self
+    }
+
+    /// Sets the maximum speed from 10 to 115. By default, it's 50.
+    pub fn max_speed(mut self, max_speed: u8) -> Self {
+        self.max_speed = max_speed;
+        self
+    }
+
+    /// Sets the change of the speed. By default, it's [`SpeedChange::Const`][].
+    pub fn speed_change(mut self, speed_change: SpeedChange) -> Self {
+        self.speed_change = speed_change;
+        self
+    }
+
+    /// Sets how to handle the pending request. By default, it's [`WriteOpt::Overwrite`][].
+    pub fn writeopt(mut self, writeopt: WriteOpt) -> Self {
+        self.writeopt = writeopt;
+        self
+    }
+
+    /// Adds the position to visit without turning.
+    pub fn target(self, x: u16, y: u16) -> Self {
+        self.target_with_angle(x, y, AngleType::None, 0)
+    }
+
+    /// Adds the position to visit with the angle. See [`MotorTargetBuilder::angle`][].
+    pub fn target_with_angle(mut self, x: u16, y: u16, angle_type: AngleType, angle: u16) -> Self {
+        self.targets.push((x, y, angle_type, angle));
+        self
+    }
+
+    /// Validates the parameters and builds the request.
+    ///
+    /// There must be 1 to 29 targets.
+    pub fn build(self) -> Result<MotorMultiTarget> {
+        if self.targets.is_empty() || self.targets.len() > MAX_TARGETS {
+            bail!(
+                "The number of targets must be from 1 to {}: {}",
+                MAX_TARGETS,
+                self.targets.len()
+            );
+        }
+        let targets = self
+            .targets
+            .into_iter()
+            .map(|(x, y, angle_type, angle)| Target::with_angle(x, y, angle_type, angle))
+            .collect::<Result<_>>()?;
+        Ok(MotorMultiTarget::new(
+            self.id,
+            self.timeout,
+            self.move_type,
+            max_speed(self.max_speed)?,
+            self.speed_change,
+            self.writeopt,
+            targets,
+        ))
+    }
+}
@@ -0,0 +1,22 @@
+use core::fmt::{self, Display};
+use derive_new::new;
+use serde::{Deserialize, Serialize};
+
+/// Helper to construct [`proto::Uuid`][Uuid] from a string at compile time.
+#[macro_export]
+macro_rules! uuid {
+    ($hex:literal) => {
+        $crate::proto::Uuid(hex_literal::hex!($hex))
+    };
+}
+
+/// Uuid for services or characteristics.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, new)]
+pub struct Uuid(pub [u8; 16]);
+
+impl Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = self.0;
+        write!(f, "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}", b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7], b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15])
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::{Event, Position};
+
+/// Emits [`Event::Proximity`][] when cubes on the same mat come close to each other.
+///
+/// Cubes joined by [`Cube::join_proximity`](crate::Cube::join_proximity) share their
+/// positions. While two cubes are within the range, both of them emit the event on
+/// each position update of either cube.
+///
+/// ```no_run
+/// use futures::prelude::*;
+/// use toio::{Cube, Event, EventFilter, Proximity};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cubes = Cube::search().all().await.unwrap();
+///     let proximity = Proximity::new(50.0);
+///     for cube in &mut cubes {
+///         cube.connect().await.unwrap();
+///         cube.join_proximity(&proximity);
+///     }
+///
+///     let mut events = cubes[0]
+///         .events_filtered(EventFilter::new().proximity())
+///         .await
+///         .unwrap();
+///     while let Some(Event::Proximity { other, distance }) = events.next().await {
+///         println!("tagged {} at {}", other, distance);
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Proximity {
+    range: f32,
+    members: Arc<Mutex<Members>>,
+}
+
+#[derive(Default)]
+struct Members {
+    next: usize,
+    members: HashMap<usize, Member>,
+}
+
+struct Member {
+    id: String,
+    position: Option<Position>,
+    notices: broadcast::Sender<Event>,
+}
+
+impl Proximity {
+    /// Creates the group emitting events within the range in mat units.
+    pub fn new(range: f32) -> Self {
+        Self {
+            range,
+            members: Arc::new(Mutex::new(Members::default())),
+        }
+    }
+
+    /// Gets the range in mat units.
+    pub fn range(&self) -> f32 {
+        self.range
+    }
+
+    /// Adds the cube, which leaves the group when the membership is dropped.
+    pub(crate) fn join(&self, id: &str, notices: broadcast::Sender<Event>) -> Membership {
+        let mut members = self.members.lock().unwrap();
+        let key = members.next;
+        members.next += 1;
+        members.members.insert(
+            key,
+            Member {
+                id: id.to_string(),
+                position: None,
+                notices,
+            },
+        );
+        Membership {
+            proximity: self.clone(),
+            key,
+        }
+    }
+
+    /// Updates the position of the cube, and emits the events for the cubes in range.
+    fn update(&self, key: usize, position: Option<Position>) {
+        let mut members = self.members.lock().unwrap();
+        let members = &mut members.members;
+        let (id, notices) = match members.get_mut(&key) {
+            Some(this) => {
+                this.position = position.clone();
+                (this.id.clone(), this.notices.clone())
+            }
+            None => return,
+        };
+        let position = match position {
+            Some(position) => position,
+            None => return,
+        };
+
+        for (_, other) in members.iter().filter(|(k, _)| **k != key) {
+            let p = match &other.position {
+                Some(p) => p,
+                None => continue,
+            };
+            let dx = p.x as f32 - position.x as f32;
+            let dy = p.y as f32 - position.y as f32;
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance <= self.range {
+                let distance = distance.round() as u16;
+                let _ = notices.send(Event::Proximity {
+                    other: other.id.clone(),
+                    distance,
+                });
+                let _ = other.notices.send(Event::Proximity {
+                    other: id.clone(),
+                    distance,
+                });
+            }
+        }
+    }
+}
+
+/// The cube joining [`Proximity`][].
+pub(crate) struct Membership {
+    proximity: Proximity,
+    key: usize,
+}
+
+impl Membership {
+    /// Updates the position of the cube.
+    pub(crate) fn update(&self, position: Option<Position>) {
+        self.proximity.update(self.key, position);
+    }
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        let mut members = self.proximity.members.lock().unwrap();
+        members.members.remove(&self.key);
+    }
+}
@@ -0,0 +1,172 @@
+use futures::{prelude::*, stream::BoxStream};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// The policy when the channel to a subscriber is full.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Overflow {
+    /// Drops the oldest value in the channel to make room for the new one.
+    DropOldest,
+    /// Drops the new value.
+    DropNewest,
+    /// Waits until the subscriber consumes values.
+    ///
+    /// A slow subscriber delays all the other subscribers of the same cube.
+    Block,
+}
+
+/// The value received from the channel.
+pub(crate) enum Recv<T> {
+    /// A value.
+    Value(T),
+    /// The number of values dropped due to overflow.
+    Lagged(usize),
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    closed: bool,
+    missed: usize,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    overflow: Overflow,
+    readable: Notify,
+    writable: Notify,
+}
+
+/// Creates a bounded channel with the overflow policy.
+pub(crate) fn channel<T>(capacity: usize, overflow: Overflow) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            closed: false,
+            missed: 0,
+        }),
+        capacity: capacity.max(1),
+        overflow,
+        readable: Notify::new(),
+        writable: Notify::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a value.
+    ///
+    /// Returns `false` if the receiver is dropped.
+    pub async fn send(&self, value: T) -> bool {
+        let mut value = Some(value);
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.closed {
+                    return false;
+                }
+                if state.queue.len() < self.shared.capacity {
+                    state.queue.extend(value.take());
+                } else {
+                    match self.shared.overflow {
+                        Overflow::DropOldest => {
+                            state.queue.pop_front();
+                            state.queue.extend(value.take());
+                            state.missed += 1;
+                        }
+                        Overflow::DropNewest => {
+                            value.take();
+                            state.missed += 1;
+                        }
+                        Overflow::Block => {}
+                    }
+                }
+            }
+            if value.is_none() {
+                self.shared.readable.notify();
+                return true;
+            }
+            self.shared.writable.notified().await;
+        }
+    }
+
+    /// Returns `true` if the receiver is dropped.
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+
+    /// Adds the number of values dropped before reaching the channel.
+    pub fn lag(&self, missed: usize) {
+        self.shared.state.lock().unwrap().missed += missed;
+        self.shared.readable.notify();
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.readable.notify();
+    }
+}
+
+pub(crate) struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Send + 'static> Receiver<T> {
+    /// Receives a value.
+    ///
+    /// If values have been dropped due to overflow, reports the number of them first.
+    /// Returns `None` if the sender is dropped and no value remains.
+    pub async fn recv(&mut self) -> Option<Recv<T>> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.missed > 0 {
+                    let missed = state.missed;
+                    state.missed = 0;
+                    return Some(Recv::Lagged(missed));
+                }
+                if let Some(value) = state.queue.pop_front() {
+                    drop(state);
+                    self.shared.writable.notify();
+                    return Some(Recv::Value(value));
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.shared.readable.notified().await;
+        }
+    }
+
+    /// Converts into a stream.
+    pub fn into_stream(self) -> BoxStream<'static, Recv<T>> {
+        stream::unfold(
+            self,
+            |mut rx| async move { rx.recv().await.map(|v| (v, rx)) },
+        )
+        .boxed()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        state.queue.clear();
+        drop(state);
+        self.shared.writable.notify();
+    }
+}
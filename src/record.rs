@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::{delay_until, Instant};
+
+use crate::{
+    ble::{mock, mock::MockHandle, Uuid},
+    proto::Message,
+    Cube, Options,
+};
+
+/// A value received from a characteristic at a point of the session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The elapsed time since the recording started.
+    pub time: Duration,
+    /// The UUID of the characteristic of the toio cube.
+    pub uuid: Uuid,
+    /// The bytes received.
+    pub value: Vec<u8>,
+}
+
+impl Record {
+    /// Decodes the value into a message.
+    pub fn message(&self) -> Result<Message> {
+        (self.uuid, &self.value as &[u8]).try_into()
+    }
+}
+
+/// Appends received values to a file in [JSON Lines](https://jsonlines.org/) format.
+///
+/// Each line is a [`Record`][] with the time elapsed since the recorder was created.
+/// Values are kept as they are received, so sessions can be decoded later
+/// even if they contain malformed bytes.
+///
+/// ```no_run
+/// use toio::Cube;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     // Record the session until the cube is dropped.
+///     cube.record("session.jsonl").await.unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Recorder<W> {
+    out: W,
+    start: Instant,
+}
+
+impl Recorder<BufWriter<File>> {
+    /// Creates a recorder appending to the file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Couldn't open {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Creates a recorder writing to the writer.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            start: Instant::now(),
+        }
+    }
+
+    /// Appends a value received from the characteristic.
+    pub fn record(&mut self, uuid: Uuid, value: Vec<u8>) -> Result<()> {
+        let record = Record {
+            time: self.start.elapsed(),
+            uuid,
+            value,
+        };
+        self.write(&record)
+    }
+
+    /// Appends a record as it is.
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        serde_json::to_writer(&mut self.out, record).context("Couldn't serialize record")?;
+        self.out.write_all(b"\n")?;
+        // Flushes each line so that the file is usable even if the program crashes.
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Unwraps the writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Plays a recorded session back through a mock peripheral.
+///
+/// Values are sent with the same intervals as recorded, so that the logic driven by
+/// the sensors behaves in the same way as in the original session without hardware.
+///
+/// ```no_run
+/// use futures::prelude::*;
+/// use toio::record::Replay;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let replay = Replay::open("session.jsonl").unwrap();
+///
+///     let (mut cube, mock) = replay.cube();
+///     cube.connect().await.unwrap();
+///     let mut events = cube.events().await.unwrap();
+///
+///     tokio::spawn(async move { replay.play(&mock).await });
+///
+///     while let Some(event) = events.next().await {
+///         println!("{:?}", event);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Replay {
+    records: Vec<Record>,
+}
+
+impl Replay {
+    /// Creates a replay of the records.
+    pub fn new(records: Vec<Record>) -> Self {
+        Self { records }
+    }
+
+    /// Loads a session recorded by [`Recorder`][].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Couldn't open {}", path.display()))?;
+        let mut records = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid record at line {}", i + 1))?;
+            records.push(record);
+        }
+        Ok(Self::new(records))
+    }
+
+    /// Gets the records.
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Creates a cube backed by a mock peripheral to play the session on.
+    pub fn cube(&self) -> (Cube, MockHandle) {
+        self.cube_with_options(Options::default())
+    }
+
+    /// Creates a cube with the options, backed by a mock peripheral to play the session on.
+    pub fn cube_with_options(&self, opts: Options) -> (Cube, MockHandle) {
+        let (dev, handle) = mock::mock("replay");
+        (Cube::from_peripheral(Box::new(dev), opts), handle)
+    }
+
+    /// Sends the records to the mock peripheral with the original timing.
+    ///
+    /// The first record is sent immediately.
+    pub async fn play(&self, mock: &MockHandle) {
+        let start = Instant::now();
+        let offset = self.records.first().map(|r| r.time).unwrap_or_default();
+        for r in &self.records {
+            delay_until(start + r.time.checked_sub(offset).unwrap_or_default()).await;
+            mock.notify(r.uuid, r.value.clone());
+        }
+    }
+}
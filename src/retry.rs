@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Exponential backoff to retry failed operations.
+///
+/// The delay before the `n`-th retry is `initial * multiplier^n`, capped by `max`.
+/// The delay is randomized by `jitter` so that many cubes don't retry at the same time.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use toio::{Backoff, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search()
+///         .connect_retry(Backoff::new(5, Duration::from_millis(500)))
+///         .nearest()
+///         .await
+///         .unwrap();
+///     cube.connect().await.unwrap();
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// The number of retries. `0` means no retry.
+    pub retries: usize,
+    /// The delay before the first retry.
+    pub initial: Duration,
+    /// The maximum delay.
+    pub max: Duration,
+    /// The factor to multiply the delay by on each retry.
+    pub multiplier: f32,
+    /// The ratio of randomization from `0.0` to `1.0`.
+    ///
+    /// For example, `0.2` randomizes the delay within ±20%.
+    pub jitter: f32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(3, Duration::from_millis(200))
+    }
+}
+
+impl Backoff {
+    /// Creates a backoff with the number of retries and the initial delay.
+    ///
+    /// The delay doubles on each retry up to 5 seconds, and is randomized within ±20%.
+    pub fn new(retries: usize, initial: Duration) -> Self {
+        Self {
+            retries,
+            initial,
+            max: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+
+    /// Creates a backoff which never retries.
+    pub fn none() -> Self {
+        Self::new(0, Duration::from_secs(0))
+    }
+
+    /// Gets the delay before the `n`-th retry without jitter.
+    pub fn base_delay(&self, n: usize) -> Duration {
+        let factor = (self.multiplier.max(1.0) as f64).powi(n.min(64) as i32);
+        let delay = self.initial.as_secs_f64() * factor;
+        if delay >= self.max.as_secs_f64() {
+            self.max
+        } else {
+            self.initial.mul_f64(factor)
+        }
+    }
+
+    /// Gets the delay before the `n`-th retry.
+    pub fn delay(&self, n: usize) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let r = fastrand::f32() * 2.0 - 1.0;
+        self.base_delay(n).mul_f64((1.0 + jitter * r) as f64)
+    }
+
+    /// Iterates over the delays before each retry.
+    pub fn delays(&self) -> impl Iterator<Item = Duration> {
+        let backoff = *self;
+        (0..self.retries).map(move |n| backoff.delay(n))
+    }
+}
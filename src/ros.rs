@@ -0,0 +1,227 @@
+use anyhow::{Context, Result};
+use futures::prelude::*;
+use log::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+use crate::{proto::*, Cube, Event, Position};
+
+#[derive(Deserialize)]
+struct Vector3 {
+    #[serde(default)]
+    x: f64,
+    #[serde(default)]
+    z: f64,
+}
+
+#[derive(Deserialize)]
+struct Twist {
+    linear: Vector3,
+    angular: Vector3,
+}
+
+#[derive(Deserialize)]
+struct Incoming {
+    op: String,
+    topic: Option<String>,
+    msg: Option<serde_json::Value>,
+}
+
+/// Bridges a cube to ROS 2 through [rosbridge](https://github.com/RobotWebTools/rosbridge_suite).
+///
+/// Publishes the pose of the cube on the mat to `<namespace>/pose` as `geometry_msgs/msg/PoseStamped`,
+/// and drives the cube by `geometry_msgs/msg/Twist` from `<namespace>/cmd_vel`.
+///
+/// The pose is in meters and radians in a right-handed frame on the mat:
+/// the x axis is the same as the mat, the y axis is flipped, and the yaw is counter-clockwise.
+/// The cube stops if no `cmd_vel` arrives within the command timeout.
+///
+/// Enabled by the `ros` feature.
+///
+/// ```no_run
+/// use toio::{ros::Bridge, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     // Run `ros2 launch rosbridge_server rosbridge_websocket_launch.xml` beforehand.
+///     Bridge::new("ws://localhost:9090").run(&mut cube).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    url: String,
+    namespace: String,
+    frame_id: String,
+    scale: f64,
+    speed_scale: f64,
+    track: f64,
+    cmd_timeout: Duration,
+}
+
+impl Bridge {
+    /// Creates a bridge to the rosbridge server at the URL.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            namespace: "/toio".to_string(),
+            frame_id: "mat".to_string(),
+            scale: 0.00136,
+            speed_scale: 0.0043,
+            track: 0.0266,
+            cmd_timeout: Duration::from_millis(500),
+        }
+    }
+
+    /// Sets the namespace of the topics. By default, `/toio` is used.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the frame id of the pose. By default, `mat` is used.
+    pub fn frame_id(mut self, frame_id: &str) -> Self {
+        self.frame_id = frame_id.to_string();
+        self
+    }
+
+    /// Sets the length in meters of a unit of the mat coordinates.
+    ///
+    /// By default, it's 1.36 mm, which is approximately the one of the toio collection mat.
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sets the wheel speed in meters per second of a unit of the motor speed.
+    ///
+    /// By default, it's 4.3 mm/s, which is approximately the one of the toio cube.
+    pub fn speed_scale(mut self, speed_scale: f64) -> Self {
+        self.speed_scale = speed_scale;
+        self
+    }
+
+    /// Sets the distance in meters between the wheels. By default, it's 26.6 mm.
+    pub fn track(mut self, track: f64) -> Self {
+        self.track = track;
+        self
+    }
+
+    /// Sets the time to keep the last `cmd_vel`. By default, it's 500 milliseconds.
+    ///
+    /// Must be less than 2560 milliseconds.
+    pub fn cmd_timeout(mut self, timeout: Duration) -> Self {
+        self.cmd_timeout = timeout;
+        self
+    }
+
+    /// Connects to rosbridge and bridges the cube until the connection or the events end.
+    pub async fn run(&self, cube: &mut Cube) -> Result<()> {
+        let (ws, _) = connect_async(self.url.as_str())
+            .await
+            .with_context(|| format!("Couldn't connect to rosbridge {}", self.url))?;
+        let (mut tx, mut rx) = ws.split();
+
+        let pose_topic = format!("{}/pose", self.namespace);
+        let cmd_topic = format!("{}/cmd_vel", self.namespace);
+        let ops = vec![
+            json!({"op": "advertise", "topic": pose_topic, "type": "geometry_msgs/msg/PoseStamped"}),
+            json!({"op": "subscribe", "topic": cmd_topic, "type": "geometry_msgs/msg/Twist"}),
+        ];
+        for op in ops {
+            tx.send(WsMessage::Text(op.to_string())).await?;
+        }
+
+        let mut events = cube.events().await?;
+        loop {
+            tokio::select! {
+                event = events.next() => {
+                    let pos = match event {
+                        Some(Event::Position(Some(pos))) => pos,
+                        Some(_) => continue,
+                        None => break,
+                    };
+                    let op = json!({"op": "publish", "topic": pose_topic, "msg": self.pose(&pos)});
+                    tx.send(WsMessage::Text(op.to_string())).await?;
+                }
+                msg = rx.next() => {
+                    let text = match msg.transpose()? {
+                        Some(WsMessage::Text(text)) => text,
+                        Some(WsMessage::Close(_)) | None => break,
+                        Some(_) => continue,
+                    };
+                    let twist = serde_json::from_str::<Incoming>(&text)
+                        .ok()
+                        .filter(|m| m.op == "publish" && m.topic.as_ref() == Some(&cmd_topic))
+                        .and_then(|m| m.msg)
+                        .map(serde_json::from_value::<Twist>);
+                    match twist {
+                        Some(Ok(twist)) => cube.write_msg(self.motor(&twist)?, false).await?,
+                        Some(Err(e)) => warn!("Invalid twist on {}: {}", cmd_topic, e),
+                        None => debug!("Ignored message from rosbridge: {}", text),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pose(&self, pos: &Position) -> serde_json::Value {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let yaw = -(pos.angle as f64).to_radians();
+        json!({
+            "header": {
+                "stamp": {"sec": now.as_secs(), "nanosec": now.subsec_nanos()},
+                "frame_id": self.frame_id,
+            },
+            "pose": {
+                "position": {
+                    "x": pos.x as f64 * self.scale,
+                    "y": -(pos.y as f64) * self.scale,
+                    "z": 0.0,
+                },
+                "orientation": {"x": 0.0, "y": 0.0, "z": (yaw / 2.0).sin(), "w": (yaw / 2.0).cos()},
+            },
+        })
+    }
+
+    fn motor(&self, twist: &Twist) -> Result<Message> {
+        // Differential drive.
+        let v = twist.linear.x;
+        let w = twist.angular.z;
+        let wheel = |v: f64| {
+            let speed = (v / self.speed_scale).round();
+            let dir = if speed < 0.0 {
+                MotorDir::Backward
+            } else {
+                MotorDir::Forward
+            };
+            (dir, speed.abs().min(115.0) as u8)
+        };
+        let (left_dir, left) = wheel(v - w * self.track / 2.0);
+        let (right_dir, right) = wheel(v + w * self.track / 2.0);
+
+        let duration = self.cmd_timeout.as_millis() / 10;
+        anyhow::ensure!(
+            duration <= 255,
+            "Command timeout must be less than 2560 milliseconds"
+        );
+
+        Ok(Message::Motor(Motor::Timed(MotorTimed::new(
+            MotorId::Left,
+            left_dir,
+            left,
+            MotorId::Right,
+            right_dir,
+            right,
+            duration as u8,
+        ))))
+    }
+}
@@ -0,0 +1,22 @@
+use futures::prelude::*;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+
+/// Gets the runtime shared by the synchronous APIs.
+pub(crate) fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .build()
+            .expect("Couldn't create runtime")
+    })
+}
+
+/// Runs the future to completion on the shared runtime.
+///
+/// Panics if called from inside a runtime.
+pub(crate) fn block_on<F: Future>(f: F) -> F::Output {
+    runtime().handle().block_on(f)
+}
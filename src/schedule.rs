@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use futures::prelude::*;
+use log::*;
+use std::time::Duration;
+use tokio::time::{delay_until, Instant};
+
+use crate::{proto::Message, Cube};
+
+/// An action to write to a cube at the deadline.
+#[derive(Debug, Clone)]
+struct Action {
+    deadline: Instant,
+    cube: usize,
+    msg: Message,
+}
+
+/// Actions of several cubes at absolute deadlines on the tokio clock.
+///
+/// Writing to the cubes one after another, or even by joining the futures, makes them
+/// act at different times, because each cube has its own BLE delay. The scheduler
+/// writes each action earlier by the delay of the cube measured by
+/// [`Cube::probe_latency`][], so that the cubes receive the actions at the deadlines.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tokio::time::Instant;
+/// use toio::{proto::*, schedule::Scheduler, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cubes = Cube::search().all().await.unwrap();
+///     for cube in &mut cubes {
+///         cube.connect().await.unwrap();
+///         cube.probe_latency(10).await.unwrap();
+///     }
+///
+///     // Lights up the cubes one by one, and then turns off all at once.
+///     let start = Instant::now() + Duration::from_secs(1);
+///     let mut scheduler = Scheduler::new();
+///     for i in 0..cubes.len() {
+///         let at = start + Duration::from_millis(200) * i as u32;
+///         scheduler.at(at, i, Light::On(LightOn::new(0, 255, 255, 255)));
+///     }
+///     let end = start + Duration::from_millis(200) * cubes.len() as u32;
+///     for i in 0..cubes.len() {
+///         scheduler.at(end, i, Light::Off(LightOff::new()));
+///     }
+///     scheduler.run(&mut cubes).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    actions: Vec<Action>,
+}
+
+impl Scheduler {
+    /// Creates an empty scheduler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the message to write to the cube at the index at the deadline.
+    pub fn at<T: Into<Message>>(&mut self, deadline: Instant, cube: usize, msg: T) -> &mut Self {
+        self.actions.push(Action {
+            deadline,
+            cube,
+            msg: msg.into(),
+        });
+        self
+    }
+
+    /// Gets the number of the actions.
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Returns `true` if no action is scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Writes all the actions to the cubes, and waits until the last one is written.
+    ///
+    /// The actions of a cube are written in the order of the deadlines; the ones already
+    /// past are written at once. If a write to a cube fails, the rest of the actions of
+    /// that cube are dropped, while the other cubes go on. Returns the first error then.
+    pub async fn run(self, cubes: &mut [Cube]) -> Result<()> {
+        if let Some(action) = self.actions.iter().find(|a| a.cube >= cubes.len()) {
+            return Err(anyhow!(
+                "Action for cube {} out of {} cubes",
+                action.cube,
+                cubes.len()
+            ));
+        }
+
+        let mut queues: Vec<Vec<Action>> = vec![vec![]; cubes.len()];
+        for action in self.actions {
+            queues[action.cube].push(action);
+        }
+
+        let results = future::join_all(cubes.iter_mut().zip(queues).map(
+            |(cube, mut actions)| async move {
+                // Stable, so the actions at the same deadline keep the order of addition.
+                actions.sort_by_key(|a| a.deadline);
+                let lead = cube.latency().map(|l| l.one_way()).unwrap_or_default();
+                for action in actions {
+                    let at = action.deadline.checked_sub(lead).unwrap_or(action.deadline);
+                    let late = Instant::now().saturating_duration_since(at);
+                    if late > Duration::from_secs(0) {
+                        debug!("Writing to {} late by {:?}", cube.id(), late);
+                    }
+                    delay_until(at).await;
+                    cube.write_msg(action.msg, false)
+                        .await
+                        .map_err(|e| anyhow!("Couldn't write to {}: {}", cube.id(), e))?;
+                }
+                Ok(())
+            },
+        ))
+        .await;
+        results.into_iter().collect()
+    }
+}
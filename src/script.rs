@@ -0,0 +1,276 @@
+use anyhow::{anyhow, Result};
+use rhai::{Dynamic, Engine, EvalAltResult, Map};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{Cube, Position, SoundPresetId};
+
+#[derive(Debug)]
+enum Call {
+    Go(isize, isize, Option<Duration>),
+    Stop,
+    LightOn(u8, u8, u8, Option<Duration>),
+    LightOff,
+    Sound(u8),
+    Battery,
+    Button,
+    Collision,
+    Slope,
+    Position,
+}
+
+#[derive(Debug)]
+enum Reply {
+    Unit,
+    Int(i64),
+    Bool(bool),
+    Position(Option<Position>),
+}
+
+type Request = (Call, oneshot::Sender<Result<Reply>>);
+
+/// Runs [rhai](https://rhai.rs/) scripts driving a cube.
+///
+/// Scripts can be edited without recompiling the host program.
+/// The following functions are available in scripts:
+///
+/// | Function | Description |
+/// |----------|-------------|
+/// | `drive(left, right)`, `drive(left, right, ms)` | Runs the motors. See [`Cube::go`][]. |
+/// | `stop()` | Stops the motors. |
+/// | `light(r, g, b)`, `light(r, g, b, ms)` | Turns on the light. |
+/// | `light_off()` | Turns off the light. |
+/// | `sound(id)` | Plays the preset sound from `0` to `10`. |
+/// | `sleep(ms)` | Waits for the milliseconds. |
+/// | `battery()` | Gets the battery level in percent. |
+/// | `button()`, `collision()`, `slope()` | Gets the sensor state. |
+/// | `position()` | Gets `#{x, y, angle}`, or `()` if the cube is off the mat. |
+///
+/// Enabled by the `script` feature.
+///
+/// ```no_run
+/// use toio::{script::Script, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cube = Cube::search().nearest().await.unwrap();
+///     cube.connect().await.unwrap();
+///
+///     let source = r#"
+///         for i in 0..4 {
+///             drive(50, 50, 1000);
+///             sleep(1000);
+///             drive(50, -50, 400);
+///             sleep(400);
+///         }
+///         sound(1);
+///     "#;
+///     Script::new().run(&mut cube, source).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Script {
+    max_operations: u64,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Script {
+    /// Creates a script runner.
+    pub fn new() -> Self {
+        Self { max_operations: 0 }
+    }
+
+    /// Sets the maximum number of operations a script can run, to stop runaway scripts.
+    ///
+    /// By default, it's unlimited.
+    pub fn max_operations(mut self, max_operations: u64) -> Self {
+        self.max_operations = max_operations;
+        self
+    }
+
+    /// Runs the script on the cube until it finishes.
+    ///
+    /// The script is aborted if the returned future is dropped.
+    pub async fn run(&self, cube: &mut Cube, source: &str) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Request>();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let _guard = CancelGuard(cancel.clone());
+
+        let source = source.to_string();
+        let max_operations = self.max_operations;
+        // Scripts run on a blocking thread as the engine is synchronous.
+        let mut script = tokio::task::spawn_blocking(move || {
+            let engine = engine(tx, cancel, max_operations);
+            engine
+                .run(&source)
+                .map_err(|e| anyhow!("Script error: {}", e))
+        });
+
+        loop {
+            tokio::select! {
+                res = &mut script => return res?,
+                req = rx.recv() => match req {
+                    Some((call, reply)) => {
+                        let _ = reply.send(execute(cube, call).await);
+                    }
+                    None => return script.await?,
+                },
+            }
+        }
+    }
+}
+
+struct CancelGuard(Arc<AtomicBool>);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+async fn execute(cube: &mut Cube, call: Call) -> Result<Reply> {
+    Ok(match call {
+        Call::Go(left, right, duration) => {
+            cube.go(left, right, duration).await?;
+            Reply::Unit
+        }
+        Call::Stop => {
+            cube.stop().await?;
+            Reply::Unit
+        }
+        Call::LightOn(r, g, b, duration) => {
+            cube.light_on(r, g, b, duration).await?;
+            Reply::Unit
+        }
+        Call::LightOff => {
+            cube.light_off().await?;
+            Reply::Unit
+        }
+        Call::Sound(id) => {
+            let id: SoundPresetId = serde_json::from_value(id.into())
+                .map_err(|_| anyhow!("Invalid preset sound: {}", id))?;
+            cube.play_preset(id).await?;
+            Reply::Unit
+        }
+        Call::Battery => Reply::Int(cube.battery().await? as i64),
+        Call::Button => Reply::Bool(cube.button().await?),
+        Call::Collision => Reply::Bool(cube.collision().await?),
+        Call::Slope => Reply::Bool(cube.slope().await?),
+        Call::Position => Reply::Position(cube.position().await?),
+    })
+}
+
+type CallResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+#[derive(Clone)]
+struct Caller(mpsc::UnboundedSender<Request>);
+
+impl Caller {
+    fn call(&self, call: Call) -> CallResult<Reply> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send((call, tx))
+            .map_err(|_| "The script is cancelled")?;
+        match futures::executor::block_on(rx) {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(e)) => Err(e.to_string().into()),
+            Err(_) => Err("The script is cancelled".into()),
+        }
+    }
+
+    fn unit(&self, call: Call) -> CallResult<()> {
+        self.call(call).map(|_| ())
+    }
+
+    fn int(&self, call: Call) -> CallResult<i64> {
+        match self.call(call)? {
+            Reply::Int(v) => Ok(v),
+            r => Err(format!("Unexpected reply: {:?}", r).into()),
+        }
+    }
+
+    fn bool(&self, call: Call) -> CallResult<bool> {
+        match self.call(call)? {
+            Reply::Bool(v) => Ok(v),
+            r => Err(format!("Unexpected reply: {:?}", r).into()),
+        }
+    }
+}
+
+fn ms(ms: i64) -> Duration {
+    Duration::from_millis(ms.max(0) as u64)
+}
+
+fn byte(v: i64) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+fn engine(tx: mpsc::UnboundedSender<Request>, cancel: Arc<AtomicBool>, max_ops: u64) -> Engine {
+    let mut engine = Engine::new();
+    let c = Caller(tx);
+
+    engine.set_max_operations(max_ops);
+    engine.on_progress(move |_| {
+        if cancel.load(Ordering::Relaxed) {
+            Some("The script is cancelled".into())
+        } else {
+            None
+        }
+    });
+
+    let cc = c.clone();
+    engine.register_fn("drive", move |l: i64, r: i64| {
+        cc.unit(Call::Go(l as isize, r as isize, None))
+    });
+    let cc = c.clone();
+    engine.register_fn("drive", move |l: i64, r: i64, t: i64| {
+        cc.unit(Call::Go(l as isize, r as isize, Some(ms(t))))
+    });
+    let cc = c.clone();
+    engine.register_fn("stop", move || cc.unit(Call::Stop));
+    let cc = c.clone();
+    engine.register_fn("light", move |r: i64, g: i64, b: i64| {
+        cc.unit(Call::LightOn(byte(r), byte(g), byte(b), None))
+    });
+    let cc = c.clone();
+    engine.register_fn("light", move |r: i64, g: i64, b: i64, t: i64| {
+        cc.unit(Call::LightOn(byte(r), byte(g), byte(b), Some(ms(t))))
+    });
+    let cc = c.clone();
+    engine.register_fn("light_off", move || cc.unit(Call::LightOff));
+    let cc = c.clone();
+    engine.register_fn("sound", move |id: i64| cc.unit(Call::Sound(byte(id))));
+    engine.register_fn("sleep", |t: i64| std::thread::sleep(ms(t)));
+    let cc = c.clone();
+    engine.register_fn("battery", move || cc.int(Call::Battery));
+    let cc = c.clone();
+    engine.register_fn("button", move || cc.bool(Call::Button));
+    let cc = c.clone();
+    engine.register_fn("collision", move || cc.bool(Call::Collision));
+    let cc = c.clone();
+    engine.register_fn("slope", move || cc.bool(Call::Slope));
+    engine.register_fn("position", move || -> CallResult<Dynamic> {
+        match c.call(Call::Position)? {
+            Reply::Position(Some(p)) => {
+                let mut map = Map::new();
+                map.insert("x".into(), (p.x as i64).into());
+                map.insert("y".into(), (p.y as i64).into());
+                map.insert("angle".into(), (p.angle as i64).into());
+                Ok(map.into())
+            }
+            Reply::Position(None) => Ok(Dynamic::UNIT),
+            r => Err(format!("Unexpected reply: {:?}", r).into()),
+        }
+    });
+
+    engine
+}
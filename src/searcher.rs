@@ -1,16 +1,73 @@
 use crate::{
     ble::{self, PeripheralOps},
-    proto, Cube,
+    cube::Options,
+    proto::Profile,
+    sim::{Mat, Model, Simulator},
+    Backoff, Cube, Overflow,
 };
 use anyhow::{anyhow, Context, Result};
+use std::cmp::Reverse;
+use std::collections::HashSet;
 use std::fmt::{self, Debug};
 use std::time::Duration;
 
 const SEARCH_TIMEOUT: Duration = Duration::from_secs(3);
 
+/// The transport to search for cubes on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    /// The platform-specific BLE backend.
+    Native,
+    /// A simulated cube at the center of the toio collection mat, which works without hardware.
+    ///
+    /// See [`Simulator`](crate::sim::Simulator).
+    Mock,
+    /// The cubes on another machine served by [`ble::remote::serve`][] at the address.
+    Remote(String),
+}
+
+impl Backend {
+    fn searcher(&self, opts: ble::Options) -> ble::Searcher {
+        match self {
+            Backend::Native => ble::native_searcher(opts),
+            Backend::Mock => Box::new(MockSearcher),
+            Backend::Remote(addr) => ble::remote::searcher(addr),
+        }
+    }
+}
+
+struct MockSearcher;
+
+#[async_trait::async_trait]
+impl ble::SearchOps for MockSearcher {
+    async fn search(&mut self, _: &ble::Uuid, _: Duration) -> Result<Vec<ble::Peripheral>> {
+        let (min, max) = Mat::Collection.area();
+        let x = (min.0 + max.0) as f32 / 2.0;
+        let y = (min.1 + max.1) as f32 / 2.0;
+        let model = Model::new(x, y, 0.0).area(min, max);
+        Ok(vec![Box::new(Simulator::named("mock", model))])
+    }
+}
+
+/// A cube discovered while searching, given to the predicate of [`Searcher::search_until`][].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discovery<'a> {
+    /// The peripheral id.
+    pub id: &'a str,
+    /// The local name in the advertisement, if the backend reports it.
+    pub name: Option<&'a str>,
+    /// The signal strength.
+    pub rssi: i32,
+    /// The number of distinct cubes discovered so far, including this one.
+    pub count: usize,
+}
+
 /// Searcher to search cubes.
 pub struct Searcher {
-    searcher: ble::Searcher,
+    searcher: Option<ble::Searcher>,
+    backend: Option<Backend>,
+    min_rssi: Option<i32>,
+    opts: Options,
 }
 
 impl Debug for Searcher {
@@ -26,10 +83,277 @@ impl Searcher {
     /// Use [`Searcher::new_with_timeout`][] to specify custom timeout.
     pub fn new() -> Self {
         Self {
-            searcher: ble::searcher(),
+            searcher: None,
+            backend: None,
+            min_rssi: None,
+            opts: Options::default(),
         }
     }
 
+    /// Sets the transport to search for cubes on.
+    ///
+    /// By default, the backend registered by [`ble::register_backend`][] is used if any,
+    /// otherwise [`Backend::Native`][].
+    ///
+    /// ```
+    /// use toio::{Backend, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let backend = match std::env::var("TOIO_REMOTE") {
+    ///         Ok(addr) => Backend::Remote(addr),
+    ///         Err(_) => Backend::Mock,
+    ///     };
+    ///
+    ///     let mut cube = Cube::search().with_backend(backend).nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = Some(backend);
+        self.searcher = None;
+        self
+    }
+
+    /// Sets the capacity of the channels to deliver messages and events.
+    ///
+    /// The capacity applies to both the BLE backend and each subscriber of the cubes found.
+    /// The default capacity is 100000.
+    ///
+    /// ```no_run
+    /// use toio::{Cube, Overflow};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cube = Cube::search()
+    ///         .channel_capacity(64)
+    ///         .overflow(Overflow::DropNewest)
+    ///         .nearest()
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.opts.capacity = capacity;
+        self
+    }
+
+    /// Sets the policy when the channel to a subscriber is full.
+    ///
+    /// The default policy is [`Overflow::DropOldest`][].
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.opts.overflow = overflow;
+        self
+    }
+
+    /// Selects the Bluetooth adapter to scan and connect on, among [`ble::adapters`][].
+    ///
+    /// By default, the default adapter of the platform is used.
+    /// Searching fails if the adapter isn't found. Only the native backend respects this.
+    pub fn adapter(mut self, adapter: ble::Adapter) -> Self {
+        self.opts.adapter = Some(adapter);
+        self
+    }
+
+    /// Sets the timeout to connect/disconnect the cubes found.
+    ///
+    /// The default timeout is 5 seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.opts.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the backoff to retry connecting the cubes found.
+    ///
+    /// By default, doesn't retry. [`Backoff::default`][] retries 3 times starting
+    /// from 200 milliseconds.
+    pub fn connect_retry(mut self, backoff: Backoff) -> Self {
+        self.opts.connect_retry = backoff;
+        self
+    }
+
+    /// Sets the timeout to wait for write responses from the cubes found.
+    ///
+    /// The default timeout is 5 seconds.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.opts.write_timeout = timeout;
+        self
+    }
+
+    /// Sets the backoff to retry failed writes with response to the cubes found.
+    ///
+    /// By default, writes are not retried.
+    /// See [`ble::RetryWrite`][] for which messages are safe to retry.
+    pub fn write_retry(mut self, backoff: Backoff) -> Self {
+        self.opts.write_retry = backoff;
+        self
+    }
+
+    /// Sets the maximum number of writes per second to the cubes found.
+    ///
+    /// Writes are queued and sent in the background at the rate, so bursty callers
+    /// (e.g. updating the light every frame) don't flood the BLE stack.
+    /// By default, there is no limit.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().max_write_rate(20).nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn max_write_rate(mut self, rate: u32) -> Self {
+        self.opts.max_write_rate = Some(rate);
+        self
+    }
+
+    /// Enables coalescing of motor/light commands to the cubes found.
+    ///
+    /// While a write is in flight, only the latest pending motor/light command
+    /// of the same kind is kept. By default, coalescing is disabled.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().coalesce(true).nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     // Only the latest command is written if the previous one is still pending.
+    ///     for i in 0..60 {
+    ///         cube.go(i, i, None).await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.opts.coalesce = coalesce;
+        self
+    }
+
+    /// Sets the timeout to read values from the cubes found.
+    ///
+    /// The default timeout is 5 seconds.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.opts.read_timeout = timeout;
+        self
+    }
+
+    /// Sets the UUIDs of the service and the characteristics.
+    ///
+    /// Use this to search for toio-compatible devices with different UUIDs.
+    /// By default, the UUIDs of the toio cube are used.
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.opts.profile = profile;
+        self
+    }
+
+    /// Enables strict decoding of notifications from the cubes found, for protocol debugging.
+    ///
+    /// Notifications with bytes left after the message are rejected and logged
+    /// with the offending field and the lengths. By default, trailing bytes are ignored.
+    pub fn strict_decode(mut self, strict: bool) -> Self {
+        self.opts.strict_decode = strict;
+        self
+    }
+
+    /// Sets the interval to refresh the signal strength of the cubes found while connected.
+    ///
+    /// Each refresh waits for the other operations on the cube, e.g., writes with response.
+    /// By default, the signal strength is not refreshed. `None` disables refreshing.
+    pub fn rssi_interval(mut self, interval: Option<Duration>) -> Self {
+        self.opts.rssi_interval = interval;
+        self
+    }
+
+    /// Reads the battery of the cubes found periodically while connected.
+    ///
+    /// Battery notifications are sparse, so polling keeps the value and
+    /// [`Event::BatteryLow`](crate::Event::BatteryLow) up to date.
+    /// By default, the battery is not polled.
+    pub fn battery_interval(mut self, interval: Duration) -> Self {
+        self.opts.battery_interval = Some(interval);
+        self
+    }
+
+    /// Emits [`Event::BatteryLow`](crate::Event::BatteryLow) when the battery falls
+    /// to the percentage or below.
+    ///
+    /// ```no_run
+    /// use futures::prelude::*;
+    /// use std::time::Duration;
+    /// use toio::{Cube, Event};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search()
+    ///         .battery_interval(Duration::from_secs(60))
+    ///         .battery_low(20)
+    ///         .nearest()
+    ///         .await
+    ///         .unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mut events = cube.events().await.unwrap();
+    ///     while let Some(event) = events.next().await {
+    ///         if let Event::BatteryLow(pct) = event {
+    ///             println!("Battery low: {}%", pct);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn battery_low(mut self, pct: usize) -> Self {
+        self.opts.battery_low = Some(pct);
+        self
+    }
+
+    /// Checks if the cubes found are alive when no notification arrives for the duration.
+    ///
+    /// The cube is asked to send the battery, and [`Event::Unresponsive`](crate::Event::Unresponsive)
+    /// is emitted if it doesn't respond within the read timeout.
+    /// This catches dead connections early. By default, the check is disabled.
+    pub fn watchdog(mut self, idle: Duration) -> Self {
+        self.opts.watchdog = Some(idle);
+        self
+    }
+
+    /// Detects dropped position notifications of the cubes found.
+    ///
+    /// The intervals of the notifications are learned while the cube moves on the mat,
+    /// and [`Event::NotificationGap`](crate::Event::NotificationGap) is emitted when
+    /// an interval is much longer than usual. By default, the detection is disabled.
+    pub fn detect_gaps(mut self) -> Self {
+        self.opts.detect_gaps = true;
+        self
+    }
+
+    /// Makes [`Searcher::nearest`][] return as soon as a cube with the signal strength
+    /// or stronger is discovered, instead of searching for the whole timeout.
+    ///
+    /// Not all backends support stopping early; the remote one always searches for the timeout.
+    ///
+    /// ```no_run
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest_min_rssi(-50).nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn nearest_min_rssi(mut self, rssi: i32) -> Self {
+        self.min_rssi = Some(rssi);
+        self
+    }
+
+    /// Sets all the options at once.
+    pub fn options(mut self, opts: Options) -> Self {
+        self.opts = opts;
+        self
+    }
+
     /// Searches for all cubes.
     ///
     /// This searches for cubes for 3 seconds.
@@ -60,13 +384,53 @@ impl Searcher {
     /// }
     /// ```
     pub async fn all_timeout(&mut self, timeout: Duration) -> Result<Vec<Cube>> {
+        self.search_until(|_| false, timeout).await
+    }
+
+    /// Searches for cubes until the predicate returns `true` for a discovery, or the timeout.
+    ///
+    /// The predicate is called every time a cube is discovered or advertises again.
+    /// The cubes found so far are returned, sorted from nearest to farthest.
+    /// Not all backends support stopping early; the remote one always searches for the timeout.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     // Stops as soon as two cubes are found.
+    ///     let cubes = Cube::search()
+    ///         .search_until(|d| d.count >= 2, Duration::from_secs(10))
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn search_until<F>(
+        &mut self,
+        mut predicate: F,
+        timeout: Duration,
+    ) -> Result<Vec<Cube>>
+    where
+        F: FnMut(&Discovery) -> bool + Send,
+    {
+        let mut ids = HashSet::new();
+        let mut stop = |p: &dyn PeripheralOps| {
+            ids.insert(p.id().to_string());
+            predicate(&Discovery {
+                id: p.id(),
+                name: p.name(),
+                rssi: p.rssi(),
+                count: ids.len(),
+            })
+        };
         let mut cubes: Vec<_> = self
-            .do_search(timeout)
+            .do_search_until(timeout, &mut stop)
             .await?
             .into_iter()
-            .map(|a| Cube::new(a))
+            .map(|a| Cube::from_peripheral(a, self.opts.clone()))
             .collect();
-        cubes.sort_by(|a, b| b.rssi().cmp(&a.rssi()));
+        cubes.sort_by_key(|c| Reverse(c.rssi()));
         Ok(cubes)
     }
 
@@ -82,19 +446,99 @@ impl Searcher {
     /// }
     /// ```
     pub async fn nearest_timeout(&mut self, timeout: Duration) -> Result<Cube> {
-        self.do_search(timeout)
+        let min = self.min_rssi;
+        self.search_until(|d| matches!(min, Some(min) if d.rssi >= min), timeout)
             .await?
             .into_iter()
-            .max_by(|a, b| a.rssi().cmp(&b.rssi()))
-            .map(|a| Cube::new(a))
+            .next()
             .ok_or_else(|| anyhow!("No cube found"))
     }
 
+    /// Finds the cubes paired before by [`Cube::pair`][] with their ids.
+    ///
+    /// The cubes remembered by the platform are retrieved at once without scanning,
+    /// and the rest are searched for within the timeout. The cubes are returned
+    /// in the order of the ids, skipping the ones not found.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use toio::Cube;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     // The first time, pair with the nearest cube and keep its id.
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///     cube.pair().await.unwrap();
+    ///     let id = cube.id().to_string();
+    ///
+    ///     // Next time, find the cube by the id.
+    ///     let mut cubes = Cube::search()
+    ///         .bonded(&[&id], Duration::from_secs(3))
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn bonded(&mut self, ids: &[&str], timeout: Duration) -> Result<Vec<Cube>> {
+        let owned: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+        let mut found = self
+            .backend()
+            .retrieve(&owned)
+            .await
+            .context("Error on retrieving cubes")?;
+        if ids.iter().any(|id| found.iter().all(|p| p.id() != *id)) {
+            for p in self.do_search(timeout).await? {
+                if found.iter().all(|f| f.id() != p.id()) {
+                    found.push(p);
+                }
+            }
+        }
+
+        let mut cubes = vec![];
+        for id in ids {
+            if let Some(i) = found.iter().position(|p| p.id() == *id) {
+                cubes.push(Cube::from_peripheral(found.remove(i), self.opts.clone()));
+            }
+        }
+        Ok(cubes)
+    }
+
+    fn backend(&mut self) -> &mut ble::Searcher {
+        if self.searcher.is_none() {
+            let opts = self.opts.ble();
+            self.searcher = Some(match &self.backend {
+                Some(backend) => backend.searcher(opts),
+                None => ble::searcher_with_options(opts),
+            });
+        }
+        self.searcher.as_mut().unwrap()
+    }
+
     async fn do_search(&mut self, timeout: Duration) -> Result<Vec<ble::Peripheral>> {
-        Ok(self
-            .searcher
-            .search(&proto::UUID_SERVICE, timeout)
+        self.do_search_until(timeout, &mut |_| false).await
+    }
+
+    /// Searches for the peripherals, returning each one once with the strongest signal.
+    async fn do_search_until(
+        &mut self,
+        timeout: Duration,
+        stop: &mut ble::StopSearch<'_>,
+    ) -> Result<Vec<ble::Peripheral>> {
+        let service = self.opts.profile.service;
+        let found = self
+            .backend()
+            .search_until(&service, timeout, stop)
             .await
-            .context("Error on searching cubes")?)
+            .context("Error on searching cubes")?;
+
+        let mut unique: Vec<ble::Peripheral> = vec![];
+        for p in found {
+            match unique.iter().position(|u| u.id() == p.id()) {
+                Some(i) if unique[i].rssi() < p.rssi() => unique[i] = p,
+                Some(_) => {}
+                None => unique.push(p),
+            }
+        }
+        Ok(unique)
     }
 }
@@ -0,0 +1,833 @@
+use anyhow::Result;
+use futures::future::{abortable, AbortHandle};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::interval;
+
+use crate::{
+    ble::{
+        self,
+        mock::{self, Mock, MockHandle},
+        LaggingStream, PeripheralOps, Uuid, ValueStream, WriteOps,
+    },
+    proto::*,
+    Cube, Options, Position,
+};
+
+mod scenario;
+
+pub use scenario::*;
+
+/// The wheel speed in mm/s per unit of the motor speed.
+const MM_PER_SPEED: f32 = 4.3;
+
+/// The mat units per mm. The toio collection mat is 297 mm wide and has 410 units.
+const UNITS_PER_MM: f32 = 410.0 / 297.0;
+
+/// The distance between the wheels in mm.
+const TRACK_MM: f32 = 26.6;
+
+/// The motor speeds below this don't rotate the wheels.
+const MIN_SPEED: f32 = 8.0;
+
+/// The distance to the target position regarded as arrived, in the mat units.
+const ARRIVAL: f32 = 3.0;
+
+/// The angle error in degrees above which [`MoveType::Straight`][] spins in place.
+const STRAIGHT_TOLERANCE: f32 = 5.0;
+
+/// The angle error in degrees regarded as done with turning.
+const TURN_TOLERANCE: f32 = 0.5;
+
+/// Converts the motor speed to mat units per second.
+pub(crate) fn units_per_sec(speed: f32) -> f32 {
+    speed * MM_PER_SPEED * UNITS_PER_MM
+}
+
+/// Converts the difference of the wheel speeds to degrees per second.
+pub(crate) fn degrees_per_sec(diff: f32) -> f32 {
+    (units_per_sec(diff) / (TRACK_MM * UNITS_PER_MM)).to_degrees()
+}
+
+/// Wraps the angle in degrees to the range from -180 to 180.
+fn wrap(angle: f32) -> f32 {
+    let angle = angle.rem_euclid(360.0);
+    if angle > 180.0 {
+        angle - 360.0
+    } else {
+        angle
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reply {
+    Target(u8),
+    MultiTarget(u8),
+}
+
+impl Reply {
+    fn message(self, res: TargetResValue) -> Motor {
+        match self {
+            Reply::Target(id) => Motor::TargetRes(MotorTargetRes::new(id, res)),
+            Reply::MultiTarget(id) => Motor::MultiTargetRes(MotorTargetRes::new(id, res)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Not started yet.
+    Start,
+    /// Moving to the position.
+    Move(f32, f32),
+    /// Turning by the remaining degrees, clockwise if positive.
+    Turn(f32),
+}
+
+#[derive(Debug, Clone)]
+struct Goal {
+    reply: Reply,
+    move_type: MoveType,
+    max_speed: f32,
+    timeout: Duration,
+    elapsed: Duration,
+    targets: VecDeque<Target>,
+    phase: Phase,
+    start_angle: f32,
+}
+
+impl Goal {
+    fn new(
+        reply: Reply,
+        move_type: MoveType,
+        max_speed: u8,
+        timeout: u8,
+        targets: Vec<Target>,
+    ) -> Self {
+        Self {
+            reply,
+            move_type,
+            max_speed: max_speed as f32,
+            timeout: Duration::from_secs(if timeout == 0 { 10 } else { timeout as u64 }),
+            elapsed: Duration::from_secs(0),
+            targets: targets.into(),
+            phase: Phase::Start,
+            start_angle: 0.0,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        (TARGET_MIN_SPEED..=TARGET_MAX_SPEED).contains(&(self.max_speed as u8))
+            && !self.targets.is_empty()
+    }
+}
+
+/// Kinematic model of a cube on the mat.
+///
+/// The model moves the cube by the motor requests in fixed time steps, so the same
+/// requests at the same steps always result in the same trajectory. The wheel speed is
+/// about 4.3 mm/s per unit of the motor speed, and the speeds below 8 don't move.
+///
+/// [`Motor::Simple`][], [`Motor::Timed`][], [`Motor::Target`][] and [`Motor::MultiTarget`][]
+/// are modeled. The target requests are answered with [`Motor::TargetRes`][] and
+/// [`Motor::MultiTargetRes`][] on arrival, timeout, leaving the mat or being overwritten.
+/// The speed change and the relative angles of more than a turn are not modeled.
+///
+/// ```
+/// use std::time::Duration;
+/// use toio::{proto::*, sim::Model, Position};
+///
+/// let mut model = Model::new(100.0, 100.0, 0.0);
+///
+/// // Both wheels at the speed 50 for 0.5 seconds.
+/// let req = MotorTimed::new(
+///     MotorId::Left,
+///     MotorDir::Forward,
+///     50,
+///     MotorId::Right,
+///     MotorDir::Forward,
+///     50,
+///     50,
+/// );
+/// model.write(&Motor::Timed(req));
+/// for _ in 0..100 {
+///     model.step(Duration::from_millis(10));
+/// }
+///
+/// let pos = model.position().unwrap();
+/// assert_eq!((pos.x, pos.y, pos.angle), (248, 100, 0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Model {
+    x: f32,
+    y: f32,
+    angle: f32,
+    area: ((u16, u16), (u16, u16)),
+    lifted: bool,
+    wheels: (f32, f32),
+    until: Option<Duration>,
+    goal: Option<Goal>,
+    queue: VecDeque<Goal>,
+}
+
+impl Model {
+    /// Creates the cube at the position and the angle in degrees on the toio collection mat.
+    pub fn new(x: f32, y: f32, angle: f32) -> Self {
+        Self {
+            x,
+            y,
+            angle: angle.rem_euclid(360.0),
+            area: ((45, 45), (455, 455)),
+            lifted: false,
+            wheels: (0.0, 0.0),
+            until: None,
+            goal: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Sets the area of the mat with the position id.
+    /// By default, it's the toio collection mat from `(45, 45)` to `(455, 455)`.
+    pub fn area(mut self, min: (u16, u16), max: (u16, u16)) -> Self {
+        self.area = (min, max);
+        self
+    }
+
+    /// Gets the position and the angle in degrees.
+    pub fn pose(&self) -> (f32, f32, f32) {
+        (self.x, self.y, self.angle)
+    }
+
+    /// Puts the cube at the position and the angle in degrees.
+    pub fn set_pose(&mut self, x: f32, y: f32, angle: f32) {
+        self.x = x;
+        self.y = y;
+        self.angle = angle.rem_euclid(360.0);
+        self.lifted = false;
+    }
+
+    /// Lifts the cube from the mat. The wheels don't move it until put by [`Model::set_pose`][].
+    pub fn lift(&mut self) {
+        self.lifted = true;
+    }
+
+    /// Gets the position read by the cube. `None` if off the mat.
+    pub fn position(&self) -> Option<Position> {
+        if self.lifted {
+            return None;
+        }
+        let ((x0, y0), (x1, y1)) = self.area;
+        let (x, y) = (self.x.round(), self.y.round());
+        if x < x0 as f32 || x > x1 as f32 || y < y0 as f32 || y > y1 as f32 {
+            return None;
+        }
+        Some(Position::new(
+            x as u16,
+            y as u16,
+            (self.angle.round() as u16) % 360,
+        ))
+    }
+
+    /// Gets the speeds of the left and right wheels in the unit of the motor speed.
+    /// Backward is negative.
+    pub fn wheels(&self) -> (f32, f32) {
+        self.wheels
+    }
+
+    /// Returns `true` if the cube is moving to the target positions.
+    pub fn is_busy(&self) -> bool {
+        self.goal.is_some()
+    }
+
+    /// Handles the motor request, and returns the responses to notify immediately.
+    pub fn write(&mut self, req: &Motor) -> Vec<Motor> {
+        let mut res = vec![];
+        match req {
+            Motor::Simple(m) => {
+                self.cancel(&mut res);
+                self.set_wheels(&[(m.motor1, m.dir1, m.speed1), (m.motor2, m.dir2, m.speed2)]);
+                self.until = None;
+            }
+            Motor::Timed(m) => {
+                self.cancel(&mut res);
+                self.set_wheels(&[(m.motor1, m.dir1, m.speed1), (m.motor2, m.dir2, m.speed2)]);
+                self.until = match m.duration {
+                    0 => None,
+                    d => Some(Duration::from_millis(d as u64 * 10)),
+                };
+            }
+            Motor::Target(m) => {
+                let target = Target::new(m.x, m.y, m.angle);
+                let goal = Goal::new(
+                    Reply::Target(m.id),
+                    m.move_type,
+                    m.max_speed,
+                    m.timeout,
+                    vec![target],
+                );
+                self.start(goal, false, &mut res);
+            }
+            Motor::MultiTarget(m) => {
+                let goal = Goal::new(
+                    Reply::MultiTarget(m.id),
+                    m.move_type,
+                    m.max_speed,
+                    m.timeout,
+                    m.targets.clone(),
+                );
+                self.start(goal, m.writeopt == WriteOpt::Append, &mut res);
+            }
+            _ => {}
+        }
+        res
+    }
+
+    /// Advances the time, and returns the responses to the requests finished meanwhile.
+    pub fn step(&mut self, dt: Duration) -> Vec<Motor> {
+        let mut res = vec![];
+
+        if let Some(until) = self.until {
+            if until <= dt {
+                // Moves for the rest of the duration, then stops.
+                self.advance(until.as_secs_f32());
+                self.wheels = (0.0, 0.0);
+                self.until = None;
+                return res;
+            }
+            self.until = Some(until - dt);
+        }
+
+        if let Some(mut goal) = self.goal.take() {
+            goal.elapsed += dt;
+            let result = if goal.elapsed > goal.timeout {
+                Some(TargetResValue::Timeout)
+            } else if self.position().is_none() {
+                Some(TargetResValue::IdMissed)
+            } else {
+                self.control(&mut goal, dt.as_secs_f32())
+            };
+            match result {
+                Some(value) => {
+                    self.wheels = (0.0, 0.0);
+                    res.push(goal.reply.message(value));
+                    self.goal = self.queue.pop_front();
+                }
+                None => self.goal = Some(goal),
+            }
+        }
+
+        self.advance(dt.as_secs_f32());
+        res
+    }
+
+    fn set_wheels(&mut self, motors: &[(MotorId, MotorDir, u8)]) {
+        for (id, dir, speed) in motors {
+            let speed = *speed as f32;
+            let speed = match dir {
+                _ if speed < MIN_SPEED => 0.0,
+                MotorDir::Forward => speed,
+                MotorDir::Backward => -speed,
+            };
+            match id {
+                MotorId::Left => self.wheels.0 = speed,
+                MotorId::Right => self.wheels.1 = speed,
+            }
+        }
+    }
+
+    /// Stops the target control by the new request.
+    fn cancel(&mut self, res: &mut Vec<Motor>) {
+        for goal in self.goal.take().into_iter().chain(self.queue.drain(..)) {
+            res.push(goal.reply.message(TargetResValue::OtherWrite));
+        }
+        self.wheels = (0.0, 0.0);
+    }
+
+    fn start(&mut self, goal: Goal, append: bool, res: &mut Vec<Motor>) {
+        if !goal.is_valid() {
+            res.push(goal.reply.message(TargetResValue::InvalidParam));
+            return;
+        }
+        if self.position().is_none() {
+            res.push(goal.reply.message(TargetResValue::IdMissed));
+            return;
+        }
+        if append && self.goal.is_some() {
+            self.queue.push_back(goal);
+            return;
+        }
+        self.cancel(res);
+        self.until = None;
+        self.goal = Some(goal);
+    }
+
+    /// Moves the cube by the wheel speeds.
+    fn advance(&mut self, dt: f32) {
+        if self.lifted {
+            return;
+        }
+        let (left, right) = self.wheels;
+        let v = units_per_sec((left + right) / 2.0);
+        let w = degrees_per_sec(left - right);
+
+        // The mat y-axis points down, so the clockwise rotation increases the angle.
+        let mid = (self.angle + w * dt / 2.0).to_radians();
+        self.x += v * mid.cos() * dt;
+        self.y += v * mid.sin() * dt;
+        self.angle = (self.angle + w * dt).rem_euclid(360.0);
+    }
+
+    /// Sets the wheel speeds toward the goal, and returns the result if finished.
+    fn control(&mut self, goal: &mut Goal, dt: f32) -> Option<TargetResValue> {
+        loop {
+            let target = goal.targets.front()?.clone();
+            match goal.phase {
+                Phase::Start => {
+                    let keep = |v: u16, cur: f32| {
+                        if v == KEEP_COORDINATE {
+                            cur
+                        } else {
+                            v as f32
+                        }
+                    };
+                    goal.start_angle = self.angle;
+                    goal.phase = Phase::Move(keep(target.x, self.x), keep(target.y, self.y));
+                }
+                Phase::Move(x, y) => {
+                    let (dx, dy) = (x - self.x, y - self.y);
+                    let dist = (dx * dx + dy * dy).sqrt();
+                    if dist <= ARRIVAL {
+                        goal.phase = Phase::Turn(self.turn(&target, goal.start_angle));
+                        continue;
+                    }
+                    self.wheels = self.steer(goal, dy.atan2(dx).to_degrees(), dist, dt);
+                    return None;
+                }
+                Phase::Turn(remaining) => {
+                    if remaining.abs() <= TURN_TOLERANCE {
+                        goal.targets.pop_front();
+                        goal.phase = Phase::Start;
+                        if goal.targets.is_empty() {
+                            return Some(TargetResValue::Ok);
+                        }
+                        continue;
+                    }
+                    let c = self.spin(remaining, goal.max_speed, dt);
+                    self.wheels = (c, -c);
+                    goal.phase = Phase::Turn(remaining - degrees_per_sec(2.0 * c) * dt);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Gets the signed degrees to turn at the target position.
+    fn turn(&self, target: &Target, start_angle: f32) -> f32 {
+        let angle = (target.angle & 0x1fff) as f32;
+        match target.angle >> 13 {
+            0x00 => wrap(angle - self.angle),
+            0x01 => (angle - self.angle).rem_euclid(360.0),
+            0x02 => -(self.angle - angle).rem_euclid(360.0),
+            0x03 => (start_angle + angle - self.angle).rem_euclid(360.0),
+            0x04 => -(self.angle - start_angle + angle).rem_euclid(360.0),
+            0x06 => wrap(start_angle - self.angle),
+            _ => 0.0,
+        }
+    }
+
+    /// Gets the wheel speed difference to turn by the degrees within the step.
+    fn spin(&self, degrees: f32, max: f32, dt: f32) -> f32 {
+        let exact = degrees / degrees_per_sec(2.0) / dt;
+        exact.clamp(-max, max)
+    }
+
+    /// Gets the wheel speeds to move toward the heading in degrees.
+    fn steer(&self, goal: &Goal, heading: f32, dist: f32, dt: f32) -> (f32, f32) {
+        let max = goal.max_speed;
+        let mut error = wrap(heading - self.angle);
+        let mut dir = 1.0;
+        if goal.move_type == MoveType::Curve && error.abs() > 90.0 {
+            error = wrap(error - 180.0);
+            dir = -1.0;
+        }
+
+        // The speed to reach the position just within the step.
+        let arrive = dist / units_per_sec(1.0) / dt;
+        let (forward, turn) = match goal.move_type {
+            MoveType::Straight if error.abs() > STRAIGHT_TOLERANCE => {
+                (0.0, self.spin(error, max, dt))
+            }
+            MoveType::Straight => (max.min(arrive), self.spin(error, max / 2.0, dt)),
+            _ => {
+                let scale = error.to_radians().cos().max(0.0);
+                ((max * scale).min(arrive), self.spin(error, max / 2.0, dt))
+            }
+        };
+        let clamp = |v: f32| v.clamp(-max, max);
+        (clamp(dir * forward + turn), clamp(dir * forward - turn))
+    }
+}
+
+/// Operation on a simulated cube scheduled by [`Simulator::at`][].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Presses or releases the button.
+    Button {
+        /// Set if pressed.
+        pressed: bool,
+    },
+    /// Detects a collision.
+    Collision,
+    /// Detects a double tap.
+    DoubleTap,
+    /// Puts the cube at the position and the angle in degrees.
+    Place {
+        /// The x coordinate.
+        x: f32,
+        /// The y coordinate.
+        y: f32,
+        /// The angle in degrees.
+        angle: f32,
+    },
+    /// Lifts the cube from the mat.
+    Lift,
+    /// Changes the battery percentage.
+    Battery {
+        /// The battery percentage.
+        level: u8,
+    },
+}
+
+struct Script {
+    clock: Duration,
+    actions: VecDeque<(Duration, Action)>,
+}
+
+struct Shared {
+    model: Mutex<Model>,
+    on_mat: Mutex<bool>,
+    script: Mutex<Script>,
+}
+
+/// Peripheral driven by [`Model`][].
+///
+/// Motor requests written to the peripheral move the model, and the positions are
+/// notified at the fixed interval. The model advances by the interval on each
+/// notification regardless of the delay of the timer, so the trajectory is deterministic.
+/// The battery is reported as 100% so that reads are answered.
+///
+/// Sensor inputs are scheduled by [`Simulator::at`][] on the same clock, or described
+/// in a file by [`Scenario`][].
+///
+/// ```no_run
+/// use toio::{
+///     proto::MotorTarget,
+///     sim::{Model, Simulator},
+/// };
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (mut cube, sim) = Simulator::new(Model::new(100.0, 100.0, 0.0)).cube();
+///     cube.connect().await.unwrap();
+///
+///     let target = MotorTarget::builder(300, 200).build().unwrap();
+///     cube.goto(target).await.unwrap();
+///
+///     println!("{:?}", sim.position());
+/// }
+/// ```
+pub struct Simulator {
+    mock: Mock,
+    handle: SimHandle,
+    interval: Duration,
+    task: Option<AbortHandle>,
+}
+
+/// Handle to observe and control [`Simulator`][].
+#[derive(Clone)]
+pub struct SimHandle {
+    mock: MockHandle,
+    shared: Arc<Shared>,
+}
+
+impl Simulator {
+    /// Creates a simulator of the model.
+    pub fn new(model: Model) -> Self {
+        Self::named("sim", model)
+    }
+
+    /// Creates a simulator of the model with the peripheral id.
+    pub fn named(id: &str, model: Model) -> Self {
+        let (mock, handle) = mock::mock(id);
+        handle.notify(UUID_BATTERY, vec![100]);
+        Self {
+            mock,
+            handle: SimHandle {
+                mock: handle,
+                shared: Arc::new(Shared {
+                    model: Mutex::new(model),
+                    on_mat: Mutex::new(false),
+                    script: Mutex::new(Script {
+                        clock: Duration::from_secs(0),
+                        actions: VecDeque::new(),
+                    }),
+                }),
+            },
+            interval: Duration::from_millis(30),
+            task: None,
+        }
+    }
+
+    /// Sets the interval of the position notifications. By default, it's 30 milliseconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Schedules the action at the time since connected.
+    ///
+    /// The action is applied at the first position notification at or after the time.
+    pub fn at(self, at: Duration, action: Action) -> Self {
+        {
+            let mut script = self.handle.shared.script.lock().unwrap();
+            let i = script.actions.iter().take_while(|(t, _)| *t <= at).count();
+            script.actions.insert(i, (at, action));
+        }
+        self
+    }
+
+    /// Gets the handle of the simulator.
+    pub fn handle(&self) -> SimHandle {
+        self.handle.clone()
+    }
+
+    /// Creates a cube on the simulator.
+    pub fn cube(self) -> (Cube, SimHandle) {
+        self.cube_with_options(Options::default())
+    }
+
+    /// Creates a cube with the options on the simulator.
+    pub fn cube_with_options(self, opts: Options) -> (Cube, SimHandle) {
+        let handle = self.handle();
+        (Cube::from_peripheral(Box::new(self), opts), handle)
+    }
+
+    fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl SimHandle {
+    /// Gets the mock peripheral under the simulator, e.g., to notify other sensors.
+    pub fn mock(&self) -> &MockHandle {
+        &self.mock
+    }
+
+    /// Gets the snapshot of the model.
+    pub fn model(&self) -> Model {
+        self.shared.model.lock().unwrap().clone()
+    }
+
+    /// Gets the position read by the cube. `None` if off the mat.
+    pub fn position(&self) -> Option<Position> {
+        self.shared.model.lock().unwrap().position()
+    }
+
+    /// Puts the cube at the position and the angle in degrees.
+    pub fn set_pose(&self, x: f32, y: f32, angle: f32) {
+        self.shared.model.lock().unwrap().set_pose(x, y, angle);
+    }
+
+    /// Applies the action now.
+    pub fn apply(&self, action: &Action) {
+        let detect = |collision, double_tap| {
+            Motion::Detect(MotionDetect::new(
+                true,
+                collision,
+                double_tap,
+                Posture::HeadUp,
+            ))
+        };
+        match action {
+            Action::Button { pressed } => self.notify(Button::Func(if *pressed {
+                ButtonState::Pressed
+            } else {
+                ButtonState::Released
+            })),
+            Action::Collision => self.notify(detect(true, false)),
+            Action::DoubleTap => self.notify(detect(false, true)),
+            Action::Place { x, y, angle } => self.set_pose(*x, *y, *angle),
+            Action::Lift => self.shared.model.lock().unwrap().lift(),
+            Action::Battery { level } => self.mock.notify(UUID_BATTERY, vec![*level]),
+        }
+    }
+
+    /// Advances the model by the time, and notifies the responses and the position.
+    ///
+    /// The actions scheduled by then are applied after moving the model.
+    pub fn advance(&self, dt: Duration) {
+        let res = self.shared.model.lock().unwrap().step(dt);
+        self.reply(res);
+
+        let due = {
+            let mut script = self.shared.script.lock().unwrap();
+            script.clock += dt;
+            let n = script
+                .actions
+                .iter()
+                .take_while(|(t, _)| *t <= script.clock)
+                .count();
+            script.actions.drain(..n).collect::<Vec<_>>()
+        };
+        for (_, action) in due {
+            self.apply(&action);
+        }
+
+        let pos = self.position();
+
+        let mut on_mat = self.shared.on_mat.lock().unwrap();
+        let id = match &pos {
+            Some(p) => Id::Pos(IdPos::new(p.x, p.y, p.angle, p.x, p.y, p.angle)),
+            None if *on_mat => Id::PosMissed,
+            None => return,
+        };
+        *on_mat = pos.is_some();
+        self.notify(id);
+    }
+
+    fn reply(&self, res: Vec<Motor>) {
+        for msg in res {
+            self.notify(msg);
+        }
+    }
+
+    /// Applies the value written to the characteristic to the model.
+    fn written(&self, uuid: &Uuid, value: &[u8]) {
+        if *uuid == UUID_MOTOR {
+            let res = match Motor::decode(value, false) {
+                Ok(req) => self.shared.model.lock().unwrap().write(&req),
+                Err(e) => {
+                    debug!("Ignored malformed motor request: {}", e);
+                    vec![]
+                }
+            };
+            self.reply(res);
+        }
+    }
+
+    fn notify<T: TryInto<(Uuid, Vec<u8>), Error = anyhow::Error>>(&self, msg: T) {
+        match msg.try_into() {
+            Ok((uuid, value)) => self.mock.notify(uuid, value),
+            Err(e) => error!("Couldn't encode simulated message: {}", e),
+        }
+    }
+}
+
+/// Writer of a simulator, moving the model as written.
+struct SimWriter {
+    mock: ble::Writer,
+    handle: SimHandle,
+}
+
+#[async_trait::async_trait]
+impl WriteOps for SimWriter {
+    async fn write(&mut self, uuid: &Uuid, value: &[u8]) -> Result<()> {
+        self.mock.write(uuid, value).await?;
+        self.handle.written(uuid, value);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl PeripheralOps for Simulator {
+    fn id(&self) -> &str {
+        self.mock.id()
+    }
+
+    fn rssi(&self) -> i32 {
+        self.mock.rssi()
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.mock.name()
+    }
+
+    fn max_write_len(&self) -> Option<usize> {
+        self.mock.max_write_len()
+    }
+
+    fn writer(&self) -> Option<ble::Writer> {
+        let mock = self.mock.writer()?;
+        Some(Box::new(SimWriter {
+            mock,
+            handle: self.handle.clone(),
+        }))
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        self.mock.connect().await?;
+        self.stop();
+
+        let handle = self.handle();
+        let period = self.interval;
+        let (task, abort) = abortable(async move {
+            let mut ticks = interval(period);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                handle.advance(period);
+            }
+        });
+        tokio::spawn(task);
+        self.task = Some(abort);
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.stop();
+        self.mock.disconnect().await
+    }
+
+    async fn pair(&mut self) -> Result<()> {
+        self.mock.pair().await
+    }
+
+    async fn unpair(&mut self) -> Result<()> {
+        self.mock.unpair().await
+    }
+
+    async fn read(&mut self, uuid: &Uuid) -> Result<()> {
+        self.mock.read(uuid).await
+    }
+
+    async fn write(&mut self, uuid: &Uuid, value: &[u8], with_resp: bool) -> Result<()> {
+        self.mock.write(uuid, value, with_resp).await?;
+        self.handle.written(uuid, value);
+        Ok(())
+    }
+
+    async fn notify(&mut self, uuid: &Uuid, enable: bool) -> Result<()> {
+        self.mock.notify(uuid, enable).await
+    }
+
+    fn subscribe(&mut self) -> Result<ValueStream> {
+        self.mock.subscribe()
+    }
+
+    fn subscribe_lagging(&mut self) -> Result<LaggingStream> {
+        self.mock.subscribe_lagging()
+    }
+}
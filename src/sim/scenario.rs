@@ -0,0 +1,197 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use super::{Action, Model, SimHandle, Simulator};
+use crate::Cube;
+
+/// Mat under the simulated cubes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Mat {
+    /// The ring side of the toio collection mat.
+    #[default]
+    Collection,
+    /// The colored tile side of the toio collection mat.
+    CollectionTiles,
+    /// The simple play mat bundled with the toio core cube.
+    Simple,
+    /// The mat of the area of the position id.
+    Custom {
+        /// The top left corner.
+        min: (u16, u16),
+        /// The bottom right corner.
+        max: (u16, u16),
+    },
+}
+
+impl Mat {
+    /// Gets the top left and the bottom right corners of the mat.
+    pub fn area(&self) -> ((u16, u16), (u16, u16)) {
+        match self {
+            Mat::Collection => ((45, 45), (455, 455)),
+            Mat::CollectionTiles => ((545, 45), (955, 455)),
+            Mat::Simple => ((98, 142), (402, 358)),
+            Mat::Custom { min, max } => (*min, *max),
+        }
+    }
+}
+
+/// Initial state of a cube in [`Scenario`][].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CubeSpec {
+    /// The peripheral id. By default, `sim0`, `sim1` and so on in the order.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// The x coordinate.
+    pub x: f32,
+    /// The y coordinate.
+    pub y: f32,
+    /// The angle in degrees.
+    #[serde(default)]
+    pub angle: f32,
+    /// The battery percentage. By default, 100.
+    #[serde(default = "full")]
+    pub battery: u8,
+}
+
+fn full() -> u8 {
+    100
+}
+
+/// Action on a cube at a time in [`Scenario`][].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScriptedAction {
+    /// The time since connected in milliseconds.
+    pub at: u64,
+    /// The index of the cube in [`Scenario::cubes`][]. By default, the first one.
+    #[serde(default)]
+    pub cube: usize,
+    /// The action.
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Reproducible situation of simulated cubes, loaded from a TOML file.
+///
+/// Each cube is a [`Simulator`][] with the actions scheduled on its own clock,
+/// so the same scenario results in the same sensor inputs at the same positions.
+///
+/// ```
+/// use toio::sim::{Action, Mat, Scenario};
+///
+/// let scenario = Scenario::from_toml(
+///     r#"
+///     mat = "simple"
+///
+///     [[cubes]]
+///     x = 150
+///     y = 200
+///
+///     [[cubes]]
+///     id = "rival"
+///     x = 350
+///     y = 200
+///     angle = 180
+///     battery = 20
+///
+///     [[actions]]
+///     at = 500
+///     cube = 1
+///     type = "button"
+///     pressed = true
+///
+///     [[actions]]
+///     at = 1200
+///     type = "collision"
+///     "#,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(scenario.mat, Mat::Simple);
+/// assert_eq!(scenario.cubes.len(), 2);
+/// assert_eq!(scenario.actions[1].action, Action::Collision);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scenario {
+    /// The mat. By default, [`Mat::Collection`][].
+    #[serde(default)]
+    pub mat: Mat,
+    /// The interval of the position notifications in milliseconds. By default, 30.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// The cubes.
+    pub cubes: Vec<CubeSpec>,
+    /// The actions.
+    #[serde(default)]
+    pub actions: Vec<ScriptedAction>,
+}
+
+fn default_interval() -> u64 {
+    30
+}
+
+impl Scenario {
+    /// Parses the scenario in TOML.
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let scenario: Self = toml::from_str(s).context("Couldn't parse scenario")?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Loads the scenario from the TOML file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't open {}", path.display()))?;
+        Self::from_toml(&s).with_context(|| format!("Invalid scenario {}", path.display()))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.interval == 0 {
+            bail!("Interval must be positive");
+        }
+        for action in &self.actions {
+            if action.cube >= self.cubes.len() {
+                bail!(
+                    "Action at {} ms refers to cube {}, but there are {} cubes",
+                    action.at,
+                    action.cube,
+                    self.cubes.len()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the simulators in the order of the cubes.
+    pub fn simulators(&self) -> Vec<Simulator> {
+        let (min, max) = self.mat.area();
+        self.cubes
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                let id = spec.id.clone().unwrap_or_else(|| format!("sim{}", i));
+                let model = Model::new(spec.x, spec.y, spec.angle).area(min, max);
+                let mut sim =
+                    Simulator::named(&id, model).interval(Duration::from_millis(self.interval));
+                sim.handle().apply(&Action::Battery {
+                    level: spec.battery,
+                });
+                for a in self.actions.iter().filter(|a| a.cube == i) {
+                    sim = sim.at(Duration::from_millis(a.at), a.action.clone());
+                }
+                sim
+            })
+            .collect()
+    }
+
+    /// Creates the cubes on the simulators in the order.
+    pub fn cubes(&self) -> Vec<(Cube, SimHandle)> {
+        self.simulators()
+            .into_iter()
+            .map(|sim| sim.cube())
+            .collect()
+    }
+}
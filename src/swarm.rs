@@ -0,0 +1,346 @@
+use futures::{
+    future::{abortable, AbortHandle},
+    prelude::*,
+    stream::BoxStream,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::{
+    sync::{broadcast, Mutex},
+    time::{delay_for, Instant},
+};
+
+use crate::{Backoff, Cube, Event};
+
+const EVENT_CAPACITY: usize = 64;
+
+/// The state of a cube in [`Swarm`][].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
+    /// Connected and responding.
+    Connected,
+    /// Lost, and trying to reconnect.
+    Reconnecting,
+    /// Gave up reconnecting.
+    Failed,
+}
+
+/// A change of the members of [`Swarm`][].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SwarmEvent {
+    /// The cube is added.
+    Joined(String),
+    /// The cube is removed.
+    Left(String),
+    /// The cube doesn't respond, so it's being reconnected.
+    Lost(String),
+    /// The cube is reconnected.
+    Reconnected(String),
+    /// The cube couldn't be reconnected.
+    Failed(String),
+    /// The battery of the cube falls to the threshold.
+    BatteryLow(String, usize),
+}
+
+/// The health of a cube in [`Swarm`][].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    /// The id of the cube.
+    pub id: String,
+    /// The connection state.
+    pub state: State,
+    /// The battery percentage last reported.
+    pub battery: Option<usize>,
+    /// Set if the battery falls to the threshold and isn't charged yet.
+    pub battery_low: bool,
+    /// The time since the last event.
+    pub idle: Option<Duration>,
+    /// The number of reconnections.
+    pub reconnects: usize,
+}
+
+/// The health of all the cubes in [`Swarm`][], in the order added.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The health of each cube.
+    pub cubes: Vec<Health>,
+}
+
+impl HealthReport {
+    /// Gets the number of the connected cubes.
+    pub fn connected(&self) -> usize {
+        self.cubes
+            .iter()
+            .filter(|h| h.state == State::Connected)
+            .count()
+    }
+
+    /// Gets the cubes which need attention: not connected, or low on battery.
+    pub fn unhealthy(&self) -> Vec<&Health> {
+        self.cubes
+            .iter()
+            .filter(|h| h.state != State::Connected || h.battery_low)
+            .collect()
+    }
+}
+
+struct Status {
+    state: State,
+    battery: Option<usize>,
+    battery_low: bool,
+    seen: Option<Instant>,
+    reconnects: usize,
+}
+
+struct Member {
+    cube: Arc<Mutex<Cube>>,
+    status: Arc<StdMutex<Status>>,
+    task: AbortHandle,
+}
+
+/// Supervises many cubes.
+///
+/// Each cube is reconnected when it stops responding, which is detected by the
+/// watchdog of the cube, so search the cubes with [`Searcher::watchdog`](crate::Searcher::watchdog).
+/// The battery and the liveness are summarized by [`Swarm::health`][], and the changes
+/// of the members are delivered by [`Swarm::subscribe`][].
+///
+/// ```no_run
+/// use futures::prelude::*;
+/// use std::time::Duration;
+/// use toio::{swarm::Swarm, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let cubes = Cube::search()
+///         .watchdog(Duration::from_secs(3))
+///         .battery_low(20)
+///         .all()
+///         .await
+///         .unwrap();
+///
+///     let mut swarm = Swarm::new();
+///     let mut changes = swarm.subscribe();
+///     for mut cube in cubes {
+///         cube.connect().await.unwrap();
+///         swarm.add(cube).await.unwrap();
+///     }
+///
+///     while let Some(change) = changes.next().await {
+///         println!("{:?}", change);
+///         println!("{:?}", swarm.health());
+///     }
+/// }
+/// ```
+pub struct Swarm {
+    members: Vec<(String, Member)>,
+    reconnect: Backoff,
+    tx: broadcast::Sender<SwarmEvent>,
+}
+
+impl Default for Swarm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Swarm {
+    /// Creates the empty swarm.
+    pub fn new() -> Self {
+        Self {
+            members: vec![],
+            reconnect: Backoff::new(10, Duration::from_secs(1)),
+            tx: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Sets the backoff between the attempts to reconnect a lost cube.
+    ///
+    /// Each attempt retries with [`Searcher::connect_retry`](crate::Searcher::connect_retry)
+    /// as well. By default, it retries 10 times from 1 second.
+    pub fn reconnect(mut self, backoff: Backoff) -> Self {
+        self.reconnect = backoff;
+        self
+    }
+
+    /// Adds the connected cube, and starts supervising it.
+    ///
+    /// A cube of the same id replaces the old one.
+    pub async fn add(&mut self, mut cube: Cube) -> anyhow::Result<()> {
+        let id = cube.id().to_string();
+        let events = cube.events().await?;
+        let cube = Arc::new(Mutex::new(cube));
+        let status = Arc::new(StdMutex::new(Status {
+            state: State::Connected,
+            battery: None,
+            battery_low: false,
+            seen: None,
+            reconnects: 0,
+        }));
+
+        let (task, handle) = abortable(supervise(
+            id.clone(),
+            cube.clone(),
+            events,
+            status.clone(),
+            self.reconnect,
+            self.tx.clone(),
+        ));
+        tokio::spawn(task);
+
+        self.remove(&id);
+        self.members.push((
+            id.clone(),
+            Member {
+                cube,
+                status,
+                task: handle,
+            },
+        ));
+        let _ = self.tx.send(SwarmEvent::Joined(id));
+        Ok(())
+    }
+
+    /// Stops supervising the cube, and removes it.
+    ///
+    /// Returns the cube if found.
+    pub fn remove(&mut self, id: &str) -> Option<Arc<Mutex<Cube>>> {
+        let i = self.members.iter().position(|(i, _)| i == id)?;
+        let (id, member) = self.members.remove(i);
+        member.task.abort();
+        let _ = self.tx.send(SwarmEvent::Left(id));
+        Some(member.cube)
+    }
+
+    /// Gets the ids of the cubes in the order added.
+    pub fn ids(&self) -> Vec<String> {
+        self.members.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    /// Gets the number of the cubes.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns `true` if the swarm has no cube.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Gets the cube to operate on it.
+    pub fn cube(&self, id: &str) -> Option<Arc<Mutex<Cube>>> {
+        self.members
+            .iter()
+            .find(|(i, _)| i == id)
+            .map(|(_, m)| m.cube.clone())
+    }
+
+    /// Gets the health of the cubes.
+    pub fn health(&self) -> HealthReport {
+        let now = Instant::now();
+        let cubes = self
+            .members
+            .iter()
+            .map(|(id, m)| {
+                let s = m.status.lock().unwrap();
+                Health {
+                    id: id.clone(),
+                    state: s.state,
+                    battery: s.battery,
+                    battery_low: s.battery_low,
+                    idle: s.seen.map(|seen| now - seen),
+                    reconnects: s.reconnects,
+                }
+            })
+            .collect();
+        HealthReport { cubes }
+    }
+
+    /// Subscribes to the changes of the members.
+    pub fn subscribe(&self) -> BoxStream<'static, SwarmEvent> {
+        self.tx
+            .subscribe()
+            .into_stream()
+            .filter_map(|e| future::ready(e.ok()))
+            .boxed()
+    }
+}
+
+impl Drop for Swarm {
+    fn drop(&mut self) {
+        for (_, member) in &self.members {
+            member.task.abort();
+        }
+    }
+}
+
+async fn supervise(
+    id: String,
+    cube: Arc<Mutex<Cube>>,
+    mut events: crate::EventStream,
+    status: Arc<StdMutex<Status>>,
+    reconnect: Backoff,
+    tx: broadcast::Sender<SwarmEvent>,
+) {
+    loop {
+        while let Some(event) = events.next().await {
+            let mut s = status.lock().unwrap();
+            s.seen = Some(Instant::now());
+            match event {
+                Event::Battery(v) => {
+                    if matches!(s.battery, Some(last) if v > last) {
+                        s.battery_low = false;
+                    }
+                    s.battery = Some(v);
+                }
+                Event::BatteryLow(v) => {
+                    s.battery = Some(v);
+                    s.battery_low = true;
+                    let _ = tx.send(SwarmEvent::BatteryLow(id.clone(), v));
+                }
+                Event::Unresponsive => break,
+                _ => {}
+            }
+        }
+
+        warn!("Cube {} is lost, reconnecting", id);
+        status.lock().unwrap().state = State::Reconnecting;
+        let _ = tx.send(SwarmEvent::Lost(id.clone()));
+
+        let mut delays = reconnect.delays();
+        events = loop {
+            let res = {
+                let mut cube = cube.lock().await;
+                match cube.connect().await {
+                    Ok(()) => cube.events().await,
+                    Err(e) => Err(e),
+                }
+            };
+            match res {
+                Ok(events) => break events,
+                Err(e) => match delays.next() {
+                    Some(d) => {
+                        debug!("Couldn't reconnect {}: {}", id, e);
+                        delay_for(d).await;
+                    }
+                    None => {
+                        error!("Couldn't reconnect {}: {}", id, e);
+                        status.lock().unwrap().state = State::Failed;
+                        let _ = tx.send(SwarmEvent::Failed(id));
+                        return;
+                    }
+                },
+            }
+        };
+
+        info!("Cube {} is reconnected", id);
+        {
+            let mut s = status.lock().unwrap();
+            s.state = State::Connected;
+            s.reconnects += 1;
+        }
+        let _ = tx.send(SwarmEvent::Reconnected(id.clone()));
+    }
+}
@@ -0,0 +1,165 @@
+/// Maps a stick position to the wheel speeds.
+///
+/// The stick is mixed in arcade style: the y axis moves forward/backward,
+/// and the x axis turns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    /// The maximum wheel speed from `0` to `100`.
+    pub max_speed: isize,
+    /// The ratio of the stick range to ignore around the center from `0.0` to `1.0`.
+    pub deadzone: f32,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Self {
+            max_speed: 60,
+            deadzone: 0.15,
+        }
+    }
+}
+
+impl Mapping {
+    /// Converts the stick position into the speeds of the left and right wheels.
+    ///
+    /// `x` is positive to the right and `y` is positive forward, both from `-1.0` to `1.0`.
+    pub fn speeds(&self, x: f32, y: f32) -> (isize, isize) {
+        let x = self.filter(x);
+        let y = self.filter(y);
+        let (mut left, mut right) = (y + x, y - x);
+        let max = left.abs().max(right.abs());
+        if max > 1.0 {
+            left /= max;
+            right /= max;
+        }
+        let max_speed = self.max_speed.clamp(0, 100) as f32;
+        (
+            (left * max_speed).round() as isize,
+            (right * max_speed).round() as isize,
+        )
+    }
+
+    fn filter(&self, v: f32) -> f32 {
+        let v = v.clamp(-1.0, 1.0);
+        let deadzone = self.deadzone.clamp(0.0, 0.99);
+        if v.abs() <= deadzone {
+            0.0
+        } else {
+            v.signum() * (v.abs() - deadzone) / (1.0 - deadzone)
+        }
+    }
+}
+
+#[cfg(feature = "teleop")]
+pub use self::gamepad::Teleop;
+
+#[cfg(feature = "teleop")]
+mod gamepad {
+    use anyhow::{anyhow, Result};
+    use gilrs::{Axis, Gilrs};
+    use log::*;
+    use std::time::Duration;
+    use tokio::{
+        sync::{oneshot, watch},
+        time::delay_for,
+    };
+
+    use super::Mapping;
+    use crate::Cube;
+
+    /// Drives a cube with the left stick of a gamepad.
+    ///
+    /// The first connected gamepad is used. The cube stops if the program stops sending
+    /// commands, e.g., when it hangs, because each command runs only for a short time.
+    ///
+    /// Enabled by the `teleop` feature.
+    ///
+    /// ```no_run
+    /// use toio::{teleop::{Mapping, Teleop}, Cube};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut cube = Cube::search().nearest().await.unwrap();
+    ///     cube.connect().await.unwrap();
+    ///
+    ///     let mapping = Mapping {
+    ///         max_speed: 80,
+    ///         ..Mapping::default()
+    ///     };
+    ///     Teleop::new(mapping).run(&mut cube).await.unwrap();
+    /// }
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct Teleop {
+        mapping: Mapping,
+        interval: Duration,
+    }
+
+    impl Teleop {
+        /// Creates a teleoperation with the mapping.
+        pub fn new(mapping: Mapping) -> Self {
+            Self {
+                mapping,
+                interval: Duration::from_millis(50),
+            }
+        }
+
+        /// Sets the interval to send commands. By default, it's 50 milliseconds.
+        pub fn interval(mut self, interval: Duration) -> Self {
+            self.interval = interval;
+            self
+        }
+
+        /// Drives the cube until an error occurs.
+        pub async fn run(&self, cube: &mut Cube) -> Result<()> {
+            let (tx, rx) = watch::channel((0.0f32, 0.0f32));
+            let (ready_tx, ready_rx) = oneshot::channel();
+            let interval = self.interval;
+
+            // Gilrs is polled on a dedicated thread as it blocks.
+            std::thread::spawn(move || {
+                let mut gilrs = match Gilrs::new() {
+                    Ok(gilrs) => {
+                        let _ = ready_tx.send(Ok(()));
+                        gilrs
+                    }
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(anyhow!("Couldn't open gamepads: {}", e)));
+                        return;
+                    }
+                };
+                loop {
+                    while gilrs.next_event().is_some() {}
+                    let stick = gilrs
+                        .gamepads()
+                        .find(|(_, pad)| pad.is_connected())
+                        .map(|(_, pad)| (pad.value(Axis::LeftStickX), pad.value(Axis::LeftStickY)))
+                        .unwrap_or((0.0, 0.0));
+                    if tx.broadcast(stick).is_err() {
+                        break;
+                    }
+                    std::thread::sleep(interval / 2);
+                }
+            });
+            ready_rx.await??;
+
+            let mut stopped = true;
+            loop {
+                delay_for(interval).await;
+                let (x, y) = *rx.borrow();
+                match self.mapping.speeds(x, y) {
+                    (0, 0) if stopped => {}
+                    (0, 0) => {
+                        cube.stop().await?;
+                        stopped = true;
+                    }
+                    (left, right) => {
+                        trace!("Teleop: left={}, right={}", left, right);
+                        cube.go(left, right, Some(interval * 4)).await?;
+                        stopped = false;
+                    }
+                }
+            }
+        }
+    }
+}
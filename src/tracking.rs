@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The period to evaluate the tracking quality over.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// How well the cube is localized on the mat recently.
+///
+/// See [`Cube::tracking_quality`](crate::Cube::tracking_quality).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct TrackingQuality {
+    /// The ratio of the position notifications to all the id notifications, from 0 to 1.
+    ///
+    /// Drops when the cube often loses the mat.
+    pub ratio: f64,
+    /// The number of the position notifications per second.
+    ///
+    /// Drops when notifications are delayed or dropped.
+    pub rate: f64,
+}
+
+/// Keeps the recent id notifications to evaluate [`TrackingQuality`][].
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    samples: VecDeque<(Instant, bool)>,
+}
+
+impl Tracker {
+    /// Adds an id notification at the time. `on_mat` is `false` if the position is missed.
+    pub fn push(&mut self, at: Instant, on_mat: bool) {
+        self.samples.push_back((at, on_mat));
+        self.expire(at);
+    }
+
+    /// Evaluates the notifications within the window until the time.
+    pub fn quality(&mut self, now: Instant) -> TrackingQuality {
+        self.expire(now);
+        if self.samples.is_empty() {
+            return TrackingQuality::default();
+        }
+        let positions = self.samples.iter().filter(|(_, on_mat)| *on_mat).count();
+        TrackingQuality {
+            ratio: positions as f64 / self.samples.len() as f64,
+            rate: positions as f64 / WINDOW.as_secs_f64(),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((t, _)) = self.samples.front() {
+            if now.duration_since(*t) > WINDOW {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
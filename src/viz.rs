@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Result};
+use eframe::egui::{
+    self, Align2, Color32, ColorImage, FontId, Pos2, Rect, Sense, Shape, Stroke, TextureHandle,
+    TextureOptions, Ui, Vec2,
+};
+use futures::{future::abortable, prelude::*, stream};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::{Cube, Event, Position};
+
+/// The width of the cube in the mat units.
+const CUBE_SIZE: f32 = 44.0;
+
+/// The interval of the grid lines in the mat units.
+const GRID: u16 = 50;
+
+const PALETTE: [Color32; 6] = [
+    Color32::from_rgb(230, 80, 60),
+    Color32::from_rgb(60, 130, 230),
+    Color32::from_rgb(70, 180, 90),
+    Color32::from_rgb(230, 170, 40),
+    Color32::from_rgb(160, 90, 210),
+    Color32::from_rgb(40, 180, 190),
+];
+
+struct CubeState {
+    id: String,
+    pos: Option<Position>,
+    last: Option<Position>,
+    trail: VecDeque<Pos2>,
+}
+
+/// Window drawing the poses of cubes on the mat in real time.
+///
+/// Each cube is drawn as a square pointing to its angle, with the trail of the recent
+/// positions. A cube off the mat is grayed out at the last position. Simulated cubes
+/// from [`Simulator`](crate::sim::Simulator) are drawn in the same way as real ones.
+///
+/// [`Visualizer::run`][] opens the window until closed. To embed it into another egui
+/// application, feed the events by [`Visualizer::update`][] and render it by
+/// [`Visualizer::draw`][].
+///
+/// Enabled by the `viz` feature.
+///
+/// ```no_run
+/// use toio::{viz::Visualizer, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cubes = Cube::search().all().await.unwrap();
+///     for cube in &mut cubes {
+///         cube.connect().await.unwrap();
+///     }
+///
+///     Visualizer::new().run(&mut cubes).await.unwrap();
+/// }
+/// ```
+pub struct Visualizer {
+    cubes: Vec<CubeState>,
+    top_left: (u16, u16),
+    bottom_right: (u16, u16),
+    trail: usize,
+    background: Option<ColorImage>,
+    texture: Option<TextureHandle>,
+}
+
+impl Default for Visualizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Visualizer {
+    /// Creates an empty visualizer.
+    pub fn new() -> Self {
+        Self {
+            cubes: vec![],
+            top_left: (45, 45),
+            bottom_right: (455, 455),
+            trail: 100,
+            background: None,
+            texture: None,
+        }
+    }
+
+    /// Sets the coordinates of the mat corners.
+    ///
+    /// By default, the ones of the toio collection mat (ring side) are used.
+    pub fn mat(mut self, top_left: (u16, u16), bottom_right: (u16, u16)) -> Self {
+        self.top_left = top_left;
+        self.bottom_right = bottom_right;
+        self
+    }
+
+    /// Sets the number of the recent positions to draw as the trail. By default, it's 100.
+    pub fn trail(mut self, trail: usize) -> Self {
+        self.trail = trail;
+        self
+    }
+
+    /// Sets the image of the mat in RGBA drawn over the area of the mat.
+    pub fn background(mut self, width: usize, height: usize, rgba: &[u8]) -> Self {
+        self.background = Some(ColorImage::from_rgba_unmultiplied([width, height], rgba));
+        self.texture = None;
+        self
+    }
+
+    /// Updates the visualizer with the event of the cube.
+    ///
+    /// The cube is added to the visualizer if it's unknown.
+    pub fn update(&mut self, id: &str, event: &Event) {
+        let limit = self.trail;
+        let cube = self.cube(id);
+        if let Event::Position(pos) = event {
+            if let Some(p) = pos {
+                cube.trail.push_back(Pos2::new(p.x as f32, p.y as f32));
+                while cube.trail.len() > limit {
+                    cube.trail.pop_front();
+                }
+                cube.last = Some(p.clone());
+            }
+            cube.pos = pos.clone();
+        }
+    }
+
+    /// Gets the ids of the cubes in the order added.
+    pub fn ids(&self) -> Vec<&str> {
+        self.cubes.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    /// Gets the last position of the cube on the mat.
+    pub fn position(&self, id: &str) -> Option<&Position> {
+        self.cubes.iter().find(|c| c.id == id)?.pos.as_ref()
+    }
+
+    fn cube(&mut self, id: &str) -> &mut CubeState {
+        match self.cubes.iter().position(|c| c.id == id) {
+            Some(index) => &mut self.cubes[index],
+            None => {
+                self.cubes.push(CubeState {
+                    id: id.to_string(),
+                    pos: None,
+                    last: None,
+                    trail: VecDeque::new(),
+                });
+                self.cubes.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Maps the mat to the largest area of the same aspect ratio at the center of the rectangle.
+    fn mat_rect(&self, rect: Rect) -> Rect {
+        let w = self.bottom_right.0.saturating_sub(self.top_left.0).max(1) as f32;
+        let h = self.bottom_right.1.saturating_sub(self.top_left.1).max(1) as f32;
+        let scale = (rect.width() / w).min(rect.height() / h);
+        Rect::from_center_size(rect.center(), Vec2::new(w, h) * scale)
+    }
+
+    /// Renders the mat and the cubes to all the available space of the ui.
+    pub fn draw(&mut self, ui: &mut Ui) {
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::hover());
+        let mat = self.mat_rect(response.rect);
+        let (x0, y0) = (self.top_left.0 as f32, self.top_left.1 as f32);
+        let scale = mat.width() / (self.bottom_right.0 as f32 - x0).max(1.0);
+        let to_screen = |x: f32, y: f32| mat.min + Vec2::new(x - x0, y - y0) * scale;
+
+        if let Some(image) = self.background.take() {
+            self.texture = Some(ui.ctx().load_texture("mat", image, TextureOptions::LINEAR));
+        }
+        match &self.texture {
+            Some(texture) => {
+                let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                painter.image(texture.id(), mat, uv, Color32::WHITE);
+            }
+            None => {
+                painter.rect_filled(mat, 0.0, Color32::from_gray(235));
+                let grid = Stroke::new(1.0, Color32::from_gray(200));
+                let mut x = self.top_left.0 - self.top_left.0 % GRID + GRID;
+                while x < self.bottom_right.0 {
+                    let sx = to_screen(x as f32, y0).x;
+                    painter.line_segment(
+                        [Pos2::new(sx, mat.top()), Pos2::new(sx, mat.bottom())],
+                        grid,
+                    );
+                    x += GRID;
+                }
+                let mut y = self.top_left.1 - self.top_left.1 % GRID + GRID;
+                while y < self.bottom_right.1 {
+                    let sy = to_screen(x0, y as f32).y;
+                    painter.line_segment(
+                        [Pos2::new(mat.left(), sy), Pos2::new(mat.right(), sy)],
+                        grid,
+                    );
+                    y += GRID;
+                }
+            }
+        }
+
+        for (i, cube) in self.cubes.iter().enumerate() {
+            let color = if cube.pos.is_some() {
+                PALETTE[i % PALETTE.len()]
+            } else {
+                Color32::GRAY
+            };
+
+            let trail: Vec<_> = cube.trail.iter().map(|p| to_screen(p.x, p.y)).collect();
+            if trail.len() > 1 {
+                painter.add(Shape::line(
+                    trail,
+                    Stroke::new(1.5, color.gamma_multiply(0.5)),
+                ));
+            }
+
+            let p = match &cube.last {
+                Some(p) => p,
+                None => continue,
+            };
+            let center = to_screen(p.x as f32, p.y as f32);
+            let dir = Vec2::angled((p.angle as f32).to_radians());
+            let half = CUBE_SIZE * scale / 2.0;
+            let corners = [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)]
+                .iter()
+                .map(|(f, s)| center + (dir * *f + dir.rot90() * *s) * half)
+                .collect();
+            painter.add(Shape::convex_polygon(
+                corners,
+                color,
+                Stroke::new(1.0, Color32::BLACK),
+            ));
+            painter.line_segment(
+                [center, center + dir * half * 1.5],
+                Stroke::new(2.0, Color32::BLACK),
+            );
+            painter.text(
+                center + Vec2::new(0.0, half * 1.5 + 8.0),
+                Align2::CENTER_CENTER,
+                &cube.id,
+                FontId::proportional(12.0),
+                Color32::BLACK,
+            );
+        }
+    }
+
+    /// Opens the window showing the connected cubes until closed.
+    ///
+    /// The window runs on the current thread, which must be the main thread on some platforms,
+    /// with the multi-threaded runtime.
+    pub async fn run(self, cubes: &mut [Cube]) -> Result<()> {
+        let mut streams = vec![];
+        for cube in cubes.iter_mut() {
+            let id = cube.id().to_string();
+            let events = cube.events().await?;
+            streams.push(events.map(move |event| (id.clone(), event)).boxed());
+        }
+        self.run_with(stream::select_all(streams)).await
+    }
+
+    /// Opens the window showing the events of the cubes until closed.
+    ///
+    /// Each event is a pair of the cube id and the event, e.g., from a session
+    /// replayed by [`Replay`](crate::record::Replay).
+    pub async fn run_with<S>(self, events: S) -> Result<()>
+    where
+        S: Stream<Item = (String, Event)> + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(self));
+        let ctx = Arc::new(Mutex::new(None::<egui::Context>));
+
+        let (task, abort) = {
+            let shared = shared.clone();
+            let ctx = ctx.clone();
+            abortable(events.for_each(move |(id, event)| {
+                shared.lock().unwrap().update(&id, &event);
+                if let Some(ctx) = ctx.lock().unwrap().as_ref() {
+                    ctx.request_repaint();
+                }
+                future::ready(())
+            }))
+        };
+        tokio::spawn(task);
+
+        let res = tokio::task::block_in_place(|| {
+            eframe::run_native(
+                "toio",
+                eframe::NativeOptions::default(),
+                Box::new(move |cc| {
+                    *ctx.lock().unwrap() = Some(cc.egui_ctx.clone());
+                    Ok(Box::new(App { shared }))
+                }),
+            )
+        });
+        abort.abort();
+        res.map_err(|e| anyhow!("Couldn't open window: {}", e))
+    }
+}
+
+struct App {
+    shared: Arc<Mutex<Visualizer>>,
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| self.shared.lock().unwrap().draw(ui));
+    }
+}
@@ -0,0 +1,497 @@
+use anyhow::{anyhow, bail, Context, Result};
+use futures::future::{abortable, AbortHandle};
+use log::*;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex as StdMutex,
+};
+use std::time::Duration;
+use tokio::{
+    sync::{oneshot, Mutex, Notify},
+    time::{delay_until, Instant},
+};
+
+use crate::{
+    ble::{self, PeripheralOps, Uuid},
+    metrics,
+    proto::{Message, Motor, Profile, MAX_MESSAGE_SIZE, UUID_LIGHT, UUID_MOTOR},
+};
+
+/// The value to write, kept inline if it fits in a message buffer.
+enum Value {
+    Inline([u8; MAX_MESSAGE_SIZE], usize),
+    Heap(Vec<u8>),
+}
+
+impl Deref for Value {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Value::Inline(buf, len) => &buf[..*len],
+            Value::Heap(buf) => buf,
+        }
+    }
+}
+
+/// The error of a pending write replaced by a newer one of the same kind.
+///
+/// With [`Options::coalesce`](crate::Options::coalesce), a write with response fails
+/// with this error if a newer command replaces it before it's written.
+///
+/// ```
+/// use toio::Replaced;
+///
+/// let err: anyhow::Error = Replaced.into();
+/// assert!(err.downcast_ref::<Replaced>().is_some());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Replaced;
+
+impl fmt::Display for Replaced {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Replaced by a newer command before written")
+    }
+}
+
+impl std::error::Error for Replaced {}
+
+/// Returns the leading bytes telling the kind of the motor/light command.
+///
+/// Target requests of different ids are of different kinds, as each is answered by its id.
+fn kind<'a>(uuid: &Uuid, value: &'a [u8]) -> Option<&'a [u8]> {
+    match (*uuid, value.first()) {
+        (UUID_MOTOR, Some(0x03)) | (UUID_MOTOR, Some(0x04)) => value.get(..2),
+        (UUID_MOTOR, _) | (UUID_LIGHT, _) => value.get(..1),
+        _ => None,
+    }
+}
+
+struct Pending {
+    uuid: Uuid,
+    value: Value,
+    with_resp: bool,
+    replaceable: bool,
+    done: Option<oneshot::Sender<Result<()>>>,
+    /// Set when a write of the same sequence failed, to skip the rest.
+    failed: Option<Arc<AtomicBool>>,
+    /// The epoch when queued. The writes of the older epochs are cancelled.
+    epoch: usize,
+}
+
+impl Pending {
+    fn cancel(self) {
+        if let Some(tx) = self.done {
+            let _ = tx.send(Err(anyhow!("Cancelled by a preempting write")));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    pending: StdMutex<VecDeque<Pending>>,
+    ready: Notify,
+    coalesce: bool,
+    epoch: AtomicUsize,
+}
+
+impl Shared {
+    fn push(&self, mut p: Pending) {
+        p.epoch = self.epoch.load(Ordering::SeqCst);
+        {
+            let mut pending = self.pending.lock().unwrap();
+
+            // Only the latest motor/light command matters, so replaces the pending one of
+            // the same kind.
+            let k = kind(&p.uuid, &p.value);
+            if self.coalesce && p.replaceable && k.is_some() {
+                if let Some(q) = pending
+                    .iter_mut()
+                    .rev()
+                    .find(|q| q.uuid == p.uuid)
+                    .filter(|q| q.replaceable && kind(&q.uuid, &q.value) == k)
+                {
+                    trace!("Replaced pending write to characteristic {}", p.uuid);
+                    q.value = p.value;
+                    q.with_resp |= p.with_resp;
+                    if let Some(done) = std::mem::replace(&mut q.done, p.done.take()) {
+                        let _ = done.send(Err(Replaced.into()));
+                    }
+                    return;
+                }
+            }
+
+            // Writing the same value again right after has no effect.
+            let dup = p.done.is_none()
+                && pending
+                    .iter()
+                    .rev()
+                    .find(|q| q.uuid == p.uuid)
+                    .map(|q| !q.with_resp && *q.value == *p.value)
+                    .unwrap_or(false);
+            if dup {
+                trace!("Coalesced write to characteristic {}", p.uuid);
+                return;
+            }
+            pending.push_back(p);
+        }
+        self.ready.notify();
+    }
+
+    fn push_all(&self, mut ps: Vec<Pending>) {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        ps.iter_mut().for_each(|p| p.epoch = epoch);
+        self.pending.lock().unwrap().extend(ps);
+        self.ready.notify();
+    }
+
+    /// Cancels all the pending writes, including the one waiting for the interval.
+    fn cancel(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        for p in pending.drain(..) {
+            p.cancel();
+        }
+    }
+}
+
+/// Encodes the message into the values to write, with whether each can be coalesced.
+fn encode(msg: Message, max_len: Option<usize>) -> Result<Vec<(Uuid, Value, bool)>> {
+    if let (Some(max_len), Message::Motor(Motor::MultiTarget(m))) = (max_len, &msg) {
+        let reqs = m.split(max_len)?;
+        if reqs.len() > 1 {
+            debug!("Split multi-target request into {}", reqs.len());
+            return reqs
+                .into_iter()
+                .map(|req| {
+                    let (uuid, value) = Message::Motor(Motor::MultiTarget(req))
+                        .try_into()
+                        .context("Couldn't pack message")?;
+                    // Replacing a part would leave the rest appended to the newer request.
+                    Ok((uuid, Value::Heap(value), false))
+                })
+                .collect();
+        }
+    }
+
+    let mut buf = [0; MAX_MESSAGE_SIZE];
+    let (uuid, value) = match msg.encode_into(&mut buf) {
+        Ok((uuid, len)) => (uuid, Value::Inline(buf, len)),
+        Err(_) => {
+            let (uuid, value) = msg.try_into().context("Couldn't pack message")?;
+            (uuid, Value::Heap(value))
+        }
+    };
+    if let Some(max_len) = max_len.filter(|max_len| value.len() > *max_len) {
+        bail!(
+            "Message of {} bytes exceeds the maximum write length {}",
+            value.len(),
+            max_len
+        );
+    }
+    Ok(vec![(uuid, value, true)])
+}
+
+/// Writes messages ahead of the queue, cancelling all the pending writes.
+///
+/// Cloned to preempt the writer from other tasks.
+#[derive(Clone)]
+pub(crate) struct Preemptor {
+    dev: Arc<Mutex<ble::Peripheral>>,
+    urgent: Arc<Mutex<Option<ble::Writer>>>,
+    max_len: Arc<StdMutex<Option<usize>>>,
+    profile: Profile,
+    shared: Arc<Shared>,
+}
+
+impl Preemptor {
+    /// Writes the messages without response right away, cancelling all the pending writes.
+    ///
+    /// The messages go to the peripheral ahead of the queue regardless of the maximum
+    /// write rate. If the peripheral provides [`ble::Writer`][], they don't wait for
+    /// the operation in progress, e.g., a write waiting for the response or a read.
+    /// Otherwise, they're written after it.
+    pub async fn preempt(&self, msgs: Vec<Message>) -> Result<()> {
+        self.shared.cancel();
+        let max_len = *self.max_len.lock().unwrap();
+        let mut values = vec![];
+        for msg in msgs {
+            for (uuid, value, _) in encode(msg, max_len)? {
+                values.push((self.profile.resolve(&uuid), value));
+            }
+        }
+
+        let mut res = Ok(());
+        let mut urgent = self.urgent.lock().await;
+        for (uuid, value) in values {
+            let r = match urgent.as_mut() {
+                Some(w) => w.write(&uuid, &value).await,
+                None => self.dev.lock().await.write(&uuid, &value, false).await,
+            };
+            // Keeps writing the rest, which may still stop the cube.
+            if let (Err(e), Ok(())) = (r, &res) {
+                res = Err(e);
+            }
+        }
+        res
+    }
+}
+
+/// Writes messages to the peripheral.
+///
+/// All writes go through a single pipeline, so they are delivered in call order
+/// regardless of whether they wait for responses, even if issued concurrently.
+/// By default, each write waits until it's delivered.
+/// If the maximum write rate is set or coalescing is enabled,
+/// writes without response return as soon as they are queued.
+///
+/// Cloned to write from other tasks through the same pipeline.
+#[derive(Clone)]
+pub(crate) struct Writer {
+    dev: Arc<Mutex<ble::Peripheral>>,
+    interval: Duration,
+    detach: bool,
+    profile: Profile,
+    shared: Arc<Shared>,
+    preemptor: Preemptor,
+    pipeline: Arc<StdMutex<Option<Pipeline>>>,
+}
+
+/// Stops the pipeline when all the writers are dropped.
+struct Pipeline(AbortHandle);
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl Writer {
+    /// Creates a writer with the maximum number of writes per second.
+    ///
+    /// If `coalesce` is `true`, pending motor/light commands are replaced by newer ones.
+    /// The UUIDs of the toio cube are converted into the ones of the profile on writing.
+    pub fn new(
+        dev: Arc<Mutex<ble::Peripheral>>,
+        max_rate: Option<u32>,
+        coalesce: bool,
+        profile: Profile,
+    ) -> Self {
+        let max_rate = max_rate.filter(|r| *r > 0);
+        let shared = Arc::new(Shared {
+            coalesce,
+            ..Shared::default()
+        });
+        Self {
+            dev: dev.clone(),
+            interval: max_rate
+                .map(|r| Duration::from_secs(1) / r)
+                .unwrap_or_default(),
+            detach: max_rate.is_some() || coalesce,
+            profile,
+            shared: shared.clone(),
+            preemptor: Preemptor {
+                dev,
+                urgent: Arc::new(Mutex::new(None)),
+                max_len: Arc::new(StdMutex::new(None)),
+                profile,
+                shared,
+            },
+            pipeline: Arc::new(StdMutex::new(None)),
+        }
+    }
+
+    /// Takes the properties of the peripheral negotiated on connection.
+    ///
+    /// Kept apart from the peripheral, so that writes don't wait for its lock to check them.
+    pub async fn attach(&self) {
+        let (max_len, writer) = {
+            let dev = self.dev.lock().await;
+            (dev.max_write_len(), dev.writer())
+        };
+        *self.preemptor.max_len.lock().unwrap() = max_len;
+        *self.preemptor.urgent.lock().await = writer;
+    }
+
+    fn max_write_len(&self) -> Option<usize> {
+        *self.preemptor.max_len.lock().unwrap()
+    }
+
+    /// Returns the number of times the writes have been preempted.
+    pub fn preempted(&self) -> usize {
+        self.shared.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Creates a handle to preempt the writer.
+    pub fn preemptor(&self) -> Preemptor {
+        self.preemptor.clone()
+    }
+
+    /// Writes a protocol message.
+    ///
+    /// Messages fitting in [`MAX_MESSAGE_SIZE`][] are encoded without allocation.
+    /// If the peripheral tells the maximum write length, longer multi-target requests
+    /// are split into appended ones, and other longer messages are rejected.
+    pub async fn write_msg<T: Into<Message>>(&self, msg: T, with_resp: bool) -> Result<()> {
+        let max_len = self.max_write_len();
+        for (uuid, value, replaceable) in encode(msg.into(), max_len)? {
+            self.write(uuid, value, with_resp, replaceable).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the messages back-to-back in order, without other writes in between.
+    ///
+    /// Nothing is written if any of the messages can't be encoded. If a write fails,
+    /// the rest are skipped, and the first error is returned. The messages are never
+    /// coalesced, and this waits until all of them are delivered.
+    pub async fn write_all(&self, msgs: Vec<(Message, bool)>) -> Result<()> {
+        let max_len = self.max_write_len();
+        let mut values = vec![];
+        for (msg, with_resp) in msgs {
+            for (uuid, value, _) in encode(msg, max_len)? {
+                values.push((uuid, value, with_resp));
+            }
+        }
+        self.start();
+
+        let failed = Arc::new(AtomicBool::new(false));
+        let (pending, done): (Vec<_>, Vec<_>) = values
+            .into_iter()
+            .map(|(uuid, value, with_resp)| {
+                let (tx, rx) = oneshot::channel();
+                let p = Pending {
+                    uuid,
+                    value,
+                    with_resp,
+                    replaceable: false,
+                    done: Some(tx),
+                    failed: Some(failed.clone()),
+                    epoch: 0,
+                };
+                (p, rx)
+            })
+            .unzip();
+        self.shared.push_all(pending);
+
+        let mut res = Ok(());
+        for rx in done {
+            let r = rx.await.context("Writer stopped before writing");
+            if res.is_ok() {
+                res = r.and_then(|r| r);
+            }
+        }
+        res
+    }
+
+    /// Writes a value to the characteristic.
+    ///
+    /// If `replaceable` is `false`, the value is never replaced by coalescing.
+    async fn write(
+        &self,
+        uuid: Uuid,
+        value: Value,
+        with_resp: bool,
+        replaceable: bool,
+    ) -> Result<()> {
+        self.start();
+
+        if with_resp || !self.detach {
+            let (tx, rx) = oneshot::channel();
+            self.shared.push(Pending {
+                uuid,
+                value,
+                with_resp,
+                replaceable,
+                done: Some(tx),
+                failed: None,
+                epoch: 0,
+            });
+            rx.await.context("Writer stopped before writing")?
+        } else {
+            self.shared.push(Pending {
+                uuid,
+                value,
+                with_resp,
+                replaceable,
+                done: None,
+                failed: None,
+                epoch: 0,
+            });
+            Ok(())
+        }
+    }
+
+    fn start(&self) {
+        let mut pipeline = self.pipeline.lock().unwrap();
+        if pipeline.is_some() {
+            return;
+        }
+
+        let interval = self.interval;
+        let profile = self.profile;
+        let dev = self.dev.clone();
+        let shared = self.shared.clone();
+        let (task, h) = abortable(async move {
+            let mut last: Option<Instant> = None;
+            loop {
+                let next = shared.pending.lock().unwrap().pop_front();
+                let p = match next {
+                    Some(p) => p,
+                    None => {
+                        shared.ready.notified().await;
+                        continue;
+                    }
+                };
+
+                let skip = p.failed.as_ref().map(|f| f.load(Ordering::SeqCst));
+                if skip == Some(true) {
+                    if let Some(tx) = p.done {
+                        let _ =
+                            tx.send(Err(anyhow!("Skipped after a failed write of the sequence")));
+                    }
+                    continue;
+                }
+
+                match last {
+                    Some(last) if interval > Duration::from_secs(0) => {
+                        delay_until(last + interval).await
+                    }
+                    _ => {}
+                }
+                let uuid = profile.resolve(&p.uuid);
+                let mut guard = dev.lock().await;
+                if p.epoch != shared.epoch.load(Ordering::SeqCst) {
+                    p.cancel();
+                    continue;
+                }
+                let start = Instant::now();
+                let res = guard.write(&uuid, &p.value, p.with_resp).await;
+                drop(guard);
+                last = Some(Instant::now());
+                if res.is_ok() {
+                    metrics::write_latency(&profile, &uuid, start.elapsed());
+                } else if let Some(failed) = &p.failed {
+                    failed.store(true, Ordering::SeqCst);
+                }
+
+                match p.done {
+                    Some(tx) => {
+                        let _ = tx.send(res);
+                    }
+                    None => {
+                        if let Err(e) = res {
+                            warn!("Couldn't write to characteristic {}: {}", p.uuid, e);
+                        }
+                    }
+                }
+            }
+        });
+        tokio::spawn(task);
+        *pipeline = Some(Pipeline(h));
+    }
+}
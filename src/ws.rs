@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use futures::{
+    prelude::*,
+    stream::{self, BoxStream},
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::{
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::Mutex,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message as WsMessage};
+
+use crate::{proto::Message, Cube, Event};
+
+/// The frame sent to clients when a cube emits an event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventFrame {
+    /// The id of the cube.
+    pub cube: String,
+    /// The event.
+    pub event: Event,
+}
+
+/// The frame sent from clients to write a message to a cube.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommandFrame {
+    /// The id of the cube.
+    pub cube: String,
+    /// The message to write. See [`Message::from_json`][] for the format.
+    pub message: Message,
+}
+
+/// Serves cubes to WebSocket clients with a JSON protocol.
+///
+/// Each text frame from the server is an [`EventFrame`][], e.g.,
+/// `{"cube": "<id>", "event": {"Battery": 80}}`, and each text frame from clients
+/// is a [`CommandFrame`][], e.g., `{"cube": "<id>", "message": {"Sound": "Stop"}}`.
+/// Every client receives the events of all the cubes.
+///
+/// Enabled by the `websocket` feature.
+///
+/// ```no_run
+/// use toio::{ws::Server, Cube};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut cubes = Cube::search().all().await.unwrap();
+///     for cube in &mut cubes {
+///         cube.connect().await.unwrap();
+///     }
+///
+///     Server::new(cubes).serve("0.0.0.0:8080").await.unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Server {
+    cubes: Arc<HashMap<String, Arc<Mutex<Cube>>>>,
+}
+
+impl Server {
+    /// Creates a server for the connected cubes.
+    pub fn new(cubes: Vec<Cube>) -> Self {
+        let cubes = cubes
+            .into_iter()
+            .map(|cube| (cube.id().to_string(), Arc::new(Mutex::new(cube))))
+            .collect();
+        Self {
+            cubes: Arc::new(cubes),
+        }
+    }
+
+    /// Accepts clients at the address until an error occurs.
+    pub async fn serve<A: ToSocketAddrs>(&self, addr: A) -> Result<()> {
+        let mut listener = TcpListener::bind(addr)
+            .await
+            .context("Couldn't bind WebSocket server")?;
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle(stream, peer).await {
+                    warn!("WebSocket client {} is closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+        let ws = accept_async(stream)
+            .await
+            .context("Couldn't accept WebSocket connection")?;
+        debug!("WebSocket client {} is connected", peer);
+        let (mut tx, mut rx) = ws.split();
+
+        let mut events = self.events().await?;
+        loop {
+            tokio::select! {
+                frame = events.next() => {
+                    let frame = match frame {
+                        Some(frame) => frame,
+                        None => break,
+                    };
+                    tx.send(WsMessage::Text(serde_json::to_string(&frame)?)).await?;
+                }
+                msg = rx.next() => {
+                    match msg.transpose()? {
+                        Some(WsMessage::Text(text)) => {
+                            if let Err(e) = self.command(&text).await {
+                                warn!("Couldn't handle command from {}: {}", peer, e);
+                            }
+                        }
+                        Some(WsMessage::Close(_)) | None => break,
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+
+        debug!("WebSocket client {} is disconnected", peer);
+        Ok(())
+    }
+
+    async fn events(&self) -> Result<BoxStream<'static, EventFrame>> {
+        let mut streams = vec![];
+        for (id, cube) in self.cubes.iter() {
+            let id = id.clone();
+            let events = cube.lock().await.events().await?;
+            streams.push(
+                events
+                    .filter(|e| future::ready(!matches!(e, Event::Lagged(_))))
+                    .map(move |event| EventFrame {
+                        cube: id.clone(),
+                        event,
+                    })
+                    .boxed(),
+            );
+        }
+        // Keeps the connection open even if there's no cube.
+        Ok(stream::select_all(streams).chain(stream::pending()).boxed())
+    }
+
+    async fn command(&self, text: &str) -> Result<()> {
+        let frame: CommandFrame = serde_json::from_str(text).context("Invalid command frame")?;
+        let cube = self
+            .cubes
+            .get(&frame.cube)
+            .with_context(|| format!("No such cube: {}", frame.cube))?;
+        cube.lock().await.write_msg(frame.message, false).await
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::Result;
+use futures::prelude::*;
+use std::sync::Mutex;
+use std::time::Duration;
+use toio::{
+    ble::{self, mock, SearchOps, Uuid},
+    proto::*,
+    Backend, Characteristic, Cube, Options,
+};
+use tokio::time::delay_for;
+
+static HANDLES: Mutex<Vec<mock::MockHandle>> = Mutex::new(vec![]);
+
+struct MockSearcher;
+
+#[async_trait::async_trait]
+impl SearchOps for MockSearcher {
+    async fn search(&mut self, _: &Uuid, _: Duration) -> Result<Vec<ble::Peripheral>> {
+        let (dev, handle) = mock::mock("mock");
+        HANDLES.lock().unwrap().push(handle);
+        Ok(vec![Box::new(dev)])
+    }
+}
+
+struct RemoteSearcher;
+
+#[async_trait::async_trait]
+impl SearchOps for RemoteSearcher {
+    async fn search(&mut self, _: &Uuid, _: Duration) -> Result<Vec<ble::Peripheral>> {
+        Ok(vec![Box::new(mock::mock("remote").0)])
+    }
+}
+
+#[tokio::test]
+async fn test_register_backend() {
+    struct Off;
+
+    #[async_trait::async_trait]
+    impl SearchOps for Off {
+        async fn search(&mut self, _: &Uuid, _: Duration) -> Result<Vec<ble::Peripheral>> {
+            Err(ble::Unavailable::PoweredOff.into())
+        }
+    }
+
+    ble::register_backend(|| Box::new(Off));
+    let err = Cube::search().nearest().await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ble::Unavailable>(),
+        Some(&ble::Unavailable::PoweredOff)
+    );
+    assert_eq!(
+        ble::Unavailable::PoweredOff.to_string(),
+        "Bluetooth is disabled"
+    );
+
+    ble::register_backend(|| Box::new(MockSearcher));
+
+    let mut cube = Cube::search().nearest().await.unwrap();
+    assert_eq!(cube.id(), "mock");
+    cube.connect().await.unwrap();
+
+    let handle = HANDLES.lock().unwrap().pop().unwrap();
+    assert!(handle.is_connected());
+    handle.notify(UUID_BATTERY, vec![60]);
+    assert_eq!(cube.battery().await.unwrap(), 60);
+
+    let found = ble::searcher()
+        .search(&UUID_SERVICE, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+
+    ble::unregister_backend();
+}
+
+#[tokio::test]
+async fn test_with_backend() {
+    let mut cube = Cube::search()
+        .with_backend(Backend::Mock)
+        .nearest()
+        .await
+        .unwrap();
+    assert_eq!(cube.id(), "mock");
+    cube.connect().await.unwrap();
+    assert_eq!(cube.battery().await.unwrap(), 100);
+    cube.go(50, 50, Some(Duration::from_millis(100)))
+        .await
+        .unwrap();
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(ble::remote::serve(addr, Box::new(RemoteSearcher)));
+    delay_for(Duration::from_millis(100)).await;
+
+    let cubes = Cube::search()
+        .with_backend(Backend::Remote(addr.to_string()))
+        .all_timeout(Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(cubes.len(), 1);
+    assert_eq!(cubes[0].id(), "remote");
+}
+
+#[test]
+fn test_adapter() {
+    let info = |index, address: Option<&str>| ble::AdapterInfo {
+        index,
+        name: format!("hci{}", index),
+        address: address.map(|a| a.to_string()),
+    };
+    let adapters = vec![info(0, Some("00:1A:7D:DA:71:13")), info(1, None)];
+
+    assert_eq!(ble::Adapter::Index(1).find(&adapters), Some(&adapters[1]));
+    assert_eq!(ble::Adapter::Index(2).find(&adapters), None);
+    assert_eq!(
+        ble::Adapter::Address("00:1a:7d:da:71:13".into()).find(&adapters),
+        Some(&adapters[0])
+    );
+    assert_eq!(
+        ble::Adapter::Address("00:1A:7D:DA:71:14".into()).find(&adapters),
+        None
+    );
+}
+
+struct BondSearcher;
+
+#[async_trait::async_trait]
+impl SearchOps for BondSearcher {
+    async fn search(&mut self, _: &Uuid, _: Duration) -> Result<Vec<ble::Peripheral>> {
+        Ok(vec![
+            Box::new(mock::mock("b").0),
+            Box::new(mock::mock("c").0),
+        ])
+    }
+
+    async fn retrieve(&mut self, ids: &[String]) -> Result<Vec<ble::Peripheral>> {
+        Ok(ids
+            .iter()
+            .filter(|id| *id == "a")
+            .map(|id| Box::new(mock::mock(id).0) as ble::Peripheral)
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn test_bonded() {
+    let (dev, handle) = mock::mock("a");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    assert!(cube.pair().await.is_err());
+    cube.connect().await.unwrap();
+    cube.pair().await.unwrap();
+    assert!(handle.is_paired());
+    cube.unpair().await.unwrap();
+    assert!(!handle.is_paired());
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(ble::remote::serve(addr, Box::new(BondSearcher)));
+    delay_for(Duration::from_millis(100)).await;
+
+    // "a" is retrieved, "c" is searched for, and "x" is not found.
+    let cubes = Cube::search()
+        .with_backend(Backend::Remote(addr.to_string()))
+        .bonded(&["c", "a", "x"], Duration::from_secs(1))
+        .await
+        .unwrap();
+    let ids: Vec<_> = cubes.iter().map(|c| c.id()).collect();
+    assert_eq!(ids, vec!["c", "a"]);
+}
+
+struct NoisySearcher;
+
+#[async_trait::async_trait]
+impl SearchOps for NoisySearcher {
+    async fn search(&mut self, _: &Uuid, _: Duration) -> Result<Vec<ble::Peripheral>> {
+        // Each cube is reported every time it advertises.
+        let mut found: Vec<ble::Peripheral> = vec![];
+        for (id, rssi) in &[("a", -70), ("b", -60), ("a", -50), ("a", -80)] {
+            let (dev, handle) = mock::mock(id);
+            handle.set_rssi(*rssi);
+            found.push(Box::new(dev));
+        }
+        Ok(found)
+    }
+}
+
+#[tokio::test]
+async fn test_dedup() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(ble::remote::serve(addr, Box::new(NoisySearcher)));
+    delay_for(Duration::from_millis(100)).await;
+
+    let cubes = Cube::search()
+        .with_backend(Backend::Remote(addr.to_string()))
+        .all_timeout(Duration::from_secs(1))
+        .await
+        .unwrap();
+    let found: Vec<_> = cubes.iter().map(|c| (c.id(), c.rssi())).collect();
+    assert_eq!(found, vec![("a", -50), ("b", -60)]);
+}
+
+#[tokio::test]
+async fn test_notify() {
+    /// A backend written before notifications could be toggled.
+    struct Minimal;
+
+    #[async_trait::async_trait]
+    impl ble::PeripheralOps for Minimal {
+        fn id(&self) -> &str {
+            "minimal"
+        }
+
+        fn rssi(&self) -> i32 {
+            0
+        }
+
+        async fn connect(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn disconnect(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn read(&mut self, _: &Uuid) -> Result<()> {
+            Ok(())
+        }
+
+        async fn write(&mut self, _: &Uuid, _: &[u8], _: bool) -> Result<()> {
+            Ok(())
+        }
+
+        fn subscribe(&mut self) -> Result<ble::ValueStream> {
+            Ok(futures::stream::empty().boxed())
+        }
+    }
+
+    let mut cube = Cube::from_peripheral(Box::new(Minimal), Options::default());
+    cube.set_notifications(Characteristic::Id, false)
+        .await
+        .unwrap();
+
+    let (dev, handle) = mock::mock("mock");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.set_notifications(Characteristic::Id, false)
+        .await
+        .unwrap();
+    assert_eq!(handle.notifications(), vec![(UUID_ID, false)]);
+    assert!(!handle.is_notifying(&UUID_ID));
+    assert!(handle.is_notifying(&UUID_BATTERY));
+
+    cube.set_notifications(Characteristic::Id, true)
+        .await
+        .unwrap();
+    assert!(handle.is_notifying(&UUID_ID));
+}
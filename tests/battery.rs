@@ -0,0 +1,65 @@
+use futures::prelude::*;
+use std::time::{Duration, Instant};
+use toio::{battery::Estimator, ble::mock, proto::*, Cube, Event, EventFilter, Options};
+
+#[test]
+fn test_estimator() {
+    let start = Instant::now();
+    let min = |m: u64| start + Duration::from_secs(m * 60);
+
+    let mut e = Estimator::default();
+    assert_eq!(e.estimate(), None);
+    e.push(min(0), 100);
+    assert_eq!(e.estimate(), None);
+
+    // Drops 10% every 10 minutes.
+    e.push(min(10), 90);
+    e.push(min(20), 80);
+    assert!((e.rate().unwrap() - 60.0).abs() < 1e-6);
+    assert_eq!(e.estimate().unwrap().as_secs(), 80 * 60);
+
+    // Charging resets the history.
+    e.push(min(30), 90);
+    assert_eq!(e.estimate(), None);
+
+    // Old samples are dropped.
+    let mut e = Estimator::new(Duration::from_secs(15 * 60));
+    e.push(min(0), 100);
+    e.push(min(10), 50);
+    e.push(min(20), 40);
+    assert!((e.rate().unwrap() - 60.0).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_battery_low() {
+    let (dev, handle) = mock::mock("cube");
+    let opts = Options {
+        battery_low: Some(20),
+        ..Options::default()
+    };
+    let mut cube = Cube::from_peripheral(Box::new(dev), opts);
+    cube.connect().await.unwrap();
+    let mut events = cube
+        .events_filtered(EventFilter::new().battery())
+        .await
+        .unwrap()
+        .filter(|event| future::ready(matches!(event, Event::BatteryLow(_))));
+
+    // Emitted once on crossing the threshold.
+    for v in &[30, 21, 20, 15, 10] {
+        handle.notify(UUID_BATTERY, vec![*v]);
+    }
+    // Charged above the threshold, and falls again.
+    for v in &[25, 18, 12] {
+        handle.notify(UUID_BATTERY, vec![*v]);
+    }
+    let lows: Vec<_> = events.by_ref().take(2).collect().await;
+    assert_eq!(lows, vec![Event::BatteryLow(20), Event::BatteryLow(18)]);
+
+    handle.notify(UUID_BATTERY, vec![5]);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), events.next())
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,301 @@
+use anyhow::Result;
+use std::convert::{TryFrom, TryInto};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use toio::{behavior::*, ble::mock::MockHandle, proto::*, Cube, StdId};
+use tokio::time::{delay_for, timeout};
+
+mod common;
+
+fn std_id(value: u32) -> Vec<u8> {
+    Id::Std(IdStd::new(value, 0)).try_into().unwrap()
+}
+
+fn motors(mock: &MockHandle) -> Vec<Motor> {
+    mock.writes()
+        .into_iter()
+        .filter(|(uuid, _)| *uuid == UUID_MOTOR)
+        .map(|(_, v)| Motor::try_from(v).unwrap())
+        .collect()
+}
+
+fn stopped(motor: &Motor) -> bool {
+    match motor {
+        Motor::Simple(m) => m.speed1 == 7 && m.speed2 == 7,
+        m => panic!("Unexpected motor command: {:?}", m),
+    }
+}
+
+async fn cube() -> (Cube, MockHandle) {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+    mock.notify(UUID_ID, vec![0x04]);
+    (cube, mock)
+}
+
+#[tokio::test]
+async fn test_dock() {
+    let (mut cube, mock) = cube().await;
+
+    // Passes another card, crosses the card, and comes back on it.
+    let cards = async {
+        delay_for(Duration::from_millis(100)).await;
+        mock.notify(UUID_ID, std_id(2));
+        delay_for(Duration::from_millis(100)).await;
+        mock.notify(UUID_ID, std_id(1));
+        delay_for(Duration::from_millis(100)).await;
+        mock.notify(UUID_ID, vec![0x04]);
+        delay_for(Duration::from_millis(20)).await;
+        mock.notify(UUID_ID, std_id(1));
+    };
+    let mut events = vec![];
+    let dock = Dock::new(1);
+    let (res, _) = futures::join!(dock.run(&mut cube, |e| events.push(e)), cards);
+    assert_eq!(res.unwrap(), StdId::new(1, 0));
+    assert_eq!(
+        events,
+        vec![
+            DockEvent::Searching,
+            DockEvent::Found(StdId::new(1, 0)),
+            DockEvent::Centering,
+            DockEvent::Docked(StdId::new(1, 0)),
+        ]
+    );
+
+    let motors = motors(&mock);
+    assert!(stopped(motors.last().unwrap()));
+    match &motors[motors.len() - 2] {
+        Motor::Simple(m) => {
+            assert_eq!(m.dir1, MotorDir::Backward);
+            assert_eq!(m.dir2, MotorDir::Backward);
+        }
+        m => panic!("Unexpected motor command: {:?}", m),
+    }
+}
+
+#[tokio::test]
+async fn test_dock_timeout() {
+    let (mut cube, mock) = cube().await;
+
+    let res = Dock::new(1)
+        .timeout(Duration::from_millis(300))
+        .run(&mut cube, |_| {})
+        .await;
+    assert!(res.is_err());
+    assert!(stopped(motors(&mock).last().unwrap()));
+    assert!(Dock::new(1).speed(0).run(&mut cube, |_| {}).await.is_err());
+}
+
+#[tokio::test]
+async fn test_wander() {
+    let (mut cube, mock) = cube().await;
+
+    // Heading out of the right edge, so turns back, and stops off the mat.
+    let positions = async {
+        delay_for(Duration::from_millis(50)).await;
+        mock.notify(UUID_ID, common::pos(440, 250, 0));
+        delay_for(Duration::from_millis(50)).await;
+        mock.notify(UUID_ID, vec![0x03]);
+        delay_for(Duration::from_millis(50)).await;
+    };
+    let wander = Wander::new().seed(1);
+    let (res, _) = futures::join!(
+        timeout(Duration::from_millis(200), wander.run(&mut cube)),
+        positions
+    );
+    assert!(res.is_err());
+
+    let motors = motors(&mock);
+    assert_eq!(motors.len(), 2);
+    match &motors[0] {
+        Motor::Timed(m) => assert_ne!(m.speed1, m.speed2),
+        m => panic!("Unexpected motor command: {:?}", m),
+    }
+    assert!(stopped(&motors[1]));
+
+    let fast = Wander::new().speed(95).jitter(10);
+    assert!(fast.run(&mut cube).await.is_err());
+}
+
+#[tokio::test]
+async fn test_bounce() {
+    let (mut cube, mock) = cube().await;
+
+    // The second collision is during the maneuver, so ignored.
+    let collision: Vec<u8> = Motion::Detect(MotionDetect::new(true, true, false, Posture::HeadUp))
+        .try_into()
+        .unwrap();
+    let collisions = async {
+        delay_for(Duration::from_millis(50)).await;
+        mock.notify(UUID_MOTION, collision.clone());
+        delay_for(Duration::from_millis(50)).await;
+        mock.notify(UUID_MOTION, collision.clone());
+    };
+    let bounce = Bounce::new()
+        .backup(Duration::from_millis(50))
+        .turn(90, 90)
+        .turn_rate(900.0);
+    let (res, _) = futures::join!(
+        timeout(Duration::from_millis(400), bounce.run(&mut cube)),
+        collisions
+    );
+    assert!(res.is_err());
+
+    let dirs: Vec<_> = motors(&mock)
+        .into_iter()
+        .map(|m| match m {
+            Motor::Simple(m) => (m.dir1, m.dir2),
+            m => panic!("Unexpected motor command: {:?}", m),
+        })
+        .collect();
+    assert_eq!(
+        dirs,
+        vec![
+            (MotorDir::Forward, MotorDir::Forward),
+            (MotorDir::Backward, MotorDir::Backward),
+            (MotorDir::Forward, MotorDir::Backward),
+            (MotorDir::Forward, MotorDir::Forward),
+        ]
+    );
+
+    assert!(Bounce::new().turn(90, 400).run(&mut cube).await.is_err());
+}
+
+/// Runs for the ticks, then finishes with the status, logging the calls.
+struct Step {
+    name: &'static str,
+    ticks: usize,
+    status: Status,
+    count: usize,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Step {
+    fn new(
+        name: &'static str,
+        ticks: usize,
+        status: Status,
+        log: &Arc<Mutex<Vec<String>>>,
+    ) -> Self {
+        Self {
+            name,
+            ticks,
+            status,
+            count: 0,
+            log: log.clone(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior for Step {
+    async fn tick(&mut self, _: &mut Cube) -> Result<Status> {
+        self.log.lock().unwrap().push(format!("tick {}", self.name));
+        self.count += 1;
+        if self.count > self.ticks {
+            self.count = 0;
+            Ok(self.status)
+        } else {
+            Ok(Status::Running)
+        }
+    }
+
+    async fn cancel(&mut self, _: &mut Cube) -> Result<()> {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("cancel {}", self.name));
+        self.count = 0;
+        Ok(())
+    }
+}
+
+fn take(log: &Arc<Mutex<Vec<String>>>) -> Vec<String> {
+    log.lock().unwrap().drain(..).collect()
+}
+
+#[tokio::test]
+async fn test_behavior_tree() {
+    let (mut cube, _mock) = cube().await;
+    let exec = Executor::new().interval(Duration::from_millis(1));
+    let log = Arc::new(Mutex::new(vec![]));
+
+    let mut seq = Sequence::new(vec![
+        Step::new("a", 1, Status::Success, &log).boxed(),
+        Step::new("b", 0, Status::Failure, &log).boxed(),
+        Step::new("c", 0, Status::Success, &log).boxed(),
+    ]);
+    assert_eq!(
+        exec.run(&mut cube, &mut seq).await.unwrap(),
+        Status::Failure
+    );
+    assert_eq!(take(&log), vec!["tick a", "tick a", "tick b"]);
+
+    let mut fallback = Fallback::new(vec![
+        Step::new("a", 0, Status::Failure, &log).boxed(),
+        Step::new("b", 1, Status::Success, &log).boxed(),
+        Step::new("c", 0, Status::Success, &log).boxed(),
+    ]);
+    assert_eq!(
+        exec.run(&mut cube, &mut fallback).await.unwrap(),
+        Status::Success
+    );
+    assert_eq!(take(&log), vec!["tick a", "tick b", "tick b"]);
+
+    // The running sibling is cancelled.
+    let mut parallel = Parallel::new(vec![
+        Step::new("a", 0, Status::Success, &log).boxed(),
+        Step::new("b", 1, Status::Failure, &log).boxed(),
+        Step::new("c", 5, Status::Success, &log).boxed(),
+    ]);
+    assert_eq!(
+        exec.run(&mut cube, &mut parallel).await.unwrap(),
+        Status::Failure
+    );
+    assert_eq!(
+        take(&log),
+        vec!["tick a", "tick b", "tick c", "tick b", "tick c", "cancel c"]
+    );
+
+    let mut any = Parallel::new(vec![
+        Step::new("a", 0, Status::Failure, &log).boxed(),
+        Step::new("b", 1, Status::Success, &log).boxed(),
+    ])
+    .any();
+    assert_eq!(
+        exec.run(&mut cube, &mut any).await.unwrap(),
+        Status::Success
+    );
+    assert_eq!(take(&log), vec!["tick a", "tick b", "tick b"]);
+
+    // Cancellation reaches the running leaf.
+    let mut seq = Sequence::new(vec![
+        Step::new("a", 0, Status::Success, &log).boxed(),
+        Step::new("b", 1000, Status::Success, &log).boxed(),
+    ]);
+    let res = exec
+        .run_until(&mut cube, &mut seq, delay_for(Duration::from_millis(20)))
+        .await
+        .unwrap();
+    assert_eq!(res, None);
+    assert_eq!(take(&log).last().unwrap(), "cancel b");
+}
+
+#[tokio::test]
+async fn test_behavior_leaves() {
+    let (mut cube, mock) = cube().await;
+
+    let mut drive = Sequence::new(vec![
+        Go::new(30, 30, None).boxed(),
+        Wait::new(Duration::from_millis(50)).boxed(),
+        Go::new(0, 0, None).boxed(),
+    ]);
+    let start = std::time::Instant::now();
+    let status = Executor::new().run(&mut cube, &mut drive).await.unwrap();
+    assert_eq!(status, Status::Success);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let motors = motors(&mock);
+    assert_eq!(motors.len(), 2);
+    assert!(stopped(&motors[1]));
+}
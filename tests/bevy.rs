@@ -0,0 +1,55 @@
+#![cfg(feature = "bevy")]
+
+use bevy_app::App;
+use std::convert::TryInto;
+use std::time::Duration;
+use toio::{
+    bevy::{Command, CubeCommand, Pose, ToioCube, ToioPlugin},
+    proto::*,
+};
+
+mod common;
+
+#[test]
+fn test_plugin() {
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let (mut cube, mock) = common::cube("cube");
+    rt.block_on(cube.connect()).unwrap();
+
+    let mut app = App::new();
+    app.add_plugins(ToioPlugin::new(rt.handle().clone(), vec![cube]));
+    app.update();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // Positions update the pose.
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 200, 90, 0, 0, 0))
+        .try_into()
+        .unwrap();
+    mock.notify(UUID_ID, pos);
+    std::thread::sleep(Duration::from_millis(100));
+    app.update();
+
+    let world = app.world_mut();
+    let (entity, cube, pose) = world
+        .query::<(bevy_ecs::entity::Entity, &ToioCube, &Pose)>()
+        .single(world);
+    assert_eq!(cube.id, "cube");
+    assert_eq!(
+        *pose,
+        Pose {
+            x: 100,
+            y: 200,
+            angle: 90,
+            on_mat: true
+        }
+    );
+
+    // Commands are sent to the cube.
+    world.send_event(CubeCommand {
+        entity,
+        command: Command::Message(Message::Sound(Sound::Stop)),
+    });
+    app.update();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(mock.writes(), vec![(UUID_SOUND, vec![0x01])]);
+}
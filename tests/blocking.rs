@@ -0,0 +1,22 @@
+use std::time::Duration;
+use toio::{blocking::Cube, proto::*, Event};
+
+mod common;
+
+#[test]
+fn test_blocking() {
+    let (cube, mock) = common::cube("cube");
+    let mut cube = Cube::from(cube);
+    cube.connect().unwrap();
+    assert_eq!(cube.id(), "cube");
+
+    cube.go(50, -50, Some(Duration::from_millis(100))).unwrap();
+    assert!(mock.writes().iter().any(|(uuid, _)| *uuid == UUID_MOTOR));
+
+    let mut events = cube.events().unwrap();
+    assert_eq!(events.next_timeout(Duration::from_millis(10)), None);
+
+    mock.notify(UUID_BATTERY, vec![80]);
+    assert_eq!(events.next(), Some(Event::Battery(80)));
+    assert_eq!(cube.battery().unwrap(), 80);
+}
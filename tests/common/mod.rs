@@ -0,0 +1,64 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::convert::{TryFrom, TryInto};
+use std::time::Duration;
+use toio::{
+    ble::mock::{self, MockHandle},
+    proto::*,
+    Cube, Options,
+};
+use tokio::time::delay_for;
+
+/// Creates a cube backed by a mock peripheral.
+pub fn cube(id: &str) -> (Cube, MockHandle) {
+    cube_with_options(id, Options::default())
+}
+
+/// Creates a cube with the options, backed by a mock peripheral.
+pub fn cube_with_options(id: &str, opts: Options) -> (Cube, MockHandle) {
+    let (dev, handle) = mock::mock(id);
+    (Cube::from_peripheral(Box::new(dev), opts), handle)
+}
+
+/// Encodes the position notification.
+pub fn pos(x: u16, y: u16, angle: u16) -> Vec<u8> {
+    Id::Pos(IdPos::new(x, y, angle, 0, 0, 0))
+        .try_into()
+        .unwrap()
+}
+
+/// Decodes the motor commands written.
+pub fn motor_writes(writes: Vec<(Uuid, Vec<u8>)>) -> Vec<Motor> {
+    writes
+        .into_iter()
+        .filter(|(uuid, _)| *uuid == UUID_MOTOR)
+        .map(|(_, v)| Motor::try_from(v).unwrap())
+        .collect()
+}
+
+/// Responds to the new target requests with the result, returning the targets requested.
+pub async fn respond_targets(
+    mock: &MockHandle,
+    res: TargetResValue,
+    count: usize,
+) -> Vec<(u16, u16)> {
+    let seen = motor_writes(mock.writes()).len();
+    let mut targets = vec![];
+    while targets.len() < count {
+        delay_for(Duration::from_millis(10)).await;
+        for m in motor_writes(mock.writes())
+            .into_iter()
+            .skip(seen + targets.len())
+        {
+            if let Motor::Target(t) = m {
+                targets.push((t.x, t.y));
+                let res: Vec<u8> = Motor::TargetRes(MotorTargetRes::new(t.id, res))
+                    .try_into()
+                    .unwrap();
+                mock.notify(UUID_MOTOR, res);
+            }
+        }
+    }
+    targets
+}
@@ -0,0 +1,55 @@
+use toio::{
+    csv::{CsvSink, Kind},
+    Event, Position, Posture,
+};
+
+fn rows(sink: CsvSink<Vec<u8>>) -> Vec<Vec<String>> {
+    let out = String::from_utf8(sink.into_inner()).unwrap();
+    out.lines()
+        .map(|l| l.split(',').map(|c| c.to_string()).collect())
+        .collect()
+}
+
+#[test]
+fn test_csv() {
+    let mut sink = CsvSink::new(vec![]);
+    assert!(sink
+        .write("a", &Event::Position(Some(Position::new(250, 300, 90))))
+        .unwrap());
+    assert!(sink.write("a", &Event::Position(None)).unwrap());
+    assert!(sink.write("a", &Event::Posture(Posture::BottomUp)).unwrap());
+    assert!(sink.write("a", &Event::Battery(80)).unwrap());
+    assert!(!sink.write("a", &Event::Button(true)).unwrap());
+
+    let rows = rows(sink);
+    assert_eq!(
+        rows[0].join(","),
+        "timestamp,elapsed,cube,kind,x,y,angle,on_mat,collision,slope,posture,battery"
+    );
+    assert_eq!(rows.len(), 5);
+    assert!(rows.iter().all(|r| r.len() == 12));
+    assert!(rows[1][0].ends_with('Z'));
+    assert!(rows[1][1].parse::<f64>().unwrap() < 1.0);
+    assert_eq!(rows[1][2..].join(","), "a,position,250,300,90,true,,,,");
+    assert_eq!(rows[2][2..].join(","), "a,position,,,,false,,,,");
+    assert_eq!(rows[3][2..].join(","), "a,motion,,,,,,,BottomUp,");
+    assert_eq!(rows[4][2..].join(","), "a,battery,,,,,,,,80");
+}
+
+#[test]
+fn test_csv_filter() {
+    let mut sink = CsvSink::new(vec![])
+        .kinds(&[Kind::Battery])
+        .cube("b", &[Kind::Motion])
+        .cube("c", &[]);
+    assert!(sink.write("a", &Event::Battery(80)).unwrap());
+    assert!(!sink.write("a", &Event::Collision(true)).unwrap());
+    assert!(!sink.write("b", &Event::Battery(80)).unwrap());
+    assert!(sink.write("b", &Event::Collision(true)).unwrap());
+    assert!(!sink.write("c", &Event::Battery(80)).unwrap());
+
+    let rows = rows(sink);
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[1][2..4].join(","), "a,battery");
+    assert_eq!(rows[2][2..].join(","), "b,motion,,,,,true,,,");
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "dashboard")]
+
+use toio::{dashboard::Dashboard, Event, Position};
+use tui::{backend::TestBackend, Terminal};
+
+fn screen(dashboard: &Dashboard) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    terminal.draw(|f| dashboard.draw(f)).unwrap();
+    let buffer = terminal.backend().buffer();
+    buffer
+        .content
+        .chunks(buffer.area.width as usize)
+        .map(|row| row.iter().map(|c| c.symbol.as_str()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn test_dashboard() {
+    let mut dashboard = Dashboard::new().history(2);
+    dashboard.update("cube-a", &Event::Battery(80));
+    dashboard.set_rssi("cube-a", -50);
+    dashboard.update("cube-b", &Event::Position(Some(Position::new(45, 45, 90))));
+    dashboard.update("cube-b", &Event::Button(true));
+
+    let screen = screen(&dashboard);
+    assert!(screen.contains("cube-a"));
+    assert!(screen.contains("80%"));
+    assert!(screen.contains("-50"));
+    assert!(screen.contains("(45, 45) 90°"));
+
+    // The second cube is at the top-left corner of the mat.
+    let mat_row = screen.lines().nth(6).unwrap();
+    assert!(mat_row.starts_with("│2..."));
+
+    // Only the recent events are kept, newest first.
+    assert!(screen.contains("cube-b Button(true)"));
+    assert!(!screen.contains("Battery(80)"));
+}
@@ -0,0 +1,421 @@
+use std::convert::{TryFrom, TryInto};
+use std::time::Duration;
+use toio::{
+    drive::{self, HeadingController, PatrolOptions, Ramp, ReacquireOptions},
+    proto::*,
+    sim::{Model, Simulator},
+    Position,
+};
+use tokio::time::{delay_for, timeout};
+
+mod common;
+
+fn motor_speeds(writes: Vec<(Uuid, Vec<u8>)>) -> Vec<(u8, u8)> {
+    common::motor_writes(writes)
+        .into_iter()
+        .map(|m| match m {
+            Motor::Simple(m) => (m.speed1, m.speed2),
+            m => panic!("Unexpected motor command: {:?}", m),
+        })
+        .collect()
+}
+
+#[test]
+fn test_ramp_steps() {
+    let ramp = Ramp::new(100.0, Duration::from_millis(100));
+    assert_eq!(ramp.steps((0, 0), (10, -10)), vec![]);
+    assert_eq!(ramp.steps((0, 0), (30, 0)), vec![(10, 0), (20, 0)],);
+    assert_eq!(ramp.steps((30, 30), (0, 30)), vec![(20, 30), (10, 30)]);
+    assert_eq!(
+        Ramp::new(0.0, Duration::from_millis(100)).steps((0, 0), (100, 100)),
+        vec![]
+    );
+}
+
+#[tokio::test]
+async fn test_ramp_go() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+    cube.set_ramp(Some(Ramp::new(1000.0, Duration::from_millis(10))));
+
+    cube.go(30, 30, None).await.unwrap();
+    cube.go(30, 30, None).await.unwrap();
+    let motors: Vec<_> = mock
+        .writes()
+        .into_iter()
+        .filter(|(uuid, _)| *uuid == UUID_MOTOR)
+        .map(|(_, v)| Motor::try_from(v).unwrap())
+        .collect();
+    let speeds: Vec<_> = motors
+        .iter()
+        .map(|m| match m {
+            Motor::Simple(m) => m.speed1,
+            m => panic!("Unexpected motor command: {:?}", m),
+        })
+        .collect();
+    // 0 -> 10 -> 20 -> 30, and no ramp for the same speeds.
+    assert_eq!(speeds, vec![17, 28, 39, 39]);
+}
+
+#[test]
+fn test_heading_controller() {
+    let dt = Duration::from_millis(100);
+    let mut ctrl = HeadingController::new(0.0)
+        .gains(1.0, 0.0, 0.0)
+        .cross_track(0.0);
+
+    // The error wraps around.
+    assert_eq!(ctrl.update(50, &Position::new(0, 0, 350), dt), (60, 40));
+    assert_eq!(ctrl.update(50, &Position::new(0, 0, 10), dt), (40, 60));
+
+    // The correction and the speeds are limited.
+    assert_eq!(ctrl.update(50, &Position::new(0, 0, 90), dt), (20, 80));
+    assert_eq!(ctrl.update(90, &Position::new(0, 0, 270), dt), (100, 60));
+
+    // Drifted below the line heading right, so steer counterclockwise; backward, clockwise.
+    let mut ctrl = HeadingController::new(0.0)
+        .gains(1.0, 0.0, 0.0)
+        .cross_track(1.0);
+    assert_eq!(ctrl.update(50, &Position::new(100, 100, 0), dt), (50, 50));
+    assert_eq!(ctrl.update(50, &Position::new(110, 105, 0), dt), (45, 55));
+    assert_eq!(
+        ctrl.update(-50, &Position::new(110, 105, 0), dt),
+        (-45, -55)
+    );
+
+    // A new heading starts a new line.
+    ctrl.set_heading(90.0);
+    assert_eq!(ctrl.heading(), 90.0);
+    assert_eq!(ctrl.update(50, &Position::new(110, 105, 90), dt), (50, 50));
+}
+
+#[tokio::test]
+async fn test_heading_drive() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+
+    let mut ctrl = HeadingController::new(0.0)
+        .gains(1.0, 0.0, 0.0)
+        .cross_track(0.0);
+    let notify = async {
+        delay_for(Duration::from_millis(50)).await;
+        let pos: Vec<u8> = Id::Pos(IdPos::new(100, 100, 10, 0, 0, 0))
+            .try_into()
+            .unwrap();
+        mock.notify(UUID_ID, pos);
+    };
+    let (res, _) = futures::join!(
+        ctrl.drive(&mut cube, 30, Duration::from_millis(150)),
+        notify
+    );
+    res.unwrap();
+
+    // Straight, steered counterclockwise by the position, then stopped.
+    let speeds = motor_speeds(mock.writes());
+    assert_eq!(speeds.len(), 3);
+    assert_eq!(speeds[0].0, speeds[0].1);
+    assert!(speeds[1].0 < speeds[1].1);
+    assert_eq!(speeds[2], (7, 7));
+}
+
+#[tokio::test]
+async fn test_align_to() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+
+    let pos = |angle| -> Vec<u8> {
+        Id::Pos(IdPos::new(100, 100, angle, 0, 0, 0))
+            .try_into()
+            .unwrap()
+    };
+
+    // Already aligned, wrapping around.
+    mock.notify(UUID_ID, pos(359));
+    cube.align_to(1).await.unwrap();
+    assert!(common::motor_writes(mock.writes()).is_empty());
+
+    // Pulses clockwise until the angle is reached.
+    mock.notify(UUID_ID, pos(45));
+    let settle = async {
+        delay_for(Duration::from_millis(300)).await;
+        mock.notify(UUID_ID, pos(89));
+    };
+    let (res, _) = futures::join!(cube.align_to(90), settle);
+    res.unwrap();
+    let motors = common::motor_writes(mock.writes());
+    assert!(!motors.is_empty());
+    for m in motors {
+        match m {
+            Motor::Timed(m) => {
+                assert_eq!(m.dir1, MotorDir::Forward);
+                assert_eq!(m.dir2, MotorDir::Backward);
+            }
+            m => panic!("Unexpected motor command: {:?}", m),
+        }
+    }
+
+    // Off the mat.
+    mock.notify(UUID_ID, vec![0x03]);
+    assert!(cube.align_to(90).await.is_err());
+    assert!(cube.align_to(400).await.is_err());
+}
+
+#[tokio::test]
+async fn test_goto() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+
+    let target = MotorTarget::builder(100, 200).build().unwrap();
+    let (res, targets) = futures::join!(
+        cube.goto(target.clone()),
+        common::respond_targets(&mock, TargetResValue::Ok, 1)
+    );
+    res.unwrap();
+    assert_eq!(targets, vec![(100, 200)]);
+
+    let (res, _) = futures::join!(
+        cube.goto(target),
+        common::respond_targets(&mock, TargetResValue::IdMissed, 1)
+    );
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn test_patrol() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+
+    let waypoints = [(100, 100), (200, 200)];
+    let once = PatrolOptions {
+        looping: false,
+        ..PatrolOptions::default()
+    };
+    let (res, targets) = futures::join!(
+        cube.patrol(&waypoints, once),
+        common::respond_targets(&mock, TargetResValue::Ok, 2)
+    );
+    res.unwrap();
+    assert_eq!(targets, waypoints);
+
+    // Cycles until cancelled.
+    let patrol = timeout(
+        Duration::from_millis(500),
+        cube.patrol(&waypoints, PatrolOptions::default()),
+    );
+    let (res, targets) = futures::join!(
+        patrol,
+        common::respond_targets(&mock, TargetResValue::Ok, 7)
+    );
+    assert!(res.is_err());
+    assert_eq!(targets[4..6], waypoints[..]);
+    assert_eq!(targets[6], waypoints[0]);
+
+    assert!(cube.patrol(&[], PatrolOptions::default()).await.is_err());
+    let fast = PatrolOptions {
+        speed: 200,
+        ..PatrolOptions::default()
+    };
+    assert!(cube.patrol(&waypoints, fast).await.is_err());
+}
+
+#[tokio::test]
+async fn test_reacquire_position() {
+    tokio::time::pause();
+
+    let interval = Duration::from_millis(20);
+    // Off the mat, facing away from it.
+    let (mut cube, sim) = Simulator::new(Model::new(35.0, 250.0, 180.0))
+        .interval(interval)
+        .cube();
+    cube.connect().await.unwrap();
+    assert_eq!(sim.position(), None);
+
+    let options = ReacquireOptions {
+        speed: 30,
+        spread: Duration::from_secs(3),
+        timeout: Duration::from_secs(10),
+    };
+    let clock = async {
+        for _ in 0..600 {
+            tokio::time::advance(interval).await;
+        }
+    };
+    let (res, _) = futures::join!(cube.reacquire_position(options.clone()), clock);
+    let pos = res.unwrap();
+    assert!(pos.x >= 45, "{:?}", pos);
+    assert_eq!(sim.model().wheels(), (0.0, 0.0));
+
+    // Already on the mat.
+    let writes = sim.mock().writes().len();
+    cube.reacquire_position(options).await.unwrap();
+    assert_eq!(sim.mock().writes().len(), writes);
+}
+
+/// Runs the target control on the model, and gets the time until the response.
+fn simulate(from: (f32, f32, f32), req: Motor) -> Duration {
+    let mut model = Model::new(from.0, from.1, from.2);
+    model.write(&req);
+    let dt = Duration::from_millis(10);
+    for i in 1..2000 {
+        if !model.step(dt).is_empty() {
+            return dt * i;
+        }
+    }
+    panic!("Not arrived");
+}
+
+#[test]
+fn test_travel_time() {
+    let close = |estimate: Duration, actual: Duration| {
+        let ratio = estimate.as_secs_f32() / actual.as_secs_f32();
+        assert!((0.8..1.25).contains(&ratio), "{:?} {:?}", estimate, actual);
+    };
+
+    for (move_type, angle) in &[
+        (MoveType::Straight, 180),
+        (MoveType::Straight, 45),
+        (MoveType::Curve, 90),
+        (MoveType::ForwardOnly, 0),
+    ] {
+        let target = MotorTarget::builder(350, 150)
+            .move_type(*move_type)
+            .max_speed(60)
+            .angle(AngleType::Absolute, 270)
+            .build()
+            .unwrap();
+        let from = Position::new(100, 300, *angle);
+        close(
+            drive::travel_time(&from, &target),
+            simulate((100.0, 300.0, *angle as f32), Motor::Target(target)),
+        );
+    }
+
+    let path = MotorMultiTarget::builder()
+        .move_type(MoveType::Straight)
+        .max_speed(80)
+        .target(300, 100)
+        .target(300, 300)
+        .target_with_angle(100, 300, AngleType::RelativePositive, 90)
+        .build()
+        .unwrap();
+    let from = Position::new(100, 100, 0);
+    let estimate = drive::path_time(&from, &path);
+    close(
+        estimate,
+        simulate((100.0, 100.0, 0.0), Motor::MultiTarget(path)),
+    );
+    assert!(drive::firmware_timeout(estimate) as f32 > estimate.as_secs_f32() * 2.0);
+
+    // Keeping the position only turns.
+    let target = MotorTarget::builder(KEEP_COORDINATE, KEEP_COORDINATE)
+        .angle(AngleType::RelativeNegative, 720)
+        .build()
+        .unwrap();
+    let turn = drive::travel_time(&Position::new(200, 200, 0), &target);
+    let half = MotorTarget::builder(KEEP_COORDINATE, KEEP_COORDINATE)
+        .angle(AngleType::RelativeNegative, 360)
+        .build()
+        .unwrap();
+    close(
+        turn,
+        drive::travel_time(&Position::new(200, 200, 0), &half) * 2,
+    );
+}
+
+#[tokio::test]
+async fn test_goto_and_wait() {
+    use futures::prelude::*;
+
+    let (mut cube, sim) = Simulator::new(Model::new(100.0, 250.0, 0.0))
+        .interval(Duration::from_millis(20))
+        .cube();
+    cube.connect().await.unwrap();
+
+    let target = MotorTarget::builder(300, 250)
+        .max_speed(80)
+        .build()
+        .unwrap();
+    let nav = cube.goto_and_wait(target).await.unwrap();
+    let progress: Vec<_> = timeout(Duration::from_secs(5), nav.collect())
+        .await
+        .unwrap();
+    assert!(progress.len() > 10, "{:?}", progress);
+    assert!(progress[0].remaining > 150.0, "{:?}", progress[0]);
+    assert!(progress.last().unwrap().remaining < 10.0);
+    let pos = sim.position().unwrap();
+    assert!((pos.x as i32 - 300).abs() <= 5, "{:?}", pos);
+
+    // The result comes after the progress.
+    let target = MotorTarget::builder(100, 250).build().unwrap();
+    let nav = cube.goto_and_wait(target).await.unwrap();
+    timeout(Duration::from_secs(10), nav.finish())
+        .await
+        .unwrap()
+        .unwrap();
+
+    sim.apply(&toio::sim::Action::Lift);
+    let target = MotorTarget::builder(300, 250).build().unwrap();
+    let nav = cube.goto_and_wait(target).await.unwrap();
+    let res = timeout(Duration::from_secs(5), nav.finish()).await.unwrap();
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn test_superseded() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+
+    let first = cube
+        .goto_and_wait(MotorTarget::builder(100, 100).build().unwrap())
+        .await
+        .unwrap();
+    let second = cube
+        .goto_and_wait(MotorTarget::builder(200, 200).build().unwrap())
+        .await
+        .unwrap();
+
+    // Both have the id 0, and the firmware answers the first one as overwritten.
+    for res in &[TargetResValue::OtherWrite, TargetResValue::Ok] {
+        let res: Vec<u8> = Motor::TargetRes(MotorTargetRes::new(0, *res))
+            .try_into()
+            .unwrap();
+        mock.notify(UUID_MOTOR, res);
+    }
+    let err = first.finish().await.unwrap_err();
+    assert!(err.downcast_ref::<drive::Superseded>().is_some(), "{}", err);
+    second.finish().await.unwrap();
+
+    // Overwritten by something else than a target request.
+    let nav = cube
+        .goto_and_wait(MotorTarget::builder(100, 100).build().unwrap())
+        .await
+        .unwrap();
+    let res: Vec<u8> = Motor::TargetRes(MotorTargetRes::new(0, TargetResValue::OtherWrite))
+        .try_into()
+        .unwrap();
+    mock.notify(UUID_MOTOR, res);
+    let err = timeout(Duration::from_secs(1), nav.finish())
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert!(err.downcast_ref::<drive::Superseded>().is_none(), "{}", err);
+}
+
+#[tokio::test]
+async fn test_emergency_stop_navigation() {
+    let (mut cube, sim) = Simulator::new(Model::new(100.0, 250.0, 0.0))
+        .interval(Duration::from_millis(20))
+        .cube();
+    cube.connect().await.unwrap();
+
+    let target = MotorTarget::builder(400, 250).build().unwrap();
+    let nav = cube.goto_and_wait(target).await.unwrap();
+    delay_for(Duration::from_millis(300)).await;
+    cube.emergency_stop().await.unwrap();
+
+    let res = timeout(Duration::from_secs(1), nav.finish()).await.unwrap();
+    assert!(res.is_err());
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(sim.model().wheels(), (0.0, 0.0));
+    assert!(sim.position().unwrap().x < 300);
+}
@@ -0,0 +1,716 @@
+use futures::prelude::*;
+use std::convert::TryInto;
+use std::time::Duration;
+use toio::{
+    ble::mock, proto::*, Characteristic, Cube, Event, EventFilter, Options, Position, PositionRate,
+    Posture, TrackingQuality, Vector3,
+};
+use tokio::time::{delay_for, timeout};
+
+/// The characteristics whose notifications follow the event filters.
+const FILTERED: [Uuid; 4] = [UUID_ID, UUID_MOTION, UUID_BUTTON, UUID_BATTERY];
+
+#[test]
+fn test_event_filter() {
+    let f = EventFilter::new().collision().position();
+    assert!(f.matches(&Event::Collision(true)));
+    assert!(f.matches(&Event::Position(None)));
+    assert!(!f.matches(&Event::Slope(true)));
+    let detect = MotionDetect::new(true, false, true, Posture::HeadUp);
+    assert!(!f.matches(&Event::Motion(detect.clone())));
+    assert!(EventFilter::new().motion().matches(&Event::Motion(detect)));
+    assert_eq!(EventFilter::new().motion().uuids(), vec![UUID_MOTION]);
+    assert_eq!(EventFilter::new().proximity().uuids(), vec![UUID_ID]);
+    assert!(!f.matches(&Event::Battery(10)));
+    assert!(!f.matches(&Event::BatteryLow(10)));
+    assert!(f.matches(&Event::Lagged(3)));
+    assert!(f.matches(&Event::Unresponsive));
+    assert_eq!(f.uuids(), vec![UUID_MOTION, UUID_ID]);
+
+    assert!(EventFilter::new().battery().matches(&Event::BatteryLow(10)));
+    assert!(EventFilter::new().uuids().is_empty());
+    assert_eq!(EventFilter::all().uuids().len(), 5);
+}
+
+#[tokio::test]
+async fn test_event_filter_notifications() {
+    use std::sync::{Arc, Mutex};
+
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+    let pressed = Arc::new(Mutex::new(vec![]));
+    let p = pressed.clone();
+    let _button = cube.on_button(move |v| p.lock().unwrap().push(v));
+
+    // The characteristics tracked by the cube itself stay enabled.
+    let mut collisions = cube
+        .events_filtered(EventFilter::new().collision())
+        .await
+        .unwrap();
+    assert!(FILTERED.iter().all(|uuid| handle.is_notifying(uuid)));
+    let motion: Vec<u8> = Motion::Detect(MotionDetect::new(true, true, false, Posture::HeadUp))
+        .try_into()
+        .unwrap();
+    handle.notify(UUID_MOTION, motion);
+    assert_eq!(collisions.next().await, Some(Event::Collision(true)));
+
+    // Dropping the stream leaves the position tracking and the callbacks working.
+    drop(collisions);
+    delay_for(Duration::from_millis(10)).await;
+    assert!(FILTERED.iter().all(|uuid| handle.is_notifying(uuid)));
+    assert!(handle.notifications().is_empty());
+
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 200, 90, 0, 0, 0))
+        .try_into()
+        .unwrap();
+    handle.notify(UUID_ID, pos);
+    handle.notify(UUID_BUTTON, vec![0x01, 0x80]);
+    delay_for(Duration::from_millis(10)).await;
+    assert_eq!(
+        cube.position().await.unwrap(),
+        Some(Position::new(100, 200, 90))
+    );
+    assert_eq!(cube.tracking_quality().ratio, 1.0);
+    assert_eq!(*pressed.lock().unwrap(), vec![true]);
+}
+
+#[tokio::test]
+async fn test_dedup_events() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+    let filter = EventFilter::new().button().battery();
+    let raw = cube.events_filtered(filter.clone()).await.unwrap();
+    cube.dedup_events(true);
+    let events = cube.events_filtered(filter).await.unwrap();
+
+    let button = |pressed| vec![0x01, if pressed { 0x80 } else { 0x00 }];
+    handle.notify(UUID_BUTTON, button(true));
+    handle.notify(UUID_BUTTON, button(true));
+    handle.notify(UUID_BATTERY, vec![50]);
+    handle.notify(UUID_BUTTON, button(false));
+    handle.notify(UUID_BATTERY, vec![50]);
+    handle.notify(UUID_BATTERY, vec![40]);
+    handle.notify(UUID_BUTTON, button(true));
+
+    // Skips the same value as the previous one of the same kind.
+    assert_eq!(
+        events.take(5).collect::<Vec<_>>().await,
+        vec![
+            Event::Button(true),
+            Event::Battery(50),
+            Event::Button(false),
+            Event::Battery(40),
+            Event::Button(true),
+        ]
+    );
+    // The streams created beforehand aren't affected.
+    assert_eq!(raw.take(7).count().await, 7);
+}
+
+#[tokio::test]
+async fn test_dedup_events_lagged() {
+    let (dev, handle) = mock::mock("cube");
+    let opts = Options {
+        capacity: 2,
+        ..Options::default()
+    };
+    let mut cube = Cube::from_peripheral(Box::new(dev), opts);
+    cube.connect().await.unwrap();
+    cube.dedup_events(true);
+    let mut events = cube
+        .events_filtered(EventFilter::new().battery())
+        .await
+        .unwrap();
+    tokio::time::pause();
+
+    handle.notify(UUID_BATTERY, vec![40]);
+    assert_eq!(events.next().await, Some(Event::Battery(40)));
+
+    // Forgets the last values on lag, as they may have changed meanwhile.
+    for _ in 0..5 {
+        handle.notify(UUID_BATTERY, vec![40]);
+    }
+    delay_for(Duration::from_millis(1)).await;
+    assert_eq!(events.next().await, Some(Event::Lagged(3)));
+    assert_eq!(events.next().await, Some(Event::Battery(40)));
+    assert!(timeout(Duration::from_millis(10), events.next())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_until() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let motion = |collision| -> Vec<u8> {
+        Motion::Detect(MotionDetect::new(true, collision, false, Posture::HeadUp))
+            .try_into()
+            .unwrap()
+    };
+    let mut until = tokio::spawn(async move { (cube.until_collision().await, cube) });
+    delay_for(Duration::from_millis(10)).await;
+    handle.notify(UUID_MOTION, motion(false));
+    handle.notify(UUID_BUTTON, vec![0x01, 0x80]);
+    delay_for(Duration::from_millis(10)).await;
+    assert!(futures::poll!(&mut until).is_pending());
+    handle.notify(UUID_MOTION, motion(true));
+    let (res, mut cube) = timeout(Duration::from_secs(1), until)
+        .await
+        .unwrap()
+        .unwrap();
+    res.unwrap();
+
+    let mut until = tokio::spawn(async move { (cube.until_button_pressed().await, cube) });
+    delay_for(Duration::from_millis(10)).await;
+    handle.notify(UUID_BUTTON, vec![0x01, 0x00]);
+    handle.notify(UUID_MOTION, motion(true));
+    delay_for(Duration::from_millis(10)).await;
+    assert!(futures::poll!(&mut until).is_pending());
+    handle.notify(UUID_BUTTON, vec![0x01, 0x80]);
+    let (res, mut cube) = timeout(Duration::from_secs(1), until)
+        .await
+        .unwrap()
+        .unwrap();
+    res.unwrap();
+
+    let mut until = tokio::spawn(async move { (cube.until_on_mat().await, cube) });
+    delay_for(Duration::from_millis(10)).await;
+    handle.notify(UUID_ID, Id::PosMissed.try_into().unwrap());
+    delay_for(Duration::from_millis(10)).await;
+    assert!(futures::poll!(&mut until).is_pending());
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 200, 90, 0, 0, 0))
+        .try_into()
+        .unwrap();
+    handle.notify(UUID_ID, pos);
+    let (res, _) = timeout(Duration::from_secs(1), until)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.unwrap(), Position::new(100, 200, 90));
+}
+
+#[tokio::test]
+async fn test_set_notifications_override() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    // The filters don't enable the characteristic disabled by the user.
+    cube.set_notifications(Characteristic::Id, false)
+        .await
+        .unwrap();
+    let mut positions = cube
+        .events_filtered(EventFilter::new().position())
+        .await
+        .unwrap();
+    assert!(!handle.is_notifying(&UUID_ID));
+
+    cube.set_notifications(Characteristic::Id, true)
+        .await
+        .unwrap();
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 200, 90, 0, 0, 0))
+        .try_into()
+        .unwrap();
+    handle.notify(UUID_ID, pos);
+    assert_eq!(
+        positions.next().await,
+        Some(Event::Position(Some(Position::new(100, 200, 90))))
+    );
+
+    // Kept when the streams are dropped, and applied again on reconnection.
+    cube.set_notifications(Characteristic::Motion, false)
+        .await
+        .unwrap();
+    drop(positions);
+    delay_for(Duration::from_millis(10)).await;
+    let _all = cube.events().await.unwrap();
+    cube.connect().await.unwrap();
+    assert!(!handle.is_notifying(&UUID_MOTION));
+    assert!(handle.is_notifying(&UUID_ID));
+    assert_eq!(
+        handle.notifications(),
+        vec![
+            (UUID_ID, false),
+            (UUID_ID, true),
+            (UUID_MOTION, false),
+            (UUID_MOTION, false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_until_notifications() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let until = tokio::spawn(async move { (cube.until_button_pressed().await, cube) });
+    delay_for(Duration::from_millis(10)).await;
+    handle.notify(UUID_BUTTON, vec![0x01, 0x80]);
+    let (res, mut cube) = timeout(Duration::from_secs(1), until)
+        .await
+        .unwrap()
+        .unwrap();
+    res.unwrap();
+    assert!(handle.notifications().is_empty());
+
+    let mut events = cube.events().await.unwrap();
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 200, 90, 0, 0, 0))
+        .try_into()
+        .unwrap();
+    handle.notify(UUID_ID, pos);
+    assert_eq!(
+        events.next().await,
+        Some(Event::Position(Some(Position::new(100, 200, 90))))
+    );
+}
+
+#[tokio::test]
+async fn test_raw_values() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    // Delivers only the bytes of the characteristic, even if it's not in the protocol.
+    let unknown = Uuid::new([0xff; 16]);
+    let mut battery = cube.raw_values(&UUID_BATTERY).await.unwrap();
+    let mut custom = cube.raw_values(&unknown).await.unwrap();
+    handle.notify(UUID_BUTTON, vec![0x01, 0x80]);
+    handle.notify(unknown, vec![0xde, 0xad]);
+    handle.notify(UUID_BATTERY, vec![90]);
+    handle.notify(unknown, vec![0xbe, 0xef]);
+
+    assert_eq!(&battery.next().await.unwrap()[..], &[90]);
+    assert_eq!(&custom.next().await.unwrap()[..], &[0xde, 0xad]);
+    assert_eq!(&custom.next().await.unwrap()[..], &[0xbe, 0xef]);
+    assert!(timeout(Duration::from_millis(50), battery.next())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_postures() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let motion = |posture| -> Vec<u8> {
+        Motion::Detect(MotionDetect::new(true, false, false, posture))
+            .try_into()
+            .unwrap()
+    };
+    let mut postures = cube.postures().await.unwrap();
+    for posture in &[
+        Posture::HeadUp,
+        Posture::HeadUp,
+        Posture::BottomUp,
+        Posture::BottomUp,
+        Posture::HeadUp,
+    ] {
+        handle.notify(UUID_MOTION, motion(*posture));
+    }
+    let changes: Vec<_> = postures.by_ref().take(3).collect().await;
+    assert_eq!(
+        changes,
+        vec![Posture::HeadUp, Posture::BottomUp, Posture::HeadUp]
+    );
+    assert_eq!(cube.posture().await.unwrap(), Posture::HeadUp);
+
+    handle.notify(UUID_MOTION, motion(Posture::FrontUp));
+    assert_eq!(cube.posture_now().await.unwrap(), Posture::FrontUp);
+    assert_eq!(postures.next().await, Some(Posture::FrontUp));
+    assert_eq!(cube.posture().await.unwrap(), Posture::FrontUp);
+}
+
+#[tokio::test]
+async fn test_motion() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let mut events = cube
+        .events_filtered(EventFilter::new().motion())
+        .await
+        .unwrap();
+    let detect = MotionDetect::new(false, true, true, Posture::RightSideUp);
+    let value: Vec<u8> = Motion::Detect(detect.clone()).try_into().unwrap();
+    handle.notify(UUID_MOTION, value);
+    assert_eq!(events.next().await, Some(Event::Motion(detect)));
+}
+
+#[tokio::test]
+async fn test_poll_motion() {
+    let version = |version: &str| -> Vec<u8> {
+        Config::VersionRes(ConfigVersionRes::new(version.into()))
+            .try_into()
+            .unwrap()
+    };
+    let detect = MotionDetect::new(true, false, false, Posture::HeadUp);
+    let motion: Vec<u8> = Motion::Detect(detect.clone()).try_into().unwrap();
+    let request: Vec<u8> = Motion::RequestDetect.try_into().unwrap();
+
+    let (dev, handle) = mock::mock("cube");
+    handle.notify(UUID_CONFIG, version("2.1.0"));
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+    cube.poll_motion(Duration::from_millis(20)).await.unwrap();
+    delay_for(Duration::from_millis(70)).await;
+    let requests = handle
+        .writes()
+        .into_iter()
+        .filter(|w| *w == (UUID_MOTION, request.clone()))
+        .count();
+    assert!(requests >= 2, "{}", requests);
+
+    // Old firmware reads the characteristic instead.
+    let (dev, handle) = mock::mock("cube");
+    handle.notify(UUID_CONFIG, version("2.0.0"));
+    handle.notify(UUID_MOTION, motion);
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+    let mut events = cube
+        .events_filtered(EventFilter::new().motion())
+        .await
+        .unwrap();
+    cube.poll_motion(Duration::from_millis(20)).await.unwrap();
+    let event = timeout(Duration::from_secs(1), events.next())
+        .await
+        .unwrap();
+    assert_eq!(event, Some(Event::Motion(detect)));
+    assert!(handle.reads().contains(&UUID_MOTION));
+    assert!(!handle.writes().iter().any(|(uuid, _)| *uuid == UUID_MOTION));
+}
+
+#[tokio::test]
+async fn test_magnet_mode() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let ack = |res| -> Vec<u8> { Config::MagnetRes(ConfigRes::new(res)).try_into().unwrap() };
+    let reply = |res| {
+        let handle = handle.clone();
+        async move {
+            delay_for(Duration::from_millis(50)).await;
+            handle.notify(UUID_CONFIG, ack(res));
+        }
+    };
+
+    let (res, _) = tokio::join!(
+        cube.set_magnet_mode(MagnetMode::Force),
+        reply(ConfigResValue::Ok)
+    );
+    res.unwrap();
+    let config: Vec<u8> = Config::Magnet(ConfigMagnet::new(
+        MagnetMode::Force,
+        1,
+        NotifyCondition::OnChange,
+    ))
+    .try_into()
+    .unwrap();
+    assert_eq!(config, vec![0x1b, 0x00, 0x02, 0x01, 0x01]);
+    assert_eq!(handle.writes(), vec![(UUID_CONFIG, config)]);
+
+    let (res, _) = tokio::join!(
+        cube.set_magnet_mode(MagnetMode::Disabled),
+        reply(ConfigResValue::Unsupported)
+    );
+    let e = res.unwrap_err();
+    assert!(format!("{:#}", e).contains("Unsupported"), "{:#}", e);
+}
+
+#[tokio::test]
+async fn test_magnetic_force() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let mut events = cube
+        .events_filtered(EventFilter::new().magnetic_force())
+        .await
+        .unwrap();
+    handle.notify(UUID_MOTION, vec![0x02, 0x00, 0x0a, 0x03, 0xfc, 0x00]);
+    let force = Vector3::new(3, -4, 0);
+    assert_eq!(events.next().await, Some(Event::MagneticForce(force)));
+    assert_eq!(force.magnitude(), 5.0);
+    assert!(EventFilter::all().matches(&Event::MagneticForce(force)));
+}
+
+#[tokio::test]
+async fn test_attitude_notify() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let ack: Vec<u8> = Config::AttitudeRes(ConfigRes::new(ConfigResValue::Ok))
+        .try_into()
+        .unwrap();
+    let reply = || {
+        let handle = handle.clone();
+        let ack = ack.clone();
+        async move {
+            delay_for(Duration::from_millis(50)).await;
+            handle.notify(UUID_CONFIG, ack);
+        }
+    };
+
+    let (res, _) = tokio::join!(
+        cube.set_attitude_notify(
+            AttitudeFormat::Quaternion,
+            Some(Duration::from_millis(25)),
+            NotifyCondition::Always
+        ),
+        reply()
+    );
+    res.unwrap();
+    let (res, _) = tokio::join!(
+        cube.set_attitude_notify(AttitudeFormat::Euler, None, NotifyCondition::OnChange),
+        reply()
+    );
+    res.unwrap();
+    assert_eq!(
+        handle.writes(),
+        vec![
+            (UUID_CONFIG, vec![0x1d, 0x00, 0x02, 0x02, 0x00]),
+            (UUID_CONFIG, vec![0x1d, 0x00, 0x01, 0x00, 0x01]),
+        ]
+    );
+
+    assert!(cube
+        .set_attitude_notify(
+            AttitudeFormat::Euler,
+            Some(Duration::from_secs(3)),
+            NotifyCondition::Always
+        )
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_position_rate() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let ack: Vec<u8> = Config::IdNotifyRes(ConfigRes::new(ConfigResValue::Ok))
+        .try_into()
+        .unwrap();
+    let reply = || {
+        let handle = handle.clone();
+        let ack = ack.clone();
+        async move {
+            delay_for(Duration::from_millis(50)).await;
+            handle.notify(UUID_CONFIG, ack);
+        }
+    };
+
+    for rate in &[
+        PositionRate::Hz(5),
+        PositionRate::Hz(1000),
+        PositionRate::Max,
+        PositionRate::OnChange,
+    ] {
+        let (res, _) = tokio::join!(cube.set_position_rate(*rate), reply());
+        res.unwrap();
+    }
+    assert_eq!(
+        handle.writes(),
+        vec![
+            (UUID_CONFIG, vec![0x18, 0x00, 20, 0x00]),
+            (UUID_CONFIG, vec![0x18, 0x00, 1, 0x00]),
+            (UUID_CONFIG, vec![0x18, 0x00, 0, 0x00]),
+            (UUID_CONFIG, vec![0x18, 0x00, 0, 0x01]),
+        ]
+    );
+    assert!(cube.set_position_rate(PositionRate::Hz(0)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_position_missed_delay() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let reply = |res| {
+        let handle = handle.clone();
+        async move {
+            delay_for(Duration::from_millis(50)).await;
+            let ack: Vec<u8> = Config::IdMissedRes(ConfigRes::new(res)).try_into().unwrap();
+            handle.notify(UUID_CONFIG, ack);
+        }
+    };
+
+    let (res, _) = tokio::join!(
+        cube.set_position_missed_delay(Duration::from_millis(200)),
+        reply(ConfigResValue::Ok)
+    );
+    res.unwrap();
+    assert_eq!(handle.writes(), vec![(UUID_CONFIG, vec![0x19, 0x00, 20])]);
+
+    let (res, _) = tokio::join!(
+        cube.set_position_missed_delay(Duration::from_millis(0)),
+        reply(ConfigResValue::Unsupported)
+    );
+    assert!(res.is_err());
+    assert!(cube
+        .set_position_missed_delay(Duration::from_secs(3))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_notification_gap() {
+    let (dev, handle) = mock::mock("cube");
+    let opts = Options {
+        detect_gaps: true,
+        ..Options::default()
+    };
+    let mut cube = Cube::from_peripheral(Box::new(dev), opts);
+    cube.connect().await.unwrap();
+    // Keeps the ID characteristic notifying, while watching only the gaps.
+    let mut events = cube
+        .events_filtered(EventFilter::new().position())
+        .await
+        .unwrap()
+        .filter(|event| future::ready(!matches!(event, Event::Position(_))));
+
+    let pos = |x| -> Vec<u8> {
+        Id::Pos(IdPos::new(x, 100, 0, x, 100, 0))
+            .try_into()
+            .unwrap()
+    };
+    for x in 0..8 {
+        handle.notify(UUID_ID, pos(100 + x));
+        delay_for(Duration::from_millis(20)).await;
+    }
+    // Stays still without notifications.
+    handle.notify(UUID_ID, pos(107));
+    delay_for(Duration::from_millis(100)).await;
+    handle.notify(UUID_ID, pos(107));
+    assert!(timeout(Duration::from_millis(50), events.next())
+        .await
+        .is_err());
+
+    // Moves without notifications.
+    delay_for(Duration::from_millis(100)).await;
+    handle.notify(UUID_ID, pos(120));
+    let event = timeout(Duration::from_secs(1), events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    match event {
+        Event::NotificationGap {
+            characteristic,
+            estimated_missed,
+        } => {
+            assert_eq!(characteristic, Characteristic::Id);
+            assert!((3..=10).contains(&estimated_missed), "{}", estimated_missed);
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn test_tracking_quality() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+    assert_eq!(cube.tracking_quality(), TrackingQuality::default());
+
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 100, 0, 100, 100, 0))
+        .try_into()
+        .unwrap();
+    for _ in 0..9 {
+        handle.notify(UUID_ID, pos.clone());
+    }
+    handle.notify(UUID_ID, Id::PosMissed.try_into().unwrap());
+    delay_for(Duration::from_millis(50)).await;
+
+    let quality = cube.tracking_quality();
+    assert!((quality.ratio - 0.9).abs() < 1e-9, "{:?}", quality);
+    assert!((quality.rate - 9.0).abs() < 1e-9, "{:?}", quality);
+
+    // The notifications get old.
+    tokio::time::pause();
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert_eq!(cube.tracking_quality(), TrackingQuality::default());
+}
+
+#[tokio::test]
+async fn test_callbacks() {
+    use std::sync::{Arc, Mutex};
+
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+
+    // Registered before connecting.
+    let pressed = Arc::new(Mutex::new(vec![]));
+    let p = pressed.clone();
+    let button = cube.on_button(move |v| p.lock().unwrap().push(v));
+    cube.connect().await.unwrap();
+
+    let seen = Arc::new(Mutex::new(vec![]));
+    let s = seen.clone();
+    let any = cube.on_event(EventFilter::new().button().battery(), move |e| {
+        s.lock().unwrap().push(e.clone())
+    });
+
+    let button_msg = |state| -> Vec<u8> { Button::Func(state).try_into().unwrap() };
+    handle.notify(UUID_BUTTON, button_msg(ButtonState::Pressed));
+    handle.notify(UUID_BATTERY, vec![50]);
+    handle.notify(UUID_BUTTON, button_msg(ButtonState::Released));
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(*pressed.lock().unwrap(), vec![true, false]);
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            Event::Button(true),
+            Event::Battery(50),
+            Event::Button(false)
+        ]
+    );
+
+    button.unregister();
+    handle.notify(UUID_BUTTON, button_msg(ButtonState::Pressed));
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(pressed.lock().unwrap().len(), 2);
+    assert_eq!(seen.lock().unwrap().len(), 4);
+
+    // Kept over reconnection.
+    cube.connect().await.unwrap();
+    handle.notify(UUID_BATTERY, vec![40]);
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(seen.lock().unwrap().last(), Some(&Event::Battery(40)));
+    any.unregister();
+}
+
+#[tokio::test]
+async fn test_subscriptions() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let subs = cube.subscriptions().await.unwrap();
+    let buttons = tokio::spawn(subs.buttons.take(2).collect::<Vec<_>>());
+    let battery = tokio::spawn(subs.battery.take(1).collect::<Vec<_>>());
+    drop(subs.positions);
+    delay_for(Duration::from_millis(10)).await;
+
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 200, 90, 0, 0, 0))
+        .try_into()
+        .unwrap();
+    handle.notify(UUID_ID, pos);
+    for state in &[ButtonState::Pressed, ButtonState::Released] {
+        handle.notify(UUID_BUTTON, Button::Func(*state).try_into().unwrap());
+    }
+    handle.notify(UUID_BATTERY, vec![70]);
+
+    let buttons = timeout(Duration::from_secs(1), buttons).await.unwrap();
+    assert_eq!(buttons.unwrap(), vec![true, false]);
+    let battery = timeout(Duration::from_secs(1), battery).await.unwrap();
+    assert_eq!(battery.unwrap(), vec![70]);
+}
@@ -0,0 +1,104 @@
+use futures::prelude::*;
+use std::time::Duration;
+use toio::{
+    fake::{Call, FakeCube},
+    proto::*,
+    sim::{Model, Simulator},
+    CubeLike, Event, EventStream, Position,
+};
+use tokio::time::timeout;
+
+/// Game logic under test: goes to the center when the button is pressed,
+/// and turns the light red on collision.
+///
+/// Takes the events subscribed by the caller, so that no event emitted after spawning is missed.
+async fn play<C: CubeLike>(cube: &mut C, mut events: EventStream) -> anyhow::Result<()> {
+    while let Some(event) = events.next().await {
+        match event {
+            Event::Button(true) => {
+                let target = MotorTarget::builder(250, 250).build()?;
+                cube.goto(target).await?;
+                return Ok(());
+            }
+            Event::Collision(true) => cube.light_on(255, 0, 0, None).await?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fake() {
+    let mut cube = FakeCube::new("fake");
+    let handle = cube.clone();
+    assert_eq!(cube.id(), "fake");
+    assert_eq!(cube.battery().await.unwrap(), 100);
+
+    handle.emit(Event::Position(Some(Position::new(100, 100, 90))));
+    let events = cube.events().await.unwrap();
+    let game = tokio::spawn(async move { play(&mut cube, events).await });
+
+    handle.emit(Event::Collision(true));
+    handle.emit(Event::Button(true));
+    timeout(Duration::from_secs(1), game)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let calls = handle.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(
+        calls[0],
+        Call::LightOn {
+            red: 255,
+            green: 0,
+            blue: 0,
+            duration: None
+        }
+    );
+    match &calls[1] {
+        Call::Goto(t) => assert_eq!((t.x, t.y), (250, 250)),
+        call => panic!("{:?}", call),
+    }
+
+    let mut cube = handle.clone();
+    assert_eq!(
+        cube.position().await.unwrap(),
+        Some(Position::new(250, 250, 90))
+    );
+    assert!(cube.go(101, 0, None).await.is_err());
+    cube.go(30, -30, None).await.unwrap();
+    assert_eq!(handle.speeds(), Some((30, -30)));
+    cube.stop().await.unwrap();
+    assert_eq!(handle.speeds(), Some((0, 0)));
+
+    handle.clear();
+    handle.fail(Some("disconnected"));
+    assert!(cube.light_off().await.is_err());
+    assert!(cube.battery().await.is_err());
+    handle.fail(None);
+    cube.light_off().await.unwrap();
+    assert_eq!(handle.calls(), vec![Call::LightOff]);
+}
+
+#[tokio::test]
+async fn test_cube_like() {
+    let (mut cube, sim) = Simulator::new(Model::new(100.0, 100.0, 0.0))
+        .interval(Duration::from_millis(20))
+        .cube();
+    cube.connect().await.unwrap();
+
+    let events = cube.events().await.unwrap();
+    let game = tokio::spawn(async move { play(&mut cube, events).await });
+    sim.apply(&toio::sim::Action::Button { pressed: true });
+    timeout(Duration::from_secs(5), game)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let pos = sim.position().unwrap();
+    assert!((pos.x as i32 - 250).abs() <= 5, "{:?}", pos);
+    assert!((pos.y as i32 - 250).abs() <= 5, "{:?}", pos);
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "ffi")]
+
+use std::ffi::CStr;
+use toio::{ffi::*, proto::*};
+
+mod common;
+
+#[test]
+fn test_ffi() {
+    let (cube, mock) = common::cube("cube");
+    let cube = cube_into_raw(cube);
+
+    unsafe {
+        assert_eq!(CStr::from_ptr(toio_cube_id(cube)).to_str().unwrap(), "cube");
+
+        // Events are not available before connecting.
+        let mut event = std::mem::zeroed::<ToioEvent>();
+        assert_eq!(toio_poll_event(cube, &mut event, 0), -1);
+        let err = CStr::from_ptr(toio_last_error()).to_str().unwrap();
+        assert!(err.contains("not connected"));
+
+        assert_eq!(toio_connect(cube), 0);
+        assert_eq!(toio_go(cube, 50, -50, 100), 0);
+        assert_eq!(toio_light_on(cube, 255, 0, 0, 0), 0);
+        assert_eq!(toio_play_preset(cube, 42), -1);
+        assert!(mock.writes().iter().any(|(uuid, _)| *uuid == UUID_MOTOR));
+        assert!(mock.writes().iter().any(|(uuid, _)| *uuid == UUID_LIGHT));
+
+        assert_eq!(toio_poll_event(cube, &mut event, 0), 0);
+        mock.notify(UUID_BATTERY, vec![80]);
+        assert_eq!(toio_poll_event(cube, &mut event, 1000), 1);
+        assert_eq!(event.kind, ToioEventKind::Battery);
+        assert_eq!(event.value, 80);
+
+        mock.notify(UUID_ID, vec![0x01, 10, 0, 20, 0, 90, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(toio_poll_event(cube, &mut event, 1000), 1);
+        assert_eq!(event.kind, ToioEventKind::Position);
+        assert_eq!((event.x, event.y, event.angle), (10, 20, 90));
+
+        toio_cube_free(cube);
+    }
+}
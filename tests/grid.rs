@@ -0,0 +1,76 @@
+use std::convert::TryInto;
+use toio::{grid::Grid, proto::*};
+
+mod common;
+
+#[test]
+fn test_grid() {
+    let mut grid = Grid::new((100, 100), 50, 3, 4);
+
+    assert_eq!(grid.center((0, 0)), Some((125, 125)));
+    assert_eq!(grid.center((2, 3)), Some((275, 225)));
+    assert_eq!(grid.center((3, 0)), None);
+    assert_eq!(grid.cell_at(100, 100), Some((0, 0)));
+    assert_eq!(grid.cell_at(299, 249), Some((2, 3)));
+    assert_eq!(grid.cell_at(300, 100), None);
+    assert_eq!(grid.cell_at(99, 100), None);
+
+    assert_eq!(grid.neighbors((0, 0)), vec![(1, 0), (0, 1)]);
+    grid.set_blocked((1, 0), true);
+    assert!(grid.is_blocked((1, 0)));
+    assert_eq!(grid.neighbors((0, 0)), vec![(0, 1)]);
+    assert_eq!(grid.neighbors((1, 1)), vec![(0, 1), (2, 1), (1, 2)]);
+    grid.set_blocked((1, 0), false);
+    assert_eq!(grid.neighbors((1, 1)).len(), 4);
+}
+
+#[test]
+fn test_grid_path() {
+    // . # . .
+    // . # . #
+    // . . . .
+    let grid = Grid::new((0, 0), 10, 3, 4).blocked(&[(0, 1), (1, 1), (1, 3)]);
+
+    let path = grid.path((0, 0), (0, 2)).unwrap();
+    assert_eq!(path.len(), 7);
+    assert_eq!(path.first(), Some(&(0, 0)));
+    assert_eq!(path.last(), Some(&(0, 2)));
+    for pair in path.windows(2) {
+        assert!(grid.neighbors(pair[0]).contains(&pair[1]));
+    }
+
+    assert_eq!(grid.path((2, 3), (2, 3)), Some(vec![(2, 3)]));
+    assert_eq!(grid.path((0, 0), (0, 1)), None);
+    assert_eq!(grid.path((0, 0), (5, 5)), None);
+
+    let walled = grid.blocked(&[(2, 1)]);
+    assert_eq!(walled.path((0, 0), (0, 2)), None);
+}
+
+#[tokio::test]
+async fn test_grid_navigate() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+
+    // Goes down and right around the wall, stopping at the turn.
+    let grid = Grid::new((0, 0), 100, 3, 3).blocked(&[(0, 1), (1, 1)]);
+    let pos: Vec<u8> = Id::Pos(IdPos::new(50, 50, 0, 0, 0, 0)).try_into().unwrap();
+    mock.notify(UUID_ID, pos);
+
+    let (res, targets) = futures::join!(
+        grid.navigate(&mut cube, (0, 2)),
+        common::respond_targets(&mock, TargetResValue::Ok, 3)
+    );
+    res.unwrap();
+    assert_eq!(targets, vec![(50, 250), (250, 250), (250, 50)]);
+
+    let (res, targets) = futures::join!(
+        grid.goto_cell(&mut cube, (1, 0)),
+        common::respond_targets(&mock, TargetResValue::Ok, 1)
+    );
+    res.unwrap();
+    assert_eq!(targets, vec![(50, 150)]);
+
+    assert!(grid.goto_cell(&mut cube, (3, 0)).await.is_err());
+    assert!(grid.navigate(&mut cube, (1, 1)).await.is_err());
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+use toio::{proto::*, CubeGroup, Position, RelativePose};
+use tokio::time::delay_for;
+
+mod common;
+
+#[test]
+fn test_relative_pose() {
+    let at = |x, y, angle| Position::new(x, y, angle);
+
+    let pose = RelativePose::between(&at(100, 100, 0), &at(130, 140, 0));
+    assert_eq!(pose.distance, 50.0);
+    assert!((pose.bearing - 53.13).abs() < 0.01);
+
+    // Behind, and wrapped around.
+    let pose = RelativePose::between(&at(100, 100, 0), &at(50, 100, 0));
+    assert_eq!(pose.bearing, 180.0);
+    let pose = RelativePose::between(&at(100, 100, 350), &at(200, 100, 0));
+    assert_eq!(pose.bearing, 10.0);
+    let pose = RelativePose::between(&at(100, 100, 10), &at(200, 100, 0));
+    assert_eq!(pose.bearing, -10.0);
+}
+
+#[tokio::test]
+async fn test_group_relative_pose() {
+    let (mut a, mock_a) = common::cube("cube");
+    let (mut b, mock_b) = common::cube("cube");
+    a.connect().await.unwrap();
+    b.connect().await.unwrap();
+    let group = CubeGroup::new(vec![a, b]).await.unwrap();
+    assert_eq!(group.len(), 2);
+    assert_eq!(group.relative_pose(0, 1), None);
+
+    mock_a.notify(UUID_ID, common::pos(100, 100, 270));
+    mock_b.notify(UUID_ID, common::pos(100, 50, 0));
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(
+        group.relative_pose(0, 1),
+        Some(RelativePose {
+            distance: 50.0,
+            bearing: 0.0
+        })
+    );
+    assert_eq!(group.relative_pose(1, 0).unwrap().bearing, 90.0);
+
+    // Updated continuously.
+    mock_b.notify(UUID_ID, common::pos(160, 180, 0));
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(group.relative_pose(0, 1).unwrap().distance, 100.0);
+
+    mock_b.notify(UUID_ID, vec![0x03]);
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(group.relative_pose(0, 1), None);
+    assert_eq!(group.relative_pose(0, 5), None);
+    assert_eq!(group.into_cubes().len(), 2);
+}
+
+#[tokio::test]
+async fn test_try_for_all() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use toio::{ble::mock, Cube, Options};
+
+    let mut cubes = vec![];
+    let mut handles = vec![];
+    for id in &["a", "b", "c", "dead"] {
+        let (dev, handle) = mock::mock(id);
+        let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+        if *id != "dead" {
+            cube.connect().await.unwrap();
+        }
+        cubes.push(cube);
+        handles.push(handle);
+    }
+    let mut group = CubeGroup::new(cubes).await.unwrap();
+
+    let running = AtomicUsize::new(0);
+    let peak = AtomicUsize::new(0);
+    let results = group
+        .try_for_all(2, |cube| {
+            let (running, peak) = (&running, &peak);
+            async move {
+                let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(n, Ordering::SeqCst);
+                delay_for(Duration::from_millis(50)).await;
+                let res = cube.light_on(255, 0, 0, None).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                res
+            }
+        })
+        .await;
+
+    assert_eq!(results.len(), 4);
+    assert!(results["a"].is_ok() && results["b"].is_ok() && results["c"].is_ok());
+    assert!(results["dead"].is_err());
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    assert!(handles[..3].iter().all(|h| h.writes().len() == 1));
+}
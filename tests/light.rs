@@ -0,0 +1,99 @@
+use std::time::Duration;
+use toio::{
+    light::{Animation, Correction},
+    proto::*,
+};
+
+#[test]
+fn test_animation_fade() {
+    let anim = Animation::new()
+        .keyframe(Duration::from_millis(0), 0, 0, 0)
+        .keyframe(Duration::from_millis(400), 200, 100, 0)
+        .step(Duration::from_millis(100));
+    let ctrls = anim.compile().unwrap();
+    assert_eq!(
+        ctrls,
+        vec![LightCtrl::new(
+            1,
+            4,
+            vec![
+                LightOn::new(10, 0, 0, 0),
+                LightOn::new(10, 66, 33, 0),
+                LightOn::new(10, 133, 66, 0),
+                LightOn::new(10, 200, 100, 0),
+            ]
+        )]
+    );
+}
+
+#[test]
+fn test_animation_last_keyframe() {
+    // Passes through the middle keyframe, and ends with the color of the last one.
+    let anim = Animation::new()
+        .keyframe(Duration::from_millis(0), 0, 0, 0)
+        .keyframe(Duration::from_millis(200), 100, 0, 0)
+        .keyframe(Duration::from_millis(350), 100, 0, 200)
+        .step(Duration::from_millis(100));
+    let ctrls = anim.compile().unwrap();
+    assert_eq!(
+        ctrls[0].ops,
+        vec![
+            LightOn::new(10, 0, 0, 0),
+            LightOn::new(10, 50, 0, 0),
+            LightOn::new(10, 100, 0, 0),
+            LightOn::new(5, 100, 0, 200),
+        ]
+    );
+}
+
+#[test]
+fn test_animation_split() {
+    // Holds a color for 10 seconds, which doesn't fit in a single operation.
+    let anim = Animation::new()
+        .keyframe(Duration::from_secs(0), 255, 0, 0)
+        .keyframe(Duration::from_secs(10), 255, 0, 0);
+    let ctrls = anim.compile().unwrap();
+    assert_eq!(ctrls.len(), 1);
+    assert_eq!(
+        ctrls[0]
+            .ops
+            .iter()
+            .map(|op| op.duration)
+            .collect::<Vec<_>>(),
+        vec![255, 255, 255, 235]
+    );
+
+    // Many color changes are split into multiple messages.
+    let anim = Animation::new()
+        .keyframe(Duration::from_secs(0), 0, 0, 0)
+        .keyframe(Duration::from_secs(6), 240, 0, 0);
+    let ctrls = anim.compile().unwrap();
+    assert_eq!(ctrls.len(), 3);
+    assert_eq!(
+        ctrls.iter().map(|c| c.num).collect::<Vec<_>>(),
+        vec![29, 29, 2]
+    );
+}
+
+#[test]
+fn test_animation_invalid() {
+    assert!(Animation::new().compile().is_err());
+    assert!(Animation::new()
+        .keyframe(Duration::from_secs(0), 0, 0, 0)
+        .compile()
+        .is_err());
+}
+
+#[test]
+fn test_correction() {
+    let c = Correction::default();
+    assert_eq!((c.apply(0), c.apply(128), c.apply(255)), (0, 128, 255));
+
+    let c = Correction::new(2.0, 1.0);
+    assert_eq!((c.apply(0), c.apply(128), c.apply(255)), (0, 64, 255));
+
+    let c = Correction::new(1.0, 0.5);
+    let mut op = LightOn::new(10, 255, 100, 0);
+    c.apply_op(&mut op);
+    assert_eq!(op, LightOn::new(10, 128, 50, 0));
+}
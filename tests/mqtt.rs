@@ -0,0 +1,71 @@
+#![cfg(feature = "mqtt")]
+
+use toio::{mqtt::Bridge, proto::*};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{delay_for, Duration},
+};
+
+mod common;
+
+async fn read_packet(s: &mut TcpStream) -> (u8, Vec<u8>) {
+    let header = s.read_u8().await.unwrap();
+    let len = s.read_u8().await.unwrap() as usize;
+    assert!(len < 128);
+    let mut body = vec![0; len];
+    s.read_exact(&mut body).await.unwrap();
+    (header, body)
+}
+
+fn topic(body: &[u8]) -> (String, &[u8]) {
+    let len = u16::from_be_bytes([body[0], body[1]]) as usize;
+    let topic = String::from_utf8(body[2..2 + len].to_vec()).unwrap();
+    (topic, &body[2 + len..])
+}
+
+#[tokio::test]
+async fn test_bridge() {
+    let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+    tokio::spawn(async move {
+        Bridge::new(&addr)
+            .commands(true)
+            .run(&mut cube)
+            .await
+            .unwrap();
+    });
+
+    let (mut s, _) = listener.accept().await.unwrap();
+    let (header, body) = read_packet(&mut s).await;
+    assert_eq!(header, 0x10);
+    assert_eq!(topic(&body).0, "MQTT");
+    s.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+
+    let (header, body) = read_packet(&mut s).await;
+    assert_eq!(header, 0x82);
+    assert_eq!(topic(&body[2..]).0, "toio/cube/command");
+
+    // Events are published.
+    delay_for(Duration::from_millis(100)).await;
+    mock.notify(UUID_BATTERY, vec![80]);
+    let (header, body) = read_packet(&mut s).await;
+    assert_eq!(header, 0x30);
+    let (name, payload) = topic(&body);
+    assert_eq!(name, "toio/cube/battery");
+    assert_eq!(payload, b"80");
+
+    // Commands are written.
+    let mut body = vec![];
+    let name = b"toio/cube/command";
+    body.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    body.extend_from_slice(name);
+    body.extend_from_slice(br#"{"Sound":"Stop"}"#);
+    s.write_all(&[0x30, body.len() as u8]).await.unwrap();
+    s.write_all(&body).await.unwrap();
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(mock.writes(), vec![(UUID_SOUND, vec![0x01])]);
+}
@@ -64,3 +64,253 @@ fn test_light() {
     let p: Light = p.try_into().unwrap();
     assert_eq!(p, l);
 }
+
+#[test]
+fn test_typed_message() {
+    assert_eq!(Id::UUID, UUID_ID);
+    assert_eq!(Button::UUID, UUID_BUTTON);
+    assert_eq!(Config::UUID, UUID_CONFIG);
+}
+
+#[test]
+fn test_profile() {
+    let custom = toio::uuid!("00000107 0000 0000 0000 000000000000");
+    let profile = Profile {
+        button: custom,
+        ..Profile::default()
+    };
+    assert_eq!(profile.uuid(toio::Characteristic::Button), custom);
+    assert_eq!(profile.resolve(&UUID_BUTTON), custom);
+    assert_eq!(profile.resolve(&UUID_MOTOR), UUID_MOTOR);
+
+    let msg = Message::Button(Button::Func(ButtonState::Pressed));
+    let (uuid, buf) = profile.encode(msg.clone()).unwrap();
+    assert_eq!(uuid, custom);
+    assert_eq!(profile.decode(&uuid, &buf).unwrap(), msg);
+    assert_eq!(
+        Profile::default().decode(&uuid, &buf).unwrap(),
+        Message::Unknown {
+            uuid: custom,
+            data: buf
+        }
+    );
+}
+
+#[test]
+fn test_serde() {
+    let msgs = vec![
+        Message::Id(Id::Pos(IdPos::new(1, 2, 3, 4, 5, 6))),
+        Message::Battery(80),
+        Message::Motor(Motor::Simple(MotorSimple::new(
+            MotorId::Left,
+            MotorDir::Forward,
+            30,
+            MotorId::Right,
+            MotorDir::Backward,
+            20,
+        ))),
+        Message::Button(Button::Func(ButtonState::Pressed)),
+    ];
+    for msg in msgs {
+        let json = serde_json::to_string(&msg).unwrap();
+        let de: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(de, msg);
+    }
+
+    let json = serde_json::to_string(&Message::Battery(80)).unwrap();
+    assert_eq!(json, r#"{"Battery":80}"#);
+}
+
+#[test]
+fn test_json() {
+    let msg = Message::Motor(Motor::Simple(MotorSimple::new(
+        MotorId::Left,
+        MotorDir::Forward,
+        30,
+        MotorId::Right,
+        MotorDir::Backward,
+        20,
+    )));
+    let json =
+        r#"{"Motor":{"Simple":{"motor1":1,"dir1":1,"speed1":30,"motor2":2,"dir2":2,"speed2":20}}}"#;
+    assert_eq!(msg.to_json().unwrap(), json);
+    assert_eq!(Message::from_json(json).unwrap(), msg);
+
+    let json = r#"{"Id": {"Pos": {"cube_x": 1, "cube_y": 2, "cube_angle": 3, "sensor_x": 4, "sensor_y": 5, "sensor_angle": 6}}}"#;
+    assert_eq!(
+        Message::from_json(json).unwrap(),
+        Message::Id(Id::Pos(IdPos::new(1, 2, 3, 4, 5, 6)))
+    );
+
+    assert!(Message::from_json(r#"{"Battery": 300}"#).is_err());
+    assert!(Message::from_json(r#"{"Unknown": 1}"#).is_err());
+}
+
+#[test]
+fn test_encode_into() {
+    let msg = Message::Motor(Motor::Simple(MotorSimple::new(
+        MotorId::Left,
+        MotorDir::Forward,
+        30,
+        MotorId::Right,
+        MotorDir::Backward,
+        20,
+    )));
+    let mut buf = [0; MAX_MESSAGE_SIZE];
+    let (uuid, len) = msg.encode_into(&mut buf).unwrap();
+    let (expected_uuid, expected): (Uuid, Vec<u8>) = msg.try_into().unwrap();
+    assert_eq!(uuid, expected_uuid);
+    assert_eq!(&buf[..len], &expected[..]);
+
+    let ops = (0..4).map(|i| LightOn::new(i, i, i, i)).collect();
+    let msg = Message::Light(Light::Ctrl(LightCtrl::new(0, 4, ops)));
+    assert!(msg.encode_into(&mut buf).is_err());
+}
+
+#[test]
+fn test_unknown() {
+    let p: Button = vec![0x7f, 0x01, 0x02].try_into().unwrap();
+    assert_eq!(p, Button::Unknown(vec![0x7f, 0x01, 0x02]));
+    let v: Vec<u8> = p.try_into().unwrap();
+    assert_eq!(v, vec![0x7f, 0x01, 0x02]);
+
+    let msg: Message = (UUID_MOTION, vec![0x7f, 0x01]).try_into().unwrap();
+    assert_eq!(msg, Message::Motion(Motion::Unknown(vec![0x7f, 0x01])));
+
+    let custom = toio::uuid!("00000107 0000 0000 0000 000000000000");
+    let msg: Message = (custom, vec![0x01, 0x02]).try_into().unwrap();
+    let mut buf = [0; MAX_MESSAGE_SIZE];
+    assert_eq!(msg.encode_into(&mut buf).unwrap(), (custom, 2));
+    assert_eq!(&buf[..2], &[0x01, 0x02]);
+}
+
+#[test]
+fn test_strict_decode() {
+    let frame = [0x01, 0x00, 0x01, 0x00, 0x04, 0xff];
+    let msg = Message::Motion(Motion::Detect(MotionDetect::new(
+        false,
+        true,
+        false,
+        Posture::FrontUp,
+    )));
+    assert_eq!(Message::decode(UUID_MOTION, &frame, false).unwrap(), msg);
+    let e = Message::decode(UUID_MOTION, &frame, true).unwrap_err();
+    assert_eq!(
+        e.downcast_ref::<DecodeError>(),
+        Some(&DecodeError::Trailing {
+            expected: 4,
+            actual: 5
+        })
+    );
+    assert!(Sound::decode(&[0x01, 0x00], true).is_err());
+    assert!(Message::decode(UUID_BATTERY, &[80, 0], true).is_err());
+
+    let e = Id::decode(&[0x01, 0x01, 0x00, 0x02], false).unwrap_err();
+    assert_eq!(
+        e.downcast_ref::<DecodeError>(),
+        Some(&DecodeError::TooShort {
+            field: Some("cube_y"),
+            expected: 2,
+            actual: 1
+        })
+    );
+    let e = Id::decode(&[0x01, 0x01, 0x00], false).unwrap_err();
+    assert_eq!(
+        e.downcast_ref::<DecodeError>(),
+        Some(&DecodeError::Missing("cube_y"))
+    );
+}
+
+#[test]
+fn test_message_helpers() {
+    let msg = Message::Config(Config::VersionRes(ConfigVersionRes::new("2.1.0".into())));
+    assert_eq!(msg.uuid(), UUID_CONFIG);
+    assert_eq!(msg.kind(), MessageKind::Config);
+    assert!(msg.is_response());
+
+    let msg = Message::Motor(Motor::Target(MotorTarget::new(
+        0,
+        0,
+        MoveType::Curve,
+        50,
+        SpeedChange::Const,
+        100,
+        100,
+        0,
+    )));
+    assert_eq!(msg.uuid(), UUID_MOTOR);
+    assert_eq!(msg.kind(), MessageKind::Motor);
+    assert!(!msg.is_response());
+
+    let msg = Message::Battery(80);
+    assert_eq!(msg.kind(), MessageKind::Battery);
+    assert!(!msg.is_response());
+
+    let custom = toio::uuid!("00000107 0000 0000 0000 000000000000");
+    let msg = Message::Unknown {
+        uuid: custom,
+        data: vec![],
+    };
+    assert_eq!(msg.uuid(), custom);
+    assert_eq!(msg.kind(), MessageKind::Unknown);
+}
+
+#[test]
+fn test_target_builder() {
+    let t = MotorTarget::builder(100, KEEP_COORDINATE)
+        .id(3)
+        .timeout(5)
+        .max_speed(TARGET_MAX_SPEED)
+        .angle(AngleType::RelativeNegative, 90)
+        .build()
+        .unwrap();
+    assert_eq!(
+        t,
+        MotorTarget::new(
+            3,
+            5,
+            MoveType::Curve,
+            115,
+            SpeedChange::Const,
+            100,
+            0xffff,
+            0x8000 | 90
+        )
+    );
+    assert!(MotorTarget::builder(0, 0).max_speed(9).build().is_err());
+    assert!(MotorTarget::builder(0, 0)
+        .angle(AngleType::Absolute, 361)
+        .build()
+        .is_err());
+
+    let many = (0..30).fold(MotorMultiTarget::builder(), |b, i| b.target(i, i));
+    let e = many.build().unwrap_err();
+    assert!(e.to_string().contains("number of targets"));
+}
+
+#[test]
+fn test_split_multi_target() {
+    let targets = (0..5)
+        .fold(MotorMultiTarget::builder().id(9), |b, i| b.target(i, i))
+        .build()
+        .unwrap();
+
+    let split = targets.split(20).unwrap();
+    assert_eq!(split.len(), 3);
+    assert_eq!(split[0].writeopt, WriteOpt::Overwrite);
+    for (i, req) in split.iter().enumerate() {
+        let (_, value): (Uuid, Vec<u8>) = Message::Motor(Motor::MultiTarget(req.clone()))
+            .try_into()
+            .unwrap();
+        assert!(value.len() <= 20);
+        assert_eq!(req.id, 9);
+        if i > 0 {
+            assert_eq!(req.writeopt, WriteOpt::Append);
+        }
+    }
+    let visited: Vec<_> = split.into_iter().flat_map(|req| req.targets).collect();
+    assert_eq!(visited, targets.targets);
+
+    assert_eq!(targets.split(182).unwrap(), vec![targets.clone()]);
+    assert!(targets.split(13).is_err());
+}
@@ -0,0 +1,57 @@
+use futures::prelude::*;
+use std::time::Duration;
+use toio::{proto::*, Event, EventFilter, Proximity};
+use tokio::time::{delay_for, timeout};
+
+mod common;
+
+#[tokio::test]
+async fn test_proximity() {
+    let (mut a, mock_a) = common::cube("a");
+    let (mut b, mock_b) = common::cube("b");
+    a.connect().await.unwrap();
+    b.connect().await.unwrap();
+
+    let proximity = Proximity::new(50.0);
+    a.join_proximity(&proximity);
+    b.join_proximity(&proximity);
+    let filter = EventFilter::new().proximity();
+    let mut events_a = a.events_filtered(filter.clone()).await.unwrap();
+    let mut events_b = b.events_filtered(filter).await.unwrap();
+    let near = |other: &str| Event::Proximity {
+        other: other.into(),
+        distance: 50,
+    };
+
+    // Out of range.
+    mock_a.notify(UUID_ID, common::pos(100, 100, 0));
+    mock_b.notify(UUID_ID, common::pos(200, 100, 0));
+    delay_for(Duration::from_millis(50)).await;
+    assert!(timeout(Duration::from_millis(50), events_a.next())
+        .await
+        .is_err());
+
+    // Both cubes get the event on the update of either.
+    mock_b.notify(UUID_ID, common::pos(130, 140, 0));
+    assert_eq!(events_a.next().await, Some(near("b")));
+    assert_eq!(events_b.next().await, Some(near("a")));
+
+    // Off the mat.
+    mock_a.notify(UUID_ID, vec![0x03]);
+    delay_for(Duration::from_millis(50)).await;
+    mock_b.notify(UUID_ID, common::pos(130, 140, 0));
+    assert!(timeout(Duration::from_millis(50), events_b.next())
+        .await
+        .is_err());
+
+    // The dropped cube leaves.
+    mock_a.notify(UUID_ID, common::pos(100, 100, 0));
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(events_b.next().await, Some(near("a")));
+    drop(events_a);
+    drop(a);
+    mock_b.notify(UUID_ID, common::pos(130, 140, 0));
+    assert!(timeout(Duration::from_millis(50), events_b.next())
+        .await
+        .is_err());
+}
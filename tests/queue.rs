@@ -0,0 +1,185 @@
+use futures::prelude::*;
+use std::time::Duration;
+use toio::{
+    ble::{self, mock},
+    proto::*,
+    Cube, Event, EventFilter, EventStream, Options, Overflow,
+};
+use tokio::time::{delay_for, timeout};
+
+async fn cube(capacity: usize, overflow: Overflow) -> (Cube, mock::MockHandle, EventStream) {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            capacity,
+            overflow,
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+    let events = cube
+        .events_filtered(EventFilter::new().battery())
+        .await
+        .unwrap();
+    (cube, handle, events)
+}
+
+/// Notifies the battery levels, and waits until the hub delivers all of them.
+async fn notify(handle: &mock::MockHandle, levels: std::ops::RangeInclusive<u8>) {
+    for v in levels {
+        handle.notify(UUID_BATTERY, vec![v]);
+    }
+    // The paused clock advances only when all the tasks are idle.
+    delay_for(Duration::from_millis(1)).await;
+}
+
+async fn take(events: &mut EventStream, n: usize) -> Vec<Event> {
+    events.take(n).collect().await
+}
+
+#[tokio::test]
+async fn test_drop_oldest() {
+    let (_cube, handle, mut events) = cube(2, Overflow::DropOldest).await;
+    tokio::time::pause();
+
+    notify(&handle, 1..=5).await;
+    assert_eq!(
+        take(&mut events, 3).await,
+        vec![Event::Lagged(3), Event::Battery(4), Event::Battery(5)]
+    );
+
+    // The count starts again after reported.
+    notify(&handle, 6..=8).await;
+    assert_eq!(
+        take(&mut events, 3).await,
+        vec![Event::Lagged(1), Event::Battery(7), Event::Battery(8)]
+    );
+
+    // Nothing is dropped while within the capacity.
+    notify(&handle, 9..=10).await;
+    assert_eq!(
+        take(&mut events, 2).await,
+        vec![Event::Battery(9), Event::Battery(10)]
+    );
+}
+
+#[tokio::test]
+async fn test_drop_newest() {
+    let (_cube, handle, mut events) = cube(2, Overflow::DropNewest).await;
+    tokio::time::pause();
+
+    notify(&handle, 1..=5).await;
+    assert_eq!(
+        take(&mut events, 3).await,
+        vec![Event::Lagged(3), Event::Battery(1), Event::Battery(2)]
+    );
+}
+
+#[tokio::test]
+async fn test_block() {
+    let (_cube, handle, mut events) = cube(2, Overflow::Block).await;
+    tokio::time::pause();
+
+    // The hub waits for the subscriber to make room.
+    notify(&handle, 1..=5).await;
+    let expected: Vec<_> = (1..=5).map(Event::Battery).collect();
+    assert_eq!(take(&mut events, 5).await, expected);
+}
+
+#[tokio::test]
+async fn test_wake_and_close() {
+    let (cube, handle, mut events) = cube(2, Overflow::DropOldest).await;
+
+    // Wakes the subscriber waiting for the value.
+    let next = tokio::spawn(async move {
+        let event = events.next().await;
+        (event, events)
+    });
+    delay_for(Duration::from_millis(10)).await;
+    handle.notify(UUID_BATTERY, vec![50]);
+    let (event, mut events) = timeout(Duration::from_secs(1), next)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event, Some(Event::Battery(50)));
+
+    // Ends the stream once the cube is dropped.
+    drop(cube);
+    assert_eq!(
+        timeout(Duration::from_secs(1), events.next())
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_backend_lag() {
+    let (dev, handle) = mock::mock_with_capacity("cube", None, 2);
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+    let mut events = cube
+        .events_filtered(EventFilter::new().battery())
+        .await
+        .unwrap();
+    let mut msgs = cube.try_raw_msgs().await.unwrap();
+
+    // Overflows the channel of the peripheral before the hub receives.
+    for v in 1..=5 {
+        handle.notify(UUID_BATTERY, vec![v]);
+    }
+    assert_eq!(
+        take(&mut events, 3).await,
+        vec![Event::Lagged(3), Event::Battery(4), Event::Battery(5)]
+    );
+
+    let e = msgs.next().await.unwrap().unwrap_err();
+    assert_eq!(e.downcast_ref::<ble::Lagged>(), Some(&ble::Lagged(3)));
+    let msgs: Vec<_> = msgs.take(2).map(|m| m.unwrap()).collect().await;
+    assert_eq!(msgs, vec![Message::Battery(4), Message::Battery(5)]);
+}
+
+#[tokio::test]
+async fn test_fan_out() {
+    let (mut cube, handle, mut battery) = cube(4, Overflow::DropOldest).await;
+    let mut all = cube.events().await.unwrap();
+    tokio::time::pause();
+
+    // Each message reaches every subscriber interested in it.
+    handle.notify(UUID_BUTTON, vec![0x01, 0x80]);
+    notify(&handle, 1..=2).await;
+    assert_eq!(
+        take(&mut all, 3).await,
+        vec![Event::Button(true), Event::Battery(1), Event::Battery(2)]
+    );
+    assert_eq!(
+        take(&mut battery, 2).await,
+        vec![Event::Battery(1), Event::Battery(2)]
+    );
+}
+
+#[tokio::test]
+async fn test_unsubscribe_on_drop() {
+    let (mut cube, handle, mut events) = cube(1, Overflow::Block).await;
+    let dropped = cube
+        .events_filtered(EventFilter::new().battery())
+        .await
+        .unwrap();
+    tokio::time::pause();
+
+    // Fills the channel of the subscriber which never reads.
+    notify(&handle, 1..=1).await;
+    assert_eq!(take(&mut events, 1).await, vec![Event::Battery(1)]);
+
+    // The hub no longer waits for the dropped subscriber.
+    drop(dropped);
+    notify(&handle, 2..=4).await;
+    let expected: Vec<_> = (2..=4).map(Event::Battery).collect();
+    assert_eq!(
+        timeout(Duration::from_secs(1), take(&mut events, 3))
+            .await
+            .unwrap(),
+        expected
+    );
+}
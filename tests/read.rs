@@ -0,0 +1,140 @@
+use anyhow::Result;
+use std::convert::TryInto;
+use std::time::Duration;
+use toio::{
+    ble::{self, mock, PeripheralOps, Uuid},
+    proto::*,
+    Cube, Options, Position, StdId,
+};
+
+/// Peripheral notifying another characteristic right before answering reads.
+struct Noisy(mock::Mock, mock::MockHandle);
+
+#[async_trait::async_trait]
+impl PeripheralOps for Noisy {
+    fn id(&self) -> &str {
+        self.0.id()
+    }
+
+    fn rssi(&self) -> i32 {
+        self.0.rssi()
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        self.0.connect().await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.0.disconnect().await
+    }
+
+    async fn read(&mut self, uuid: &Uuid) -> Result<()> {
+        self.1.notify(UUID_BUTTON, vec![0x01, 0x80]);
+        self.0.read(uuid).await
+    }
+
+    async fn write(&mut self, uuid: &Uuid, value: &[u8], with_resp: bool) -> Result<()> {
+        self.0.write(uuid, value, with_resp).await
+    }
+
+    fn subscribe(&mut self) -> Result<ble::ValueStream> {
+        self.0.subscribe()
+    }
+}
+
+#[tokio::test]
+async fn test_read_value() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(Noisy(dev, handle.clone())), Options::default());
+    cube.connect().await.unwrap();
+    handle.notify(UUID_BATTERY, vec![80]);
+
+    // Takes the reply of the characteristic, skipping the other notification.
+    let value = cube.read_value(&UUID_BATTERY).await.unwrap();
+    assert_eq!(&value[..], &[80]);
+    assert_eq!(handle.reads(), vec![UUID_BATTERY]);
+
+    let pos = Id::Pos(IdPos::new(1, 2, 3, 4, 5, 6));
+    handle.notify(UUID_ID, pos.clone().try_into().unwrap());
+    assert_eq!(cube.read_parsed::<Id>().await.unwrap(), pos);
+}
+
+#[tokio::test]
+async fn test_read_value_timeout() {
+    let (dev, handle) = mock::mock("cube");
+    let opts = Options {
+        read_timeout: Duration::from_millis(100),
+        ..Options::default()
+    };
+    let mut cube = Cube::from_peripheral(Box::new(Noisy(dev, handle.clone())), opts);
+    cube.connect().await.unwrap();
+
+    // Only the other characteristic notifies.
+    assert!(cube.read_value(&UUID_BATTERY).await.is_err());
+    assert_eq!(handle.reads(), vec![UUID_BATTERY]);
+}
+
+#[tokio::test]
+async fn test_now() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    // Checks that a single read of the characteristic is issued.
+    let read = |uuid| {
+        let reads = handle.reads();
+        assert_eq!(reads.last(), Some(&uuid));
+        reads.len()
+    };
+    let notify = |uuid, value: Vec<u8>| handle.notify(uuid, value);
+
+    let version: Vec<u8> = Config::VersionRes(ConfigVersionRes::new("2.3.0".into()))
+        .try_into()
+        .unwrap();
+    notify(UUID_CONFIG, version);
+    assert_eq!(cube.version_now().await.unwrap(), "2.3.0");
+    let mut n = read(UUID_CONFIG);
+
+    notify(UUID_BATTERY, vec![70]);
+    assert_eq!(cube.battery_now().await.unwrap(), 70);
+    assert_eq!(read(UUID_BATTERY), n + 1);
+    n += 1;
+
+    let motion: Vec<u8> = Motion::Detect(MotionDetect::new(false, true, false, Posture::BackUp))
+        .try_into()
+        .unwrap();
+    notify(UUID_MOTION, motion);
+    assert!(cube.collision_now().await.unwrap());
+    assert_eq!(read(UUID_MOTION), n + 1);
+    assert!(cube.slope_now().await.unwrap());
+    assert_eq!(read(UUID_MOTION), n + 2);
+    assert_eq!(cube.posture_now().await.unwrap(), Posture::BackUp);
+    assert_eq!(read(UUID_MOTION), n + 3);
+    n += 3;
+
+    notify(UUID_BUTTON, vec![0x01, 0x80]);
+    assert!(cube.button_now().await.unwrap());
+    assert_eq!(read(UUID_BUTTON), n + 1);
+    n += 1;
+
+    let pos: Vec<u8> = Id::Pos(IdPos::new(10, 20, 30, 0, 0, 0)).try_into().unwrap();
+    notify(UUID_ID, pos);
+    assert_eq!(
+        cube.position_now().await.unwrap(),
+        Some(Position::new(10, 20, 30))
+    );
+    assert_eq!(read(UUID_ID), n + 1);
+    assert_eq!(cube.std_id_now().await.unwrap(), None);
+    assert_eq!(read(UUID_ID), n + 2);
+    n += 2;
+
+    let std: Vec<u8> = Id::Std(IdStd::new(3670016, 90)).try_into().unwrap();
+    notify(UUID_ID, std);
+    assert_eq!(
+        cube.std_id_now().await.unwrap(),
+        Some(StdId::new(3670016, 90))
+    );
+    assert_eq!(read(UUID_ID), n + 1);
+    assert_eq!(cube.position_now().await.unwrap(), None);
+    assert_eq!(read(UUID_ID), n + 2);
+}
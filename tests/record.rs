@@ -0,0 +1,60 @@
+use std::time::Duration;
+use toio::{
+    proto::*,
+    record::{Record, Recorder, Replay},
+};
+
+#[test]
+fn test_recorder() {
+    let mut rec = Recorder::new(vec![]);
+    rec.record(UUID_BATTERY, vec![0x50]).unwrap();
+    rec.record(UUID_BUTTON, vec![0x01, 0x80]).unwrap();
+    let out = String::from_utf8(rec.into_inner()).unwrap();
+
+    let records: Vec<Record> = out
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert!(records[0].time <= records[1].time);
+    assert!(records[1].time < Duration::from_secs(1));
+    assert_eq!(records[0].message().unwrap(), Message::Battery(0x50));
+    assert_eq!(
+        records[1].message().unwrap(),
+        Message::Button(Button::Func(ButtonState::Pressed))
+    );
+}
+
+#[tokio::test]
+async fn test_replay() {
+    use futures::prelude::*;
+    use std::time::Instant;
+    use toio::Event;
+
+    let record = |ms: u64, uuid, value: Vec<u8>| Record {
+        time: Duration::from_millis(ms),
+        uuid,
+        value,
+    };
+    let replay = Replay::new(vec![
+        record(1000, UUID_BUTTON, vec![0x01, 0x80]),
+        record(1100, UUID_BATTERY, vec![0x50]),
+        record(1200, UUID_BUTTON, vec![0x01, 0x00]),
+    ]);
+
+    let (mut cube, mock) = replay.cube();
+    cube.connect().await.unwrap();
+    assert!(mock.is_connected());
+    let mut events = cube.events().await.unwrap();
+
+    let start = Instant::now();
+    replay.play(&mock).await;
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    assert_eq!(events.next().await, Some(Event::Button(true)));
+    assert_eq!(events.next().await, Some(Event::Battery(80)));
+    assert_eq!(events.next().await, Some(Event::Button(false)));
+
+    // Reads are answered with the latest value.
+    assert_eq!(cube.battery_now().await.unwrap(), 80);
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use futures::prelude::*;
+use std::time::Duration;
+use toio::{
+    ble::{self, mock, PeripheralOps, SearchOps, Uuid},
+    proto::*,
+    Cube, Options,
+};
+use tokio::time::delay_for;
+
+struct MockSearcher(Option<mock::Mock>);
+
+#[async_trait::async_trait]
+impl SearchOps for MockSearcher {
+    async fn search(&mut self, _: &Uuid, _: Duration) -> Result<Vec<ble::Peripheral>> {
+        Ok(self
+            .0
+            .take()
+            .into_iter()
+            .map(|p| Box::new(p) as ble::Peripheral)
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn test_remote() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (dev, handle) = mock::mock("cube");
+    tokio::spawn(ble::remote::serve(addr, Box::new(MockSearcher(Some(dev)))));
+    delay_for(Duration::from_millis(100)).await;
+
+    let mut searcher = ble::remote::searcher(&addr.to_string());
+    let mut found = searcher
+        .search(&UUID_SERVICE, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    let mut p = found.pop().unwrap();
+    assert_eq!(p.id(), "cube");
+
+    p.connect().await.unwrap();
+    assert!(handle.is_connected());
+    p.pair().await.unwrap();
+    assert!(handle.is_paired());
+
+    p.write(&UUID_SOUND, &[0x01], true).await.unwrap();
+    assert_eq!(handle.writes(), vec![(UUID_SOUND, vec![0x01])]);
+
+    let mut values = p.subscribe().unwrap();
+    handle.notify(UUID_BATTERY, vec![80]);
+    assert_eq!(values.next().await, Some((UUID_BATTERY, vec![80].into())));
+    p.read(&UUID_BATTERY).await.unwrap();
+    assert_eq!(values.next().await, Some((UUID_BATTERY, vec![80].into())));
+
+    p.disconnect().await.unwrap();
+    assert!(!handle.is_connected());
+}
+
+#[tokio::test]
+async fn test_remote_cube() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (dev, handle) = mock::mock("cube");
+    tokio::spawn(ble::remote::serve(addr, Box::new(MockSearcher(Some(dev)))));
+    delay_for(Duration::from_millis(100)).await;
+
+    let mut searcher = ble::remote::searcher(&addr.to_string());
+    let mut found = searcher
+        .search(&UUID_SERVICE, Duration::from_secs(1))
+        .await
+        .unwrap();
+    let mut cube = Cube::from_peripheral(found.pop().unwrap(), Options::default());
+    assert_eq!(cube.id(), "cube");
+
+    cube.connect().await.unwrap();
+    assert!(handle.is_connected());
+    assert!(handle.reads().is_empty());
+    assert!(handle.writes().is_empty());
+
+    handle.notify(UUID_BATTERY, vec![80]);
+    assert_eq!(cube.battery().await.unwrap(), 80);
+
+    cube.go(0, 0, None).await.unwrap();
+    assert!(handle.writes().iter().any(|(uuid, _)| *uuid == UUID_MOTOR));
+}
@@ -0,0 +1,129 @@
+use anyhow::{anyhow, Result};
+use futures::prelude::*;
+use std::time::Duration;
+use toio::{
+    ble::{PeripheralOps, RetryWrite, Uuid, ValueStream},
+    Backoff, Options,
+};
+
+/// Peripheral whose writes fail a number of times.
+struct Flaky {
+    failures: usize,
+    writes: usize,
+}
+
+#[async_trait::async_trait]
+impl PeripheralOps for Flaky {
+    fn id(&self) -> &str {
+        "flaky"
+    }
+
+    fn rssi(&self) -> i32 {
+        0
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn read(&mut self, _: &Uuid) -> Result<()> {
+        Ok(())
+    }
+
+    async fn write(&mut self, _: &Uuid, _: &[u8], _: bool) -> Result<()> {
+        self.writes += 1;
+        if self.writes <= self.failures {
+            Err(anyhow!("Write error"))
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn notify(&mut self, _: &Uuid, _: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn subscribe(&mut self) -> Result<ValueStream> {
+        Ok(stream::empty().boxed())
+    }
+}
+
+#[test]
+fn test_backoff() {
+    let b = Backoff {
+        jitter: 0.0,
+        ..Backoff::new(5, Duration::from_millis(100))
+    };
+    assert_eq!(
+        b.delays().collect::<Vec<_>>(),
+        vec![100, 200, 400, 800, 1600]
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect::<Vec<_>>()
+    );
+
+    let b = Backoff {
+        max: Duration::from_millis(300),
+        ..b
+    };
+    assert_eq!(b.base_delay(10), Duration::from_millis(300));
+
+    let d = Backoff::new(1, Duration::from_millis(1000)).delay(0);
+    assert!(d >= Duration::from_millis(800) && d <= Duration::from_millis(1200));
+
+    assert_eq!(Backoff::none().delays().count(), 0);
+    assert_eq!(Options::default().connect_retry, Backoff::none());
+}
+
+#[test]
+fn test_jitter() {
+    let b = Backoff::new(1, Duration::from_millis(1000));
+    let delays: Vec<_> = (0..100).map(|_| b.delay(0)).collect();
+    assert!(delays
+        .iter()
+        .all(|d| *d >= Duration::from_millis(800) && *d <= Duration::from_millis(1200)));
+    // The delays spread over the range.
+    assert!(delays.iter().any(|d| *d < Duration::from_millis(950)));
+    assert!(delays.iter().any(|d| *d > Duration::from_millis(1050)));
+}
+
+#[tokio::test]
+async fn test_retry_write() {
+    let backoff = Backoff::new(2, Duration::from_millis(1));
+    let uuid = Uuid::new([0; 16]);
+
+    let mut p = RetryWrite::new(
+        Flaky {
+            failures: 2,
+            writes: 0,
+        },
+        backoff,
+    );
+    assert!(p.write(&uuid, &[], true).await.is_ok());
+    assert_eq!(p.into_inner().writes, 3);
+
+    let mut p = RetryWrite::new(
+        Flaky {
+            failures: 3,
+            writes: 0,
+        },
+        backoff,
+    );
+    assert!(p.write(&uuid, &[], true).await.is_err());
+    assert_eq!(p.into_inner().writes, 3);
+
+    // Writes without response are not retried.
+    let mut p = RetryWrite::new(
+        Flaky {
+            failures: 1,
+            writes: 0,
+        },
+        backoff,
+    );
+    assert!(p.write(&uuid, &[], false).await.is_err());
+    assert_eq!(p.into_inner().writes, 1);
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "ros")]
+
+use futures::prelude::*;
+use serde_json::{json, Value};
+use std::convert::TryInto;
+use toio::{proto::*, ros::Bridge};
+use tokio::{
+    net::TcpListener,
+    time::{delay_for, Duration},
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message as WsMessage};
+
+mod common;
+
+async fn next<S, E>(ws: &mut S) -> Value
+where
+    S: Stream<Item = Result<WsMessage, E>> + Unpin,
+    E: std::fmt::Debug,
+{
+    match ws.next().await.unwrap().unwrap() {
+        WsMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+        m => panic!("unexpected frame: {:?}", m),
+    }
+}
+
+#[tokio::test]
+async fn test_bridge() {
+    let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+    tokio::spawn(async move {
+        Bridge::new(&format!("ws://{}", addr))
+            .scale(0.001)
+            .run(&mut cube)
+            .await
+            .unwrap();
+    });
+
+    let (s, _) = listener.accept().await.unwrap();
+    let mut ws = accept_async(s).await.unwrap();
+    assert_eq!(next(&mut ws).await["op"], "advertise");
+    assert_eq!(next(&mut ws).await["topic"], "/toio/cmd_vel");
+    delay_for(Duration::from_millis(100)).await;
+
+    // The pose is published.
+    let pos: Vec<u8> = Id::Pos(IdPos::new(100, 200, 90, 0, 0, 0))
+        .try_into()
+        .unwrap();
+    mock.notify(UUID_ID, pos);
+    let op = next(&mut ws).await;
+    assert_eq!(op["topic"], "/toio/pose");
+    let pose = &op["msg"]["pose"];
+    assert!((pose["position"]["x"].as_f64().unwrap() - 0.1).abs() < 1e-9);
+    assert!((pose["position"]["y"].as_f64().unwrap() + 0.2).abs() < 1e-9);
+    let z = pose["orientation"]["z"].as_f64().unwrap();
+    let w = pose["orientation"]["w"].as_f64().unwrap();
+    assert!((2.0 * z.atan2(w) + std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+
+    // Twists drive the cube.
+    let op = json!({
+        "op": "publish",
+        "topic": "/toio/cmd_vel",
+        "msg": {"linear": {"x": 0.1, "y": 0.0, "z": 0.0}, "angular": {"x": 0.0, "y": 0.0, "z": 0.0}},
+    });
+    ws.send(WsMessage::Text(op.to_string())).await.unwrap();
+    delay_for(Duration::from_millis(100)).await;
+    let motor: Vec<u8> = Motor::Timed(MotorTimed::new(
+        MotorId::Left,
+        MotorDir::Forward,
+        23,
+        MotorId::Right,
+        MotorDir::Forward,
+        23,
+        50,
+    ))
+    .try_into()
+    .unwrap();
+    assert_eq!(mock.writes(), vec![(UUID_MOTOR, motor)]);
+}
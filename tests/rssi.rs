@@ -0,0 +1,34 @@
+use futures::prelude::*;
+use std::time::Duration;
+use toio::{ble::mock, Cube, Options};
+use tokio::time::delay_for;
+
+#[tokio::test]
+async fn test_rssi_interval() {
+    // Not refreshed by default.
+    let (dev, handle) = mock::mock("cube");
+    handle.set_rssi(-70);
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+    tokio::time::pause();
+    handle.set_rssi(-40);
+    delay_for(Duration::from_secs(10)).await;
+    assert_eq!(cube.rssi(), -70);
+
+    let (dev, handle) = mock::mock("cube");
+    handle.set_rssi(-70);
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            rssi_interval: Some(Duration::from_secs(1)),
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+    let mut rssi = cube.rssi_stream();
+    assert_eq!(rssi.next().await, Some(-70));
+
+    handle.set_rssi(-40);
+    assert_eq!(rssi.next().await, Some(-40));
+    assert_eq!(cube.rssi(), -40);
+}
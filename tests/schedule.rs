@@ -0,0 +1,58 @@
+use std::time::Duration;
+use toio::{ble::mock, proto::*, schedule::Scheduler, Cube, Latency, Options};
+use tokio::time::{delay_for, Instant};
+
+#[tokio::test]
+async fn test_scheduler() {
+    let mut cubes = vec![];
+    let mut handles = vec![];
+    for id in &["a", "b"] {
+        let (dev, handle) = mock::mock(id);
+        let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+        cube.connect().await.unwrap();
+        cubes.push(cube);
+        handles.push(handle);
+    }
+    let ms = Duration::from_millis;
+    cubes[0].set_latency(Latency::from_round_trips(&[ms(200)]));
+    tokio::time::pause();
+
+    let start = Instant::now();
+    let mut scheduler = Scheduler::new();
+    scheduler
+        .at(start + ms(1000), 1, Light::Off(LightOff::new()))
+        .at(start + ms(1000), 0, Light::Off(LightOff::new()))
+        .at(start + ms(500), 0, Sound::Stop);
+    assert_eq!(scheduler.len(), 3);
+
+    let check = async {
+        let writes = || (handles[0].writes().len(), handles[1].writes().len());
+        delay_for(ms(350)).await;
+        assert_eq!(writes(), (0, 0));
+        // Written earlier by the one-way delay of 100 ms.
+        delay_for(ms(100)).await;
+        assert_eq!(writes(), (1, 0));
+        delay_for(ms(500)).await;
+        assert_eq!(writes(), (2, 0));
+        delay_for(ms(100)).await;
+        assert_eq!(writes(), (2, 1));
+    };
+    let (res, _) = futures::join!(scheduler.run(&mut cubes), check);
+    res.unwrap();
+    assert_eq!(handles[0].writes()[0].0, UUID_SOUND);
+
+    let mut scheduler = Scheduler::new();
+    scheduler.at(Instant::now(), 2, Sound::Stop);
+    assert!(scheduler.run(&mut cubes).await.is_err());
+
+    // A dead cube doesn't stop the others.
+    let (dev, _) = mock::mock("dead");
+    cubes.push(Cube::from_peripheral(Box::new(dev), Options::default()));
+    let mut scheduler = Scheduler::new();
+    scheduler
+        .at(Instant::now(), 2, Sound::Stop)
+        .at(Instant::now() + ms(10), 0, Sound::Stop);
+    let e = scheduler.run(&mut cubes).await.unwrap_err();
+    assert!(e.to_string().contains("dead"), "{}", e);
+    assert_eq!(handles[0].writes().len(), 3);
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "script")]
+
+use toio::{proto::*, script::Script};
+
+mod common;
+
+#[tokio::test]
+async fn test_script() {
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+    mock.notify(UUID_BATTERY, vec![80]);
+    mock.notify(UUID_ID, vec![0x03]);
+
+    let source = r#"
+        if battery() != 80 {
+            throw "unexpected battery";
+        }
+        if position() != () {
+            throw "unexpected position";
+        }
+        drive(50, -50, 100);
+        sleep(10);
+        light(300, 0, 0);
+        sound(1);
+    "#;
+    Script::new().run(&mut cube, source).await.unwrap();
+
+    let writes: Vec<_> = mock
+        .writes()
+        .into_iter()
+        .filter(|(uuid, _)| *uuid != UUID_CONFIG)
+        .map(|(uuid, value)| (uuid, value[0]))
+        .collect();
+    assert_eq!(
+        writes,
+        vec![(UUID_MOTOR, 0x02), (UUID_LIGHT, 0x03), (UUID_SOUND, 0x02)]
+    );
+}
+
+#[tokio::test]
+async fn test_script_error() {
+    let (mut cube, _mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+
+    let err = Script::new()
+        .run(&mut cube, "sound(42);")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Invalid preset sound"));
+
+    // Runaway scripts are stopped.
+    assert!(Script::new()
+        .max_operations(1000)
+        .run(&mut cube, "loop {}")
+        .await
+        .is_err());
+}
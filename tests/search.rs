@@ -0,0 +1,93 @@
+use anyhow::Result;
+use std::time::Duration;
+use toio::{
+    ble::{self, mock, SearchOps, StopSearch, Uuid},
+    Cube,
+};
+use tokio::time::{delay_for, Instant};
+
+/// Discovers a cube every 100 milliseconds, each nearer than the previous one.
+struct Approaching;
+
+#[async_trait::async_trait]
+impl SearchOps for Approaching {
+    async fn search(&mut self, uuid: &Uuid, timeout: Duration) -> Result<Vec<ble::Peripheral>> {
+        self.search_until(uuid, timeout, &mut |_| false).await
+    }
+
+    async fn search_until(
+        &mut self,
+        _: &Uuid,
+        timeout: Duration,
+        stop: &mut StopSearch<'_>,
+    ) -> Result<Vec<ble::Peripheral>> {
+        let start = Instant::now();
+        let mut found: Vec<ble::Peripheral> = vec![];
+        for (i, rssi) in [-80, -60, -45, -30].iter().enumerate() {
+            delay_for(Duration::from_millis(100)).await;
+            let name = format!("toio-{}", (b'a' + i as u8) as char);
+            let (dev, handle) = mock::mock_named(&format!("cube{}", i), Some(&name));
+            handle.set_rssi(*rssi);
+            found.push(Box::new(dev));
+            if stop(&**found.last().unwrap()) {
+                return Ok(found);
+            }
+        }
+        delay_for(timeout - (Instant::now() - start)).await;
+        Ok(found)
+    }
+}
+
+#[tokio::test]
+async fn test_nearest_min_rssi() {
+    ble::register_backend(|| Box::new(Approaching));
+    tokio::time::pause();
+
+    let start = Instant::now();
+    let cube = Cube::search().nearest().await.unwrap();
+    assert_eq!(cube.id(), "cube3");
+    assert!(Instant::now() - start >= Duration::from_secs(3));
+
+    let start = Instant::now();
+    let cube = Cube::search()
+        .nearest_min_rssi(-50)
+        .nearest()
+        .await
+        .unwrap();
+    assert_eq!(cube.id(), "cube2");
+    assert!(Instant::now() - start < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_search_until() {
+    ble::register_backend(|| Box::new(Approaching));
+    tokio::time::pause();
+
+    let mut seen = vec![];
+    let cubes = Cube::search()
+        .search_until(
+            |d| {
+                seen.push((d.id.to_string(), d.name.map(|n| n.to_string()), d.rssi));
+                d.count >= 2
+            },
+            Duration::from_secs(3),
+        )
+        .await
+        .unwrap();
+    let ids: Vec<_> = cubes.iter().map(|c| c.id()).collect();
+    assert_eq!(ids, vec!["cube1", "cube0"]);
+    assert_eq!(
+        seen,
+        vec![
+            ("cube0".into(), Some("toio-a".into()), -80),
+            ("cube1".into(), Some("toio-b".into()), -60),
+        ]
+    );
+
+    let cubes = Cube::search()
+        .search_until(|d| d.name == Some("toio-c"), Duration::from_secs(3))
+        .await
+        .unwrap();
+    assert_eq!(cubes.len(), 3);
+    assert_eq!(cubes[0].id(), "cube2");
+}
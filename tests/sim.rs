@@ -0,0 +1,437 @@
+use futures::prelude::*;
+use std::time::{Duration, Instant};
+use toio::{
+    drive::HeadingController,
+    proto::*,
+    sim::{Action, Mat, Model, Scenario, Simulator},
+    Event, EventStream, Position,
+};
+use tokio::time::timeout;
+
+const STEP: Duration = Duration::from_millis(10);
+
+fn timed(left: (MotorDir, u8), right: (MotorDir, u8), duration: u8) -> Motor {
+    Motor::Timed(MotorTimed::new(
+        MotorId::Left,
+        left.0,
+        left.1,
+        MotorId::Right,
+        right.0,
+        right.1,
+        duration,
+    ))
+}
+
+/// Steps the model until a response, and returns it with the elapsed steps.
+fn run(model: &mut Model, max_steps: usize) -> Option<(Motor, usize)> {
+    for i in 0..max_steps {
+        if let Some(res) = model.step(STEP).pop() {
+            return Some((res, i));
+        }
+    }
+    None
+}
+
+fn near(pos: &Position, x: u16, y: u16, angle: u16) -> bool {
+    let diff = |a: u16, b: u16| (a as i32 - b as i32).abs();
+    let angle_diff = diff(pos.angle, angle) % 360;
+    diff(pos.x, x) <= 3 && diff(pos.y, y) <= 3 && angle_diff.min(360 - angle_diff) <= 2
+}
+
+/// Collects the events for the duration.
+async fn events_for(mut events: EventStream, duration: Duration) -> Vec<Event> {
+    let deadline = Instant::now() + duration;
+    let mut collected = vec![];
+    while let Ok(Some(event)) = timeout(
+        deadline.saturating_duration_since(Instant::now()),
+        events.next(),
+    )
+    .await
+    {
+        collected.push(event);
+    }
+    collected
+}
+
+#[test]
+fn test_model_timed() {
+    use MotorDir::*;
+
+    let mut model = Model::new(200.0, 200.0, 90.0);
+
+    // The speeds below 8 don't move.
+    model.write(&timed((Forward, 7), (Forward, 7), 0));
+    model.step(STEP);
+    assert_eq!(model.position(), Some(Position::new(200, 200, 90)));
+
+    // Spins clockwise in place for 100 milliseconds, then stops.
+    model.write(&timed((Forward, 30), (Backward, 30), 10));
+    assert_eq!(model.wheels(), (30.0, -30.0));
+    for _ in 0..20 {
+        model.step(STEP);
+    }
+    assert_eq!(model.wheels(), (0.0, 0.0));
+    let (x, y, angle) = model.pose();
+    assert_eq!((x.round(), y.round()), (200.0, 200.0));
+    assert!((angle - 145.6).abs() < 0.5, "{}", angle);
+
+    // Backward from the angle pointing down-right on the mat.
+    let mut model = Model::new(200.0, 200.0, 45.0);
+    model.write(&timed((Backward, 20), (Backward, 20), 100));
+    for _ in 0..100 {
+        model.step(STEP);
+    }
+    let (x, y, _) = model.pose();
+    assert!(
+        (x - 116.0).abs() < 1.0 && (y - 116.0).abs() < 1.0,
+        "{} {}",
+        x,
+        y
+    );
+
+    // Deterministic.
+    let trace = || {
+        let mut model = Model::new(150.0, 300.0, 10.0);
+        model.write(&timed((Forward, 60), (Forward, 35), 120));
+        (0..100)
+            .map(|_| {
+                model.step(STEP);
+                model.position()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(trace(), trace());
+}
+
+#[test]
+fn test_model_target() {
+    for move_type in &[MoveType::Curve, MoveType::ForwardOnly, MoveType::Straight] {
+        let mut model = Model::new(100.0, 100.0, 0.0);
+        let target = MotorTarget::builder(300, 200)
+            .id(3)
+            .move_type(*move_type)
+            .angle(AngleType::Absolute, 270)
+            .build()
+            .unwrap();
+        assert!(model.write(&Motor::Target(target)).is_empty());
+        assert!(model.is_busy());
+
+        let (res, steps) = run(&mut model, 1000).unwrap();
+        assert_eq!(
+            res,
+            Motor::TargetRes(MotorTargetRes::new(3, TargetResValue::Ok))
+        );
+        // About 220 units at the speed 50.
+        assert!(steps > 70 && steps < 200, "{:?}: {}", move_type, steps);
+        assert!(near(&model.position().unwrap(), 300, 200, 270));
+        assert_eq!(model.wheels(), (0.0, 0.0));
+        assert!(!model.is_busy());
+    }
+
+    // Goes backward to the target behind.
+    let mut model = Model::new(300.0, 200.0, 0.0);
+    let target = MotorTarget::builder(200, 200).build().unwrap();
+    model.write(&Motor::Target(target));
+    model.step(STEP);
+    assert!(model.wheels().0 < 0.0 && model.wheels().1 < 0.0);
+    run(&mut model, 1000).unwrap();
+    assert!(near(&model.position().unwrap(), 200, 200, 0));
+
+    // Turns by the relative angle, keeping the position.
+    let mut model = Model::new(300.0, 200.0, 350.0);
+    let target = MotorTarget::builder(KEEP_COORDINATE, KEEP_COORDINATE)
+        .angle(AngleType::RelativePositive, 30)
+        .build()
+        .unwrap();
+    model.write(&Motor::Target(target));
+    run(&mut model, 1000).unwrap();
+    assert!(near(&model.position().unwrap(), 300, 200, 20));
+}
+
+#[test]
+fn test_model_target_failures() {
+    let target = |id, speed| {
+        MotorTarget::builder(400, 400)
+            .id(id)
+            .timeout(1)
+            .build()
+            .map(|mut t| {
+                t.max_speed = speed;
+                Motor::Target(t)
+            })
+            .unwrap()
+    };
+    let res = |id, value| Motor::TargetRes(MotorTargetRes::new(id, value));
+
+    // Too slow to arrive in a second.
+    let mut model = Model::new(100.0, 100.0, 45.0);
+    model.write(&target(1, 10));
+    let (r, steps) = run(&mut model, 1000).unwrap();
+    assert_eq!(r, res(1, TargetResValue::Timeout));
+    assert_eq!(steps, 100);
+
+    // Overwritten.
+    model.write(&target(2, 50));
+    assert_eq!(
+        model.write(&timed((MotorDir::Forward, 20), (MotorDir::Forward, 20), 0)),
+        vec![res(2, TargetResValue::OtherWrite)]
+    );
+    assert_eq!(model.wheels(), (20.0, 20.0));
+
+    // Invalid speed.
+    assert_eq!(
+        model.write(&target(3, 5)),
+        vec![res(3, TargetResValue::InvalidParam)]
+    );
+
+    // Off the mat.
+    model.set_pose(20.0, 20.0, 0.0);
+    assert_eq!(
+        model.write(&target(4, 50)),
+        vec![res(4, TargetResValue::IdMissed)]
+    );
+
+    // Leaves the mat.
+    model.set_pose(450.0, 300.0, 0.0);
+    let target = MotorTarget::builder(500, 300).id(5).build().unwrap();
+    model.write(&Motor::Target(target));
+    let (r, _) = run(&mut model, 1000).unwrap();
+    assert_eq!(r, res(5, TargetResValue::IdMissed));
+}
+
+#[test]
+fn test_model_multi_target() {
+    let mut model = Model::new(100.0, 100.0, 0.0);
+    let targets = MotorMultiTarget::builder()
+        .id(7)
+        .max_speed(80)
+        .target(200, 100)
+        .target(200, 200)
+        .target_with_angle(100, 200, AngleType::Absolute, 0)
+        .build()
+        .unwrap();
+    model.write(&Motor::MultiTarget(targets));
+
+    // Passes the waypoints in order.
+    let waypoints = [(200, 100), (200, 200)];
+    let mut visited = 0;
+    for _ in 0..1000 {
+        let res = model.step(STEP);
+        let pos = model.position().unwrap();
+        if let Some((x, y)) = waypoints.get(visited) {
+            if near(&pos, *x, *y, pos.angle) {
+                visited += 1;
+            }
+        }
+        if let Some(res) = res.first() {
+            assert_eq!(
+                *res,
+                Motor::MultiTargetRes(MotorTargetRes::new(7, TargetResValue::Ok))
+            );
+            break;
+        }
+    }
+    assert_eq!(visited, waypoints.len());
+    assert!(near(&model.position().unwrap(), 100, 200, 0));
+}
+
+#[tokio::test]
+async fn test_simulator() {
+    let (mut cube, sim) = Simulator::new(Model::new(100.0, 100.0, 0.0))
+        .interval(Duration::from_millis(20))
+        .cube();
+    cube.connect().await.unwrap();
+
+    let mut events = cube.events().await.unwrap();
+    let event = timeout(Duration::from_secs(1), events.next())
+        .await
+        .unwrap();
+    assert_eq!(
+        event,
+        Some(Event::Position(Some(Position::new(100, 100, 0))))
+    );
+    assert_eq!(cube.battery().await.unwrap(), 100);
+
+    // Drives right for 200 milliseconds.
+    cube.go(50, 50, Some(Duration::from_millis(200)))
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(400)).await;
+    let pos = sim.position().unwrap();
+    assert!(pos.x > 160 && pos.x < 185, "{:?}", pos);
+    assert_eq!(cube.position_now().await.unwrap(), Some(pos));
+
+    let target = MotorTarget::builder(300, 250)
+        .max_speed(100)
+        .angle(AngleType::Absolute, 180)
+        .build()
+        .unwrap();
+    timeout(Duration::from_secs(5), cube.goto(target))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(near(&sim.position().unwrap(), 300, 250, 180));
+
+    // Off the mat.
+    sim.set_pose(10.0, 10.0, 0.0);
+    let mut missed = events.filter(|e| future::ready(*e == Event::Position(None)));
+    timeout(Duration::from_secs(1), missed.next())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_scenario() {
+    let scenario = Scenario::from_toml(
+        r#"
+        interval = 20
+
+        [[cubes]]
+        x = 100
+        y = 100
+
+        [[cubes]]
+        id = "b"
+        x = 300
+        y = 300
+        angle = 90
+        battery = 15
+
+        [[actions]]
+        at = 100
+        type = "collision"
+
+        [[actions]]
+        at = 60
+        cube = 1
+        type = "button"
+        pressed = true
+
+        [[actions]]
+        at = 200
+        cube = 1
+        type = "lift"
+
+        [[actions]]
+        at = 300
+        cube = 1
+        type = "place"
+        x = 200
+        y = 250
+        angle = 0
+        "#,
+    )
+    .unwrap();
+    assert_eq!(scenario.mat, Mat::Collection);
+    assert_eq!(scenario.actions[0].action, Action::Collision);
+
+    let mut cubes = scenario.cubes();
+    let (mut b, sim_b) = cubes.pop().unwrap();
+    let (mut a, _) = cubes.pop().unwrap();
+    assert_eq!(a.id(), "sim0");
+    assert_eq!(b.id(), "b");
+    a.connect().await.unwrap();
+    b.connect().await.unwrap();
+    assert_eq!(b.battery().await.unwrap(), 15);
+
+    let a_events = events_for(a.events().await.unwrap(), Duration::from_millis(500));
+    let b_events = events_for(b.events().await.unwrap(), Duration::from_millis(500));
+    let (a_events, b_events) = futures::join!(a_events, b_events);
+
+    assert!(a_events.contains(&Event::Collision(true)));
+    assert!(!a_events.contains(&Event::Position(None)));
+    assert!(!b_events.contains(&Event::Collision(true)));
+    let pressed = b_events.iter().position(|e| *e == Event::Button(true));
+    let lifted = b_events.iter().position(|e| *e == Event::Position(None));
+    let placed = b_events
+        .iter()
+        .position(|e| *e == Event::Position(Some(Position::new(200, 250, 0))));
+    assert!(pressed < lifted && lifted < placed, "{:?}", b_events);
+    assert_eq!(sim_b.position(), Some(Position::new(200, 250, 0)));
+}
+
+#[test]
+fn test_scenario_invalid() {
+    let err = Scenario::from_toml(
+        r#"
+        [[cubes]]
+        x = 100
+        y = 100
+
+        [[actions]]
+        at = 100
+        cube = 1
+        type = "collision"
+        "#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("cube 1"));
+
+    assert!(
+        Scenario::from_toml("[[cubes]]\nx = 1\ny = 1\n[[actions]]\nat = 0\ntype = \"jump\"")
+            .is_err()
+    );
+    assert!(Scenario::from_toml("mat = \"garden\"\ncubes = []").is_err());
+    let scenario =
+        Scenario::from_toml("mat = { custom = { min = [0, 0], max = [100, 100] } }\ncubes = []")
+            .unwrap();
+    assert_eq!(scenario.mat.area(), ((0, 0), (100, 100)));
+}
+
+#[tokio::test]
+async fn test_simulator_paused() {
+    tokio::time::pause();
+
+    let interval = Duration::from_millis(20);
+    let (mut cube, sim) = Simulator::new(Model::new(50.0, 200.0, 0.0))
+        .interval(interval)
+        .cube();
+    cube.connect().await.unwrap();
+    cube.go(50, 50, Some(Duration::from_secs(1))).await.unwrap();
+
+    // Fast-forwards 2 seconds.
+    for _ in 0..100 {
+        tokio::time::advance(interval).await;
+    }
+
+    let mut model = Model::new(50.0, 200.0, 0.0);
+    model.write(&timed(
+        (MotorDir::Forward, 61),
+        (MotorDir::Forward, 61),
+        100,
+    ));
+    for _ in 0..100 {
+        model.step(interval);
+    }
+    assert_eq!(sim.position(), model.position());
+    assert_eq!(sim.position().unwrap().x, 412);
+}
+
+#[tokio::test]
+async fn test_heading_drive_paused() {
+    tokio::time::pause();
+
+    let interval = Duration::from_millis(30);
+    let (mut cube, sim) = Simulator::new(Model::new(100.0, 250.0, 10.0))
+        .interval(interval)
+        .cube();
+    cube.connect().await.unwrap();
+
+    // The deadline of the drive follows the paused clock.
+    let mut ctrl = HeadingController::new(0.0);
+    let drive = ctrl.drive(&mut cube, 30, Duration::from_secs(1));
+    let clock = async {
+        for _ in 0..50 {
+            tokio::time::advance(interval).await;
+        }
+    };
+    let (res, _) = futures::join!(drive, clock);
+    res.unwrap();
+
+    let model = sim.model();
+    assert_eq!(model.wheels(), (0.0, 0.0));
+    let pos = model.position().unwrap();
+    assert!(pos.x > 250 && pos.x < 350, "{:?}", pos);
+    assert!((pos.y as i32 - 250).abs() < 15, "{:?}", pos);
+    assert!(pos.angle < 10 || pos.angle > 350, "{:?}", pos);
+}
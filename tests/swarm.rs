@@ -0,0 +1,88 @@
+use futures::prelude::*;
+use std::time::Duration;
+use toio::{
+    proto::*,
+    swarm::{State, Swarm, SwarmEvent},
+    Backoff, Options,
+};
+use tokio::time::{delay_for, timeout};
+
+mod common;
+
+async fn next(events: &mut (impl Stream<Item = SwarmEvent> + Unpin)) -> SwarmEvent {
+    timeout(Duration::from_secs(2), events.next())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_swarm_battery() {
+    let opts = Options {
+        battery_low: Some(20),
+        ..Options::default()
+    };
+    let (mut cube, mock) = common::cube_with_options("cube", opts);
+    cube.connect().await.unwrap();
+
+    let mut swarm = Swarm::new();
+    let mut events = swarm.subscribe();
+    swarm.add(cube).await.unwrap();
+    assert_eq!(next(&mut events).await, SwarmEvent::Joined("cube".into()));
+    assert_eq!(swarm.ids(), vec!["cube".to_string()]);
+    assert!(swarm.cube("cube").is_some());
+
+    let health = swarm.health();
+    assert_eq!(health.connected(), 1);
+    assert_eq!(health.cubes[0].battery, None);
+    assert_eq!(health.cubes[0].idle, None);
+
+    mock.notify(UUID_BATTERY, vec![15]);
+    assert_eq!(
+        next(&mut events).await,
+        SwarmEvent::BatteryLow("cube".into(), 15)
+    );
+    let health = swarm.health();
+    assert_eq!(health.cubes[0].battery, Some(15));
+    assert!(health.cubes[0].battery_low);
+    assert!(health.cubes[0].idle.is_some());
+    assert_eq!(health.unhealthy().len(), 1);
+
+    // Charged.
+    mock.notify(UUID_BATTERY, vec![40]);
+    delay_for(Duration::from_millis(50)).await;
+    let health = swarm.health();
+    assert_eq!(health.cubes[0].battery, Some(40));
+    assert!(health.unhealthy().is_empty());
+
+    assert!(swarm.remove("cube").is_some());
+    assert_eq!(next(&mut events).await, SwarmEvent::Left("cube".into()));
+    assert!(swarm.is_empty());
+    assert!(swarm.remove("cube").is_none());
+}
+
+#[tokio::test]
+async fn test_swarm_reconnect() {
+    // No battery value to answer the liveness check, so the watchdog fires.
+    let opts = Options {
+        watchdog: Some(Duration::from_millis(100)),
+        read_timeout: Duration::from_millis(50),
+        ..Options::default()
+    };
+    let (mut cube, _mock) = common::cube_with_options("cube", opts);
+    cube.connect().await.unwrap();
+
+    let mut swarm = Swarm::new().reconnect(Backoff::none());
+    let mut events = swarm.subscribe();
+    swarm.add(cube).await.unwrap();
+    assert_eq!(next(&mut events).await, SwarmEvent::Joined("cube".into()));
+
+    assert_eq!(next(&mut events).await, SwarmEvent::Lost("cube".into()));
+    assert_eq!(
+        next(&mut events).await,
+        SwarmEvent::Reconnected("cube".into())
+    );
+    let health = swarm.health();
+    assert_eq!(health.cubes[0].state, State::Connected);
+    assert!(health.cubes[0].reconnects >= 1);
+}
@@ -0,0 +1,31 @@
+use toio::teleop::Mapping;
+
+#[test]
+fn test_mapping() {
+    let mapping = Mapping {
+        max_speed: 100,
+        deadzone: 0.1,
+    };
+
+    // Within the deadzone.
+    assert_eq!(mapping.speeds(0.05, -0.08), (0, 0));
+
+    // Forward, backward and spin.
+    assert_eq!(mapping.speeds(0.0, 1.0), (100, 100));
+    assert_eq!(mapping.speeds(0.0, -1.0), (-100, -100));
+    assert_eq!(mapping.speeds(1.0, 0.0), (100, -100));
+
+    // Rescaled from the edge of the deadzone.
+    assert_eq!(mapping.speeds(0.0, 0.55), (50, 50));
+
+    // Normalized to keep the ratio of the wheels.
+    assert_eq!(mapping.speeds(1.0, 1.0), (100, 0));
+    assert_eq!(mapping.speeds(-0.55, 1.0), (33, 100));
+
+    // Limited by the max speed.
+    let mapping = Mapping {
+        max_speed: 40,
+        ..mapping
+    };
+    assert_eq!(mapping.speeds(0.0, 2.0), (40, 40));
+}
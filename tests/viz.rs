@@ -0,0 +1,48 @@
+#![cfg(feature = "viz")]
+
+use eframe::egui::{self, epaint::Shape, Pos2, RawInput, Rect, Vec2};
+use toio::{viz::Visualizer, Event, Position};
+
+/// Draws the visualizer on a 500x500 screen, and gets the texts drawn.
+fn texts(viz: &mut Visualizer) -> Vec<String> {
+    let ctx = egui::Context::default();
+    let input = RawInput {
+        screen_rect: Some(Rect::from_min_size(Pos2::ZERO, Vec2::splat(500.0))),
+        ..Default::default()
+    };
+    let output = ctx.run(input, |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| viz.draw(ui));
+    });
+    output
+        .shapes
+        .into_iter()
+        .filter_map(|s| match s.shape {
+            Shape::Text(text) => Some(text.galley.text().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_visualizer() {
+    let mut viz = Visualizer::new().trail(2);
+    viz.update("cube-a", &Event::Battery(80));
+    viz.update(
+        "cube-b",
+        &Event::Position(Some(Position::new(100, 100, 90))),
+    );
+    assert_eq!(viz.ids(), vec!["cube-a", "cube-b"]);
+    assert_eq!(viz.position("cube-b"), Some(&Position::new(100, 100, 90)));
+
+    // Only the cube with a position is drawn.
+    assert_eq!(texts(&mut viz), vec!["cube-b".to_string()]);
+
+    // Drawn at the last position even if off the mat.
+    viz.update("cube-b", &Event::Position(None));
+    assert_eq!(viz.position("cube-b"), None);
+    assert_eq!(texts(&mut viz), vec!["cube-b".to_string()]);
+
+    let mut viz = viz.background(2, 2, &[255; 16]);
+    viz.update("cube-a", &Event::Position(Some(Position::new(200, 300, 0))));
+    assert_eq!(texts(&mut viz).len(), 2);
+}
@@ -0,0 +1,450 @@
+use anyhow::Result;
+use futures::prelude::*;
+use std::convert::TryFrom;
+use std::time::Duration;
+use toio::{
+    ble::{self, mock, PeripheralOps, Uuid},
+    proto::*,
+    Cube, LightOp, Note, Options, Repeat, SoundOp, SoundPresetId,
+};
+
+#[tokio::test]
+async fn test_max_write_len() {
+    let (dev, handle) = mock::mock("cube");
+    handle.set_max_write_len(Some(20));
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            coalesce: true,
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+
+    let targets = (0..5)
+        .fold(MotorMultiTarget::builder(), |b, i| b.target(i, i))
+        .build()
+        .unwrap();
+    cube.write_msg(Message::Motor(Motor::MultiTarget(targets)), true)
+        .await
+        .unwrap();
+    let writes = handle.writes();
+    assert_eq!(writes.len(), 3);
+    assert!(writes
+        .iter()
+        .all(|(uuid, value)| *uuid == UUID_MOTOR && value.len() <= 20));
+
+    let ops = vec![SoundOp::new(Note::C5, Duration::from_millis(100)); 10];
+    let e = cube.play(Repeat::Forever, ops).await.unwrap_err();
+    assert!(e.to_string().contains("maximum write length"), "{}", e);
+    assert_eq!(handle.writes().len(), 3);
+
+    handle.set_max_write_len(None);
+    cube.connect().await.unwrap();
+    let ops = vec![SoundOp::new(Note::C5, Duration::from_millis(100)); 10];
+    cube.play(Repeat::Forever, ops).await.unwrap();
+    assert_eq!(handle.writes().len(), 4);
+}
+
+fn messages(handle: &mock::MockHandle) -> Vec<Message> {
+    handle
+        .writes()
+        .into_iter()
+        .map(|w| Message::try_from(w).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_max_write_rate() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            max_write_rate: Some(10),
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+    tokio::time::pause();
+
+    // Returns at once, leaving the writes queued.
+    let start = tokio::time::Instant::now();
+    for i in 0..5 {
+        cube.go(10 + i, 10, None).await.unwrap();
+    }
+    assert_eq!(start.elapsed(), Duration::from_secs(0));
+
+    // Written every 100 milliseconds.
+    let ms = Duration::from_millis;
+    tokio::time::delay_for(ms(50)).await;
+    assert_eq!(handle.writes().len(), 1);
+    for n in 2..=5 {
+        tokio::time::delay_for(ms(100)).await;
+        assert_eq!(handle.writes().len(), n);
+    }
+    tokio::time::delay_for(ms(500)).await;
+    assert_eq!(handle.writes().len(), 5);
+}
+
+#[tokio::test]
+async fn test_order() {
+    for max_write_rate in &[None, Some(50)] {
+        let (dev, handle) = mock::mock("cube");
+        let mut cube = Cube::from_peripheral(
+            Box::new(dev),
+            Options {
+                max_write_rate: *max_write_rate,
+                ..Options::default()
+            },
+        );
+        cube.connect().await.unwrap();
+
+        // Writes without response are queued, while the others wait until written.
+        cube.go(10, 10, None).await.unwrap();
+        cube.light_on(255, 0, 0, None).await.unwrap();
+        cube.go(20, 20, None).await.unwrap();
+        cube.play_preset(SoundPresetId::Enter).await.unwrap();
+        cube.go(30, 30, None).await.unwrap();
+        cube.light_off().await.unwrap();
+        cube.go(0, 0, None).await.unwrap();
+        cube.stop_sound().await.unwrap();
+
+        let writes = messages(&handle);
+        let uuids: Vec<_> = writes.iter().map(|m| m.uuid()).collect();
+        assert_eq!(
+            uuids,
+            vec![
+                UUID_MOTOR, UUID_LIGHT, UUID_MOTOR, UUID_SOUND, UUID_MOTOR, UUID_LIGHT, UUID_MOTOR,
+                UUID_SOUND
+            ],
+            "{:?}",
+            writes
+        );
+        let speeds: Vec<_> = writes
+            .iter()
+            .filter_map(|m| match m {
+                Message::Motor(Motor::Simple(m)) => Some(m.speed1),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(speeds, vec![17, 28, 39, 7]);
+    }
+}
+
+#[tokio::test]
+async fn test_coalesce() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            max_write_rate: Some(10),
+            coalesce: true,
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+    tokio::time::pause();
+
+    cube.go(10, 10, None).await.unwrap();
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert_eq!(handle.writes().len(), 1);
+
+    // Queued behind the rate limit.
+    cube.go(20, 20, None).await.unwrap();
+    cube.go(30, 30, None).await.unwrap();
+    for id in 1..=2 {
+        let target = MotorTarget::builder(100, 100).id(id).build().unwrap();
+        cube.write_msg(Message::Motor(Motor::Target(target)), false)
+            .await
+            .unwrap();
+    }
+    cube.write_msg(Message::Sound(Sound::Stop), false)
+        .await
+        .unwrap();
+    cube.write_msg(Message::Sound(Sound::Stop), false)
+        .await
+        .unwrap();
+
+    // The abandoned write is replaced, and the newer one completes once written.
+    let red = tokio::time::timeout(Duration::from_millis(1), cube.light_on(255, 0, 0, None));
+    assert!(red.await.is_err());
+    cube.light_on(0, 255, 0, None).await.unwrap();
+
+    let writes = messages(&handle);
+    assert_eq!(writes.len(), 6, "{:?}", writes);
+    match (&writes[0], &writes[1]) {
+        (Message::Motor(Motor::Simple(a)), Message::Motor(Motor::Simple(b))) => {
+            assert_eq!((a.speed1, b.speed1), (17, 39))
+        }
+        msgs => panic!("{:?}", msgs),
+    }
+    match (&writes[2], &writes[3]) {
+        (Message::Motor(Motor::Target(a)), Message::Motor(Motor::Target(b))) => {
+            assert_eq!((a.id, b.id), (1, 2))
+        }
+        msgs => panic!("{:?}", msgs),
+    }
+    assert!(matches!(writes[4], Message::Sound(Sound::Stop)));
+    match &writes[5] {
+        Message::Light(Light::On(on)) => assert_eq!((on.red, on.green), (0, 255)),
+        msg => panic!("{:?}", msg),
+    }
+}
+
+#[tokio::test]
+async fn test_split_not_replaced() {
+    let (dev, handle) = mock::mock("cube");
+    handle.set_max_write_len(Some(20));
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            max_write_rate: Some(10),
+            coalesce: true,
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+    tokio::time::pause();
+
+    // Queued behind the rate limit, where coalescing could replace them.
+    for n in 0..2 {
+        let targets = (0..5)
+            .fold(MotorMultiTarget::builder(), |b, i| b.target(i + n, i))
+            .build()
+            .unwrap();
+        cube.write_msg(Message::Motor(Motor::MultiTarget(targets)), false)
+            .await
+            .unwrap();
+    }
+    tokio::time::delay_for(Duration::from_secs(1)).await;
+    assert_eq!(handle.writes().len(), 6);
+}
+
+#[tokio::test]
+async fn test_sequence() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            coalesce: true,
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+
+    cube.sequence()
+        .light_on(255, 0, 0, None)
+        .play_preset(SoundPresetId::Enter)
+        .go(50, -50, Some(Duration::from_millis(500)))
+        .light_off()
+        .send()
+        .await
+        .unwrap();
+    let writes: Vec<_> = handle
+        .writes()
+        .into_iter()
+        .map(|w| Message::try_from(w).unwrap())
+        .collect();
+    assert_eq!(writes.len(), 4);
+    assert!(matches!(writes[0], Message::Light(Light::On(_))));
+    assert!(matches!(writes[1], Message::Sound(Sound::Preset(_))));
+    assert!(matches!(writes[2], Message::Motor(Motor::Timed(_))));
+    assert!(matches!(writes[3], Message::Light(Light::Off(_))));
+
+    // Nothing is written if any of them is invalid.
+    let e = cube
+        .sequence()
+        .light_on(0, 255, 0, None)
+        .go(150, 0, None)
+        .send()
+        .await
+        .unwrap_err();
+    assert!(e.to_string().contains("Wheel speed"), "{}", e);
+
+    handle.set_max_write_len(Some(8));
+    cube.connect().await.unwrap();
+    let ops = (0..10)
+        .map(|_| toio::proto::SoundOp::new(10, Note::C5, 255))
+        .collect();
+    let e = cube
+        .sequence()
+        .stop()
+        .msg(Sound::Play(SoundPlay::new(1, 10, ops)), true)
+        .send()
+        .await
+        .unwrap_err();
+    assert!(e.to_string().contains("maximum write length"), "{}", e);
+    assert_eq!(handle.writes().len(), 4);
+}
+
+#[tokio::test]
+async fn test_emergency_stop() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            max_write_rate: Some(5),
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+
+    // Queued behind the rate limit.
+    for i in 0..5 {
+        cube.go(10 + i, 10, None).await.unwrap();
+    }
+    cube.emergency_stop().await.unwrap();
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+
+    let writes: Vec<_> = handle
+        .writes()
+        .into_iter()
+        .map(|w| Message::try_from(w).unwrap())
+        .collect();
+    let n = writes.len();
+    assert!(n <= 5, "{:?}", writes);
+    match &writes[n - 3] {
+        Message::Motor(Motor::Simple(m)) => assert_eq!((m.speed1, m.speed2), (7, 7)),
+        msg => panic!("{:?}", msg),
+    }
+    assert!(matches!(writes[n - 2], Message::Light(Light::Off(_))));
+    assert!(matches!(writes[n - 1], Message::Sound(Sound::Stop)));
+}
+
+/// Peripheral never answering writes with response.
+struct Stuck(mock::Mock);
+
+#[async_trait::async_trait]
+impl PeripheralOps for Stuck {
+    fn id(&self) -> &str {
+        self.0.id()
+    }
+
+    fn rssi(&self) -> i32 {
+        self.0.rssi()
+    }
+
+    fn writer(&self) -> Option<ble::Writer> {
+        self.0.writer()
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        self.0.connect().await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.0.disconnect().await
+    }
+
+    async fn read(&mut self, uuid: &Uuid) -> Result<()> {
+        self.0.read(uuid).await
+    }
+
+    async fn write(&mut self, uuid: &Uuid, value: &[u8], with_resp: bool) -> Result<()> {
+        if with_resp {
+            future::pending::<()>().await;
+        }
+        self.0.write(uuid, value, with_resp).await
+    }
+
+    fn subscribe(&mut self) -> Result<ble::ValueStream> {
+        self.0.subscribe()
+    }
+}
+
+#[tokio::test]
+async fn test_stop_handle() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(Stuck(dev)), Options::default());
+    cube.connect().await.unwrap();
+    cube.go(50, 50, None).await.unwrap();
+
+    // Borrows the cube and holds the peripheral waiting for the response.
+    let stop = cube.stop_handle();
+    let busy = tokio::spawn(async move { cube.light_on(255, 0, 0, None).await });
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+
+    tokio::time::timeout(Duration::from_secs(1), stop.emergency_stop())
+        .await
+        .unwrap()
+        .unwrap();
+    let writes: Vec<_> = handle
+        .writes()
+        .into_iter()
+        .map(|w| Message::try_from(w).unwrap())
+        .collect();
+    assert_eq!(writes.len(), 4, "{:?}", writes);
+    match &writes[1] {
+        Message::Motor(Motor::Simple(m)) => assert_eq!((m.speed1, m.speed2), (7, 7)),
+        msg => panic!("{:?}", msg),
+    }
+    assert!(matches!(writes[2], Message::Light(Light::Off(_))));
+    assert!(matches!(writes[3], Message::Sound(Sound::Stop)));
+
+    // The light is still waiting for the response.
+    assert!(tokio::time::timeout(Duration::from_millis(10), busy)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_probe_latency() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(
+        Box::new(dev),
+        Options {
+            max_write_rate: Some(1),
+            ..Options::default()
+        },
+    );
+    cube.connect().await.unwrap();
+    assert_eq!(cube.latency(), None);
+
+    // Not limited by the write rate.
+    let latency = tokio::time::timeout(Duration::from_secs(1), cube.probe_latency(5))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(latency.samples, 5);
+    assert!(latency.round_trip < Duration::from_millis(100));
+    assert_eq!(cube.latency(), Some(latency));
+    let writes = handle.writes();
+    assert_eq!(writes.len(), 5);
+    assert!(writes.iter().all(|(uuid, _)| *uuid == UUID_CONFIG));
+
+    assert!(cube.probe_latency(0).await.is_err());
+    assert_eq!(cube.latency(), Some(latency));
+}
+
+#[tokio::test]
+async fn test_repeat() {
+    let (dev, handle) = mock::mock("cube");
+    let mut cube = Cube::from_peripheral(Box::new(dev), Options::default());
+    cube.connect().await.unwrap();
+
+    let sound = || vec![SoundOp::new(Note::C5, Duration::from_millis(100))];
+    let light = || vec![LightOp::new(255, 0, 0, Some(Duration::from_millis(100)))];
+    for (repeat, count) in [
+        (Repeat::Forever, 0),
+        (Repeat::Times(1), 1),
+        (Repeat::Times(255), 255),
+    ] {
+        cube.play(repeat, sound()).await.unwrap();
+        cube.light(repeat, light()).await.unwrap();
+        let msgs = messages(&handle);
+        match &msgs[msgs.len() - 2..] {
+            [Message::Sound(Sound::Play(s)), Message::Light(Light::Ctrl(l))] => {
+                assert_eq!((s.repeat, l.repeat), (count, count));
+            }
+            m => panic!("Unexpected messages: {:?}", m),
+        }
+    }
+
+    // Nothing is written with counts out of range.
+    let written = handle.writes().len();
+    for repeat in [Repeat::Times(0), Repeat::Times(256)] {
+        assert!(cube.play(repeat, sound()).await.is_err());
+        assert!(cube.light(repeat, light()).await.is_err());
+    }
+    assert_eq!(handle.writes().len(), written);
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "websocket")]
+
+use futures::prelude::*;
+use toio::{
+    proto::*,
+    ws::{CommandFrame, EventFrame, Server},
+    Event,
+};
+use tokio::time::{delay_for, Duration};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+mod common;
+
+#[tokio::test]
+async fn test_server() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let (mut cube, mock) = common::cube("cube");
+    cube.connect().await.unwrap();
+    tokio::spawn(async move { Server::new(vec![cube]).serve(addr).await.unwrap() });
+    delay_for(Duration::from_millis(100)).await;
+
+    let (mut ws, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    // Events are sent to clients.
+    mock.notify(UUID_BATTERY, vec![80]);
+    let frame = match ws.next().await.unwrap().unwrap() {
+        WsMessage::Text(text) => serde_json::from_str::<EventFrame>(&text).unwrap(),
+        m => panic!("unexpected frame: {:?}", m),
+    };
+    assert_eq!(
+        frame,
+        EventFrame {
+            cube: "cube".into(),
+            event: Event::Battery(80),
+        }
+    );
+
+    // Commands are written.
+    let frame = CommandFrame {
+        cube: "cube".into(),
+        message: Message::Sound(Sound::Stop),
+    };
+    ws.send(WsMessage::Text(serde_json::to_string(&frame).unwrap()))
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(mock.writes(), vec![(UUID_SOUND, vec![0x01])]);
+}